@@ -0,0 +1,114 @@
+use std::{env, path::PathBuf};
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{
+    parse::{Parse, ParseStream},
+    parse_macro_input,
+    punctuated::Punctuated,
+    Ident, LitStr, Token,
+};
+
+/// The arguments of `include_postgres_schema!`
+struct SchemaInput {
+    /// Whether to connect to the database instead of reading a snapshot
+    live: bool,
+    /// The snapshot followed by the command line arguments, or only the arguments when live
+    args: Vec<LitStr>,
+}
+
+impl Parse for SchemaInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        // Get the live marker, if any
+        let live = input.peek(Ident);
+        if live {
+            let ident: Ident = input.parse()?;
+            if ident != "live" {
+                return Err(syn::Error::new(
+                    ident.span(),
+                    "expected `live` or the path of a snapshot",
+                ));
+            }
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+
+        let args = Punctuated::<LitStr, Token![,]>::parse_terminated(input)?;
+        Ok(SchemaInput {
+            live,
+            args: args.into_iter().collect(),
+        })
+    }
+}
+
+/// Expand to the code generated for a PostgreSQL schema
+///
+/// The first argument is a snapshot written with `--snapshot`, relative to the crate's
+/// `Cargo.toml`, and the rest are command line arguments:
+/// ```ignore
+/// include_postgres_schema!("schema.json", "--uuid", "--column-enums");
+/// ```
+///
+/// With the `live` feature, `live` connects to the database while compiling instead, with the
+/// connection settings from the environment:
+/// ```ignore
+/// include_postgres_schema!(live, "--database", "mydb", "--schema", "app");
+/// ```
+#[proc_macro]
+pub fn include_postgres_schema(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as SchemaInput);
+    match expand(input) {
+        Ok(code) => code.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+/// Generate the code for the macro's arguments
+fn expand(input: SchemaInput) -> syn::Result<proc_macro2::TokenStream> {
+    let span = input
+        .args
+        .first()
+        .map(LitStr::span)
+        .unwrap_or_else(Span::call_site);
+
+    let mut args = Vec::new();
+    let mut snapshot = None;
+    if input.live {
+        if !cfg!(feature = "live") {
+            return Err(syn::Error::new(
+                span,
+                "connecting to the database needs the `live` feature of rustgres-schema-macros",
+            ));
+        }
+    } else {
+        // Snapshot paths are relative to the crate, not the directory rustc runs in
+        let path = input
+            .args
+            .first()
+            .ok_or_else(|| syn::Error::new(span, "expected the path of a snapshot"))?;
+        let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap_or_default();
+        let path = PathBuf::from(manifest_dir).join(path.value());
+        args.push("--from-snapshot".to_string());
+        args.push(path.to_string_lossy().to_string());
+        snapshot = Some(path);
+    }
+    let skip = if input.live { 0 } else { 1 };
+    args.extend(input.args.iter().skip(skip).map(LitStr::value));
+
+    let code = postgres_to_rust::expand(&args).map_err(|error| syn::Error::new(span, error))?;
+    let code: proc_macro2::TokenStream =
+        code.parse().map_err(|error| syn::Error::new(span, error))?;
+
+    // Include the snapshot, so the crate is rebuilt when it changes
+    let snapshot = snapshot.map(|path| {
+        let path = path.to_string_lossy().to_string();
+        quote! { const _: &[u8] = include_bytes!(#path); }
+    });
+
+    Ok(quote! {
+        #snapshot
+        #code
+    })
+}
@@ -0,0 +1,136 @@
+use std::time::{Duration, SystemTime};
+
+use aws_config::{BehaviorVersion, Region};
+use aws_credential_types::provider::ProvideCredentials;
+use aws_sigv4::{
+    http_request::{sign, SignableBody, SignableRequest, SignatureLocation, SigningSettings},
+    sign::v4,
+};
+use tracing::{debug, info};
+
+use crate::error::{Error, Result};
+
+/// How long a generated token can be used to log in, RDS doesn't accept more than 15 minutes
+const TOKEN_LIFETIME: Duration = Duration::from_secs(15 * 60);
+
+/// Generate a short-lived RDS IAM authentication token to use as the password
+///
+/// Credentials and the region come from the usual AWS sources (environment, profile, SSO, instance
+/// metadata), the region can be overridden with `region`.
+pub fn generate_token(
+    host: &str,
+    port: u16,
+    username: &str,
+    region: Option<&str>,
+) -> Result<String> {
+    let iam_error = |message: &str, error: &dyn std::fmt::Display| {
+        Error::AwsIam(format!("{}: {}", message, error))
+    };
+
+    info!("Generating RDS IAM authentication token");
+
+    // The AWS config loaders are async, so load them on a small runtime
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|error| iam_error("failed to start the AWS config loader", &error))?;
+    let (credentials, region) = runtime.block_on(async {
+        let mut loader = aws_config::defaults(BehaviorVersion::latest());
+        if let Some(region) = region {
+            loader = loader.region(Region::new(region.to_string()));
+        }
+        let config = loader.load().await;
+
+        let region = config
+            .region()
+            .map(|region| region.to_string())
+            .ok_or_else(|| {
+                Error::AwsIam("no AWS region is configured, pass --aws-region".to_string())
+            })?;
+        let provider = config
+            .credentials_provider()
+            .ok_or_else(|| Error::AwsIam("no AWS credentials are configured".to_string()))?;
+        let credentials = provider
+            .provide_credentials()
+            .await
+            .map_err(|error| iam_error("failed to load AWS credentials", &error))?;
+
+        Ok::<_, Error>((credentials, region))
+    })?;
+    debug!("Signing RDS IAM token for {}:{} in {}", host, port, region);
+
+    // The token is a presigned rds-db:connect request, without the scheme
+    let mut settings = SigningSettings::default();
+    settings.expires_in = Some(TOKEN_LIFETIME);
+    settings.signature_location = SignatureLocation::QueryParams;
+
+    let identity = credentials.into();
+    let params = v4::SigningParams::builder()
+        .identity(&identity)
+        .region(&region)
+        .name("rds-db")
+        .time(SystemTime::now())
+        .settings(settings)
+        .build()
+        .map_err(|error| iam_error("failed to sign RDS IAM token", &error))?
+        .into();
+
+    let mut url = connect_url(host, port, username)
+        .map_err(|error| iam_error("failed to sign RDS IAM token", &error))?;
+    let request = SignableRequest::new(
+        "GET",
+        url.as_str(),
+        std::iter::empty(),
+        SignableBody::Bytes(&[]),
+    )
+    .map_err(|error| iam_error("failed to sign RDS IAM token", &error))?;
+    let (instructions, _) = sign(request, &params)
+        .map_err(|error| iam_error("failed to sign RDS IAM token", &error))?
+        .into_parts();
+
+    for (name, value) in instructions.params() {
+        url.query_pairs_mut().append_pair(name, value);
+    }
+
+    Ok(url.as_str().trim_start_matches("https://").to_string())
+}
+
+/// Get the rds-db:connect request of `username`, which is signed for the token
+///
+/// The user name is encoded like the query parameters of the signature, so names with characters
+/// like `&` or `+` are signed and sent as they are.
+fn connect_url(
+    host: &str,
+    port: u16,
+    username: &str,
+) -> std::result::Result<url::Url, url::ParseError> {
+    let mut url = url::Url::parse(&format!("https://{}:{}/", host, port))?;
+    url.query_pairs_mut()
+        .append_pair("Action", "connect")
+        .append_pair("DBUser", username);
+
+    Ok(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_names_are_encoded() {
+        let url = connect_url("db.example.com", 5432, "app").unwrap();
+        assert_eq!(
+            url.as_str(),
+            "https://db.example.com:5432/?Action=connect&DBUser=app"
+        );
+
+        let url = connect_url("db.example.com", 5432, "a&b=c+d e").unwrap();
+        assert_eq!(
+            url.as_str(),
+            "https://db.example.com:5432/?Action=connect&DBUser=a%26b%3Dc%2Bd+e"
+        );
+        assert!(url
+            .query_pairs()
+            .any(|(name, value)| name == "DBUser" && value == "a&b=c+d e"));
+    }
+}
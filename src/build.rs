@@ -0,0 +1,110 @@
+use std::{env, path::PathBuf};
+
+use clap::Command;
+use tracing::info;
+
+use crate::cli::{drop_args, with_profile};
+use crate::config::{Config, DEFAULT_CONFIG_FILE};
+use crate::error::{Error, Result};
+
+/// The environment variables that change what is generated besides the `PG2RUST_*` variables of
+/// the arguments, so Cargo reruns the build script when any of them do
+const ENV_VARIABLES: [&str; 7] = [
+    "POSTGRES_USER",
+    "POSTGRES_PASSWORD",
+    "POSTGRES_HOST",
+    "POSTGRES_PORT",
+    "PGPASSFILE",
+    "PGSERVICEFILE",
+    "PGSYSCONFDIR",
+];
+
+/// Generate code from a build script into `OUT_DIR`
+///
+/// The arguments come from the `[build]` section of `pg2rust.toml` next to `Cargo.toml`, or the
+/// config file in `PG2RUST_CONFIG`, and the connection settings from the usual environment
+/// variables. The generated code can then be included with
+/// `include!(concat!(env!("OUT_DIR"), "/schema.rs"))`.
+///
+/// Cargo can't tell when the database changes, so the build script only reruns when the config,
+/// the environment or the files it reads change. Generate from a snapshot with `--from-snapshot`
+/// to keep builds reproducible.
+pub fn generate() -> Result<()> {
+    let build_env = |variable: &str| {
+        env::var(variable).map_err(|_| {
+            Error::Config(format!(
+                "{} isn't set, build::generate must be called from a build script",
+                variable
+            ))
+        })
+    };
+    let manifest_dir = PathBuf::from(build_env("CARGO_MANIFEST_DIR")?);
+    let out_dir = build_env("OUT_DIR")?;
+
+    let command = crate::cli::command();
+    let argument_variables = command
+        .get_arguments()
+        .filter_map(|argument| argument.get_env())
+        .map(|variable| variable.to_string_lossy().to_string());
+    for variable in ENV_VARIABLES
+        .map(String::from)
+        .into_iter()
+        .chain(argument_variables)
+    {
+        println!("cargo:rerun-if-env-changed={}", variable);
+    }
+
+    // Get the config file, which is optional unless it was set explicitly
+    let config_path = env::var("PG2RUST_CONFIG")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| manifest_dir.join(DEFAULT_CONFIG_FILE));
+    println!("cargo:rerun-if-changed={}", config_path.display());
+    let explicit_config = env::var("PG2RUST_CONFIG").is_ok();
+
+    // Always write to OUT_DIR, with the rest of the arguments from the config
+    let mut args = vec![
+        env!("CARGO_PKG_NAME").to_string(),
+        "--output-directory".to_string(),
+        out_dir,
+    ];
+    let config = if explicit_config || config_path.exists() {
+        let config_path = config_path.to_string_lossy().to_string();
+        let config = Config::load(Some(&config_path))?;
+        args.extend(["--config".to_string(), config_path]);
+        config
+    } else {
+        Config::default()
+    };
+    args.extend(overridable_args(&command, config.build.args));
+    let args = with_profile(args)?;
+    let matches = command
+        .try_get_matches_from(args)
+        .map_err(|error| Error::Config(format!("Invalid [build] args: {}", error)))?;
+
+    // Rerun when the files the schema is read from change
+    for argument in ["from_snapshot", "env_file"] {
+        if let Some(path) = matches.get_one::<String>(argument) {
+            println!(
+                "cargo:rerun-if-changed={}",
+                manifest_dir.join(path).display()
+            );
+        }
+    }
+
+    if let Some(profile) = matches.get_one::<String>("profile") {
+        info!("Using profile {}", profile);
+    }
+    info!("Generating code from the build script");
+    crate::run(&matches)
+}
+
+/// Drop the arguments from the config whose `PG2RUST_*` variable is set, since the environment
+/// is used over the config
+fn overridable_args(command: &Command, args: Vec<String>) -> Vec<String> {
+    drop_args(command, args, |argument| {
+        argument
+            .get_env()
+            .filter(|variable| env::var_os(variable).is_some())
+            .map(|variable| format!("{} is set", variable.to_string_lossy()))
+    })
+}
@@ -0,0 +1,208 @@
+use std::borrow::Cow;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+use postgres::GenericClient;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{debug, info, warn};
+
+use crate::connection::{query, ConnectionSettings};
+use crate::error::{Error, Result};
+use crate::introspect::Introspection;
+use crate::model::Schema;
+use crate::version::ServerVersion;
+
+/// The catalogs the schema is read from. Any change to the schema adds, updates or deletes rows
+/// in one of them, which changes its row count or the sum of its `xmin`s
+const CATALOGS: [&str; 9] = [
+    "pg_namespace",
+    "pg_class",
+    "pg_attribute",
+    "pg_attrdef",
+    "pg_type",
+    "pg_enum",
+    "pg_constraint",
+    "pg_index",
+    "pg_description",
+];
+
+/// Get the directory the schemas are cached in, `$XDG_CACHE_HOME/pg2rust` or
+/// `~/.cache/pg2rust`
+fn cache_directory() -> Option<PathBuf> {
+    match env::var_os("XDG_CACHE_HOME") {
+        Some(cache) if !cache.is_empty() => Some(PathBuf::from(cache).join("pg2rust")),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache").join("pg2rust")),
+    }
+}
+
+/// Where a schema is cached, and the state of the database it was read in
+pub struct CacheKey {
+    /// The name of the entry, made of the server, database and schema, which is replaced when the
+    /// state changes
+    entry: String,
+    /// The state the cached schema is valid for
+    state: String,
+}
+
+/// A cached schema, with the state of the database it was read in
+#[derive(Serialize, Deserialize)]
+struct CacheEntry<'a> {
+    state: Cow<'a, str>,
+    schema: Cow<'a, Schema>,
+}
+
+/// Get the key a schema is cached under, for `--cached`
+///
+/// Every server, database and schema has one entry, which holds the schema with the state it
+/// was read in: the server version, how the schema is read, and the state of the catalogs, so
+/// any change to the database replaces it. The version of rustgres-schema is part of the state
+/// too, since the model may change between versions.
+pub fn key(
+    client: &mut impl GenericClient,
+    simple_query: bool,
+    settings: &ConnectionSettings,
+    schema: &str,
+    version: ServerVersion,
+    introspection: Introspection,
+    include_views: bool,
+) -> Result<CacheKey> {
+    let catalogs: Vec<String> = CATALOGS
+        .iter()
+        .map(|catalog| {
+            format!(
+                "(SELECT count(*) || ':' || coalesce(sum(xmin::text::bigint), 0) FROM pg_catalog.{})",
+                catalog
+            )
+        })
+        .collect();
+    let rows = query(
+        client,
+        simple_query,
+        &format!("SELECT {}", catalogs.join(" || ' ' || ")),
+        &[],
+    )
+    .map_err(Error::query("catalog state"))?;
+    let catalog_state: String = rows[0].get(0);
+
+    let entry = sha256(&[
+        settings.host.clone(),
+        settings.port.clone(),
+        settings.database.clone(),
+        schema.to_string(),
+    ]);
+    let state = sha256(&[
+        env!("CARGO_PKG_VERSION").to_string(),
+        version.to_string(),
+        format!("{:?}", introspection),
+        include_views.to_string(),
+        catalog_state,
+    ]);
+
+    Ok(CacheKey { entry, state })
+}
+
+/// Get the SHA-256 hash of some lines, in hexadecimal
+fn sha256(parts: &[String]) -> String {
+    Sha256::digest(parts.join("\n").as_bytes())
+        .iter()
+        .fold(String::new(), |mut hex, byte| {
+            write!(hex, "{:02x}", byte).unwrap();
+            hex
+        })
+}
+
+/// Read the schema cached under `key`, if there is one and the database is in the same state
+///
+/// An unreadable entry is ignored, so the schema is read from the database again and the entry
+/// replaced.
+pub fn read(key: &CacheKey) -> Option<Schema> {
+    read_entry(&cache_directory()?, key)
+}
+
+/// Cache the schema under `key`, replacing the schema cached for an earlier state
+///
+/// Failing to write the cache only costs the next run some time, so it is logged rather than
+/// an error.
+pub fn write(key: &CacheKey, schema: &Schema) {
+    let Some(directory) = cache_directory() else {
+        warn!("Neither XDG_CACHE_HOME nor HOME is set, not caching the schema");
+        return;
+    };
+    write_entry(&directory, key, schema);
+}
+
+/// Read the schema cached under `key` in `directory`, if the database is in the same state
+fn read_entry(directory: &Path, key: &CacheKey) -> Option<Schema> {
+    let path = directory.join(format!("{}.json", key.entry));
+    let contents = fs::read_to_string(&path).ok()?;
+    match serde_json::from_str::<CacheEntry>(&contents) {
+        Ok(entry) if entry.state == key.state => {
+            info!("Read the schema from the cache {}", path.display());
+            Some(entry.schema.into_owned())
+        }
+        Ok(_) => {
+            debug!("The database changed since {} was cached", path.display());
+            None
+        }
+        Err(error) => {
+            warn!("Ignoring the cached schema {}: {}", path.display(), error);
+            None
+        }
+    }
+}
+
+/// Cache the schema under `key` in `directory`
+fn write_entry(directory: &Path, key: &CacheKey, schema: &Schema) {
+    let path = directory.join(format!("{}.json", key.entry));
+    let entry = CacheEntry {
+        state: Cow::Borrowed(&key.state),
+        schema: Cow::Borrowed(schema),
+    };
+    let contents = serde_json::to_string(&entry).expect("Schemas are always serializable");
+    match fs::create_dir_all(directory).and_then(|_| fs::write(&path, contents)) {
+        Ok(()) => debug!("Cached the schema in {}", path.display()),
+        Err(error) => warn!(
+            "Failed to cache the schema in {}: {}",
+            path.display(),
+            error
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn an_entry_is_replaced_when_the_state_changes() {
+        let directory = env::temp_dir().join(format!("pg2rust-cache-test-{}", std::process::id()));
+        let schema = |name: &str| Schema {
+            name: name.to_string(),
+            tables: Vec::new(),
+            enums: Vec::new(),
+            composites: Vec::new(),
+            scope: None,
+        };
+        let key = |state: &str| CacheKey {
+            entry: "entry".to_string(),
+            state: state.to_string(),
+        };
+
+        assert_eq!(read_entry(&directory, &key("first")), None);
+        write_entry(&directory, &key("first"), &schema("first"));
+        assert_eq!(read_entry(&directory, &key("first")), Some(schema("first")));
+        assert_eq!(read_entry(&directory, &key("second")), None);
+
+        write_entry(&directory, &key("second"), &schema("second"));
+        assert_eq!(read_entry(&directory, &key("first")), None);
+        assert_eq!(
+            read_entry(&directory, &key("second")),
+            Some(schema("second"))
+        );
+        assert_eq!(fs::read_dir(&directory).unwrap().count(), 1);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,828 @@
+use std::io::{self, Write};
+
+use clap::parser::ValueSource;
+use clap::{command, Arg, ArgAction, Command};
+use clap_complete::Shell;
+use tracing::info;
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+
+/// The command line interface, shared by the binary and [`crate::build::generate`]
+pub fn command() -> Command {
+    command!()
+        .subcommand_negates_reqs(true)
+        .arg(Arg::new("verbose")
+            .long("verbose")
+            .env("PG2RUST_VERBOSE")
+            .short('v')
+            .help("Sets the level of verbosity. Pass once for debug logs and twice for trace logs")
+            .required(false)
+            .action(ArgAction::Count)
+        )
+        .arg(Arg::new("log_format")
+            .long("log-format")
+            .env("PG2RUST_LOG_FORMAT")
+            .help("Sets the format of the logs")
+            .required(false)
+            .value_parser(["pretty", "json"])
+            .default_value("pretty")
+        )
+        .arg(Arg::new("log_file")
+            .long("log-file")
+            .env("PG2RUST_LOG_FILE")
+            .help("Write the logs to a file instead of stderr")
+            .required(false)
+        )
+        .arg(Arg::new("config")
+            .long("config")
+            .env("PG2RUST_CONFIG")
+            .help("Sets the config file. Defaults to pg2rust.toml, if it exists")
+            .required(false)
+        )
+        .arg(Arg::new("profile")
+            .long("profile")
+            .env("PG2RUST_PROFILE")
+            .help("Adds the args of a [profile.<name>] of the config file, like the connection settings and output of an environment. The command line and environment take precedence over them")
+            .required(false)
+        )
+        .arg(Arg::new("env_file")
+            .long("env-file")
+            .env("PG2RUST_ENV_FILE")
+            .help("Sets the environment file, which the POSTGRES_USER, POSTGRES_PASSWORD, POSTGRES_HOST and POSTGRES_PORT connection settings are read from. The username, password, host and port arguments are used over it")
+            .required(false)
+        )
+        .arg(Arg::new("service")
+            .long("service")
+            .env("PG2RUST_SERVICE")
+            .help("Reads the host, port, database, username and password from this service of the connection service file, ~/.pg_service.conf or PGSERVICEFILE, like libpq. The command line arguments are used over it")
+            .required(false)
+        )
+        .arg(Arg::new("host")
+            .long("host")
+            .env("PG2RUST_HOST")
+            .help("Sets the PostgreSQL host")
+            .required(false)
+            .default_value("localhost")
+        )
+        .arg(Arg::new("port")
+            .long("port")
+            .env("PG2RUST_PORT")
+            .help("Sets the PostgreSQL port")
+            .required(false)
+            .default_value("5432")
+        )
+        .arg(Arg::new("username")
+            .long("username")
+            .env("PG2RUST_USERNAME")
+            .help("Sets the PostgreSQL username")
+            .required(false)
+        )
+        .arg(Arg::new("password")
+            .long("password")
+            .env("PG2RUST_PASSWORD")
+            .help("Sets the PostgreSQL password")
+            .required(false)
+        )
+        .arg(Arg::new("no_password")
+            .short('w')
+            .long("no-password")
+            .env("PG2RUST_NO_PASSWORD")
+            .help("Never prompt for a password. Fails if no password is set and none is found in the password file")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("ssh")
+            .long("ssh")
+            .env("PG2RUST_SSH")
+            .value_name("USER@HOST[:PORT]")
+            .help("Connect through an SSH tunnel to this server. The database host is resolved by the SSH server")
+            .required(false)
+        )
+        .arg(Arg::new("ssh_identity")
+            .long("ssh-identity")
+            .env("PG2RUST_SSH_IDENTITY")
+            .value_name("FILE")
+            .help("The private key to authenticate with the SSH server. The SSH agent and default keys are tried otherwise")
+            .required(false)
+            .requires("ssh")
+        )
+        .arg(Arg::new("ssh_accept_unknown_host")
+            .long("ssh-accept-unknown-host")
+            .env("PG2RUST_SSH_ACCEPT_UNKNOWN_HOST")
+            .help("Connect to an SSH server whose host key isn't in ~/.ssh/known_hosts, or when there's no known_hosts file. A key that doesn't match known_hosts is still refused")
+            .required(false)
+            .action(ArgAction::SetTrue)
+            .requires("ssh")
+        )
+        .arg(Arg::new("aws_iam")
+            .long("aws-iam")
+            .env("PG2RUST_AWS_IAM")
+            .help("Log in with a short-lived RDS IAM token instead of a password. Implies sslmode=require")
+            .required(false)
+            .action(ArgAction::SetTrue)
+            .conflicts_with("password")
+        )
+        .arg(Arg::new("aws_region")
+            .long("aws-region")
+            .env("PG2RUST_AWS_REGION")
+            .help("The AWS region of the database, for --aws-iam. Defaults to the configured region")
+            .required(false)
+            .requires("aws_iam")
+        )
+        .arg(Arg::new("connect_timeout")
+            .long("connect-timeout")
+            .env("PG2RUST_CONNECT_TIMEOUT")
+            .value_name("SECONDS")
+            .help("Give up on connecting to the database after this many seconds")
+            .required(false)
+            .value_parser(clap::value_parser!(u64).range(1..))
+        )
+        .arg(Arg::new("statement_timeout")
+            .long("statement-timeout")
+            .env("PG2RUST_STATEMENT_TIMEOUT")
+            .value_name("SECONDS")
+            .help("Cancel introspection queries that run longer than this many seconds")
+            .required(false)
+            .value_parser(clap::value_parser!(u64).range(1..))
+        )
+        .arg(Arg::new("retries")
+            .long("retries")
+            .env("PG2RUST_RETRIES")
+            .value_name("N")
+            .help("Retry a connection that fails because of the network up to N times, with exponential backoff")
+            .required(false)
+            .value_parser(clap::value_parser!(u32))
+        )
+        .arg(Arg::new("database")
+            .long("database")
+            .env("PG2RUST_DATABASE")
+            .help("Sets the PostgreSQL database")
+            .required_unless_present_any(["from_snapshot", "from_ir", "service"])
+        )
+        .arg(Arg::new("simple_query")
+            .long("simple-query")
+            .env("PG2RUST_SIMPLE_QUERY")
+            .help("Read the schema without prepared statements, for connections through PgBouncer in transaction pooling mode")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("include_views")
+            .short('i')
+            .long("include-views")
+            .env("PG2RUST_INCLUDE_VIEWS")
+            .help("Include PostgreSQL views in the generated schema")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("view_not_null")
+            .long("view-not-null")
+            .env("PG2RUST_VIEW_NOT_NULL")
+            .value_name("VIEW.COLUMN")
+            .help("View columns that can't be NULL, for when it can't be inferred. Comma separated, e.g. user_counts.post_count")
+            .required(false)
+            .requires("include_views")
+        )
+        .arg(Arg::new("schema")
+            .short('s')
+            .long("schema")
+            .env("PG2RUST_SCHEMA")
+            .help("Sets the PostgreSQL schema. '*' generates every user schema in the database as its own module")
+            .required(false)
+            .default_value("public")
+        )
+        .arg(Arg::new("exclude_schema")
+            .long("exclude-schema")
+            .env("PG2RUST_EXCLUDE_SCHEMA")
+            .value_name("SCHEMAS")
+            .help("Schemas to leave out with --schema '*', besides pg_catalog, information_schema and pg_toast. Comma separated")
+            .required(false)
+        )
+        .arg(Arg::new("dedup_schemas")
+            .long("dedup-schemas")
+            .env("PG2RUST_DEDUP_SCHEMAS")
+            .help("With --schema '*', generate schemas with identical tables and types once, like the schemas of tenants, and have the others re-export that module")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("layout")
+            .long("layout")
+            .env("PG2RUST_LAYOUT")
+            .help("Sets how the code is split into files: everything in the output file, a file per table, or a module per schema")
+            .required(false)
+            .value_parser(["single", "per-table", "per-schema"])
+            .default_value("single")
+        )
+        .arg(Arg::new("table_file")
+            .long("table-file")
+            .env("PG2RUST_TABLE_FILE")
+            .help("Map a PostgreSQL table to a specific file, overriding the layout. Format: 'table:file'. To map multiple table separate with a comma. Example: 'users:users,posts:posts'. Can be repeated")
+            .required(false)
+            .action(ArgAction::Append)
+        )
+        .arg(Arg::new("root_tables")
+            .long("root-tables")
+            .env("PG2RUST_ROOT_TABLES")
+            .value_name("TABLES")
+            .help("Only generate these tables and views, instead of the include list of the config file. Comma separated, e.g. orders,invoices")
+            .required(false)
+            .conflicts_with("interactive")
+        )
+        .arg(Arg::new("with_dependencies")
+            .long("with-dependencies")
+            .env("PG2RUST_WITH_DEPENDENCIES")
+            .help("Also generate every table the root tables reference through foreign keys, transitively")
+            .required(false)
+            .requires("root_tables")
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("interactive")
+            .long("interactive")
+            .env("PG2RUST_INTERACTIVE")
+            .help("Pick the tables and views to generate and the files they go to in a terminal UI, and optionally save the picks to the [tables] of the config file")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("ext_files")
+            .long("ext-files")
+            .env("PG2RUST_EXT_FILES")
+            .help("Create a users_ext.rs companion file for every table file, which is never overwritten, for hand-written code")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("uuid")
+            .long("uuid")
+            .env("PG2RUST_UUID")
+            .help("Use UUIDs for columns of type uuid")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("no_precise_types")
+            .long("no-precise-types")
+            .env("PG2RUST_NO_PRECISE_TYPES")
+            .help("Map numeric columns to f64 and character columns to String whatever their precision and length, instead of numeric(10,0) to i64, numeric(5,2) to rust_decimal::Decimal and character(1) to char")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("citext_as_string")
+            .long("citext-as-string")
+            .env("PG2RUST_CITEXT_AS_STRING")
+            .help("Map citext columns to String, which compares with case, instead of the generated CiString newtype, which compares and hashes without it like the database")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("bytes_type")
+            .long("bytes-type")
+            .env("PG2RUST_BYTES_TYPE")
+            .help("The Rust type of bytea columns: vec for Vec<u8>, or bytes for bytes::Bytes, which is cheap to clone and slice in zero-copy pipelines")
+            .required(false)
+            .value_parser(["vec", "bytes"])
+            .default_value("vec")
+        )
+        .arg(Arg::new("prelude")
+            .long("prelude")
+            .env("PG2RUST_PRELUDE")
+            .help("Generate a prelude module that re-exports every generated struct")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("column_enums")
+            .long("column-enums")
+            .env("PG2RUST_COLUMN_ENUMS")
+            .help("Generate a column enum per table implementing AsRef<str> with the database column names")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("sea_query")
+            .long("sea-query")
+            .env("PG2RUST_SEA_QUERY")
+            .help("Generate a sea-query Iden enum per table alongside the structs")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("functions")
+            .long("functions")
+            .env("PG2RUST_FUNCTIONS")
+            .help("Generate typed wrapper functions for the stored functions and procedures in the schema")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("index_consts")
+            .long("index-consts")
+            .env("PG2RUST_INDEX_CONSTS")
+            .help("Generate an INDEXES const on every struct with the indexes of its table, with their columns, uniqueness, access method and predicate")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("sequences")
+            .long("sequences")
+            .env("PG2RUST_SEQUENCES")
+            .help("Generate a sequences module with a const per sequence in the schema, tied to the column it numbers, a SEQUENCES slice of all of them, and a next_ helper per sequence")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("metadata")
+            .long("metadata")
+            .env("PG2RUST_METADATA")
+            .help("Generate a metadata module with a TableMeta const per table, with its columns, their types and the primary key, and a TABLES slice of every table")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("default_constants")
+            .long("default-constants")
+            .env("PG2RUST_DEFAULT_CONSTANTS")
+            .help("Generate a module per table with a const of each column default as the database reports it, and of its value when it is a plain literal of the field's type")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("repository")
+            .long("repository")
+            .env("PG2RUST_REPOSITORY")
+            .help("Generate list, get, insert, update and delete helpers for every table. Implies --postgres-derives")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("soft_delete_column")
+            .long("soft-delete-column")
+            .env("PG2RUST_SOFT_DELETE_COLUMN")
+            .value_name("COLUMN")
+            .help("The nullable timestamp column that marks rows soft deleted. The repository helpers of tables with it leave those rows out of reads, and add soft_delete")
+            .required(false)
+            .default_value("deleted_at")
+        )
+        .arg(Arg::new("created_at_column")
+            .long("created-at-column")
+            .env("PG2RUST_CREATED_AT_COLUMN")
+            .value_name("COLUMN")
+            .help("The timestamp column of when a row was created, which the repository helpers fill in as --timestamps says")
+            .required(false)
+            .default_value("created_at")
+        )
+        .arg(Arg::new("updated_at_column")
+            .long("updated-at-column")
+            .env("PG2RUST_UPDATED_AT_COLUMN")
+            .value_name("COLUMN")
+            .help("The timestamp column of when a row was last updated, which the repository helpers fill in as --timestamps says")
+            .required(false)
+            .default_value("updated_at")
+        )
+        .arg(Arg::new("timestamps")
+            .long("timestamps")
+            .env("PG2RUST_TIMESTAMPS")
+            .help("How the repository helpers fill in the created and updated timestamps: set them to now(), leave them to the defaults and triggers of the database, or write them like any other column")
+            .required(false)
+            .value_parser(["now", "database", "off"])
+            .default_value("now")
+        )
+        .arg(Arg::new("version_columns")
+            .long("version-columns")
+            .env("PG2RUST_VERSION_COLUMNS")
+            .value_name("COLUMNS")
+            .help("The integer columns that version rows for optimistic locking, separated with a comma. The first one a table has is checked and bumped by its update helper")
+            .required(false)
+            .default_value("version,lock_version")
+        )
+        .arg(Arg::new("relations")
+            .long("relations")
+            .env("PG2RUST_RELATIONS")
+            .help("Generate a struct per foreign key that holds a row with the rows that reference it, and a load_with helper that loads them in one query. Implies --repository")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("scaffold")
+            .long("scaffold")
+            .env("PG2RUST_SCAFFOLD")
+            .help("Generate a web service module serving the repository helpers of every table: REST with axum, or gRPC with tonic, which needs --emit proto. Implies --repository")
+            .required(false)
+            .value_parser(["axum", "tonic"])
+        )
+        .arg(Arg::new("with_tests")
+            .long("with-tests")
+            .env("PG2RUST_WITH_TESTS")
+            .help("Generate a test per table that inserts and selects a row in a Postgres container started with testcontainers, to check the type mappings. Implies --repository")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("serde_tests")
+            .long("serde-tests")
+            .env("PG2RUST_SERDE_TESTS")
+            .help("Generate a test per table that serializes a row of sample values to JSON and deserializes it back, to catch serde attributes that don't match the types")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("live_tests")
+            .long("live-tests")
+            .env("PG2RUST_LIVE_TESTS")
+            .help("Generate a test per enum and composite type that sends every value to the database in DATABASE_URL and reads it back, to check the names of --postgres-derives against the catalog. They only build with the live-tests feature of your crate")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("serde_skip_none")
+            .long("serde-skip-none")
+            .env("PG2RUST_SERDE_SKIP_NONE")
+            .help("Skip serializing Option fields that are None")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("redact_pattern")
+            .long("redact-pattern")
+            .env("PG2RUST_REDACT_PATTERN")
+            .value_name("REGEX")
+            .help("Treat the columns whose name matches this case-insensitive regular expression as sensitive, e.g. 'password|secret|token'. See --redact-mode")
+            .required(false)
+        )
+        .arg(Arg::new("redact_mode")
+            .long("redact-mode")
+            .env("PG2RUST_REDACT_MODE")
+            .help("What to do with the columns that match --redact-pattern: mask keeps the fields but skips them when serializing and writes <redacted> in their place in Debug, skip leaves them out of the structs")
+            .required(false)
+            .value_parser(["mask", "skip"])
+            .default_value("mask")
+        )
+        .arg(Arg::new("serde_default")
+            .long("serde-default")
+            .env("PG2RUST_SERDE_DEFAULT")
+            .help("Default the fields of nullable, defaulted, identity and generated columns when deserializing, so they can be left out")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("serde_crate")
+            .long("serde-crate")
+            .env("PG2RUST_SERDE_CRATE")
+            .help("Sets the path serde is used through, like my_facade::serde, for crates that re-export it instead of depending on it")
+            .required(false)
+        )
+        .arg(Arg::new("edition")
+            .long("edition")
+            .env("PG2RUST_EDITION")
+            .help("Sets the Rust edition of the crate the code is generated into. Keywords of the edition are raw identifiers, and 2015 edition paths to other crates start with ::")
+            .required(false)
+            .value_parser(["2015", "2018", "2021", "2024"])
+            .default_value("2021")
+        )
+        .arg(Arg::new("visibility")
+            .long("visibility")
+            .env("PG2RUST_VISIBILITY")
+            .help("Sets the visibility of the generated structs, fields, enums and functions. pub(crate) keeps them out of a library's API, and private keeps them in the module of the output file")
+            .required(false)
+            .value_parser(["pub", "pub(crate)", "private"])
+            .default_value("pub")
+        )
+        .arg(Arg::new("import_style")
+            .long("import-style")
+            .env("PG2RUST_IMPORT_STYLE")
+            .help("Sets how the types of other crates are named: by their path, like chrono::NaiveDate, or by their name with a use block at the top of each file")
+            .required(false)
+            .value_parser(["qualified", "use"])
+            .default_value("qualified")
+        )
+        .arg(Arg::new("rustfmt_bin")
+            .long("rustfmt-bin")
+            .env("PG2RUST_RUSTFMT_BIN")
+            .value_name("PATH")
+            .help("Format the generated code with this rustfmt binary instead of in-process, with the --edition")
+            .required(false)
+        )
+        .arg(Arg::new("rustfmt_config")
+            .long("rustfmt-config")
+            .env("PG2RUST_RUSTFMT_CONFIG")
+            .value_name("PATH")
+            .help("Format the generated code with rustfmt and this rustfmt.toml, so it's formatted like the rest of your project")
+            .required(false)
+        )
+        .arg(Arg::new("no_format")
+            .long("no-format")
+            .env("PG2RUST_NO_FORMAT")
+            .help("Write the generated code without formatting it, which is faster when only checking it in CI")
+            .required(false)
+            .action(ArgAction::SetTrue)
+            .conflicts_with_all(["rustfmt_bin", "rustfmt_config"])
+        )
+        .arg(Arg::new("no_std")
+            .long("no-std")
+            .env("PG2RUST_NO_STD")
+            .help("Generate code that only needs core and alloc, importing String and Vec from alloc, so the models can be shared with no_std crates like embedded or wasm ones")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("ref_structs")
+            .long("ref-structs")
+            .env("PG2RUST_REF_STRUCTS")
+            .help("Also generate a [Struct]Ref<'a> per table that borrows its text and bytes fields, with conversions to and from the owned struct")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("enum_derives")
+            .long("enum-derives")
+            .env("PG2RUST_ENUM_DERIVES")
+            .help("Derive strum's EnumString, Display and EnumIter for every enum, so values can be parsed from and written as their labels")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("postgres_derives")
+            .long("postgres-derives")
+            .env("PG2RUST_POSTGRES_DERIVES")
+            .help("Derive postgres-types' ToSql and FromSql for every enum and composite type, so they can be used as query parameters and results")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("xml_validator")
+            .long("xml-validator")
+            .env("PG2RUST_XML_VALIDATOR")
+            .value_name("PATH")
+            .help("The path of a function like crate::xml::validate that checks the text of xml values, taking a &str and returning a Result whose error is Display. The Xml newtype is then only converted from a String or deserialized through it")
+            .required(false)
+        )
+        .arg(Arg::new("non_exhaustive_enums")
+            .long("non-exhaustive-enums")
+            .env("PG2RUST_NON_EXHAUSTIVE_ENUMS")
+            .help("Mark every enum #[non_exhaustive], so labels can be added without breaking matches in other crates")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("non_exhaustive_structs")
+            .long("non-exhaustive-structs")
+            .env("PG2RUST_NON_EXHAUSTIVE_STRUCTS")
+            .help("Mark every table and view struct #[non_exhaustive], so columns can be added without breaking other crates")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("display")
+            .long("display")
+            .env("PG2RUST_DISPLAY")
+            .help("Implement std::fmt::Display for every struct, showing its name, title or primary key column")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("display_column")
+            .long("display-column")
+            .env("PG2RUST_DISPLAY_COLUMN")
+            .value_name("TABLE.COLUMN")
+            .help("The column a table is displayed as, instead of its name, title or primary key. Comma separated, e.g. users.email")
+            .required(false)
+            .requires("display")
+        )
+        .arg(Arg::new("introspection")
+            .long("introspection")
+            .env("PG2RUST_INTROSPECTION")
+            .help("Sets where the schema is read from. information-schema is used as a fallback when pg-catalog fails")
+            .required(false)
+            .value_parser(["pg-catalog", "information-schema"])
+            .default_value("pg-catalog")
+        )
+        .arg(Arg::new("dialect")
+            .long("dialect")
+            .env("PG2RUST_DIALECT")
+            .help("Sets the kind of database the schema is read from. redshift reads Redshift's reduced information_schema and ignores --introspection")
+            .required(false)
+            .value_parser(["postgres", "redshift"])
+            .default_value("postgres")
+        )
+        .arg(Arg::new("snapshot")
+            .long("snapshot")
+            .env("PG2RUST_SNAPSHOT")
+            .help("Save the introspected schema to a JSON snapshot file")
+            .required(false)
+        )
+        .arg(Arg::new("from_snapshot")
+            .long("from-snapshot")
+            .env("PG2RUST_FROM_SNAPSHOT")
+            .help("Generate code from a JSON snapshot file instead of connecting to the database")
+            .required(false)
+            .conflicts_with("snapshot")
+        )
+        .arg(Arg::new("from_ir")
+            .long("from-ir")
+            .env("PG2RUST_FROM_IR")
+            .help("Generate code from a model written with --emit ir, which can be edited first, instead of connecting to the database")
+            .required(false)
+            .conflicts_with_all(["from_snapshot", "view_not_null"])
+        )
+        .arg(Arg::new("cached")
+            .long("cached")
+            .env("PG2RUST_CACHED")
+            .help("Reuse the schema read by an earlier run while the database's catalogs haven't changed, from a cache in ~/.cache/pg2rust")
+            .required(false)
+            .conflicts_with_all(["from_snapshot", "from_ir"])
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("refresh")
+            .long("refresh")
+            .env("PG2RUST_REFRESH")
+            .help("Read the schema from the database even if it is cached, and cache it again")
+            .required(false)
+            .requires("cached")
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("output_directory")
+            .short('d')
+            .long("output-directory")
+            .env("PG2RUST_OUTPUT_DIRECTORY")
+            .help("Sets the output directory")
+            .required(false)
+            .default_value("src")
+        )
+        .arg(Arg::new("output")
+            .short('o')
+            .long("output")
+            .env("PG2RUST_OUTPUT")
+            .help("Sets the output file")
+            .required(false)
+            .default_value("schema.rs")
+        )
+        .arg(Arg::new("emit")
+            .long("emit")
+            .env("PG2RUST_EMIT")
+            .help("Also write the schema in another format: `ir` for the resolved model as JSON, `markdown` for documentation, or `mermaid` or `dot` for an ER diagram, `proto` for protobuf messages and services, or `sql` for the SQL that creates it. Example: `--emit markdown docs/schema.md`. Can be repeated")
+            .required(false)
+            .num_args(2)
+            .value_names(["FORMAT", "PATH"])
+            .action(ArgAction::Append)
+        )
+        .arg(Arg::new("dependencies")
+            .long("dependencies")
+            .env("PG2RUST_DEPENDENCIES")
+            .help("Print the crates and features the generated code needs")
+            .required(false)
+            .value_parser(["text", "json"])
+        )
+        .arg(Arg::new("strict")
+            .long("strict")
+            .env("PG2RUST_STRICT")
+            .help("Fails when a column has a type without a Rust mapping, instead of making it a String, unless its type is set with [column_types] or a @rust(type = ...) directive")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(Arg::new("report")
+            .long("report")
+            .env("PG2RUST_REPORT")
+            .help("Writes a JSON summary of the run to this file: what was generated, the files written, the columns that fell back to String, and the warnings")
+            .required(false)
+        )
+        .arg(Arg::new("check_manifest")
+            .long("check-manifest")
+            .env("PG2RUST_CHECK_MANIFEST")
+            .help("Checks that a Cargo.toml has the crates and features the generated code needs")
+            .required(false)
+        )
+        .subcommand(Command::new("diff")
+            .about("Report how the schema differs from a previous snapshot. Exits with status 1 when there are differences")
+            .arg(Arg::new("previous_snapshot")
+                .help("The snapshot to compare against")
+                .required(true)
+            )
+            .arg(Arg::new("format")
+                .long("format")
+                .env("PG2RUST_FORMAT")
+                .help("Sets the format of the report")
+                .required(false)
+                .value_parser(["text", "json"])
+                .default_value("text")
+            )
+            .arg(Arg::new("migration")
+                .long("migration")
+                .env("PG2RUST_MIGRATION")
+                .help("Writes a migration skeleton with the SQL of the differences to a new file in this directory")
+                .required(false)
+            )
+            .arg(Arg::new("migration_style")
+                .long("migration-style")
+                .env("PG2RUST_MIGRATION_STYLE")
+                .help("Sets how the migration file is named, sqlx for <timestamp>_<name>.sql or refinery for V<version>__<name>.sql")
+                .required(false)
+                .value_parser(["sqlx", "refinery"])
+                .default_value("sqlx")
+            )
+            .arg(Arg::new("migration_name")
+                .long("migration-name")
+                .env("PG2RUST_MIGRATION_NAME")
+                .help("Sets the name of the migration, after its version")
+                .required(false)
+                .default_value("schema_changes")
+            )
+        )
+        .subcommand(Command::new("verify")
+            .about("Check whether a generated file is stale, by comparing the schema checksum in its header against the database. Exits with status 1 when it is stale")
+            .arg(Arg::new("file")
+                .help("The generated file to check, defaults to the output file")
+                .required(false)
+            )
+        )
+        .subcommand(Command::new("completions")
+            .about("Print the completion script of a shell, to install where the shell loads completions from")
+            .arg(Arg::new("shell")
+                .help("The shell to complete in")
+                .required(true)
+                .value_parser(["bash", "zsh", "fish", "powershell"])
+            )
+        )
+}
+
+/// Print the completion script of a shell for the command line interface
+pub fn print_completions(shell: &str) {
+    let shell = match shell {
+        "zsh" => Shell::Zsh,
+        "fish" => Shell::Fish,
+        "powershell" => Shell::PowerShell,
+        _ => Shell::Bash,
+    };
+    let mut command = command();
+    let name = command.get_name().to_string();
+
+    // Write it in one go, so piping it into a command that stops reading early isn't an error
+    let mut script = Vec::new();
+    clap_complete::generate(shell, &mut command, name, &mut script);
+    io::stdout().write_all(&script).ok();
+}
+
+/// Add the args of the profile selected with `--profile` to the command line arguments `args`,
+/// which start with the program name
+///
+/// The profile's args go first, so they apply to the command rather than a subcommand. The ones
+/// also given on the command line or with a `PG2RUST_*` variable are left out, since those take
+/// precedence.
+pub fn with_profile(args: Vec<String>) -> Result<Vec<String>> {
+    // Only the profile and the config file are needed, before the profile fills in what is
+    // required, so every other error is left to the real parse
+    let Ok(matches) = command().ignore_errors(true).try_get_matches_from(&args) else {
+        return Ok(args);
+    };
+    let Some(profile) = matches.get_one::<String>("profile") else {
+        return Ok(args);
+    };
+
+    let config = Config::load(matches.get_one::<String>("config"))?;
+    let profile_args = config
+        .profile
+        .get(profile)
+        .ok_or_else(|| {
+            Error::Config(format!(
+                "The config file has no [profile.{}] for --profile",
+                profile
+            ))
+        })?
+        .args
+        .clone();
+
+    let command = command();
+    let profile_args = drop_args(&command, profile_args, |argument| {
+        let source = matches.value_source(argument.get_id().as_str());
+        matches!(
+            source,
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+        )
+        .then(|| format!("{} is set", argument.get_id()))
+    });
+
+    let mut args = args.into_iter();
+    Ok(args
+        .next()
+        .into_iter()
+        .chain(profile_args)
+        .chain(args)
+        .collect())
+}
+
+/// Drop the args for which `reason` gives a reason to, with the values that follow them
+pub fn drop_args(
+    command: &Command,
+    args: Vec<String>,
+    reason: impl Fn(&Arg) -> Option<String>,
+) -> Vec<String> {
+    let mut kept = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let long = arg
+            .strip_prefix("--")
+            .map(|long| long.split_once('=').map_or(long, |(long, _)| long));
+        let short = arg
+            .strip_prefix('-')
+            .filter(|short| short.len() == 1)
+            .and_then(|short| short.chars().next());
+        let argument = command.get_arguments().find(|argument| {
+            (long.is_some() && argument.get_long() == long)
+                || (short.is_some() && argument.get_short() == short)
+        });
+        let Some(argument) = argument else {
+            kept.push(arg);
+            continue;
+        };
+        let Some(reason) = reason(argument) else {
+            kept.push(arg);
+            continue;
+        };
+        info!("{}, ignoring {} in the config", reason, arg);
+
+        // Drop the values that follow the argument too
+        if !arg.contains('=') && argument.get_action().takes_values() {
+            let count = argument
+                .get_num_args()
+                .map_or(1, |range| range.min_values());
+            for _ in 0..count {
+                args.next();
+            }
+        }
+    }
+
+    kept
+}
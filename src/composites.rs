@@ -0,0 +1,136 @@
+use convert_case::{Case, Casing};
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::Ident;
+
+use crate::error::{Error, Result};
+use crate::ir::ResolvedComposite;
+use crate::output;
+use crate::routines::RowStruct;
+
+/// The struct generated for a composite type, which its columns and the stored functions that
+/// return it map to
+pub struct CompositeStruct {
+    /// The name of the type in the catalog
+    pub type_name: String,
+    pub row_struct: RowStruct,
+    /// The definition of the struct, which is written with the other shared types
+    pub definition: TokenStream,
+}
+
+/// Get the Rust name of a composite type
+pub fn composite_ident(type_name: &str) -> Ident {
+    Ident::new(&type_name.to_case(Case::Pascal), Span::call_site())
+}
+
+/// Generate a Rust struct for a PostgreSQL composite type
+///
+/// Every field is an `Option`, since attributes of composite types can't be declared `NOT NULL`.
+/// With `postgres`, it derives `ToSql` and `FromSql` for the type and is told the exact name of
+/// every attribute whose field is named differently.
+pub fn generate_composite(
+    composite: &ResolvedComposite,
+    postgres: bool,
+) -> Result<CompositeStruct> {
+    let context = format!("composite type {}", composite.name);
+    let struct_ident = output::ident(&composite.rust_name, &context)?;
+
+    let mut fields = Vec::new();
+    let mut row_fields = Vec::new();
+    for field in &composite.fields {
+        let name = &field.name;
+        let context = format!("attribute {}.{}", composite.name, name);
+        let field_ident = output::ident(&field.rust_name, &context)?;
+        let rust_type: syn::Type =
+            syn::parse_str(&field.rust_type).map_err(|source| Error::InvalidCode {
+                context: format!("{} as `{}`", context, field.rust_type),
+                source,
+            })?;
+        if postgres && field.rust_name != *name {
+            fields.push(quote! {
+                #[postgres(name = #name)]
+                pub #field_ident: #rust_type,
+            });
+        } else {
+            fields.push(quote! { pub #field_ident: #rust_type, });
+        }
+        row_fields.push((field_ident, quote! { row.get(#name) }));
+    }
+
+    let type_name = &composite.name;
+    let definition = if postgres {
+        quote! {
+            #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, postgres_types::ToSql, postgres_types::FromSql)]
+            #[postgres(name = #type_name)]
+            pub struct #struct_ident {
+                #(#fields)*
+            }
+        }
+    } else {
+        quote! {
+            #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+            pub struct #struct_ident {
+                #(#fields)*
+            }
+        }
+    };
+
+    Ok(CompositeStruct {
+        type_name: composite.name.clone(),
+        row_struct: RowStruct {
+            path: quote!(#struct_ident),
+            fields: row_fields,
+            deprecated: false,
+            feature: None,
+        },
+        definition,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::ResolvedAttribute;
+    use crate::model::{DataType, TypeKind};
+
+    fn attribute(name: &str, rust_name: &str) -> ResolvedAttribute {
+        ResolvedAttribute {
+            name: name.to_string(),
+            rust_name: rust_name.to_string(),
+            rust_type: "Option<String>".to_string(),
+            data_type: DataType {
+                name: "text".to_string(),
+                schema: "pg_catalog".to_string(),
+                kind: TypeKind::Base,
+                dimensions: 0,
+                element_not_null: false,
+                length: None,
+                precision: None,
+                scale: None,
+            },
+        }
+    }
+
+    #[test]
+    fn renamed_attributes_are_named_for_postgres() {
+        let composite = ResolvedComposite {
+            name: "street_address".to_string(),
+            rust_name: "StreetAddress".to_string(),
+            fields: vec![attribute("city", "city"), attribute("Zip", "zip")],
+        };
+
+        let generated = generate_composite(&composite, true).unwrap();
+        let definition = generated.definition.to_string();
+        assert!(definition.contains("postgres_types :: ToSql"));
+        assert!(definition.contains("# [postgres (name = \"street_address\")]"));
+        assert!(definition.contains("# [postgres (name = \"Zip\")] pub zip"));
+        assert!(!definition.contains("name = \"city\""));
+        assert_eq!(generated.row_struct.fields.len(), 2);
+
+        let definition = generate_composite(&composite, false)
+            .unwrap()
+            .definition
+            .to_string();
+        assert!(!definition.contains("postgres"));
+    }
+}
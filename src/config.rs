@@ -0,0 +1,230 @@
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+use serde::Deserialize;
+use toml_edit::{value, Array, DocumentMut, Item, Table};
+use tracing::{debug, info};
+
+use crate::error::{Error, Result};
+
+/// The config file that is read when `--config` isn't given, if it exists
+pub const DEFAULT_CONFIG_FILE: &str = "pg2rust.toml";
+
+/// Settings read from the `pg2rust.toml` config file
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The header written at the top of every generated file, `{timestamp}` is replaced with the
+    /// time of the run and `{checksum}` with the checksum of the schema
+    pub header: Option<String>,
+    /// Settings for generating code from a build script
+    pub build: BuildConfig,
+    /// Commands to run before and after generating
+    pub hooks: Hooks,
+    /// The Rust types of specific columns, keyed by `table.column`
+    pub column_types: BTreeMap<String, ColumnType>,
+    /// How array columns are mapped
+    pub arrays: ArrayConfig,
+    /// How enum types are mapped
+    pub enums: EnumConfig,
+    /// How the structs are written with `Debug`
+    pub debug: DebugConfig,
+    /// The tables to generate and the files they go to
+    pub tables: TableConfig,
+    /// Named sets of arguments, like the connection settings of an environment, for `--profile`
+    pub profile: BTreeMap<String, ProfileConfig>,
+    /// How the code of specific schemas is named, keyed by schema
+    pub schemas: BTreeMap<String, SchemaConfig>,
+}
+
+/// The `[arrays]` section of the config file
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArrayConfig {
+    /// How arrays with more than one dimension are mapped
+    pub dimensions: Dimensions,
+    /// Whether elements are mapped to `Option`, unless their type is a `NOT NULL` domain
+    pub nullable_elements: bool,
+}
+
+/// How arrays with more than one dimension are mapped
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Dimensions {
+    /// A `Vec` per dimension, e.g. `Vec<Vec<i32>>` for `integer[][]`
+    #[default]
+    Nested,
+    /// A single `Vec` of every element, e.g. `Vec<i32>` for `integer[][]`
+    Flatten,
+    /// An error, for code that can't handle them
+    Reject,
+}
+
+/// The `[enums]` section of the config file
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnumConfig {
+    /// The case enum labels are converted to for the names of their variants
+    pub variant_case: VariantCase,
+}
+
+/// The `[debug]` section of the config file
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DebugConfig {
+    /// The columns written as `<redacted>` by `Debug`, like PII, keyed by `table.column`
+    pub mask: Vec<String>,
+}
+
+/// The case of the variants of enums, which are renamed back to their labels for serde, strum
+/// and postgres
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VariantCase {
+    /// `InProgress` for `in_progress`
+    #[default]
+    Pascal,
+    /// `IN_PROGRESS` for `in_progress`
+    UpperSnake,
+    /// `in_progress` for `in_progress`
+    Snake,
+}
+
+/// The Rust type of a column in the `[column_types]` section of the config file
+///
+/// Either just the type, like `"crate::OrderMetadata"`, or a table that also names the module
+/// serde should (de)serialize it with, like `{ type = "crate::Money", with = "crate::money" }`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum ColumnType {
+    /// Just the Rust type
+    Type(String),
+    /// The Rust type and the module it's serialized with
+    WithSerde {
+        /// The Rust type
+        #[serde(rename = "type")]
+        rust_type: String,
+        /// The module passed to `#[serde(with = ...)]`, if any
+        with: Option<String>,
+    },
+}
+
+impl ColumnType {
+    /// Get the Rust type of the column, without the `Option` of nullable columns
+    pub fn rust_type(&self) -> &str {
+        match self {
+            ColumnType::Type(rust_type) | ColumnType::WithSerde { rust_type, .. } => rust_type,
+        }
+    }
+
+    /// Get the module the column is (de)serialized with, if any
+    pub fn with(&self) -> Option<&str> {
+        match self {
+            ColumnType::Type(_) => None,
+            ColumnType::WithSerde { with, .. } => with.as_deref(),
+        }
+    }
+}
+
+/// The `[tables]` section of the config file, which `--interactive` can write
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TableConfig {
+    /// The tables and views to generate, or every one when empty
+    pub include: Vec<String>,
+    /// The module each table is written to, like `--table-file`, keyed by table
+    pub files: BTreeMap<String, String>,
+    /// The cargo feature the code of each table is gated behind, keyed by table
+    pub features: BTreeMap<String, String>,
+}
+
+/// A `[schemas.<name>]` section of the config file, which tells apart schemas whose names or
+/// tables are the same in Rust
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SchemaConfig {
+    /// The module the schema is generated as, instead of its name in snake case
+    pub module: Option<String>,
+    /// Put in front of the struct names of its tables, like `Audit` for `AuditUsers`
+    pub prefix: Option<String>,
+}
+
+/// The `[hooks]` section of the config file
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Hooks {
+    /// Shell commands to run before anything is read
+    pub pre_generate: Vec<String>,
+    /// Shell commands to run after the files are written, with the files that changed as
+    /// arguments
+    pub post_generate: Vec<String>,
+}
+
+/// The `[build]` section of the config file, read by [`crate::build::generate`]
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BuildConfig {
+    /// The command line arguments to generate with, like `["--schema", "app", "--uuid"]`
+    pub args: Vec<String>,
+}
+
+/// A `[profile.<name>]` section of the config file, selected with `--profile`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProfileConfig {
+    /// The command line arguments the profile adds, like `["--host", "db.internal"]`
+    pub args: Vec<String>,
+}
+
+impl Config {
+    /// Read the config file given with `--config`, or `pg2rust.toml` if it exists
+    pub fn load(path: Option<&String>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path.as_str(),
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => DEFAULT_CONFIG_FILE,
+            None => return Ok(Config::default()),
+        };
+
+        debug!("Reading config from {}", path);
+        let contents = fs::read_to_string(path).map_err(Error::io(path))?;
+        toml::from_str(&contents)
+            .map_err(|error| Error::Config(format!("Invalid config file {}: {}", path, error)))
+    }
+}
+
+/// Write the `[tables]` section of a config file, keeping the rest of it as it is
+pub fn save_tables(path: &str, tables: &TableConfig) -> Result<()> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(error) => return Err(Error::io(path)(error)),
+    };
+    let mut document: DocumentMut = contents
+        .parse()
+        .map_err(|error| Error::Config(format!("Invalid config file {}: {}", path, error)))?;
+
+    // Fill in the section in place, so its comments and position stay the same
+    let invalid = || {
+        Error::Config(format!(
+            "Invalid config file {}: tables isn't a table",
+            path
+        ))
+    };
+    let section = document
+        .entry("tables")
+        .or_insert(Item::Table(Table::new()))
+        .as_table_mut()
+        .ok_or_else(invalid)?;
+    section.insert("include", value(Array::from_iter(&tables.include)));
+    let files = section
+        .entry("files")
+        .or_insert(Item::Table(Table::new()))
+        .as_table_mut()
+        .ok_or_else(invalid)?;
+    files.clear();
+    for (table, file) in &tables.files {
+        files.insert(table, value(file));
+    }
+
+    info!("Saving the picked tables to {}", path);
+    fs::write(path, document.to_string()).map_err(Error::io(path))
+}
@@ -0,0 +1,353 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    io::{self, IsTerminal},
+    thread,
+    time::Duration,
+};
+
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use native_tls::TlsConnector;
+use postgres::types::{ToSql, Type};
+use postgres::{config::SslMode, error::SqlState, GenericClient, Row};
+use postgres_native_tls::MakeTlsConnector;
+use tracing::{debug, info, warn};
+
+use crate::aws_iam;
+use crate::error::{Error, Result};
+use crate::pgpass;
+use crate::pgservice;
+use crate::tunnel::{self, SshDestination};
+
+/// The settings used to connect to the PostgreSQL database
+pub struct ConnectionSettings {
+    pub username: String,
+    /// The password, `None` when an RDS IAM token is generated instead
+    pub password: Option<String>,
+    pub host: String,
+    pub port: String,
+    pub database: String,
+    /// The SSH server to tunnel the connection through, if any
+    pub ssh: Option<SshDestination>,
+    /// The private key to authenticate with the SSH server
+    pub ssh_identity: Option<String>,
+    /// Whether to trust an SSH server whose host key isn't known
+    pub ssh_accept_unknown_host: bool,
+    /// Whether to log in with an RDS IAM token, over TLS
+    pub aws_iam: bool,
+    /// The AWS region of the database, instead of the configured one
+    pub aws_region: Option<String>,
+    /// How long to wait for the connection to be established
+    pub connect_timeout: Option<Duration>,
+    /// How long a query can run before the server cancels it
+    pub statement_timeout: Option<Duration>,
+    /// How many times to retry a connection that failed because of the network
+    pub retries: u32,
+}
+
+impl ConnectionSettings {
+    /// Read the connection settings from the command line arguments, the connection service,
+    /// the environment file, or the environment, in that order
+    pub fn from_matches(matches: &ArgMatches) -> Result<Self> {
+        // Get the environment variables from the command line arguments or the environment file
+        let env_file: Option<&String> = matches.get_one::<String>("env_file");
+
+        // Get the settings of the service, if any
+        let service = match matches.get_one::<String>("service") {
+            Some(name) => pgservice::find_service(name)?,
+            None => BTreeMap::new(),
+        };
+        for key in service.keys() {
+            if !SERVICE_KEYS.contains(&key.as_str()) {
+                debug!(
+                    "{} isn't supported in the connection service file, ignoring it",
+                    key
+                );
+            }
+        }
+        let sources = Sources {
+            matches,
+            env_file,
+            service: &service,
+        };
+
+        let username = sources.setting("POSTGRES_USER", "user", "username")?;
+        let host = sources.setting("POSTGRES_HOST", "host", "host")?;
+        let port = sources.setting("POSTGRES_PORT", "port", "port")?;
+        let database = match matches.value_source("database") {
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable) => {
+                matches.get_one::<String>("database").cloned()
+            }
+            _ => service.get("dbname").cloned(),
+        }
+        .ok_or_else(|| Error::Config("--database must be set".to_string()))?;
+
+        // Without a configured password, look in the password file and then ask for it, the
+        // same way psql does. With IAM authentication, a token is generated when connecting
+        let aws_iam = matches.get_flag("aws_iam");
+        let password = if aws_iam {
+            None
+        } else {
+            Some(
+                match sources.optional_setting("POSTGRES_PASSWORD", "password", "password") {
+                    Some(password) => password,
+                    None => match pgpass::find_password(&host, &port, &database, &username) {
+                        Some(password) => password,
+                        None => prompt_password(matches, &username)?,
+                    },
+                },
+            )
+        };
+
+        // Get the SSH server to tunnel through, if any
+        let ssh = matches
+            .get_one::<String>("ssh")
+            .map(|destination| SshDestination::parse(destination))
+            .transpose()?;
+
+        Ok(ConnectionSettings {
+            username,
+            password,
+            host,
+            port,
+            database,
+            ssh,
+            ssh_identity: matches.get_one::<String>("ssh_identity").cloned(),
+            ssh_accept_unknown_host: matches.get_flag("ssh_accept_unknown_host"),
+            aws_iam,
+            aws_region: matches.get_one::<String>("aws_region").cloned(),
+            connect_timeout: matches
+                .get_one::<u64>("connect_timeout")
+                .map(|seconds| Duration::from_secs(*seconds)),
+            statement_timeout: matches
+                .get_one::<u64>("statement_timeout")
+                .map(|seconds| Duration::from_secs(*seconds)),
+            retries: matches.get_one::<u32>("retries").copied().unwrap_or(0),
+        })
+    }
+
+    /// Connect to the PostgreSQL database
+    pub fn connect(&self) -> Result<postgres::Client> {
+        let port: u16 = self
+            .port
+            .parse()
+            .map_err(|_| Error::Config(format!("Invalid PostgreSQL port {}", self.port)))?;
+
+        // RDS IAM tokens are signed for the database endpoint, even through a tunnel
+        let password = match &self.password {
+            Some(password) => password.clone(),
+            None => aws_iam::generate_token(
+                &self.host,
+                port,
+                &self.username,
+                self.aws_region.as_deref(),
+            )?,
+        };
+
+        // Through a tunnel, connect to its local end instead of the database host
+        let (host, port) = match &self.ssh {
+            Some(ssh) => (
+                "127.0.0.1",
+                tunnel::open(
+                    ssh,
+                    self.ssh_identity.as_deref(),
+                    self.ssh_accept_unknown_host,
+                    &self.host,
+                    port,
+                )?,
+            ),
+            None => (self.host.as_str(), port),
+        };
+
+        // Build the connection config field by field, so passwords don't need URL escaping
+        let mut config = postgres::Config::new();
+        config
+            .host(host)
+            .port(port)
+            .user(&self.username)
+            .password(&password)
+            .dbname(&self.database);
+        if let Some(connect_timeout) = self.connect_timeout {
+            config.connect_timeout(connect_timeout);
+        }
+        if let Some(statement_timeout) = self.statement_timeout {
+            config.options(&format!(
+                "-c statement_timeout={}",
+                statement_timeout.as_millis()
+            ));
+        }
+
+        debug!(
+            "Connection string: postgres://{}@{}:{}/{}",
+            self.username, self.host, self.port, self.database
+        );
+        info!("Connecting to PostgreSQL database");
+
+        // RDS only takes IAM tokens over TLS, which is required like sslmode=require: encrypted,
+        // without verifying the certificate
+        let tls = if self.aws_iam {
+            config.ssl_mode(SslMode::Require);
+            let connector = TlsConnector::builder()
+                .danger_accept_invalid_certs(true)
+                .danger_accept_invalid_hostnames(true)
+                .build()
+                .map_err(|error| Error::AwsIam(format!("failed to set up TLS: {}", error)))?;
+            Some(MakeTlsConnector::new(connector))
+        } else {
+            None
+        };
+
+        // Connect to the PostgreSQL database, backing off between retries
+        let mut attempt = 0;
+        let result = loop {
+            let result = match &tls {
+                Some(tls) => config.connect(tls.clone()),
+                None => config.connect(postgres::NoTls),
+            };
+
+            match result {
+                Err(error) if attempt < self.retries && is_transient(&error) => {
+                    let delay = retry_delay(attempt);
+                    attempt += 1;
+                    warn!(
+                        "Failed to connect to PostgreSQL database, retrying in {}s ({}/{}): {}",
+                        delay.as_secs(),
+                        attempt,
+                        self.retries,
+                        error
+                    );
+                    thread::sleep(delay);
+                }
+                result => break result,
+            }
+        };
+
+        let client = match result {
+            Ok(client) => client,
+            Err(error) => {
+                // Tell bad credentials apart from an unreachable database
+                let code = error.code();
+                if code == Some(&SqlState::INVALID_PASSWORD)
+                    || code == Some(&SqlState::INVALID_AUTHORIZATION_SPECIFICATION)
+                {
+                    return Err(Error::Authentication(error));
+                }
+                return Err(Error::Connect(error));
+            }
+        };
+
+        info!("Connected to PostgreSQL database");
+        Ok(client)
+    }
+}
+
+/// Run a query that reads the schema
+///
+/// With `--simple-query`, the statement isn't prepared first, so nothing is left on the server
+/// for the next transaction, which transaction pooling PgBouncer hands to another client. The
+/// types of the parameters are given instead of being looked up by preparing the statement.
+pub fn query(
+    client: &mut impl GenericClient,
+    simple_query: bool,
+    sql: &str,
+    params: &[(&(dyn ToSql + Sync), Type)],
+) -> std::result::Result<Vec<Row>, postgres::Error> {
+    if simple_query {
+        return client.query_typed(sql, params);
+    }
+
+    let params: Vec<&(dyn ToSql + Sync)> = params.iter().map(|(param, _)| *param).collect();
+    client.query(sql, &params)
+}
+
+/// Whether a connection error is worth retrying, i.e. it came from the network or the server
+/// isn't ready yet, rather than from bad settings
+fn is_transient(error: &postgres::Error) -> bool {
+    match error.code() {
+        None => true,
+        Some(code) => {
+            code.code().starts_with("08")
+                || code == &SqlState::CANNOT_CONNECT_NOW
+                || code == &SqlState::TOO_MANY_CONNECTIONS
+        }
+    }
+}
+
+/// How long to wait before the retry after `attempt` failed attempts: 1s, 2s, 4s, ... up to 30s
+fn retry_delay(attempt: u32) -> Duration {
+    Duration::from_secs(2u64.saturating_pow(attempt).min(30))
+}
+
+/// The keys of the connection service file that are used, the others are ignored
+const SERVICE_KEYS: [&str; 5] = ["host", "port", "dbname", "user", "password"];
+
+/// Where the connection settings are read from
+struct Sources<'a> {
+    matches: &'a ArgMatches,
+    env_file: Option<&'a String>,
+    /// The settings of the connection service, empty without `--service`
+    service: &'a BTreeMap<String, String>,
+}
+
+impl Sources<'_> {
+    /// Get a setting from a command line argument, the connection service, the environment file,
+    /// or the environment
+    ///
+    /// When an environment file is given, the setting must be in it (or already in the
+    /// environment).
+    fn setting(&self, variable: &str, key: &str, argument: &str) -> Result<String> {
+        self.optional_setting(variable, key, argument)
+            .ok_or_else(|| match self.env_file {
+                Some(env_file) => {
+                    Error::Config(format!("{} must be set in {}", variable, env_file))
+                }
+                None => Error::Config(format!("{} or --{} must be set", variable, argument)),
+            })
+    }
+
+    /// Get a setting from a command line argument, its `PG2RUST_*` variable, the `key` of the
+    /// connection service, the environment file, the environment, or the default of the
+    /// argument, if it's set anywhere
+    fn optional_setting(&self, variable: &str, key: &str, argument: &str) -> Option<String> {
+        if matches!(
+            self.matches.value_source(argument),
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+        ) {
+            return self.matches.get_one::<String>(argument).cloned();
+        }
+
+        if let Some(value) = self.service.get(key) {
+            return Some(value.clone());
+        }
+
+        if let Some(env_file) = self.env_file {
+            dotenv::from_filename(env_file).ok();
+            return dotenv::var(variable).ok();
+        }
+
+        if let Ok(value) = env::var(variable) {
+            return Some(value);
+        }
+
+        self.matches.get_one::<String>(argument).cloned()
+    }
+}
+
+/// Ask for the password on the terminal, without echoing it
+///
+/// Prompting is skipped with --no-password or when there is no terminal to ask on, such as in CI.
+fn prompt_password(matches: &ArgMatches, username: &str) -> Result<String> {
+    let missing = || {
+        Error::Config(
+            "POSTGRES_PASSWORD or --password must be set, or the password must be in the password file"
+                .to_string(),
+        )
+    };
+
+    if matches.get_flag("no_password") || !io::stdin().is_terminal() {
+        return Err(missing());
+    }
+
+    rpassword::prompt_password(format!("Password for user {}: ", username)).map_err(|_| missing())
+}
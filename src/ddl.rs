@@ -0,0 +1,593 @@
+use std::fmt::Write;
+
+use tracing::debug;
+
+use crate::ir::{ResolvedSchema, ResolvedTable};
+use crate::model::{Column, DataType, EnumType, Schema, Table, TableKind, TypeKind};
+use crate::repository::quote_name;
+
+/// Write the SQL that creates the schema, its enums and its tables, so the generated code can be
+/// tried against a fresh database
+///
+/// Foreign keys are left out, so every table can be filled on its own, but primary keys and
+/// unique constraints are kept. Views and tables with a
+/// column whose type isn't built in or one of the schema's enums can't be recreated from the
+/// model, so they are left out too.
+pub fn create_schema(schema: &ResolvedSchema) -> String {
+    let mut sql = String::new();
+    writeln!(
+        sql,
+        "CREATE SCHEMA IF NOT EXISTS {};",
+        quote_name(&schema.name)
+    )
+    .unwrap();
+
+    for enum_type in &schema.enums {
+        let labels: Vec<String> = enum_type
+            .variants
+            .iter()
+            .map(|variant| format!("'{}'", variant.label.replace('\'', "''")))
+            .collect();
+        writeln!(
+            sql,
+            "CREATE TYPE {}.{} AS ENUM ({});",
+            quote_name(&schema.name),
+            quote_name(&enum_type.name),
+            labels.join(", ")
+        )
+        .unwrap();
+    }
+
+    for table in &schema.tables {
+        if !can_create(schema, table) {
+            continue;
+        }
+
+        let mut definitions: Vec<String> = table
+            .columns
+            .iter()
+            .map(|column| column_definition(&column.to_column()))
+            .collect();
+        if !table.primary_key.is_empty() {
+            let key: Vec<String> = table
+                .primary_key
+                .iter()
+                .map(|name| quote_name(name))
+                .collect();
+            definitions.push(format!("PRIMARY KEY ({})", key.join(", ")));
+        }
+        for unique_constraint in &table.unique_constraints {
+            let columns: Vec<String> = unique_constraint
+                .columns
+                .iter()
+                .map(|name| quote_name(name))
+                .collect();
+            definitions.push(format!("UNIQUE ({})", columns.join(", ")));
+        }
+        writeln!(
+            sql,
+            "CREATE TABLE {}.{} ({});",
+            quote_name(&schema.name),
+            quote_name(&table.name),
+            definitions.join(", ")
+        )
+        .unwrap();
+    }
+
+    sql
+}
+
+/// Write the SQL that creates the schema, for `--emit sql`: its enums, its tables with their
+/// keys and constraints, then its foreign keys and views
+///
+/// Everything is written in the same order and spelling every time, so the files can be compared.
+/// Views are created in the order of their names, so views that select from later ones need to be
+/// moved down.
+pub fn schema_sql(schema: &Schema) -> String {
+    let name = quote_name(&schema.name);
+    let mut sql = String::new();
+    writeln!(sql, "CREATE SCHEMA IF NOT EXISTS {};", name).unwrap();
+
+    // Only the schema's own enums are described by the model, so other types are left to the user
+    let mut other_types: Vec<String> = schema
+        .tables
+        .iter()
+        .flat_map(|table| &table.columns)
+        .map(|column| &column.data_type)
+        .filter(|data_type| match data_type.kind {
+            TypeKind::Enum => {
+                data_type.schema != schema.name
+                    || !schema
+                        .enums
+                        .iter()
+                        .any(|enum_type| enum_type.name == data_type.name)
+            }
+            _ => data_type.schema != "pg_catalog",
+        })
+        .map(|data_type| {
+            format!(
+                "{}.{}",
+                quote_name(&data_type.schema),
+                quote_name(&data_type.name)
+            )
+        })
+        .collect();
+    other_types.sort();
+    other_types.dedup();
+    if !other_types.is_empty() {
+        writeln!(sql).unwrap();
+    }
+    for other_type in &other_types {
+        writeln!(
+            sql,
+            "-- TODO: create the type {}, which isn't part of the model",
+            other_type
+        )
+        .unwrap();
+    }
+
+    for enum_type in &schema.enums {
+        writeln!(sql).unwrap();
+        write_create_enum(&mut sql, &name, enum_type);
+    }
+    let tables = schema
+        .tables
+        .iter()
+        .filter(|table| table.kind == TableKind::Table);
+    for table in tables.clone() {
+        writeln!(sql).unwrap();
+        write_create_table(&mut sql, &name, table);
+    }
+    for table in tables {
+        write_foreign_keys(&mut sql, &name, table);
+    }
+    for view in schema
+        .tables
+        .iter()
+        .filter(|table| table.kind != TableKind::Table)
+    {
+        writeln!(sql).unwrap();
+        write_create_view(&mut sql, &name, view);
+    }
+
+    sql
+}
+
+/// Whether [`create_schema`] creates a table
+pub fn can_create(schema: &ResolvedSchema, table: &ResolvedTable) -> bool {
+    if table.kind != TableKind::Table {
+        return false;
+    }
+
+    // Only built-in types and the schema's own enums are known
+    let unknown = table.columns.iter().find(|column| {
+        let data_type = &column.data_type;
+        match data_type.kind {
+            TypeKind::Enum => {
+                data_type.schema != schema.name
+                    || !schema
+                        .enums
+                        .iter()
+                        .any(|enum_type| enum_type.name == data_type.name)
+            }
+            TypeKind::Composite => true,
+            TypeKind::Base | TypeKind::Range => data_type.schema != "pg_catalog",
+        }
+    });
+    if let Some(column) = unknown {
+        debug!(
+            "{} has column {} of type {}.{}, which can't be created, skipping it",
+            table.name, column.name, column.data_type.schema, column.data_type.name
+        );
+        return false;
+    }
+
+    true
+}
+
+/// Write the definition of a column
+pub fn column_definition(column: &Column) -> String {
+    let data_type = &column.data_type;
+    let is_serial = !column.identity
+        && column
+            .default
+            .as_deref()
+            .is_some_and(|default| default.starts_with("nextval("));
+
+    // Serial columns bring their own sequence, instead of the one the default refers to
+    let serial = match data_type.name.as_str() {
+        _ if !is_serial || data_type.dimensions > 0 => None,
+        "smallint" => Some("smallserial"),
+        "integer" => Some("serial"),
+        "bigint" => Some("bigserial"),
+        _ => None,
+    };
+    let sql_type = match serial {
+        Some(serial) => serial.to_string(),
+        None => column_type(data_type),
+    };
+
+    let mut definition = format!("{} {}", quote_name(&column.name), sql_type);
+    if column.identity {
+        definition.push_str(" GENERATED BY DEFAULT AS IDENTITY");
+    } else if column.generated {
+        if let Some(expression) = &column.default {
+            write!(definition, " GENERATED ALWAYS AS ({}) STORED", expression).unwrap();
+        }
+    } else if let Some(default) = column.default.as_deref().filter(|_| serial.is_none()) {
+        write!(definition, " DEFAULT {}", default).unwrap();
+    }
+    if !column.nullable {
+        definition.push_str(" NOT NULL");
+    }
+
+    definition
+}
+
+/// Write a type the way it is declared, qualified with its schema unless it is built in
+pub fn column_type(data_type: &DataType) -> String {
+    let dimensions = "[]".repeat(data_type.dimensions.max(0) as usize);
+    if data_type.schema != "pg_catalog" {
+        return format!(
+            "{}.{}{}",
+            quote_name(&data_type.schema),
+            quote_name(&data_type.name),
+            dimensions
+        );
+    }
+
+    // The precision of time types goes before the time zone
+    match (data_type.precision, data_type.name.split_once(" with")) {
+        (Some(precision), Some((name, zone))) => {
+            format!("{}({}) with{}{}", name, precision, zone, dimensions)
+        }
+        _ => data_type.to_string(),
+    }
+}
+
+/// Write the `CREATE TABLE` of a table with its primary key and unique constraints
+pub fn write_create_table(sql: &mut String, schema: &str, table: &Table) {
+    let mut definitions: Vec<String> = table.columns.iter().map(column_definition).collect();
+    if !table.primary_key.is_empty() {
+        let key: Vec<String> = table
+            .primary_key
+            .iter()
+            .map(|name| quote_name(name))
+            .collect();
+        definitions.push(format!("PRIMARY KEY ({})", key.join(", ")));
+    }
+    for unique_constraint in &table.unique_constraints {
+        let columns: Vec<String> = unique_constraint
+            .columns
+            .iter()
+            .map(|name| quote_name(name))
+            .collect();
+        definitions.push(format!(
+            "CONSTRAINT {} UNIQUE ({})",
+            quote_name(&unique_constraint.name),
+            columns.join(", ")
+        ));
+    }
+
+    writeln!(
+        sql,
+        "CREATE TABLE {}.{} (\n    {}\n);",
+        schema,
+        quote_name(&table.name),
+        definitions.join(",\n    ")
+    )
+    .unwrap();
+}
+
+/// Write the `CREATE VIEW` of a view, or a reminder to write it when its query isn't known
+pub fn write_create_view(sql: &mut String, schema: &str, table: &Table) {
+    let keyword = match table.kind {
+        TableKind::MaterializedView => "MATERIALIZED VIEW",
+        _ => "VIEW",
+    };
+    match &table.definition {
+        Some(definition) => writeln!(
+            sql,
+            "CREATE {} {}.{} AS\n{};",
+            keyword,
+            schema,
+            quote_name(&table.name),
+            definition.trim().trim_end_matches(';')
+        )
+        .unwrap(),
+        None => writeln!(
+            sql,
+            "-- TODO: CREATE {} {}.{} AS ...",
+            keyword,
+            schema,
+            quote_name(&table.name)
+        )
+        .unwrap(),
+    }
+}
+
+/// Write the `DROP` of a table or view
+pub fn write_drop(sql: &mut String, schema: &str, table: &Table) {
+    let keyword = match table.kind {
+        TableKind::Table => "TABLE",
+        TableKind::View => "VIEW",
+        TableKind::MaterializedView => "MATERIALIZED VIEW",
+    };
+    writeln!(
+        sql,
+        "DROP {} {}.{};",
+        keyword,
+        schema,
+        quote_name(&table.name)
+    )
+    .unwrap();
+}
+
+/// Write the `CREATE TYPE` of an enum type
+pub fn write_create_enum(sql: &mut String, schema: &str, enum_type: &EnumType) {
+    let labels: Vec<String> = enum_type
+        .labels
+        .iter()
+        .map(|label| literal(label))
+        .collect();
+    writeln!(
+        sql,
+        "CREATE TYPE {}.{} AS ENUM ({});",
+        schema,
+        quote_name(&enum_type.name),
+        labels.join(", ")
+    )
+    .unwrap();
+}
+
+/// Write an `ALTER TABLE ... ADD CONSTRAINT` for each foreign key of a table, each after a blank line
+pub fn write_foreign_keys(sql: &mut String, schema: &str, table: &Table) {
+    for foreign_key in &table.foreign_keys {
+        let columns: Vec<String> = foreign_key
+            .columns
+            .iter()
+            .map(|name| quote_name(name))
+            .collect();
+        let referenced: Vec<String> = foreign_key
+            .referenced_columns
+            .iter()
+            .map(|name| quote_name(name))
+            .collect();
+        writeln!(
+            sql,
+            "\nALTER TABLE {}.{} ADD CONSTRAINT {} FOREIGN KEY ({}) REFERENCES {}.{} ({});",
+            schema,
+            quote_name(&table.name),
+            quote_name(&foreign_key.name),
+            columns.join(", "),
+            quote_name(&foreign_key.referenced_schema),
+            quote_name(&foreign_key.referenced_table),
+            referenced.join(", ")
+        )
+        .unwrap();
+    }
+}
+
+/// Quote a string literal
+pub fn literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ArrayConfig, EnumConfig};
+    use crate::model::{ForeignKey, UniqueConstraint};
+    use crate::types::BytesType;
+
+    fn data_type(schema: &str, name: &str, kind: TypeKind) -> DataType {
+        DataType {
+            name: name.to_string(),
+            schema: schema.to_string(),
+            kind,
+            dimensions: 0,
+            element_not_null: false,
+            length: None,
+            precision: None,
+            scale: None,
+        }
+    }
+
+    fn column(name: &str, data_type: DataType) -> Column {
+        Column {
+            name: name.to_string(),
+            data_type,
+            nullable: true,
+            default: None,
+            identity: false,
+            generated: false,
+            comment: None,
+        }
+    }
+
+    fn builtin(name: &str, type_name: &str) -> Column {
+        column(name, data_type("pg_catalog", type_name, TypeKind::Base))
+    }
+
+    fn table(name: &str, columns: Vec<Column>) -> Table {
+        Table {
+            name: name.to_string(),
+            kind: TableKind::Table,
+            columns,
+            primary_key: Vec::new(),
+            foreign_keys: Vec::new(),
+            unique_constraints: Vec::new(),
+            indexes: Vec::new(),
+            comment: None,
+            definition: None,
+        }
+    }
+
+    #[test]
+    fn names_and_literals_are_quoted() {
+        let column = builtin("Say \"hi\"", "text");
+        assert_eq!(column_definition(&column), "\"Say \"\"hi\"\"\" text");
+        assert_eq!(literal("it's"), "'it''s'");
+
+        let mut sql = String::new();
+        let enum_type = EnumType {
+            name: "Mood".to_string(),
+            labels: vec!["ok".to_string(), "can't say".to_string()],
+        };
+        write_create_enum(&mut sql, &quote_name("my schema"), &enum_type);
+        assert_eq!(
+            sql,
+            "CREATE TYPE \"my schema\".\"Mood\" AS ENUM ('ok', 'can''t say');\n"
+        );
+    }
+
+    #[test]
+    fn types_are_declared_with_their_modifiers() {
+        let mut numeric = data_type("pg_catalog", "numeric", TypeKind::Base);
+        numeric.precision = Some(10);
+        numeric.scale = Some(2);
+        assert_eq!(column_type(&numeric), "numeric(10,2)");
+
+        let mut varchar = data_type("pg_catalog", "character varying", TypeKind::Base);
+        varchar.length = Some(40);
+        varchar.dimensions = 1;
+        assert_eq!(column_type(&varchar), "character varying(40)[]");
+
+        let mut timestamp = data_type("pg_catalog", "timestamp with time zone", TypeKind::Base);
+        timestamp.precision = Some(3);
+        assert_eq!(column_type(&timestamp), "timestamp(3) with time zone");
+
+        let mut other = data_type("app", "Address", TypeKind::Composite);
+        other.dimensions = 2;
+        assert_eq!(column_type(&other), "\"app\".\"Address\"[][]");
+    }
+
+    #[test]
+    fn defaults_become_serials_identities_and_generated_columns() {
+        let mut id = builtin("id", "bigint");
+        id.default = Some("nextval('users_id_seq'::regclass)".to_string());
+        id.nullable = false;
+        assert_eq!(column_definition(&id), "\"id\" bigserial NOT NULL");
+
+        // Only integers become serials, other types and arrays keep the default
+        let mut code = builtin("code", "text");
+        code.default = Some("nextval('codes'::regclass)".to_string());
+        assert_eq!(
+            column_definition(&code),
+            "\"code\" text DEFAULT nextval('codes'::regclass)"
+        );
+
+        let mut identity = builtin("id", "integer");
+        identity.identity = true;
+        identity.nullable = false;
+        assert_eq!(
+            column_definition(&identity),
+            "\"id\" integer GENERATED BY DEFAULT AS IDENTITY NOT NULL"
+        );
+
+        let mut generated = builtin("total", "integer");
+        generated.generated = true;
+        generated.default = Some("(price * quantity)".to_string());
+        assert_eq!(
+            column_definition(&generated),
+            "\"total\" integer GENERATED ALWAYS AS ((price * quantity)) STORED"
+        );
+
+        let mut created = builtin("created", "timestamp with time zone");
+        created.default = Some("now()".to_string());
+        assert_eq!(
+            column_definition(&created),
+            "\"created\" timestamp with time zone DEFAULT now()"
+        );
+    }
+
+    #[test]
+    fn tables_keep_their_keys_and_constraints() {
+        let mut users = table(
+            "users",
+            vec![builtin("id", "integer"), builtin("email", "text")],
+        );
+        users.primary_key = vec!["id".to_string()];
+        users.unique_constraints = vec![UniqueConstraint {
+            name: "users_email_key".to_string(),
+            columns: vec!["email".to_string()],
+        }];
+        users.foreign_keys = vec![ForeignKey {
+            name: "users_team_fkey".to_string(),
+            columns: vec!["id".to_string()],
+            referenced_schema: "auth".to_string(),
+            referenced_table: "teams".to_string(),
+            referenced_columns: vec!["id".to_string()],
+        }];
+
+        let mut sql = String::new();
+        write_create_table(&mut sql, "\"app\"", &users);
+        write_foreign_keys(&mut sql, "\"app\"", &users);
+        assert_eq!(
+            sql,
+            "CREATE TABLE \"app\".\"users\" (
+    \"id\" integer,
+    \"email\" text,
+    PRIMARY KEY (\"id\"),
+    CONSTRAINT \"users_email_key\" UNIQUE (\"email\")
+);
+
+ALTER TABLE \"app\".\"users\" ADD CONSTRAINT \"users_team_fkey\" FOREIGN KEY (\"id\") REFERENCES \"auth\".\"teams\" (\"id\");
+"
+        );
+    }
+
+    #[test]
+    fn enums_are_created_but_composite_types_are_left_to_the_user() {
+        let schema = Schema {
+            name: "app".to_string(),
+            tables: vec![
+                table(
+                    "moods",
+                    vec![column("mood", data_type("app", "mood", TypeKind::Enum))],
+                ),
+                table(
+                    "users",
+                    vec![column(
+                        "home",
+                        data_type("app", "address", TypeKind::Composite),
+                    )],
+                ),
+            ],
+            enums: vec![EnumType {
+                name: "mood".to_string(),
+                labels: vec!["happy".to_string()],
+            }],
+            composites: Vec::new(),
+            scope: None,
+        };
+
+        let sql = schema_sql(&schema);
+        assert!(sql.contains("-- TODO: create the type \"app\".\"address\""));
+        assert!(sql.contains("CREATE TYPE \"app\".\"mood\" AS ENUM ('happy');"));
+        assert!(sql.contains("CREATE TABLE \"app\".\"users\""));
+
+        // Tables are only created for tests when every type is known
+        let resolved = ResolvedSchema::resolve(
+            &schema,
+            false,
+            true,
+            false,
+            BytesType::default(),
+            &ArrayConfig::default(),
+            &EnumConfig::default(),
+        )
+        .unwrap();
+        let created: Vec<&str> = resolved
+            .tables
+            .iter()
+            .filter(|table| can_create(&resolved, table))
+            .map(|table| table.name.as_str())
+            .collect();
+        assert_eq!(created, ["moods"]);
+        let sql = create_schema(&resolved);
+        assert!(sql.contains("CREATE TYPE \"app\".\"mood\" AS ENUM ('happy');"));
+        assert!(sql.contains("CREATE TABLE \"app\".\"moods\" (\"mood\" \"app\".\"mood\");"));
+        assert!(!sql.contains("users"));
+    }
+}
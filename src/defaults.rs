@@ -0,0 +1,151 @@
+use convert_case::{Case, Casing};
+use proc_macro2::{Literal, Span, TokenStream};
+use quote::quote;
+use syn::Ident;
+
+use crate::error::{Error, Result};
+use crate::ir::ResolvedTable;
+
+/// The name of the module that holds the defaults of a table, e.g. `user_posts_defaults`
+pub fn module_name(table: &ResolvedTable) -> String {
+    format!("{}_defaults", table.rust_name.to_case(Case::Snake))
+}
+
+/// Generate a module with a const per column default of a table, for `--default-constants`
+///
+/// Every default is kept as the expression the database reports, e.g. `STATUS` for
+/// `'active'::text`. Defaults that are plain literals of the field's type are also parsed into a
+/// `_VALUE` const, e.g. `STATUS_VALUE` for `"active"`. Tables without defaults get no module.
+pub fn generate_defaults(table: &ResolvedTable) -> Result<Option<TokenStream>> {
+    let mut consts = Vec::new();
+    let mut names = Vec::new();
+    let mut push = |name: String, column: &str, doc: String, value: TokenStream| {
+        if names.contains(&name) {
+            return Err(Error::Config(format!(
+                "The default of {}.{} would be named {}, which is already taken",
+                table.name, column, name
+            )));
+        }
+        let ident = Ident::new(&name, Span::call_site());
+        names.push(name);
+        consts.push((ident, doc, value));
+        Ok(())
+    };
+
+    // Generated columns keep their expression in the default, which isn't a default at all
+    for column in table.columns.iter().filter(|column| !column.generated) {
+        let Some(default) = &column.default else {
+            continue;
+        };
+        let name = column
+            .rust_name
+            .trim_start_matches("r#")
+            .to_case(Case::UpperSnake);
+
+        let doc = format!(
+            " The default of `{}`, as the database reports it",
+            column.name
+        );
+        push(name.clone(), &column.name, doc, quote! { &str = #default })?;
+
+        if let Some(value) = parse_value(default, &column.rust_type) {
+            let doc = format!(" The default of `{}`", column.name);
+            push(format!("{}_VALUE", name), &column.name, doc, value)?;
+        }
+    }
+    if consts.is_empty() {
+        return Ok(None);
+    }
+
+    let module = Ident::new(&module_name(table), Span::call_site());
+    let doc = format!(" The defaults of the columns of `{}`", table.name);
+    let consts = consts.iter().map(|(ident, doc, value)| {
+        quote! {
+            #[doc = #doc]
+            pub const #ident: #value;
+        }
+    });
+
+    Ok(Some(quote! {
+        #[doc = #doc]
+        pub mod #module {
+            #(#consts)*
+        }
+    }))
+}
+
+/// Parse a default expression into the type and value of its const, if it is a plain literal
+/// of `rust_type`
+///
+/// Strings may be cast, like `'active'::text`, and so may numbers, which the database quotes
+/// when they are negative, like `'-1'::integer`. Function calls like `now()` aren't parsed.
+fn parse_value(default: &str, rust_type: &str) -> Option<TokenStream> {
+    let rust_type = rust_type
+        .strip_prefix("Option<")
+        .and_then(|inner| inner.strip_suffix('>'))
+        .unwrap_or(rust_type);
+
+    // Get the literal without its casts, unquoting strings
+    let (literal, quoted) = match default.strip_prefix('\'') {
+        Some(rest) => {
+            let mut literal = String::new();
+            let mut chars = rest.chars();
+            loop {
+                match chars.next()? {
+                    '\'' if chars.as_str().starts_with('\'') => {
+                        chars.next();
+                        literal.push('\'');
+                    }
+                    '\'' => break,
+                    c => literal.push(c),
+                }
+            }
+            let casts = chars.as_str();
+            if !casts.is_empty() && !casts.starts_with("::") {
+                return None;
+            }
+            (literal, true)
+        }
+        None => {
+            let literal = default.split("::").next()?.trim();
+            let literal = literal
+                .strip_prefix('(')
+                .and_then(|literal| literal.strip_suffix(')'))
+                .unwrap_or(literal);
+            (literal.to_string(), false)
+        }
+    };
+
+    match rust_type {
+        "String" if quoted => Some(quote! { &str = #literal }),
+        "char" if quoted && literal.chars().count() == 1 => {
+            let value = literal.chars().next()?;
+            Some(quote! { char = #value })
+        }
+        "bool" => {
+            let value: bool = literal.parse().ok()?;
+            Some(quote! { bool = #value })
+        }
+        "i16" | "i32" | "i64" | "u32" => {
+            let value = match rust_type {
+                "i16" => literal.parse::<i16>().ok()? as i64,
+                "i32" => literal.parse::<i32>().ok()? as i64,
+                "u32" => literal.parse::<u32>().ok()? as i64,
+                _ => literal.parse::<i64>().ok()?,
+            };
+            let ty = Ident::new(rust_type, Span::call_site());
+            let value = Literal::i64_unsuffixed(value);
+            Some(quote! { #ty = #value })
+        }
+        "f32" | "f64" => {
+            let value: f64 = literal
+                .parse()
+                .ok()
+                .filter(|value: &f64| value.is_finite())?;
+            let ty = Ident::new(rust_type, Span::call_site());
+            let value = Literal::f64_unsuffixed(value);
+            Some(quote! { #ty = #value })
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,280 @@
+use std::{fmt, fs};
+
+use serde::Serialize;
+use tracing::warn;
+
+use crate::error::{Error, Result};
+
+/// A crate the generated code needs, with the features it relies on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Dependency {
+    /// The name of the crate
+    pub name: &'static str,
+    /// The version requirement the generated code is written against
+    pub version: &'static str,
+    /// The features of the crate the generated code uses
+    pub features: Vec<&'static str>,
+    /// Whether only the generated tests use the crate, so it belongs in `[dev-dependencies]`
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub dev: bool,
+}
+
+impl fmt::Display for Dependency {
+    /// Format the dependency as a line of a Cargo.toml `[dependencies]` table
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.features.is_empty() {
+            write!(f, "{} = \"{}\"", self.name, self.version)
+        } else {
+            let features: Vec<String> = self
+                .features
+                .iter()
+                .map(|feature| format!("\"{}\"", feature))
+                .collect();
+            write!(
+                f,
+                "{} = {{ version = \"{}\", features = [{}] }}",
+                self.name,
+                self.version,
+                features.join(", ")
+            )
+        }
+    }
+}
+
+/// Work out the crates the generated code needs from the paths it uses
+pub fn required_dependencies(files: &[String]) -> Vec<Dependency> {
+    let uses = |path: &str| files.iter().any(|code| code.contains(path));
+    let uses_serde = uses("serde::");
+    let uses_chrono = uses("chrono::");
+    let uses_uuid = uses("uuid::");
+    let uses_decimal = uses("rust_decimal::");
+    let uses_bytes = uses("bytes::Bytes");
+    let uses_serde_json = uses("serde_json::");
+    let uses_postgres = uses("postgres::");
+
+    // Types used in derived serde impls need their serde feature
+    let serde_feature = || {
+        if uses_serde {
+            vec!["serde"]
+        } else {
+            vec![]
+        }
+    };
+
+    let mut dependencies = Vec::new();
+    if uses_serde {
+        dependencies.push(Dependency {
+            name: "serde",
+            version: "1",
+            features: vec!["derive"],
+            dev: false,
+        });
+    }
+    if uses_serde_json {
+        dependencies.push(Dependency {
+            name: "serde_json",
+            version: "1",
+            features: vec![],
+            dev: false,
+        });
+    }
+    if uses_chrono {
+        dependencies.push(Dependency {
+            name: "chrono",
+            version: "0.4",
+            features: serde_feature(),
+            dev: false,
+        });
+    }
+    if uses_uuid {
+        dependencies.push(Dependency {
+            name: "uuid",
+            version: "1",
+            features: serde_feature(),
+            dev: false,
+        });
+    }
+    if uses_bytes {
+        dependencies.push(Dependency {
+            name: "bytes",
+            version: "1",
+            features: serde_feature(),
+            dev: false,
+        });
+    }
+    if uses_decimal {
+        // Decimals are only read and passed through postgres with its postgres support
+        let mut features = serde_feature();
+        if uses_postgres {
+            features.push("db-postgres");
+        }
+        dependencies.push(Dependency {
+            name: "rust_decimal",
+            version: "1",
+            features,
+            dev: false,
+        });
+    }
+    if uses_postgres {
+        // Function wrappers read and pass these types through postgres
+        let mut features = Vec::new();
+        if uses_chrono {
+            features.push("with-chrono-0_4");
+        }
+        if uses_uuid {
+            features.push("with-uuid-1");
+        }
+        if uses_serde_json || uses("postgres::types::Json") {
+            features.push("with-serde_json-1");
+        }
+        dependencies.push(Dependency {
+            name: "postgres",
+            version: "0.19",
+            features,
+            dev: false,
+        });
+    }
+    if uses("postgres_types::") {
+        dependencies.push(Dependency {
+            name: "postgres-types",
+            version: "0.2",
+            features: vec!["derive"],
+            dev: false,
+        });
+    }
+    if uses("strum::") {
+        dependencies.push(Dependency {
+            name: "strum",
+            version: "0.26",
+            features: vec!["derive"],
+            dev: false,
+        });
+    }
+    if uses("sea_query::") {
+        dependencies.push(Dependency {
+            name: "sea-query",
+            version: "0.30",
+            features: vec!["derive"],
+            dev: false,
+        });
+    }
+    if uses("axum::") {
+        dependencies.push(Dependency {
+            name: "axum",
+            version: "0.8",
+            features: vec![],
+            dev: false,
+        });
+    }
+    if uses("tonic::") {
+        // The messages are compiled by tonic-prost-build, a build dependency
+        dependencies.push(Dependency {
+            name: "tonic",
+            version: "0.14",
+            features: vec![],
+            dev: false,
+        });
+        dependencies.push(Dependency {
+            name: "tonic-prost",
+            version: "0.14",
+            features: vec![],
+            dev: false,
+        });
+        dependencies.push(Dependency {
+            name: "prost",
+            version: "0.14",
+            features: vec![],
+            dev: false,
+        });
+    }
+    if uses("tokio::") {
+        // The scaffold runs blocking queries with spawn_blocking
+        dependencies.push(Dependency {
+            name: "tokio",
+            version: "1",
+            features: vec!["rt"],
+            dev: false,
+        });
+    }
+    if uses("testcontainers_modules::") {
+        dependencies.push(Dependency {
+            name: "testcontainers-modules",
+            version: "0.15",
+            features: vec!["postgres", "blocking"],
+            dev: true,
+        });
+    }
+
+    dependencies
+}
+
+/// Check that a project's Cargo.toml has the dependencies the generated code needs
+///
+/// Dependencies inherited from the workspace are only checked to be there, since their features
+/// are set in the workspace's Cargo.toml. Crates only the tests use may also be dev-dependencies.
+pub fn check_manifest(path: &str, dependencies: &[Dependency]) -> Result<()> {
+    let contents = fs::read_to_string(path).map_err(Error::io(path))?;
+    let manifest: toml::Table = toml::from_str(&contents)
+        .map_err(|error| Error::Config(format!("Invalid manifest {}: {}", path, error)))?;
+    let table = |name: &str| {
+        manifest
+            .get(name)
+            .and_then(toml::Value::as_table)
+            .cloned()
+            .unwrap_or_default()
+    };
+    let declared = table("dependencies");
+    let dev_declared = table("dev-dependencies");
+
+    let mut missing = Vec::new();
+    for dependency in dependencies {
+        // Tests can also use regular dependencies
+        let mut candidates: Vec<_> = declared.iter().collect();
+        if dependency.dev {
+            candidates.extend(dev_declared.iter());
+        }
+
+        // Dependencies can be renamed, so look for the package name too
+        let found = candidates.into_iter().find(|(name, value)| {
+            *name == dependency.name
+                || value.get("package").and_then(toml::Value::as_str) == Some(dependency.name)
+        });
+        let Some((_, value)) = found else {
+            warn!("{} is missing {}", path, dependency);
+            missing.push(dependency.name.to_string());
+            continue;
+        };
+        if value.get("workspace").and_then(toml::Value::as_bool) == Some(true) {
+            continue;
+        }
+
+        let features: Vec<&str> = value
+            .get("features")
+            .and_then(toml::Value::as_array)
+            .map(|features| features.iter().filter_map(toml::Value::as_str).collect())
+            .unwrap_or_default();
+        // derive is a default feature of sea-query
+        let default_features =
+            value.get("default-features").and_then(toml::Value::as_bool) != Some(false);
+        for feature in &dependency.features {
+            let is_default = dependency.name == "sea-query" && *feature == "derive";
+            let enabled = features.contains(feature) || (is_default && default_features);
+            if !enabled {
+                warn!(
+                    "{} is missing the {} feature of {}",
+                    path, feature, dependency.name
+                );
+                missing.push(format!("{}/{}", dependency.name, feature));
+            }
+        }
+    }
+
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::MissingDependencies {
+            path: path.to_string(),
+            missing,
+        })
+    }
+}
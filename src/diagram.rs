@@ -0,0 +1,148 @@
+use std::fmt::Write;
+
+use crate::ir::{ResolvedSchema, ResolvedTable};
+use crate::model::ForeignKey;
+
+/// Write a Mermaid `erDiagram` of the tables and their foreign keys, for `--emit mermaid`
+pub fn mermaid(schema: &ResolvedSchema) -> String {
+    let mut diagram = String::from("erDiagram\n");
+
+    for table in &schema.tables {
+        writeln!(diagram, "    {} {{", mermaid_name(&table.name)).unwrap();
+        for column in &table.columns {
+            // Columns that are part of a key are marked as such
+            let primary = table.primary_key.contains(&column.name);
+            let key = match (primary, is_foreign_key(table, &column.name)) {
+                (true, true) => " PK, FK",
+                (true, false) => " PK",
+                (false, true) => " FK",
+                (false, false) => "",
+            };
+            writeln!(
+                diagram,
+                "        {} {}{}",
+                mermaid_name(&column.data_type.to_string()),
+                mermaid_name(&column.name),
+                key
+            )
+            .unwrap();
+        }
+        writeln!(diagram, "    }}").unwrap();
+    }
+
+    for table in &schema.tables {
+        for foreign_key in &table.foreign_keys {
+            // A nullable foreign key doesn't have to reference anything
+            let referenced = if is_optional(table, foreign_key) {
+                "o|"
+            } else {
+                "||"
+            };
+            writeln!(
+                diagram,
+                "    {} }}o--{} {} : \"{}\"",
+                mermaid_name(&table.name),
+                referenced,
+                mermaid_name(&referenced_name(schema, foreign_key)),
+                foreign_key.name.replace('"', "'")
+            )
+            .unwrap();
+        }
+    }
+
+    diagram
+}
+
+/// Write a Graphviz DOT graph of the tables and their foreign keys, for `--emit dot`
+pub fn dot(schema: &ResolvedSchema) -> String {
+    let mut diagram =
+        String::from("digraph schema {\n    rankdir=LR;\n    node [shape=plaintext];\n");
+
+    for table in &schema.tables {
+        let mut label = format!(
+            "<table border=\"0\" cellborder=\"1\" cellspacing=\"0\"><tr><td><b>{}</b></td></tr>",
+            html(&table.name)
+        );
+        for column in &table.columns {
+            write!(
+                label,
+                "<tr><td align=\"left\">{}: {}</td></tr>",
+                html(&column.name),
+                html(&column.data_type.to_string())
+            )
+            .unwrap();
+        }
+        label.push_str("</table>");
+        writeln!(diagram, "    \"{}\" [label=<{}>];", table.name, label).unwrap();
+    }
+
+    for table in &schema.tables {
+        for foreign_key in &table.foreign_keys {
+            let style = if is_optional(table, foreign_key) {
+                ", style=dashed"
+            } else {
+                ""
+            };
+            writeln!(
+                diagram,
+                "    \"{}\" -> \"{}\" [label=\"{}\"{}];",
+                table.name,
+                referenced_name(schema, foreign_key),
+                foreign_key.name.replace('"', "\\\""),
+                style
+            )
+            .unwrap();
+        }
+    }
+
+    diagram.push_str("}\n");
+    diagram
+}
+
+/// Whether a column is part of a foreign key of its table
+fn is_foreign_key(table: &ResolvedTable, column: &str) -> bool {
+    table
+        .foreign_keys
+        .iter()
+        .any(|foreign_key| foreign_key.columns.iter().any(|name| name == column))
+}
+
+/// Whether a foreign key can be NULL, so a row doesn't have to reference anything
+fn is_optional(table: &ResolvedTable, foreign_key: &ForeignKey) -> bool {
+    table
+        .columns
+        .iter()
+        .any(|column| column.nullable && foreign_key.columns.contains(&column.name))
+}
+
+/// Get the name of a referenced table, qualified with its schema when it's in another one
+fn referenced_name(schema: &ResolvedSchema, foreign_key: &ForeignKey) -> String {
+    if foreign_key.referenced_schema == schema.name {
+        foreign_key.referenced_table.clone()
+    } else {
+        format!(
+            "{}.{}",
+            foreign_key.referenced_schema, foreign_key.referenced_table
+        )
+    }
+}
+
+/// Make a name or type usable in a Mermaid diagram, which only allows a few characters
+fn mermaid_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || "_-[]()".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Escape text for a Graphviz HTML label
+fn html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
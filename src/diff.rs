@@ -0,0 +1,499 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::model::{Column, Schema, Table, TableKind};
+
+/// The differences between two versions of a schema
+#[derive(Debug, Default, Serialize)]
+pub struct SchemaDiff {
+    /// Tables that only exist in the new schema
+    pub added_tables: Vec<String>,
+    /// Tables that only exist in the old schema
+    pub removed_tables: Vec<String>,
+    /// Tables that exist in both schemas but with different columns
+    pub changed_tables: Vec<TableDiff>,
+    /// Enum types that only exist in the new schema
+    pub added_enums: Vec<String>,
+    /// Enum types that only exist in the old schema
+    pub removed_enums: Vec<String>,
+    /// Enum types that exist in both schemas but with different labels
+    pub changed_enums: Vec<EnumDiff>,
+}
+
+/// The differences between two versions of a table
+#[derive(Debug, Serialize)]
+pub struct TableDiff {
+    pub name: String,
+    /// Columns that only exist in the new table, with their type
+    pub added_columns: Vec<ColumnSummary>,
+    /// Columns that only exist in the old table, with their type
+    pub removed_columns: Vec<ColumnSummary>,
+    /// Columns whose type or nullability changed
+    pub changed_columns: Vec<ColumnChange>,
+}
+
+/// A column and its type
+#[derive(Debug, Serialize)]
+pub struct ColumnSummary {
+    pub name: String,
+    pub data_type: String,
+    pub nullable: bool,
+}
+
+/// A column whose type or nullability changed
+#[derive(Debug, Serialize)]
+pub struct ColumnChange {
+    pub name: String,
+    pub old: ColumnSummary,
+    pub new: ColumnSummary,
+}
+
+/// An enum type whose labels changed
+#[derive(Debug, Serialize)]
+pub struct EnumDiff {
+    pub name: String,
+    pub old_labels: Vec<String>,
+    pub new_labels: Vec<String>,
+}
+
+impl SchemaDiff {
+    /// Whether the two schemas are the same
+    pub fn is_empty(&self) -> bool {
+        self.added_tables.is_empty()
+            && self.removed_tables.is_empty()
+            && self.changed_tables.is_empty()
+            && self.added_enums.is_empty()
+            && self.removed_enums.is_empty()
+            && self.changed_enums.is_empty()
+    }
+}
+
+/// Get the kinds of relations only one of the schemas was read with, which can't be compared
+pub fn skipped_kinds(old: &Schema, new: &Schema) -> Vec<TableKind> {
+    [TableKind::View, TableKind::MaterializedView]
+        .into_iter()
+        .filter(|kind| old.includes(*kind) != new.includes(*kind))
+        .collect()
+}
+
+/// Compare an old version of a schema against a new one
+///
+/// Relations of the kinds only one of them was read with are left out, so a snapshot taken with
+/// `--include-views` doesn't show every view as removed when the schema is read without it.
+pub fn diff_schemas(old: &Schema, new: &Schema) -> SchemaDiff {
+    let mut schema_diff = SchemaDiff::default();
+    let skipped = skipped_kinds(old, new);
+    let compared = |table: &&Table| !skipped.contains(&table.kind);
+
+    for table in new.tables.iter().filter(compared) {
+        match old
+            .tables
+            .iter()
+            .find(|old_table| old_table.name == table.name)
+        {
+            Some(old_table) => {
+                if let Some(table_diff) = diff_tables(old_table, table) {
+                    schema_diff.changed_tables.push(table_diff);
+                }
+            }
+            None => schema_diff.added_tables.push(table.name.clone()),
+        }
+    }
+    for table in old.tables.iter().filter(compared) {
+        if !new
+            .tables
+            .iter()
+            .any(|new_table| new_table.name == table.name)
+        {
+            schema_diff.removed_tables.push(table.name.clone());
+        }
+    }
+
+    for enum_type in &new.enums {
+        match old
+            .enums
+            .iter()
+            .find(|old_enum| old_enum.name == enum_type.name)
+        {
+            Some(old_enum) => {
+                if old_enum.labels != enum_type.labels {
+                    schema_diff.changed_enums.push(EnumDiff {
+                        name: enum_type.name.clone(),
+                        old_labels: old_enum.labels.clone(),
+                        new_labels: enum_type.labels.clone(),
+                    });
+                }
+            }
+            None => schema_diff.added_enums.push(enum_type.name.clone()),
+        }
+    }
+    for enum_type in &old.enums {
+        if !new
+            .enums
+            .iter()
+            .any(|new_enum| new_enum.name == enum_type.name)
+        {
+            schema_diff.removed_enums.push(enum_type.name.clone());
+        }
+    }
+
+    schema_diff
+}
+
+/// Compare the columns of two versions of a table, returning None if they are the same
+fn diff_tables(old: &Table, new: &Table) -> Option<TableDiff> {
+    let mut table_diff = TableDiff {
+        name: new.name.clone(),
+        added_columns: Vec::new(),
+        removed_columns: Vec::new(),
+        changed_columns: Vec::new(),
+    };
+
+    for column in &new.columns {
+        match old
+            .columns
+            .iter()
+            .find(|old_column| old_column.name == column.name)
+        {
+            Some(old_column) => {
+                if old_column.data_type != column.data_type
+                    || old_column.nullable != column.nullable
+                {
+                    table_diff.changed_columns.push(ColumnChange {
+                        name: column.name.clone(),
+                        old: ColumnSummary::from(old_column),
+                        new: ColumnSummary::from(column),
+                    });
+                }
+            }
+            None => table_diff.added_columns.push(ColumnSummary::from(column)),
+        }
+    }
+    for column in &old.columns {
+        if !new
+            .columns
+            .iter()
+            .any(|new_column| new_column.name == column.name)
+        {
+            table_diff.removed_columns.push(ColumnSummary::from(column));
+        }
+    }
+
+    if table_diff.added_columns.is_empty()
+        && table_diff.removed_columns.is_empty()
+        && table_diff.changed_columns.is_empty()
+    {
+        None
+    } else {
+        Some(table_diff)
+    }
+}
+
+impl From<&Column> for ColumnSummary {
+    fn from(column: &Column) -> Self {
+        ColumnSummary {
+            name: column.name.clone(),
+            data_type: column.data_type.to_string(),
+            nullable: column.nullable,
+        }
+    }
+}
+
+impl fmt::Display for ColumnSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.data_type)?;
+        if !self.nullable {
+            write!(f, " NOT NULL")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for SchemaDiff {
+    /// Format the differences as a human-readable report, one line per change
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No differences");
+        }
+
+        for table in &self.added_tables {
+            writeln!(f, "+ table {}", table)?;
+        }
+        for table in &self.removed_tables {
+            writeln!(f, "- table {}", table)?;
+        }
+        for table in &self.changed_tables {
+            writeln!(f, "~ table {}", table.name)?;
+            for column in &table.added_columns {
+                writeln!(f, "    + column {}: {}", column.name, column)?;
+            }
+            for column in &table.removed_columns {
+                writeln!(f, "    - column {}: {}", column.name, column)?;
+            }
+            for column in &table.changed_columns {
+                writeln!(
+                    f,
+                    "    ~ column {}: {} -> {}",
+                    column.name, column.old, column.new
+                )?;
+            }
+        }
+        for enum_type in &self.added_enums {
+            writeln!(f, "+ enum {}", enum_type)?;
+        }
+        for enum_type in &self.removed_enums {
+            writeln!(f, "- enum {}", enum_type)?;
+        }
+        for enum_type in &self.changed_enums {
+            writeln!(
+                f,
+                "~ enum {}: ({}) -> ({})",
+                enum_type.name,
+                enum_type.old_labels.join(", "),
+                enum_type.new_labels.join(", ")
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{DataType, EnumType, Scope, TypeKind};
+
+    fn column(name: &str, data_type: &str, nullable: bool) -> Column {
+        Column {
+            name: name.to_string(),
+            data_type: DataType {
+                name: data_type.to_string(),
+                schema: "pg_catalog".to_string(),
+                kind: TypeKind::Base,
+                dimensions: 0,
+                element_not_null: false,
+                length: None,
+                precision: None,
+                scale: None,
+            },
+            nullable,
+            default: None,
+            identity: false,
+            generated: false,
+            comment: None,
+        }
+    }
+
+    fn table(name: &str, kind: TableKind, columns: Vec<Column>) -> Table {
+        Table {
+            name: name.to_string(),
+            kind,
+            columns,
+            primary_key: Vec::new(),
+            foreign_keys: Vec::new(),
+            unique_constraints: Vec::new(),
+            indexes: Vec::new(),
+            comment: None,
+            definition: None,
+        }
+    }
+
+    fn schema(tables: Vec<Table>, views: bool) -> Schema {
+        Schema {
+            name: "public".to_string(),
+            tables,
+            enums: Vec::new(),
+            composites: Vec::new(),
+            scope: Some(Scope {
+                views,
+                materialized_views: views,
+            }),
+        }
+    }
+
+    fn enum_type(name: &str, labels: &[&str]) -> EnumType {
+        EnumType {
+            name: name.to_string(),
+            labels: labels.iter().map(|label| label.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn identical_schemas_have_no_differences() {
+        let users = table(
+            "users",
+            TableKind::Table,
+            vec![column("id", "integer", false)],
+        );
+        let mut old = schema(vec![users], false);
+        old.enums = vec![enum_type("mood", &["happy", "sad"])];
+
+        let schema_diff = diff_schemas(&old, &old.clone());
+        assert!(schema_diff.is_empty());
+        assert_eq!(schema_diff.to_string(), "No differences\n");
+    }
+
+    #[test]
+    fn tables_and_columns_are_added_removed_and_altered() {
+        let old = schema(
+            vec![
+                table(
+                    "orders",
+                    TableKind::Table,
+                    vec![column("id", "integer", false)],
+                ),
+                table(
+                    "users",
+                    TableKind::Table,
+                    vec![
+                        column("id", "integer", false),
+                        column("age", "integer", true),
+                        column("email", "text", true),
+                        column("fax", "text", true),
+                    ],
+                ),
+            ],
+            false,
+        );
+        let new = schema(
+            vec![
+                table(
+                    "teams",
+                    TableKind::Table,
+                    vec![column("id", "integer", false)],
+                ),
+                table(
+                    "users",
+                    TableKind::Table,
+                    vec![
+                        column("id", "integer", false),
+                        column("age", "bigint", true),
+                        column("email", "text", false),
+                        column("name", "text", false),
+                    ],
+                ),
+            ],
+            false,
+        );
+
+        let schema_diff = diff_schemas(&old, &new);
+        assert_eq!(schema_diff.added_tables, ["teams"]);
+        assert_eq!(schema_diff.removed_tables, ["orders"]);
+        let [users] = schema_diff.changed_tables.as_slice() else {
+            panic!("expected one changed table: {}", schema_diff);
+        };
+        assert_eq!(users.name, "users");
+        let names = |columns: &[ColumnSummary]| -> Vec<String> {
+            columns.iter().map(|column| column.name.clone()).collect()
+        };
+        assert_eq!(names(&users.added_columns), ["name"]);
+        assert_eq!(names(&users.removed_columns), ["fax"]);
+        let changed: Vec<(&str, String, String)> = users
+            .changed_columns
+            .iter()
+            .map(|change| {
+                (
+                    change.name.as_str(),
+                    change.old.to_string(),
+                    change.new.to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            changed,
+            [
+                ("age", "integer".to_string(), "bigint".to_string()),
+                ("email", "text".to_string(), "text NOT NULL".to_string()),
+            ]
+        );
+
+        assert_eq!(
+            schema_diff.to_string(),
+            "+ table teams
+- table orders
+~ table users
+    + column name: text NOT NULL
+    - column fax: text
+    ~ column age: integer -> bigint
+    ~ column email: text -> text NOT NULL
+"
+        );
+    }
+
+    #[test]
+    fn enums_and_their_labels_are_compared() {
+        let mut old = schema(Vec::new(), false);
+        old.enums = vec![
+            enum_type("mood", &["happy", "sad"]),
+            enum_type("size", &["small", "large"]),
+            enum_type("status", &["on", "off"]),
+        ];
+        let mut new = schema(Vec::new(), false);
+        new.enums = vec![
+            enum_type("color", &["red"]),
+            enum_type("mood", &["happy", "sad", "meh"]),
+            enum_type("status", &["on", "off"]),
+        ];
+
+        let schema_diff = diff_schemas(&old, &new);
+        assert_eq!(schema_diff.added_enums, ["color"]);
+        assert_eq!(schema_diff.removed_enums, ["size"]);
+        let [mood] = schema_diff.changed_enums.as_slice() else {
+            panic!("expected one changed enum: {}", schema_diff);
+        };
+        assert_eq!(mood.name, "mood");
+        assert_eq!(mood.old_labels, ["happy", "sad"]);
+        assert_eq!(mood.new_labels, ["happy", "sad", "meh"]);
+        assert!(schema_diff.changed_tables.is_empty());
+
+        // Reordering labels changes the enum too, since their order is how they sort
+        let mut reordered = new.clone();
+        reordered.enums[1] = enum_type("mood", &["sad", "happy", "meh"]);
+        assert_eq!(
+            diff_schemas(&new, &reordered).to_string(),
+            "~ enum mood: (happy, sad, meh) -> (sad, happy, meh)\n"
+        );
+    }
+
+    #[test]
+    fn views_are_only_compared_when_both_schemas_include_them() {
+        let users = || {
+            table(
+                "users",
+                TableKind::Table,
+                vec![column("id", "integer", false)],
+            )
+        };
+        let active = table("active_users", TableKind::View, Vec::new());
+        let totals = table("totals", TableKind::MaterializedView, Vec::new());
+        let with_views = schema(vec![active, users(), totals], true);
+        let without_views = schema(vec![users()], false);
+
+        let schema_diff = diff_schemas(&with_views, &without_views);
+        assert!(schema_diff.is_empty(), "{}", schema_diff);
+        assert_eq!(
+            skipped_kinds(&with_views, &without_views),
+            [TableKind::View, TableKind::MaterializedView]
+        );
+        assert!(diff_schemas(&without_views, &with_views).is_empty());
+
+        // Both include views, so the views that are gone are removed
+        let mut empty = schema(vec![users()], true);
+        assert_eq!(
+            diff_schemas(&with_views, &empty).removed_tables,
+            ["active_users", "totals"]
+        );
+
+        // Snapshots that don't record their scope include the kinds they have a relation of
+        empty.scope = None;
+        let mut old = with_views.clone();
+        old.scope = None;
+        assert_eq!(skipped_kinds(&old, &with_views), []);
+        assert_eq!(
+            skipped_kinds(&empty, &with_views),
+            [TableKind::View, TableKind::MaterializedView]
+        );
+    }
+}
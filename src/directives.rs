@@ -0,0 +1,187 @@
+use crate::error::{Error, Result};
+
+/// Directives for the generated code, written as `@rust(...)` in the comment on a table or column
+///
+/// For example `@rust(type = "crate::Money")` or `@rust(skip)`. Several directives in one comment
+/// are combined. A `@deprecated reason` marker deprecates the struct or field.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Directives {
+    /// Leave the table or column out of the generated code
+    pub skip: bool,
+    /// The Rust type of a column, without the `Option` of nullable columns
+    pub rust_type: Option<String>,
+    /// The name of the Rust struct or field
+    pub rename: Option<String>,
+    /// Extra derives for the struct of a table
+    pub derives: Vec<String>,
+    /// Don't derive `Copy`, `PartialOrd` or `Hash` for the struct of a table, even when its fields
+    /// allow it
+    pub no_auto_derive: bool,
+    /// Why the table or column is deprecated, which may be empty, if it is
+    pub deprecated: Option<String>,
+}
+
+/// Read the directives in a comment, where `context` names the table or column for errors
+pub fn parse(comment: Option<&str>, context: &str) -> Result<Directives> {
+    let mut directives = Directives::default();
+    let Some(mut rest) = comment else {
+        return Ok(directives);
+    };
+
+    // The reason of a deprecation is the rest of its line, up to any other directive
+    if let Some(start) = find_marker(rest, "@deprecated") {
+        let reason = rest[start + "@deprecated".len()..]
+            .lines()
+            .next()
+            .unwrap_or_default();
+        let reason = reason.split("@rust(").next().unwrap_or_default();
+        directives.deprecated = Some(reason.trim().to_string());
+    }
+
+    let invalid = |message: String| {
+        Error::Config(format!(
+            "Invalid @rust directive on {}: {}",
+            context, message
+        ))
+    };
+
+    while let Some(start) = rest.find("@rust(") {
+        rest = &rest[start + "@rust(".len()..];
+
+        // Split the arguments on the commas outside of quotes, up to the closing parenthesis
+        let mut arguments = vec![String::new()];
+        let mut quoted = false;
+        let mut end = None;
+        for (index, c) in rest.char_indices() {
+            match c {
+                '"' => quoted = !quoted,
+                ',' if !quoted => {
+                    arguments.push(String::new());
+                    continue;
+                }
+                ')' if !quoted => {
+                    end = Some(index);
+                    break;
+                }
+                _ => {}
+            }
+            arguments.last_mut().unwrap().push(c);
+        }
+        let end = end.ok_or_else(|| invalid("missing closing parenthesis".to_string()))?;
+        rest = &rest[end + 1..];
+
+        for argument in arguments {
+            let argument = argument.trim();
+            if argument.is_empty() {
+                continue;
+            }
+
+            let (key, value) = match argument.split_once('=') {
+                Some((key, value)) => {
+                    let value = value.trim();
+                    let value = value
+                        .strip_prefix('"')
+                        .and_then(|value| value.strip_suffix('"'))
+                        .ok_or_else(|| {
+                            invalid(format!("{} must be a quoted string", key.trim()))
+                        })?;
+                    (key.trim(), Some(value.to_string()))
+                }
+                None => (argument, None),
+            };
+
+            match (key, value) {
+                ("skip", None) => directives.skip = true,
+                ("no_auto_derive", None) => directives.no_auto_derive = true,
+                ("type", Some(value)) => directives.rust_type = Some(value),
+                ("rename", Some(value)) => directives.rename = Some(value),
+                ("derive", Some(value)) => directives
+                    .derives
+                    .extend(value.split(',').map(|derive| derive.trim().to_string())),
+                ("skip" | "no_auto_derive", Some(_)) => {
+                    return Err(invalid(format!("{} doesn't take a value", key)))
+                }
+                ("type" | "rename" | "derive", None) => {
+                    return Err(invalid(format!("{} needs a value", key)))
+                }
+                (key, _) => return Err(invalid(format!("unknown argument {}", key))),
+            }
+        }
+    }
+
+    Ok(directives)
+}
+
+/// Find a marker that isn't part of a longer word, like `@deprecated` but not `@deprecatedly`
+fn find_marker(text: &str, marker: &str) -> Option<usize> {
+    text.match_indices(marker)
+        .map(|(start, _)| start)
+        .find(|start| {
+            !text[start + marker.len()..].starts_with(|c: char| c.is_alphanumeric() || c == '_')
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_combines_the_directives_of_a_comment() {
+        let comment = r#"Prices of the products
+@rust(type = "crate::Money", rename = "amount") @rust(derive = "Eq, Hash", no_auto_derive)"#;
+        let directives = parse(Some(comment), "column prices.price").unwrap();
+        assert_eq!(
+            directives,
+            Directives {
+                rust_type: Some("crate::Money".to_string()),
+                rename: Some("amount".to_string()),
+                derives: vec!["Eq".to_string(), "Hash".to_string()],
+                no_auto_derive: true,
+                ..Directives::default()
+            }
+        );
+
+        assert!(parse(Some("@rust(skip)"), "table audit").unwrap().skip);
+        assert_eq!(parse(None, "table audit").unwrap(), Directives::default());
+        assert_eq!(
+            parse(Some("Just a comment"), "table audit").unwrap(),
+            Directives::default()
+        );
+    }
+
+    #[test]
+    fn parse_reads_the_reason_of_a_deprecation() {
+        let directives = parse(
+            Some("@deprecated Use orders instead @rust(skip)\nMore text"),
+            "table purchases",
+        )
+        .unwrap();
+        assert_eq!(directives.deprecated.as_deref(), Some("Use orders instead"));
+        assert!(directives.skip);
+
+        let directives = parse(Some("@deprecatedly not"), "table purchases").unwrap();
+        assert_eq!(directives.deprecated, None);
+    }
+
+    #[test]
+    fn parse_rejects_invalid_directives() {
+        for (comment, message) in [
+            ("@rust(skip", "missing closing parenthesis"),
+            ("@rust(type = crate::Money)", "type must be a quoted string"),
+            (r#"@rust(skip = "yes")"#, "skip doesn't take a value"),
+            ("@rust(rename)", "rename needs a value"),
+            ("@rust(flatten)", "unknown argument flatten"),
+        ] {
+            let error = parse(Some(comment), "column prices.price").unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!(
+                    "Invalid @rust directive on column prices.price: {}",
+                    message
+                ),
+                "{}",
+                comment
+            );
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use std::collections::HashSet;
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use tracing::warn;
+
+use crate::error::{Error, Result};
+use crate::ir::{ResolvedColumn, ResolvedTable};
+use crate::output;
+
+/// The columns used to display a row when no display column is set, in order of preference
+const DISPLAY_COLUMNS: [&str; 2] = ["name", "title"];
+
+/// Generate `impl std::fmt::Display` for the struct of a table
+///
+/// The row is displayed as `display_column` when it is set, otherwise as its `name` or `title`
+/// column, otherwise as its primary key. Tables without any of them aren't given an impl.
+/// Columns whose type is in `debug_types`, like the generated enums, are written with `Debug`.
+pub fn generate_display(
+    table: &ResolvedTable,
+    struct_name: &TokenStream,
+    display_column: Option<&str>,
+    debug_types: &HashSet<String>,
+) -> Result<Option<TokenStream>> {
+    let find = |name: &str| table.columns.iter().find(|column| column.name == name);
+
+    // Get the columns the row is displayed as
+    let columns: Vec<&ResolvedColumn> = match display_column {
+        Some(name) => vec![find(name).ok_or_else(|| {
+            Error::Config(format!("Display column {}.{} not found", table.name, name))
+        })?],
+        None => match DISPLAY_COLUMNS.iter().find_map(|name| find(name)) {
+            Some(column) => vec![column],
+            None => table
+                .primary_key
+                .iter()
+                .filter_map(|name| find(name))
+                .collect(),
+        },
+    };
+    if columns.is_empty() {
+        warn!(
+            "Table {} has no name, title or primary key column, skipping its Display impl",
+            table.name
+        );
+        return Ok(None);
+    }
+
+    // Write every column, separated by commas for composite primary keys
+    let mut statements = Vec::new();
+    for (index, column) in columns.iter().enumerate() {
+        if index > 0 {
+            statements.push(quote! { f.write_str(", ")?; });
+        }
+
+        let context = format!("column {}.{}", table.name, column.name);
+        let field = output::ident(&column.rust_name, &context)?;
+        let format = if is_display(column, debug_types) {
+            "{}"
+        } else {
+            "{:?}"
+        };
+        statements.push(if column.nullable {
+            quote! {
+                if let Some(value) = &self.#field {
+                    write!(f, #format, value)?;
+                }
+            }
+        } else {
+            quote! { write!(f, #format, self.#field)?; }
+        });
+    }
+
+    Ok(Some(quote! {
+        impl std::fmt::Display for #struct_name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                #(#statements)*
+                Ok(())
+            }
+        }
+    }))
+}
+
+/// Whether the Rust type of a column implements `Display`, otherwise it's written with `Debug`
+///
+/// Arrays, bytes and the types in `debug_types` don't.
+fn is_display(column: &ResolvedColumn, debug_types: &HashSet<String>) -> bool {
+    let rust_type = column.rust_type.as_str();
+    let rust_type = match rust_type.strip_prefix("Option<") {
+        Some(inner) if column.nullable => inner.strip_suffix('>').unwrap_or(inner),
+        _ => rust_type,
+    };
+
+    !rust_type.starts_with("Vec<") && !debug_types.contains(rust_type)
+}
@@ -0,0 +1,142 @@
+use std::fmt::Write;
+
+use crate::indexes;
+use crate::ir::{ResolvedColumn, ResolvedSchema, ResolvedTable};
+use crate::model::TableKind;
+
+/// Write Markdown documentation of every table and enum, for `--emit markdown`
+pub fn markdown(schema: &ResolvedSchema) -> String {
+    let mut docs = String::new();
+    writeln!(docs, "# Schema `{}`", schema.name).unwrap();
+
+    // List everything first, so the document is easy to navigate
+    if !schema.tables.is_empty() {
+        writeln!(docs, "\n## Tables\n").unwrap();
+        for table in &schema.tables {
+            let heading = heading(table);
+            writeln!(docs, "- [{}](#{})", heading, anchor(&heading)).unwrap();
+        }
+    }
+    if !schema.enums.is_empty() {
+        writeln!(docs, "\n## Enums\n").unwrap();
+        for enum_type in &schema.enums {
+            writeln!(docs, "- [{}](#{})", enum_type.name, anchor(&enum_type.name)).unwrap();
+        }
+    }
+
+    for table in &schema.tables {
+        write_table(&mut docs, table);
+    }
+
+    for enum_type in &schema.enums {
+        writeln!(docs, "\n## {}\n", enum_type.name).unwrap();
+        writeln!(docs, "Rust enum: `{}`\n", enum_type.rust_name).unwrap();
+        writeln!(docs, "| Label | Rust variant |").unwrap();
+        writeln!(docs, "| ----- | ------------ |").unwrap();
+        for variant in &enum_type.variants {
+            writeln!(
+                docs,
+                "| `{}` | `{}` |",
+                cell(&variant.label),
+                variant.rust_name
+            )
+            .unwrap();
+        }
+    }
+
+    docs
+}
+
+/// Write the section of a table, with its columns, foreign keys and indexes
+fn write_table(docs: &mut String, table: &ResolvedTable) {
+    writeln!(docs, "\n## {}\n", heading(table)).unwrap();
+    if let Some(comment) = &table.comment {
+        writeln!(docs, "{}\n", comment.trim()).unwrap();
+    }
+    writeln!(docs, "Rust struct: `{}`\n", table.rust_name).unwrap();
+
+    writeln!(
+        docs,
+        "| Column | Type | Nullable | Default | Rust type | Description |"
+    )
+    .unwrap();
+    writeln!(
+        docs,
+        "| ------ | ---- | -------- | ------- | --------- | ----------- |"
+    )
+    .unwrap();
+    for column in &table.columns {
+        writeln!(
+            docs,
+            "| `{}` | `{}` | {} | {} | `{}` | {} |",
+            column.name,
+            column.data_type,
+            if column.nullable { "yes" } else { "no" },
+            default(column),
+            column.rust_type,
+            column.comment.as_deref().map(cell).unwrap_or_default()
+        )
+        .unwrap();
+    }
+
+    if !table.primary_key.is_empty() {
+        writeln!(docs, "\nPrimary key: ({})", table.primary_key.join(", ")).unwrap();
+    }
+
+    if !table.foreign_keys.is_empty() {
+        writeln!(docs, "\nForeign keys:\n").unwrap();
+        for foreign_key in &table.foreign_keys {
+            writeln!(
+                docs,
+                "- `{}`: ({}) references `{}.{}` ({})",
+                foreign_key.name,
+                foreign_key.columns.join(", "),
+                foreign_key.referenced_schema,
+                foreign_key.referenced_table,
+                foreign_key.referenced_columns.join(", ")
+            )
+            .unwrap();
+        }
+    }
+
+    if !table.indexes.is_empty() {
+        writeln!(docs, "\nIndexes:\n").unwrap();
+        for index in &table.indexes {
+            writeln!(docs, "- {}", indexes::describe(index)).unwrap();
+        }
+    }
+}
+
+/// Get the heading of a table, which tells views apart
+fn heading(table: &ResolvedTable) -> String {
+    match table.kind {
+        TableKind::Table => table.name.clone(),
+        TableKind::View => format!("{} (view)", table.name),
+        TableKind::MaterializedView => format!("{} (materialized view)", table.name),
+    }
+}
+
+/// Describe where the value of a column comes from when it isn't given
+fn default(column: &ResolvedColumn) -> String {
+    match (&column.default, column.identity, column.generated) {
+        (_, true, _) => "identity".to_string(),
+        (Some(default), _, true) => format!("generated as `{}`", cell(default)),
+        (Some(default), _, false) => format!("`{}`", cell(default)),
+        (None, _, _) => String::new(),
+    }
+}
+
+/// Make text safe to put in a table cell
+fn cell(text: &str) -> String {
+    text.trim().replace('|', "\\|").replace('\n', "<br>")
+}
+
+/// Get the anchor GitHub generates for a heading
+fn anchor(heading: &str) -> String {
+    heading
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric() || *c == '-' || *c == '_' || *c == ' ')
+        .map(|c| if c == ' ' { '-' } else { c })
+        .collect()
+}
@@ -0,0 +1,91 @@
+use convert_case::{Case, Casing};
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::Ident;
+
+use crate::error::Result;
+use crate::ir::ResolvedEnum;
+use crate::output;
+
+/// Get the Rust name of an enum type
+pub fn enum_ident(type_name: &str) -> Ident {
+    Ident::new(&type_name.to_case(Case::Pascal), Span::call_site())
+}
+
+/// Generate a Rust enum for a PostgreSQL enum type
+///
+/// serde is told the exact label whenever the variant name differs from it. With `strum`, the
+/// enum also derives strum's `EnumString`, `Display` and `EnumIter`, which use the labels too.
+/// With `postgres`, it derives `ToSql` and `FromSql` for the type and its labels. With
+/// `non_exhaustive`, it's marked `#[non_exhaustive]`.
+pub fn generate_enum(
+    enum_type: &ResolvedEnum,
+    strum: bool,
+    postgres: bool,
+    non_exhaustive: bool,
+) -> Result<TokenStream> {
+    let context = format!("enum {}", enum_type.name);
+    let enum_name = output::ident(&enum_type.rust_name, &context)?;
+
+    let mut variants = Vec::new();
+    for variant in &enum_type.variants {
+        let label = &variant.label;
+        let context = format!("label {}.{}", enum_type.name, label);
+        let name = output::ident(&variant.rust_name, &context)?;
+        if variant.rust_name == *label {
+            variants.push(quote! { #name, });
+            continue;
+        }
+
+        let mut attributes = vec![quote! { #[serde(rename = #label)] }];
+        if strum {
+            attributes.push(quote! { #[strum(to_string = #label)] });
+        }
+        if postgres {
+            attributes.push(quote! { #[postgres(name = #label)] });
+        }
+        variants.push(quote! {
+            #(#attributes)*
+            #name,
+        });
+    }
+
+    let mut derives = Vec::new();
+    if strum {
+        derives.push(quote! { strum::EnumString, strum::Display, strum::EnumIter, });
+    }
+    let type_name = &enum_type.name;
+    let postgres_name = if postgres {
+        derives.push(quote! { postgres_types::ToSql, postgres_types::FromSql, });
+        quote! { #[postgres(name = #type_name)] }
+    } else {
+        quote! {}
+    };
+
+    // Variants in another case are fine when the config asks for them
+    let allow_case = if enum_type
+        .variants
+        .iter()
+        .any(|variant| variant.rust_name != variant.rust_name.to_case(Case::Pascal))
+    {
+        quote! { #[allow(non_camel_case_types, clippy::upper_case_acronyms)] }
+    } else {
+        quote! {}
+    };
+
+    let non_exhaustive = if non_exhaustive {
+        quote! { #[non_exhaustive] }
+    } else {
+        quote! {}
+    };
+
+    Ok(quote! {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize, #(#derives)*)]
+        #postgres_name
+        #allow_case
+        #non_exhaustive
+        pub enum #enum_name {
+            #(#variants)*
+        }
+    })
+}
@@ -0,0 +1,107 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Everything that can go wrong while generating code
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The command line arguments or environment are missing or malformed
+    #[error("{0}")]
+    Config(String),
+    /// The database could not be reached
+    #[error("failed to connect to PostgreSQL database: {0}")]
+    Connect(#[source] postgres::Error),
+    /// The SSH tunnel to the database could not be opened
+    #[error("failed to open SSH tunnel: {0}")]
+    Tunnel(String),
+    /// The database rejected the credentials
+    #[error("authentication with PostgreSQL database failed: {0}")]
+    Authentication(#[source] postgres::Error),
+    /// An RDS IAM authentication token couldn't be generated
+    #[error("failed to authenticate with AWS IAM: {0}")]
+    AwsIam(String),
+    /// A query against the database failed
+    #[error("failed to query {context}: {source}")]
+    Query {
+        context: &'static str,
+        #[source]
+        source: postgres::Error,
+    },
+    /// The schema doesn't contain anything to generate code for
+    #[error("schema {0} doesn't contain any tables or types, check the schema name")]
+    EmptySchema(String),
+    /// Reading or writing a file failed
+    #[error("failed to access {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    /// A snapshot or model couldn't be parsed or serialized
+    #[error("invalid snapshot or model {}: {source}", path.display())]
+    Snapshot {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+    /// The generated code isn't valid Rust, usually because of a name that can't be an identifier
+    #[error("generated invalid Rust for {context}: {source}")]
+    InvalidCode {
+        context: String,
+        #[source]
+        source: syn::Error,
+    },
+    /// A command of a hook failed
+    #[error("{hook} hook `{command}` failed: {message}")]
+    Hook {
+        hook: &'static str,
+        command: String,
+        message: String,
+    },
+    /// The project's Cargo.toml is missing crates or features the generated code needs
+    #[error("{path} is missing dependencies of the generated code: {}", missing.join(", "))]
+    MissingDependencies { path: String, missing: Vec<String> },
+    /// `--strict` found columns whose type has no Rust mapping
+    #[error("columns have types without a Rust mapping, set their type with [column_types] or a @rust(type = ...) directive:\n{}", columns.join("\n"))]
+    UnmappedTypes { columns: Vec<String> },
+    /// rustfmt couldn't format a generated file
+    #[error("failed to format {path} with rustfmt: {message}")]
+    Format { path: String, message: String },
+}
+
+impl Error {
+    /// The exit code of the process when it fails with this error
+    ///
+    /// Exit code 1 is left to the diff and verify subcommands, which use it to report differences.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Config(_) => 2,
+            Error::Connect(_) | Error::Tunnel(_) => 3,
+            Error::Authentication(_) | Error::AwsIam(_) => 4,
+            Error::EmptySchema(_) => 5,
+            Error::Query { .. } => 6,
+            Error::Io { .. } => 7,
+            Error::Snapshot { .. } => 8,
+            Error::InvalidCode { .. } => 9,
+            Error::MissingDependencies { .. } => 10,
+            Error::Hook { .. } => 11,
+            Error::UnmappedTypes { .. } => 12,
+            Error::Format { .. } => 13,
+        }
+    }
+
+    /// Build a closure that wraps a query error with what was being queried, for `map_err`
+    pub fn query(context: &'static str) -> impl FnOnce(postgres::Error) -> Error {
+        move |source| Error::Query { context, source }
+    }
+
+    /// Build a closure that wraps an IO error with the path it happened on, for `map_err`
+    pub fn io(path: impl AsRef<Path>) -> impl FnOnce(io::Error) -> Error {
+        let path = path.as_ref().to_path_buf();
+        move |source| Error::Io { path, source }
+    }
+}
+
+/// A result whose error is [`Error`]
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,3211 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt, fs,
+    path::Path,
+};
+
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use convert_case::{Case, Casing};
+use postgres::types::Type;
+use postgres::IsolationLevel;
+use proc_macro2::TokenStream;
+use quote::{__private::Span, quote};
+use rayon::prelude::*;
+use syn::Ident;
+use tracing::{debug, info, warn};
+
+use crate::config::{
+    save_tables, ColumnType, Config, Dimensions, TableConfig, DEFAULT_CONFIG_FILE,
+};
+use crate::connection::{self, ConnectionSettings};
+use crate::error::{Error, Result};
+use crate::model::{Schema, TableKind};
+use crate::version::ServerVersion;
+use crate::{
+    cache, composites, ddl, defaults, dependencies, diagram, diff, display, docs, enums, hooks,
+    indexes, interactive, introspect, ir, logging, metadata, migration, newtypes, output, proto,
+    redact, refs, relations, report, repository, roundtrip, routines, scaffold, sequences,
+    snapshot, types,
+};
+
+/// The `--schema` that generates code for every user schema in the database
+const ALL_SCHEMAS: &str = "*";
+
+/// The code generated for a schema, before it is written
+pub struct Generated {
+    /// The path and formatted contents of every generated file, starting with the output file
+    pub files: Vec<(String, String)>,
+    /// The path and contents of every file written with `--emit`, which aren't Rust code
+    pub emitted: Vec<(String, String)>,
+    /// The path and initial contents of the companion files for hand-written code, which are only
+    /// written when they don't exist yet
+    pub companion_files: Vec<(String, String)>,
+    /// The files of mapped tables that weren't generated, which should be deleted
+    stale_files: Vec<String>,
+    /// The header written at the top of every file
+    header: output::Header,
+    /// What was generated, for the `--report`
+    report: report::Report,
+    /// The names of the table structs, to find the ones other schemas also have
+    struct_names: Vec<String>,
+    /// The resolved tables and types without the schema name, to find identical schemas
+    fingerprint: String,
+}
+
+/// The code generated for a table, and the names of the items it defines
+struct TableCode {
+    /// The struct and everything generated with it, validated
+    definition: String,
+    /// The name of the struct
+    struct_name: String,
+    /// The fields of the struct, which the stored functions, the scaffold and the tests use
+    fields: Vec<FieldCode>,
+    /// Whether the struct or any of its fields is deprecated
+    deprecated: bool,
+    /// The borrowed struct, if one was generated
+    ref_struct_name: Option<String>,
+    /// The struct of the rows to insert, which is generated with the repository helpers of tables
+    insert_struct_name: Option<String>,
+    /// The struct of the changes to make to a row, which is generated with `update`
+    patch_struct_name: Option<String>,
+    /// The structs of the rows referencing the table, with the tables of those rows
+    relation_names: Vec<(String, String)>,
+    /// The name of the column enum, which is only generated with `--column-enums`
+    column_enum_name: String,
+    /// The name of the sea-query `Iden` enum, which is only generated with `--sea-query`
+    iden_enum_name: String,
+    /// The module of the column defaults, if one was generated
+    defaults_module: Option<String>,
+    /// Whether the repository helpers were generated
+    has_repository: bool,
+}
+
+impl TableCode {
+    /// Get the name of the struct
+    fn struct_ident(&self) -> Ident {
+        syn::parse_str(&self.struct_name)
+            .expect("Struct names were checked when the table was generated")
+    }
+}
+
+/// A field of the struct of a table, kept as strings like the rest of the code of the table
+struct FieldCode {
+    /// The name of the field
+    name: String,
+    /// The type of the field, through the types module when the struct is written to its own file
+    rust_type: String,
+    /// The expression that reads the field from `row`
+    read: String,
+}
+
+impl FieldCode {
+    /// Keep a field of the struct of a table as strings
+    fn new(field: &repository::RepositoryField) -> FieldCode {
+        FieldCode {
+            name: field.name.to_string(),
+            rust_type: field.rust_type.to_string(),
+            read: field.read.to_string(),
+        }
+    }
+
+    /// Get the name of the field
+    fn name(&self) -> Ident {
+        syn::parse_str(&self.name).expect("Field names were checked when the table was generated")
+    }
+
+    /// Get the type of the field
+    fn rust_type(&self) -> TokenStream {
+        self.rust_type
+            .parse()
+            .expect("Field types were checked when the table was generated")
+    }
+
+    /// Get the expression that reads the field from `row`
+    fn read(&self) -> TokenStream {
+        self.read
+            .parse()
+            .expect("Field reads were checked when the table was generated")
+    }
+}
+
+/// Read the schema and generate code for it, as described by the command line arguments
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    // Get the format of the dependency report, if any
+    let dependency_report = matches.get_one::<String>("dependencies");
+
+    // Get the Cargo.toml to check the dependencies against, if any
+    let check_manifest = matches.get_one::<String>("check_manifest");
+
+    // Get the path of the run report, if any
+    let report_path = matches.get_one::<String>("report");
+
+    // Read the config file, if any
+    let config = Config::load(matches.get_one::<String>("config"))?;
+
+    hooks::run_hook("pre_generate", &config.hooks.pre_generate, &[])?;
+    let mut generated = generate(matches, &config)?;
+
+    // Write every file, skipping the ones that haven't changed
+    let mut written = Vec::new();
+    let mut unchanged = Vec::new();
+    for (path, contents) in &generated.files {
+        if output::write_file(path, &generated.header, contents)? {
+            written.push(path.clone());
+        } else {
+            unchanged.push(path.clone());
+        }
+    }
+
+    // Write the other formats, which don't get the header
+    for (path, contents) in &generated.emitted {
+        if output::write_file(path, &output::Header::none(), contents)? {
+            written.push(path.clone());
+        } else {
+            unchanged.push(path.clone());
+        }
+    }
+
+    // Create the companion files that don't exist yet, leaving the others to their owners
+    for (path, contents) in &generated.companion_files {
+        if !Path::new(path).exists() {
+            info!("Creating {}", path);
+            output::write_file(path, &output::Header::none(), contents)?;
+            written.push(path.clone());
+        }
+    }
+
+    // Delete the files of mapped tables that no longer exist
+    let mut deleted = Vec::new();
+    for file_path in &generated.stale_files {
+        if Path::new(file_path).exists() {
+            debug!("Deleting {}", file_path);
+            fs::remove_file(file_path).map_err(Error::io(file_path))?;
+            deleted.push(file_path.clone());
+        }
+    }
+
+    // Report the crates the generated code needs, based on the types that were actually used
+    let generated_files: Vec<String> = generated
+        .files
+        .into_iter()
+        .map(|(_, contents)| contents)
+        .collect();
+    let required_dependencies = dependencies::required_dependencies(&generated_files);
+    match dependency_report.map(String::as_str) {
+        Some("json") => {
+            let report = serde_json::to_string_pretty(&required_dependencies)
+                .expect("Dependencies are always serializable");
+            println!("{}", report);
+        }
+        Some(_) => {
+            for dependency in required_dependencies
+                .iter()
+                .filter(|dependency| !dependency.dev)
+            {
+                println!("{}", dependency);
+            }
+
+            // Crates only the tests use go in their own table
+            let dev_dependencies: Vec<_> = required_dependencies
+                .iter()
+                .filter(|dependency| dependency.dev)
+                .collect();
+            if !dev_dependencies.is_empty() {
+                println!("\n[dev-dependencies]");
+                for dependency in dev_dependencies {
+                    println!("{}", dependency);
+                }
+            }
+        }
+        None => {}
+    }
+
+    // Check them against the project's Cargo.toml
+    if let Some(manifest) = check_manifest {
+        dependencies::check_manifest(manifest, &required_dependencies)?;
+        info!("{} has every dependency of the generated code", manifest);
+    }
+
+    // Let the post hooks format or check the files that changed
+    if written.is_empty() {
+        debug!("No files changed, skipping the post_generate hooks");
+    } else {
+        hooks::run_hook("post_generate", &config.hooks.post_generate, &written)?;
+    }
+
+    // List the columns that fell back to String in one place, where they can't be missed
+    let fallback_columns = &generated.report.fallback_columns;
+    if !fallback_columns.is_empty() {
+        let qualify = generated.report.schemas.len() > 1;
+        let columns: Vec<String> = fallback_columns
+            .iter()
+            .map(|column| match qualify {
+                true => format!("    {}.{}", column.schema, column),
+                false => format!("    {}", column),
+            })
+            .collect();
+        warn!(
+            "These columns have types without a Rust mapping and fell back to String, set their type with [column_types] or a @rust(type = ...) directive, or pass --strict to fail on them:\n{}",
+            columns.join("\n")
+        );
+    }
+
+    // Summarize the run, if requested
+    if let Some(path) = report_path {
+        let mut report = std::mem::take(&mut generated.report);
+        report.files_written = written;
+        report.files_unchanged = unchanged;
+        report.files_deleted = deleted;
+        report.warnings = logging::warnings();
+        report::write_report(path, &report)?;
+    }
+
+    Ok(())
+}
+
+/// How the schema differs from a previous snapshot, as reported by the `diff` subcommand
+pub struct DiffOutcome {
+    /// The differences in the requested format, ready to be printed
+    pub report: String,
+    /// Whether there are any differences
+    pub differs: bool,
+}
+
+/// Compare the schema against a previous snapshot, as described by the arguments of the `diff`
+/// subcommand, and write a migration skeleton for the differences, if requested
+///
+/// Printing the report and exiting with a status that tells whether there are differences is
+/// left to the caller.
+pub fn diff_schema(matches: &ArgMatches) -> Result<DiffOutcome> {
+    let diff_matches = matches
+        .subcommand_matches("diff")
+        .ok_or_else(|| Error::Config("The diff subcommand wasn't given".to_string()))?;
+    let previous_snapshot = diff_matches
+        .get_one::<String>("previous_snapshot")
+        .expect("Previous snapshot must be set");
+    let previous = snapshot::read_snapshot(previous_snapshot)?;
+    let schema_model = read_schema_model(matches)?;
+    for kind in diff::skipped_kinds(&previous, &schema_model) {
+        let kind = match kind {
+            TableKind::MaterializedView => "materialized views",
+            _ => "views",
+        };
+        warn!(
+            "Only one of {} and the schema was read with {}, so they aren't compared",
+            previous_snapshot, kind
+        );
+    }
+    let schema_diff = diff::diff_schemas(&previous, &schema_model);
+
+    let report = match diff_matches.get_one::<String>("format").map(String::as_str) {
+        Some("json") => {
+            serde_json::to_string_pretty(&schema_diff).expect("Diffs always serialize") + "\n"
+        }
+        _ => schema_diff.to_string(),
+    };
+
+    // Write a migration skeleton for the differences, if requested
+    if let Some(directory) = diff_matches.get_one::<String>("migration") {
+        if schema_diff.is_empty() {
+            info!("No differences, not writing a migration");
+        } else {
+            let style = match diff_matches
+                .get_one::<String>("migration_style")
+                .map(String::as_str)
+            {
+                Some("refinery") => migration::MigrationStyle::Refinery,
+                _ => migration::MigrationStyle::Sqlx,
+            };
+            let name = diff_matches
+                .get_one::<String>("migration_name")
+                .expect("Migration name has a default");
+            migration::write_migration(
+                directory,
+                style,
+                name,
+                &schema_diff,
+                &previous,
+                &schema_model,
+            )?;
+        }
+    }
+
+    Ok(DiffOutcome {
+        report,
+        differs: !schema_diff.is_empty(),
+    })
+}
+
+/// Whether a generated file is stale, as reported by the `verify` subcommand
+pub struct VerifyOutcome {
+    /// The generated file that was checked
+    pub path: String,
+    /// The schema checksum in the header of the file
+    pub stored: String,
+    /// The checksum of the schema as it is now
+    pub checksum: String,
+}
+
+impl VerifyOutcome {
+    /// Whether the file was generated from a different schema
+    pub fn is_stale(&self) -> bool {
+        self.stored != self.checksum
+    }
+}
+
+impl fmt::Display for VerifyOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_stale() {
+            write!(
+                f,
+                "{} is stale, it was generated from schema checksum {} but the schema is now {}",
+                self.path, self.stored, self.checksum
+            )
+        } else {
+            write!(f, "{} is up to date", self.path)
+        }
+    }
+}
+
+/// Check whether a generated file is stale, as described by the arguments of the `verify`
+/// subcommand, by comparing the schema checksum in its header against the schema
+///
+/// Printing the result and exiting with a status that tells whether the file is stale is left to
+/// the caller.
+pub fn verify(matches: &ArgMatches) -> Result<VerifyOutcome> {
+    let verify_matches = matches
+        .subcommand_matches("verify")
+        .ok_or_else(|| Error::Config("The verify subcommand wasn't given".to_string()))?;
+    let config = Config::load(matches.get_one::<String>("config"))?;
+    let header = output::Header::new(config.header.as_deref());
+
+    // Check the output file, unless another file was given
+    let path = match verify_matches.get_one::<String>("file") {
+        Some(file) => file.clone(),
+        None => {
+            let output_directory = matches
+                .get_one::<String>("output_directory")
+                .expect("Output directory must be set");
+            let output_file = matches
+                .get_one::<String>("output")
+                .expect("Output must be set");
+            format!("{}/{}", output_directory, output_file)
+        }
+    };
+    let contents = fs::read_to_string(&path).map_err(Error::io(&path))?;
+    let stored = header.read_checksum(&contents).ok_or_else(|| {
+        Error::Config(format!(
+            "{} has no schema checksum in its header, regenerate it first",
+            path
+        ))
+    })?;
+
+    let schema_model = read_schema_model(matches)?;
+    Ok(VerifyOutcome {
+        path,
+        stored,
+        checksum: snapshot::checksum(&schema_model),
+    })
+}
+
+/// Read the tables and types of the schema for a subcommand, from the model, the snapshot or the
+/// database, as the arguments say
+fn read_schema_model(matches: &ArgMatches) -> Result<Schema> {
+    let schema = matches
+        .get_one::<String>("schema")
+        .expect("Schema must be set");
+    if schema == ALL_SCHEMAS {
+        return Err(Error::Config(
+            "The subcommands check one schema, pass it with --schema".to_string(),
+        ));
+    }
+
+    // Read the connection settings, unless the schema comes from a snapshot or a model
+    let settings = if matches.contains_id("from_snapshot") || matches.contains_id("from_ir") {
+        None
+    } else {
+        Some(ConnectionSettings::from_matches(matches)?)
+    };
+
+    let mut client = settings
+        .as_ref()
+        .map(ConnectionSettings::connect)
+        .transpose()?;
+    let mut transaction = client.as_mut().map(read_only_transaction).transpose()?;
+    let source = read_schema(matches, schema, settings.as_ref(), transaction.as_mut())?;
+    transaction
+        .map(postgres::Transaction::commit)
+        .transpose()
+        .map_err(Error::query("transaction"))?;
+    if let Some(client) = client {
+        client.close().map_err(Error::Connect)?;
+        info!("Closed PostgreSQL connection");
+    }
+
+    Ok(source.model)
+}
+
+/// Generate the code for a schema as a single file, for the `include_postgres_schema!` macro
+///
+/// `args` are the command line arguments, without the program name. A macro expands to one
+/// file, so the options that split the code into several files can't be used.
+pub fn expand(args: &[String]) -> Result<String> {
+    let args = std::iter::once(env!("CARGO_PKG_NAME")).chain(args.iter().map(String::as_str));
+    let matches = crate::cli::command()
+        .try_get_matches_from(args)
+        .map_err(|error| Error::Config(format!("Invalid arguments: {}", error)))?;
+
+    let config = Config::load(matches.get_one::<String>("config"))?;
+    let mut generated = generate(&matches, &config)?;
+    if generated.files.len() > 1 {
+        return Err(Error::Config(
+            "--layout, --table-file and --prelude write several files, which a macro can't expand to"
+                .to_string(),
+        ));
+    }
+
+    Ok(generated.files.remove(0).1)
+}
+
+/// Read the schema and generate the code for it in memory, without writing anything
+pub fn generate(matches: &ArgMatches, config: &Config) -> Result<Generated> {
+    // The subcommands check the schema instead of generating code for it
+    if let Some(subcommand) = matches.subcommand_name() {
+        return Err(Error::Config(format!(
+            "The {} subcommand doesn't generate code, run it with the rustgres-schema binary",
+            subcommand
+        )));
+    }
+
+    // Get the PostgreSQL schema
+    let schema = matches
+        .get_one::<String>("schema")
+        .expect("Schema must be set");
+
+    // Get the output file
+    let output_file = matches
+        .get_one::<String>("output")
+        .expect("Output must be set");
+
+    // Get the output directory
+    let output_directory = matches
+        .get_one::<String>("output_directory")
+        .expect("Output directory must be set");
+
+    if schema == ALL_SCHEMAS {
+        return generate_all_schemas(matches, config, output_directory, output_file);
+    }
+
+    // Read the connection settings, unless the schema comes from a snapshot or a model
+    let settings = if matches.contains_id("from_snapshot") || matches.contains_id("from_ir") {
+        None
+    } else {
+        Some(ConnectionSettings::from_matches(matches)?)
+    };
+
+    // Generate the schema as a module of the output file, if requested
+    if matches.get_one::<String>("layout").map(String::as_str) == Some("per-schema") {
+        return generate_schema_modules(
+            matches,
+            config,
+            std::slice::from_ref(schema),
+            output_directory,
+            output_file,
+            settings.as_ref(),
+        );
+    }
+
+    generate_schema(
+        matches,
+        config,
+        schema,
+        output_directory,
+        output_file,
+        settings.as_ref(),
+    )
+}
+
+/// Generate the code of every user schema in the database, for `--schema '*'`
+///
+/// Each schema is generated like a single one, as a module named after it in the directory of
+/// the output file, which declares them.
+fn generate_all_schemas(
+    matches: &ArgMatches,
+    config: &Config,
+    output_directory: &str,
+    output_file: &str,
+) -> Result<Generated> {
+    // A snapshot or model only holds one schema, and the other outputs would overwrite each other
+    for (id, flag) in [
+        ("from_snapshot", "--from-snapshot"),
+        ("from_ir", "--from-ir"),
+        ("snapshot", "--snapshot"),
+        ("emit", "--emit"),
+    ] {
+        if matches.contains_id(id) {
+            return Err(Error::Config(format!(
+                "{} can't be used with --schema '*'",
+                flag
+            )));
+        }
+    }
+    if matches.get_flag("interactive") {
+        return Err(Error::Config(
+            "--interactive can't be used with --schema '*'".to_string(),
+        ));
+    }
+    // The root tables are the tables of one schema
+    if matches.contains_id("root_tables") {
+        return Err(Error::Config(
+            "--root-tables can't be used with --schema '*'".to_string(),
+        ));
+    }
+    for subcommand in ["diff", "verify"] {
+        if matches.subcommand_matches(subcommand).is_some() {
+            return Err(Error::Config(format!(
+                "{} can't be used with --schema '*'",
+                subcommand
+            )));
+        }
+    }
+
+    // Get the schemas to leave out
+    let excluded = excluded_schemas(
+        matches
+            .get_one::<String>("exclude_schema")
+            .map(String::as_str),
+    );
+
+    // Find every schema, leaving out the temporary schemas of other sessions
+    let settings = ConnectionSettings::from_matches(matches)?;
+    let mut client = settings.connect()?;
+    let rows = connection::query(
+        &mut client,
+        matches.get_flag("simple_query"),
+        "SELECT nspname FROM pg_catalog.pg_namespace
+        WHERE nspname NOT LIKE 'pg\\_temp\\_%' AND nspname NOT LIKE 'pg\\_toast\\_temp\\_%'
+        AND nspname <> ALL($1)
+        ORDER BY nspname",
+        &[(&excluded, Type::NAME_ARRAY)],
+    )
+    .map_err(Error::query("schemas"))?;
+    client.close().map_err(Error::Connect)?;
+    let schemas: Vec<String> = rows.iter().map(|row| row.get(0)).collect();
+    info!("Generating code for schemas {}", schemas.join(", "));
+
+    generate_schema_modules(
+        matches,
+        config,
+        &schemas,
+        output_directory,
+        output_file,
+        Some(&settings),
+    )
+}
+
+/// The schemas of the system, which `--schema '*'` always leaves out
+const SYSTEM_SCHEMAS: [&str; 3] = ["pg_catalog", "information_schema", "pg_toast"];
+
+/// Get the schemas `--schema '*'` leaves out: the system schemas, and the comma separated schemas
+/// of `--exclude-schema`
+fn excluded_schemas(exclude_schema: Option<&str>) -> Vec<String> {
+    let mut excluded: Vec<String> = SYSTEM_SCHEMAS
+        .iter()
+        .map(|schema| schema.to_string())
+        .collect();
+    for schema in exclude_schema.unwrap_or_default().split(',') {
+        let schema = schema.trim();
+        if !schema.is_empty() && !excluded.iter().any(|excluded| excluded == schema) {
+            excluded.push(schema.to_string());
+        }
+    }
+
+    excluded
+}
+
+/// Generate the code of each schema as a module named after it, in the directory of the output
+/// file, which declares them
+///
+/// When there are several schemas, the ones without anything to generate are skipped.
+fn generate_schema_modules(
+    matches: &ArgMatches,
+    config: &Config,
+    schemas: &[String],
+    output_directory: &str,
+    output_file: &str,
+    settings: Option<&ConnectionSettings>,
+) -> Result<Generated> {
+    // Every schema is a module next to the table files of the output file
+    let schema_directory = format!("{}/{}", output_directory, output_file.replace(".rs", ""));
+    let mut module_defs = String::new();
+    let mut files = Vec::new();
+    let mut emitted = Vec::new();
+    let mut stale_files = Vec::new();
+    let mut companion_files = Vec::new();
+    let mut checksums = Vec::new();
+    let mut report = report::Report::default();
+    let code_style = code_style(matches)?;
+    if code_style.visibility == output::Visibility::Private {
+        return Err(Error::Config(
+            "--visibility private keeps the generated items in the output file's module, so it can't be used with a module per schema".to_string(),
+        ));
+    }
+
+    // Get the module of every schema, which can't be shared, since one would overwrite the other
+    let mut module_names: Vec<(String, &String)> = Vec::new();
+    for schema in schemas {
+        let module_name = match config
+            .schemas
+            .get(schema)
+            .and_then(|schema_config| schema_config.module.as_ref())
+        {
+            Some(module_name) => module_name.clone(),
+            None => schema.to_case(Case::Snake),
+        };
+        output::ident(&module_name, &format!("schema {}", schema))?;
+        if let Some((_, other)) = module_names.iter().find(|(name, _)| *name == module_name) {
+            return Err(Error::Config(format!(
+                "Schemas {} and {} are both generated as module {}, set the module of one of them in [schemas.{}]",
+                other, schema, module_name, schema
+            )));
+        }
+        module_names.push((module_name, schema));
+    }
+
+    // Identical schemas share the code of the first one, which can't name the schema anywhere
+    let dedup_schemas = matches.get_flag("dedup_schemas");
+    if dedup_schemas {
+        for (id, flag) in [
+            ("repository", "--repository"),
+            ("relations", "--relations"),
+            ("scaffold", "--scaffold"),
+            ("with_tests", "--with-tests"),
+            ("metadata", "--metadata"),
+            ("functions", "--functions"),
+            ("sequences", "--sequences"),
+        ] {
+            if matches!(
+                matches.value_source(id),
+                Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+            ) {
+                return Err(Error::Config(format!(
+                    "{} names the schema in the generated code, so it can't be used with --dedup-schemas",
+                    flag
+                )));
+            }
+        }
+    }
+    let mut fingerprints: Vec<(String, String)> = Vec::new();
+
+    let mut struct_schemas: BTreeMap<String, Vec<&String>> = BTreeMap::new();
+    for (module_name, schema) in module_names {
+        let schema_file = format!("{}.rs", module_name);
+        let generated = generate_schema(
+            matches,
+            config,
+            schema,
+            &schema_directory,
+            &schema_file,
+            settings,
+        );
+        match generated {
+            Ok(generated) => {
+                module_defs.push_str(&format!("pub mod {};\n", module_name));
+                stale_files.extend(generated.stale_files);
+                checksums.push(generated.header.checksum().to_string());
+                report.merge(generated.report);
+
+                // Re-export the code of an identical schema instead of generating it again
+                let shared = fingerprints
+                    .iter()
+                    .filter(|_| dedup_schemas)
+                    .find(|(fingerprint, _)| *fingerprint == generated.fingerprint);
+                if let Some((_, shared_module)) = shared {
+                    info!(
+                        "Schema {} is identical to module {}, sharing its code",
+                        schema, shared_module
+                    );
+                    let path = format!("{}/{}", schema_directory, schema_file);
+                    let contents = format!(
+                        "//! The tables and types of schema {}, which are the same as the ones of `{}`\npub use super::{}::*;\n",
+                        schema, shared_module, shared_module
+                    );
+                    let contents = output::format_code(&contents, &path, &code_style)?;
+                    files.push((path, contents));
+                    continue;
+                }
+
+                fingerprints.push((generated.fingerprint, module_name.clone()));
+                files.extend(generated.files);
+                emitted.extend(generated.emitted);
+                companion_files.extend(generated.companion_files);
+                for struct_name in generated.struct_names {
+                    struct_schemas.entry(struct_name).or_default().push(schema);
+                }
+            }
+            Err(Error::EmptySchema(_)) if schemas.len() > 1 => {
+                warn!("Schema {} is empty, skipping it", schema)
+            }
+            Err(error) => return Err(error),
+        }
+    }
+
+    // Tables with the same name in several schemas are told apart by their modules
+    for (struct_name, schemas) in struct_schemas {
+        if schemas.len() > 1 {
+            let schemas: Vec<&str> = schemas.iter().map(|schema| schema.as_str()).collect();
+            info!(
+                "Struct {} is generated for schemas {}, each in its own module. Set a prefix in [schemas] to give them different names",
+                struct_name,
+                schemas.join(", ")
+            );
+        }
+    }
+
+    // Declare the schema modules in the output file
+    let output = format!("{}/{}", output_directory, output_file);
+    let contents = output::format_code(&module_defs, &output, &code_style)?;
+    files.insert(0, (output, contents));
+
+    // Every file gets the checksum of all the schemas
+    let checksums: Vec<&str> = checksums.iter().map(String::as_str).collect();
+    let checksum = snapshot::combine_checksums(&checksums);
+
+    Ok(Generated {
+        files,
+        emitted,
+        companion_files,
+        stale_files,
+        header: output::Header::new(config.header.as_deref()).with_checksum(checksum),
+        report,
+        struct_names: Vec::new(),
+        fingerprint: String::new(),
+    })
+}
+
+/// Generate the code of one schema into `output_file` in `output_directory`
+///
+/// The database is connected to with `settings`, which is `None` when the schema comes from a
+/// snapshot or a model.
+fn generate_schema(
+    matches: &ArgMatches,
+    config: &Config,
+    schema: &str,
+    output_directory: &str,
+    output_file: &str,
+    settings: Option<&ConnectionSettings>,
+) -> Result<Generated> {
+    // Get the UUID flag
+    let use_uuid = matches.get_flag("uuid");
+
+    // Get whether the precision and length of columns pick their Rust type
+    let precise_types = !matches.get_flag("no_precise_types");
+
+    // Get whether citext columns are plain strings rather than case-insensitive ones
+    let citext_as_string = matches.get_flag("citext_as_string");
+
+    // Get the Rust type of bytea columns
+    let bytes_type = match matches.get_one::<String>("bytes_type").map(String::as_str) {
+        Some("bytes") => types::BytesType::Bytes,
+        _ => types::BytesType::Vec,
+    };
+
+    // Get the pattern of the sensitive columns, and what to do with them
+    let redact_pattern = matches
+        .get_one::<String>("redact_pattern")
+        .map(|pattern| {
+            regex::RegexBuilder::new(pattern)
+                .case_insensitive(true)
+                .build()
+                .map_err(|error| {
+                    Error::Config(format!("Invalid --redact-pattern {}: {}", pattern, error))
+                })
+        })
+        .transpose()?;
+    let redact_mode = match matches.get_one::<String>("redact_mode").map(String::as_str) {
+        Some("skip") => redact::RedactMode::Skip,
+        _ => redact::RedactMode::Mask,
+    };
+
+    // Get whether columns without a Rust type mapping are an error
+    let strict = matches.get_flag("strict");
+
+    // Get the prelude flag
+    let generate_prelude = matches.get_flag("prelude");
+
+    // Get the column enums flag
+    let generate_column_enums = matches.get_flag("column_enums");
+
+    // Get the sea-query flag
+    let generate_sea_query = matches.get_flag("sea_query");
+
+    // Get the functions flag
+    let generate_functions = matches.get_flag("functions");
+
+    // Get the web framework to scaffold a service for, if any
+    let scaffold = matches.get_one::<String>("scaffold");
+
+    // Get the table metadata flag
+    let generate_metadata = matches.get_flag("metadata");
+
+    // Get the sequences flag
+    let generate_sequences = matches.get_flag("sequences");
+
+    // Get the index consts flag
+    let generate_index_consts = matches.get_flag("index_consts");
+
+    // Get the default constants flag
+    let generate_default_constants = matches.get_flag("default_constants");
+
+    // Get the round trip tests flag
+    let generate_tests = matches.get_flag("with_tests");
+
+    // Get the serde round trip tests flag
+    let generate_serde_tests = matches.get_flag("serde_tests");
+
+    // Get the live tests flag
+    let generate_live_tests = matches.get_flag("live_tests");
+
+    // Get the relations flag
+    let generate_relations = matches.get_flag("relations");
+
+    // Get the repository flag, which the scaffold, the tests and the relations need
+    let generate_repository = matches.get_flag("repository")
+        || scaffold.is_some()
+        || generate_tests
+        || generate_relations;
+
+    // Get the columns the repository helpers treat by convention
+    let conventions = conventions(matches);
+
+    // Get the enum derives flag
+    let generate_enum_derives = matches.get_flag("enum_derives");
+
+    // Get the serde skip none flag
+    let serde_skip_none = matches.get_flag("serde_skip_none");
+
+    // Get the serde default flag
+    let serde_default = matches.get_flag("serde_default");
+
+    // Get the postgres derives flag, which the repository helpers need to pass enums, composite
+    // types and newtypes to postgres
+    let generate_postgres_derives = matches.get_flag("postgres_derives") || generate_repository;
+
+    // Get the function that checks xml values, if any
+    let xml_validator = matches
+        .get_one::<String>("xml_validator")
+        .map(|path| output::path(path, "--xml-validator"))
+        .transpose()?;
+
+    // Get how the code is written, which crates it uses and how it's formatted
+    let code_style = code_style(matches)?;
+    if code_style.paths.edition == output::Edition::E2015 && scaffold.is_some() {
+        return Err(Error::Config(
+            "The scaffold's handlers are async fns, which the 2015 edition doesn't have, so --scaffold can't be used with --edition 2015".to_string(),
+        ));
+    }
+    if code_style.paths.no_std {
+        for (enabled, flag) in [
+            (scaffold.is_some(), "--scaffold"),
+            (generate_tests, "--with-tests"),
+            (generate_relations, "--relations"),
+            (generate_repository, "--repository"),
+            (generate_functions, "--functions"),
+            (generate_sequences, "--sequences"),
+            (generate_postgres_derives, "--postgres-derives"),
+            (generate_live_tests, "--live-tests"),
+            (generate_sea_query, "--sea-query"),
+        ] {
+            if enabled {
+                return Err(Error::Config(format!(
+                    "The code of {} needs std, so it can't be used with --no-std",
+                    flag
+                )));
+            }
+        }
+    }
+
+    // Get the non-exhaustive flags
+    let non_exhaustive_enums = matches.get_flag("non_exhaustive_enums");
+    let non_exhaustive_structs = matches.get_flag("non_exhaustive_structs");
+
+    // Get the borrowed structs flag
+    let generate_ref_structs = matches.get_flag("ref_structs");
+
+    // Get the display flag
+    let generate_display = matches.get_flag("display");
+
+    // Get the companion files flag
+    let generate_ext_files = matches.get_flag("ext_files");
+
+    // Get the columns the tables are displayed as
+    let display_columns = display_columns(matches)?;
+
+    // Get the kind of database
+    let dialect = dialect(matches);
+
+    // Get the other formats to write the schema in, and where
+    let emits = emits(matches)?;
+
+    // The tonic scaffold implements the services of the proto file, so it needs one
+    if scaffold.is_some_and(|framework| framework == "tonic")
+        && !emits.iter().any(|(format, _)| format == "proto")
+    {
+        return Err(Error::Config(
+            "--scaffold tonic implements the services of --emit proto, add --emit proto [PATH]"
+                .to_string(),
+        ));
+    }
+
+    // Get the header written to the top of every generated file
+    let header = output::Header::new(config.header.as_deref());
+
+    // Get whether to read the database without prepared statements
+    let simple_query = matches.get_flag("simple_query");
+
+    // Get the table file mappings, from --table-file and the config file
+    let mut table_file_mappings = table_file_mappings(matches, config)?;
+
+    let mut module_defs: Vec<String> = Vec::new();
+    let mut output_file_contents: Vec<String> = Vec::new();
+    let mut table_files: BTreeMap<String, String> = BTreeMap::new();
+    let mut table_modules: BTreeMap<String, String> = BTreeMap::new();
+    let mut prelude_exports: Vec<String> = Vec::new();
+    let mut row_structs: HashMap<String, routines::RowStruct> = HashMap::new();
+    let mut scaffold_tables: Vec<scaffold::ScaffoldTable> = Vec::new();
+    let mut tests = Tests {
+        round_trip: generate_tests.then(Vec::new),
+        serde: generate_serde_tests.then(Vec::new),
+        live: generate_live_tests,
+    };
+
+    // The structs of the composite types, for the live tests
+    let mut composite_structs: Vec<composites::CompositeStruct> = Vec::new();
+
+    // Print the table file mappings, if any
+    if !table_file_mappings.is_empty() {
+        debug!("Table file mappings:");
+        for (table, file) in &table_file_mappings {
+            debug!("{} -> {}/{}.rs", table, output_directory, file);
+        }
+    }
+
+    // Connect to the database, unless the schema comes from a snapshot or a model
+    let mut client = settings.map(ConnectionSettings::connect).transpose()?;
+    let mut transaction = client.as_mut().map(read_only_transaction).transpose()?;
+
+    // Read the tables and types of the schema, from the model, the snapshot or the database
+    let SchemaSource {
+        model: schema_model,
+        resolved: resolved_model,
+        server_version,
+    } = read_schema(matches, schema, settings, transaction.as_mut())?;
+
+    // Get the checksum of the schema, which is written to the header of every file
+    let checksum = snapshot::checksum(&schema_model);
+
+    // Reject multi-dimensional arrays, if the config says so
+    if config.arrays.dimensions == Dimensions::Reject {
+        for table in &schema_model.tables {
+            for column in &table.columns {
+                if column.data_type.dimensions > 1 {
+                    return Err(Error::Config(format!(
+                        "Column {}.{} is a {}-dimensional array, which the config rejects",
+                        table.name, column.name, column.data_type.dimensions
+                    )));
+                }
+            }
+        }
+    }
+
+    // Resolve the Rust names and types of everything in the schema, unless they were read
+    let mut resolved = match resolved_model {
+        Some(resolved) => resolved,
+        None => ir::ResolvedSchema::resolve(
+            &schema_model,
+            use_uuid,
+            precise_types,
+            citext_as_string,
+            bytes_type,
+            &config.arrays,
+            &config.enums,
+        )?,
+    };
+
+    // Apply the column types, redaction, masking and struct prefix of the config file
+    let redact = redact_pattern
+        .as_ref()
+        .map(|pattern| (pattern, redact_mode));
+    apply_config(&mut resolved, config, schema, redact)?;
+
+    // Pick the tables to generate
+    select_tables(
+        matches,
+        config,
+        &mut resolved,
+        &mut table_file_mappings,
+        scaffold.is_some(),
+    )?;
+
+    // Summarize what is generated, for the report
+    let report = report::Report {
+        schemas: vec![resolved.name.clone()],
+        tables: schema_model.tables.len(),
+        structs: resolved.tables.len(),
+        enums: resolved.enums.len(),
+        fallback_columns: resolved.fallback_columns(
+            use_uuid,
+            precise_types,
+            citext_as_string,
+            bytes_type,
+            &config.arrays,
+            &config.column_types,
+        ),
+        ..Default::default()
+    };
+
+    // Fail instead of falling back to String, if requested
+    if strict && !report.fallback_columns.is_empty() {
+        return Err(Error::UnmappedTypes {
+            columns: report
+                .fallback_columns
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+        });
+    }
+
+    // Give every table its own file, unless it is mapped to one, if requested
+    if matches.get_one::<String>("layout").map(String::as_str) == Some("per-table") {
+        for table in &resolved.tables {
+            if !table_file_mappings.contains_key(&table.name) {
+                let file = table.rust_name.to_case(Case::Snake);
+                output::ident(&file, &format!("table file of {}", table.name))?;
+                table_file_mappings.insert(table.name.clone(), file);
+            }
+        }
+    }
+
+    // Private structs can't be used outside of the module they are generated in
+    if code_style.visibility == output::Visibility::Private {
+        for (split, flag) in [
+            (!table_file_mappings.is_empty(), "table files"),
+            (generate_prelude, "--prelude"),
+        ] {
+            if split {
+                return Err(Error::Config(format!(
+                    "--visibility private keeps the generated items in the output file's module, so it can't be used with {}",
+                    flag
+                )));
+            }
+        }
+    }
+
+    // Warn about display columns of tables that don't exist, which are usually typos
+    for table_name in display_columns.keys() {
+        if !resolved
+            .tables
+            .iter()
+            .any(|table| &table.name == table_name)
+        {
+            warn!("Display column table {} not found, ignoring it", table_name);
+        }
+    }
+
+    // Write the schema in the other formats that were asked for
+    let emitted = emits
+        .iter()
+        .map(|(format, path)| {
+            debug!("Emitting {} to {}", format, path);
+            (path.clone(), emit(&resolved, format))
+        })
+        .collect();
+
+    // Generate code for the schema that was read, which may differ from --schema for snapshots
+    let schema = &resolved.name;
+
+    // The types shared by every table go to a types module next to the table files, if there are
+    // any, which the output file re-exports. Otherwise they're written to the output file
+    let has_types_file = resolved
+        .tables
+        .iter()
+        .any(|table| table_file_mappings.contains_key(&table.name));
+    let mut shared_types: Vec<String> = Vec::new();
+
+    // The generated enums only implement Display when they derive it with strum, and the
+    // composite structs never do
+    let mut debug_types: HashSet<String> = resolved
+        .composites
+        .iter()
+        .map(|composite| composite.rust_name.clone())
+        .collect();
+    if !generate_enum_derives {
+        debug_types.extend(
+            resolved
+                .enums
+                .iter()
+                .map(|enum_type| enum_type.rust_name.clone()),
+        );
+    }
+
+    // Generate the enums and newtypes, which structs written to their own file refer to through
+    // the parent module
+    let mut enum_names = HashSet::new();
+    let type_definitions = generate_types(
+        &resolved,
+        generate_enum_derives,
+        generate_postgres_derives,
+        non_exhaustive_enums,
+        xml_validator.as_ref(),
+    )?;
+    for (rust_name, definition) in type_definitions {
+        shared_types.push(definition);
+        prelude_exports.push(format!("pub use super::{};", rust_name));
+        enum_names.insert(rust_name);
+    }
+
+    // Generate the structs of the composite types, which their columns and the stored functions
+    // that return them map to
+    let mut composite_names = HashSet::new();
+    for composite in &resolved.composites {
+        info!("Generating struct for composite type {}", composite.name);
+        let composite_struct =
+            composites::generate_composite(composite, generate_postgres_derives)?;
+        shared_types.push(composite_struct.definition.to_string());
+        prelude_exports.push(format!("pub use super::{};", composite.rust_name));
+        composite_names.insert(composite.rust_name.clone());
+        row_structs.insert(composite.name.clone(), composite_struct.row_struct.clone());
+        composite_structs.push(composite_struct);
+    }
+
+    // Generate the types shared by the repository helpers of every table, if requested
+    if generate_repository {
+        if let Some(update_error) = repository_types(&resolved, &conventions)? {
+            shared_types.push(update_error.to_string());
+            prelude_exports.push("pub use super::UpdateError;".to_string());
+        }
+    }
+
+    // Generate the struct of the index consts, which is shared by every table that has indexes
+    if generate_index_consts
+        && resolved
+            .tables
+            .iter()
+            .any(|table| !table.indexes.is_empty())
+    {
+        shared_types.push(indexes::generate_index_meta().to_string());
+        prelude_exports.push("pub use super::IndexMeta;".to_string());
+    }
+
+    // Generate the tables in parallel, which is most of the work for big schemas, keeping their
+    // order
+    let table_options = TableOptions {
+        config,
+        resolved: &resolved,
+        table_files: &table_file_mappings,
+        enum_names: &enum_names,
+        composite_names: &composite_names,
+        debug_types: &debug_types,
+        display_columns: &display_columns,
+        conventions: &conventions,
+        serde_default,
+        serde_skip_none,
+        non_exhaustive_structs,
+        index_consts: generate_index_consts,
+        ref_structs: generate_ref_structs,
+        display: generate_display,
+        column_enums: generate_column_enums,
+        default_constants: generate_default_constants,
+        sea_query: generate_sea_query,
+        repository: generate_repository,
+        relations: generate_relations,
+    };
+    let table_codes = resolved
+        .tables
+        .par_iter()
+        .map(|table| generate_table(table, &table_options))
+        .collect::<Result<Vec<_>>>()?;
+
+    // The files next to the output file are written to the directory of its module
+    let module_directory = format!("{}/{}", output_directory, output_file.replace(".rs", ""));
+
+    // Put every table in the file it's written to, and keep what the stored functions, the
+    // scaffold and the tests need of its struct
+    for (table, table_code) in resolved.tables.iter().zip(table_codes) {
+        // Tables mapped to a file are written to it, the others to the output file
+        let module_name = table_file_mappings.get(&table.name).map(String::as_str);
+        prelude_exports.extend(table_exports(
+            table,
+            &table_code,
+            module_name,
+            &table_options,
+        ));
+
+        let row_struct = row_struct(table, &table_code, module_name, &table_options);
+        if scaffold.is_some() && table_code.has_repository {
+            scaffold_tables.push(scaffold_table(
+                table,
+                &table_code,
+                &table_options,
+                &row_struct.path,
+            ));
+        }
+        tests.add_table(table, &table_code, &table_options, &row_struct.path);
+        row_structs.insert(table.name.clone(), row_struct);
+
+        if let Some(module_name) = module_name {
+            let file_path = format!("{}/{}.rs", module_directory, module_name);
+            debug!("Writing struct definition to {}", file_path);
+
+            // Add the struct definition to the file, which is written once every table is done
+            let contents = table_files.entry(file_path.clone()).or_default();
+            contents.push_str(&table_code.definition);
+            contents.push('\n');
+
+            // Add the file to the list of modules, once no matter how many tables share it
+            let module_def = format!("pub mod {};", module_name);
+            table_modules.insert(file_path, module_name.to_string());
+            if !module_defs.contains(&module_def) {
+                module_defs.push(module_def);
+            }
+        } else {
+            output_file_contents.push(table_code.definition);
+        }
+    }
+
+    // Generate the stored function and procedure wrappers, if requested. They aren't part of
+    // snapshots, so they need a database connection
+    if generate_functions && transaction.is_none() {
+        warn!("Stored functions aren't included in snapshots, skipping --functions");
+    }
+    if generate_functions && transaction.is_some() && dialect == introspect::Dialect::Redshift {
+        warn!("Stored functions can't be read from Redshift, skipping --functions");
+    }
+    if let (true, Some(client), Some(version)) =
+        (generate_functions, transaction.as_mut(), server_version)
+    {
+        let wrappers = generate_routines(
+            client,
+            version,
+            simple_query,
+            schema,
+            use_uuid,
+            &row_structs,
+        )?;
+        output_file_contents.extend(wrappers.definitions);
+        for struct_name in wrappers.struct_names {
+            prelude_exports.push(format!("pub use super::{};", struct_name));
+        }
+    }
+
+    // Generate the sequence helpers, if requested. Sequences aren't part of snapshots either
+    let mut sequences_code = None;
+    if generate_sequences && transaction.is_none() {
+        warn!("Sequences aren't included in snapshots, skipping --sequences");
+    }
+    if generate_sequences && transaction.is_some() && dialect == introspect::Dialect::Redshift {
+        warn!("Sequences can't be read from Redshift, skipping --sequences");
+    }
+    if let (true, Some(client)) = (generate_sequences, transaction.as_mut()) {
+        if dialect != introspect::Dialect::Redshift {
+            sequences_code = sequences::generate_sequences(client, simple_query, schema)?;
+            if sequences_code.is_none() {
+                warn!("Schema {} has no sequences, skipping --sequences", schema);
+            }
+        }
+    }
+
+    // Everything has been read from the database, end the transaction
+    transaction
+        .map(postgres::Transaction::commit)
+        .transpose()
+        .map_err(Error::query("transaction"))?;
+
+    // Everything has been read, so the connection can be closed before formatting
+    if let Some(client) = client {
+        client.close().map_err(Error::Connect)?;
+        info!("Closed PostgreSQL connection");
+    }
+
+    // The path and code of every file, which are formatted together at the end
+    let mut files = Vec::new();
+
+    // Pair every table file with a companion file for hand-written code, if requested. The table
+    // file re-exports it, and it can use everything in the table file. Both may be empty, so
+    // neither import is required to be used
+    let mut companion_files = Vec::new();
+    if generate_ext_files {
+        for (file_path, module_name) in &table_modules {
+            let ext_module = format!("{}_ext", module_name);
+            let ext_path = format!("{}_ext.rs", file_path.trim_end_matches(".rs"));
+            let contents = format!(
+                "// Hand-written code for {}.rs, which rustgres-schema creates once and never overwrites\n\
+                #[allow(unused_imports)]\n\
+                use super::{}::*;\n",
+                module_name, module_name
+            );
+            companion_files.push((ext_path, contents));
+
+            if let Some(code) = table_files.get_mut(file_path) {
+                code.push_str(&format!(
+                    "#[allow(unused_imports)]\npub use super::{}::*;\n",
+                    ext_module
+                ));
+            }
+            module_defs.push(format!("pub mod {};", ext_module));
+        }
+    }
+
+    // Write the shared types next to the table files, or else at the top of the output file
+    if has_types_file && !shared_types.is_empty() {
+        let types_path = format!("{}/types.rs", module_directory);
+        info!("Generating shared types {}", types_path);
+        files.push((types_path, shared_types.join("\n")));
+        module_defs.push("pub mod types;\npub use types::*;".to_string());
+    } else {
+        output_file_contents.splice(0..0, shared_types);
+    }
+
+    // Generate the web service, if requested, next to the table files
+    if let Some(framework) = scaffold {
+        let scaffold_path = format!("{}/api.rs", module_directory);
+        info!("Generating {} scaffold {}", framework, scaffold_path);
+        let code = scaffold_code(framework, &scaffold_tables, &resolved)?;
+        files.push((scaffold_path, code));
+        module_defs.push("pub mod api;".to_string());
+    }
+
+    // Generate the table metadata, if requested, next to the table files
+    if generate_metadata {
+        let metadata_path = format!("{}/metadata.rs", module_directory);
+        info!("Generating table metadata {}", metadata_path);
+        let code = metadata::generate_metadata(&resolved)?;
+        output::validate(&code, "table metadata")?;
+        files.push((metadata_path, code.to_string()));
+        module_defs.push("pub mod metadata;".to_string());
+    }
+
+    // Write the sequence helpers, if requested, next to the table files
+    if let Some(code) = sequences_code {
+        let sequences_path = format!("{}/sequences.rs", module_directory);
+        info!("Generating sequence helpers {}", sequences_path);
+        output::validate(&code, "sequence helpers")?;
+        files.push((sequences_path, code.to_string()));
+        module_defs.push("pub mod sequences;".to_string());
+    }
+
+    // Generate the tests, if requested, next to the table files
+    for (tests_path, code, module_def) in tests.files(
+        &module_directory,
+        &resolved,
+        &composite_structs,
+        generate_postgres_derives,
+    )? {
+        files.push((tests_path, code));
+        module_defs.push(module_def.to_string());
+    }
+
+    // If the user wants a prelude, put it next to the table files and declare it as a module
+    if generate_prelude {
+        let prelude_path = format!("{}/prelude.rs", module_directory);
+
+        // The prelude is always rewritten from scratch
+        let mut code = String::new();
+        for export in &prelude_exports {
+            code.push_str(export);
+            code.push('\n');
+        }
+        files.push((prelude_path, code));
+
+        module_defs.push("pub mod prelude;".to_string());
+    }
+
+    // Get the path of the output file
+    let output = format!("{}/{}", output_directory, output_file);
+
+    // Add the module definitions and the generated code, which is formatted with the table files.
+    // Comments aren't part of the token streams, so the header is added when writing
+    let mut code = String::new();
+    for module_def in module_defs {
+        code.push_str(&module_def);
+        code.push('\n');
+    }
+    for line in output_file_contents {
+        code.push_str(&line);
+        code.push('\n');
+    }
+    files.insert(0, (output, code));
+    for (file, code) in &table_files {
+        files.push((file.clone(), code.clone()));
+    }
+
+    // Format every file in parallel, now that every struct has been added to them
+    let files = files
+        .into_par_iter()
+        .map(|(path, code)| {
+            let contents = output::format_code(&code, &path, &code_style)?;
+            Ok((path, contents))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // Find the files of mapped tables that no longer exist, so they don't go stale
+    let stale_files = table_file_mappings
+        .values()
+        .map(|file| format!("{}/{}.rs", module_directory, file))
+        .filter(|file_path| !table_files.contains_key(file_path))
+        .collect();
+
+    // Every file is written once, so a table file can't share its path with a generated module
+    let mut paths = HashSet::new();
+    for (path, _) in files.iter().chain(&companion_files) {
+        if !paths.insert(path) {
+            return Err(Error::Config(format!(
+                "Two generated files would be written to {}, rename the table file",
+                path
+            )));
+        }
+    }
+
+    Ok(Generated {
+        files,
+        emitted,
+        companion_files,
+        stale_files,
+        header: header.with_checksum(checksum),
+        report,
+        struct_names: resolved
+            .tables
+            .iter()
+            .map(|table| table.rust_name.clone())
+            .collect(),
+        fingerprint: schema_fingerprint(&resolved),
+    })
+}
+
+/// Get the columns the repository helpers treat by convention, from the command line arguments
+fn conventions(matches: &ArgMatches) -> repository::Conventions {
+    repository::Conventions {
+        soft_delete: matches
+            .get_one::<String>("soft_delete_column")
+            .unwrap()
+            .clone(),
+        created_at: matches
+            .get_one::<String>("created_at_column")
+            .unwrap()
+            .clone(),
+        updated_at: matches
+            .get_one::<String>("updated_at_column")
+            .unwrap()
+            .clone(),
+        timestamps: match matches.get_one::<String>("timestamps").map(String::as_str) {
+            Some("database") => repository::Timestamps::Database,
+            Some("off") => repository::Timestamps::Off,
+            _ => repository::Timestamps::Now,
+        },
+        versions: matches
+            .get_one::<String>("version_columns")
+            .unwrap()
+            .split(',')
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .collect(),
+    }
+}
+
+/// Get the columns the tables are displayed as, by table name, from `--display-column`
+fn display_columns(matches: &ArgMatches) -> Result<HashMap<String, String>> {
+    let mut display_columns = HashMap::new();
+    if let Some(columns) = matches.get_one::<String>("display_column") {
+        for column in columns.split(',') {
+            let (table, column) = column.split_once('.').ok_or_else(|| {
+                Error::Config(format!(
+                    "Invalid display column {}, expected table.column",
+                    column
+                ))
+            })?;
+            display_columns.insert(table.to_string(), column.to_string());
+        }
+    }
+
+    Ok(display_columns)
+}
+
+/// Get the other formats to write the schema in, and where, from `--emit`
+///
+/// PG2RUST_EMIT holds the formats and paths in one value, separated by whitespace.
+fn emits(matches: &ArgMatches) -> Result<Vec<(String, String)>> {
+    let mut emit_values: Vec<String> = matches
+        .get_many::<String>("emit")
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect();
+    if matches.value_source("emit") == Some(ValueSource::EnvVariable) {
+        emit_values = emit_values
+            .iter()
+            .flat_map(|value| value.split_whitespace().map(str::to_string))
+            .collect();
+    }
+    if !emit_values.len().is_multiple_of(2) {
+        return Err(Error::Config(
+            "PG2RUST_EMIT must hold a format and a path for every file, e.g. `markdown docs/schema.md`".to_string(),
+        ));
+    }
+    let mut emits = Vec::new();
+    for pair in emit_values.chunks(2) {
+        let (format, path) = (&pair[0], &pair[1]);
+        if !["ir", "markdown", "mermaid", "dot", "proto", "sql"].contains(&format.as_str()) {
+            return Err(Error::Config(format!(
+                "Unknown --emit format {}, expected ir, markdown, mermaid, dot, proto or sql",
+                format
+            )));
+        }
+        emits.push((format.clone(), path.clone()));
+    }
+
+    Ok(emits)
+}
+
+/// Get the files tables are written to, by table name, from every `--table-file` and then the
+/// config file, which `--table-file` overrides
+fn table_file_mappings(matches: &ArgMatches, config: &Config) -> Result<HashMap<String, String>> {
+    let mut table_file_mappings: HashMap<String, String> = HashMap::new();
+    for table_file in matches
+        .get_many::<String>("table_file")
+        .into_iter()
+        .flatten()
+        .flat_map(|table_files| table_files.split(','))
+    {
+        let (table, file) = table_file.split_once(':').ok_or_else(|| {
+            Error::Config(
+                "Please provide a table file mapping in the format 'table:file'".to_string(),
+            )
+        })?;
+        check_table_file(table, file)?;
+
+        if table_file_mappings
+            .insert(table.to_string(), file.to_string())
+            .is_some()
+        {
+            return Err(Error::Config(format!(
+                "Table {} is mapped to more than one file",
+                table
+            )));
+        }
+    }
+
+    // Add the table files of the config file, which --table-file overrides
+    for (table, file) in &config.tables.files {
+        check_table_file(table, file)?;
+        table_file_mappings
+            .entry(table.clone())
+            .or_insert_with(|| file.clone());
+    }
+
+    Ok(table_file_mappings)
+}
+
+/// Apply the config file to the resolved schema: the Rust types it sets for columns, the columns
+/// it masks in Debug, and the prefix of the struct names of the schema. The sensitive columns
+/// `redact` matches are masked or left out before the masks apply
+fn apply_config(
+    resolved: &mut ir::ResolvedSchema,
+    config: &Config,
+    schema: &str,
+    redact: Option<(&regex::Regex, redact::RedactMode)>,
+) -> Result<()> {
+    // Use the Rust types the config file sets for specific columns
+    for (key, column_type) in &config.column_types {
+        let (table_name, column_name) = key.split_once('.').ok_or_else(|| {
+            Error::Config(format!(
+                "Invalid column type key {}, expected table.column",
+                key
+            ))
+        })?;
+        let found = resolved
+            .tables
+            .iter_mut()
+            .filter(|table| table.name == table_name)
+            .flat_map(|table| table.columns.iter_mut())
+            .find(|column| column.name == column_name);
+        match found {
+            Some(column) if column.nullable => {
+                column.rust_type = format!("Option<{}>", column_type.rust_type());
+            }
+            Some(column) => column.rust_type = column_type.rust_type().to_string(),
+            None => warn!("Column {} not found, ignoring its type", key),
+        }
+    }
+
+    // Mask or leave out the sensitive columns, if requested
+    if let Some((pattern, redact_mode)) = redact {
+        redact::apply(resolved, pattern, redact_mode);
+    }
+
+    // Mask the columns the config file sets in Debug
+    for key in &config.debug.mask {
+        let (table_name, column_name) = key.split_once('.').ok_or_else(|| {
+            Error::Config(format!(
+                "Invalid masked column {}, expected table.column",
+                key
+            ))
+        })?;
+        let found = resolved
+            .tables
+            .iter_mut()
+            .filter(|table| table.name == table_name)
+            .flat_map(|table| table.columns.iter_mut())
+            .find(|column| column.name == column_name);
+        match found {
+            Some(column) => column.masked = true,
+            None => warn!("Column {} not found, ignoring its masking", key),
+        }
+    }
+
+    // Put the prefix of the schema in front of its struct names, if it has one
+    if let Some(prefix) = config
+        .schemas
+        .get(schema)
+        .and_then(|schema_config| schema_config.prefix.as_ref())
+    {
+        output::ident(prefix, &format!("prefix of schema {}", schema))?;
+        for table in &mut resolved.tables {
+            table.rust_name = format!("{}{}", prefix, table.rust_name);
+        }
+    }
+
+    Ok(())
+}
+
+/// Leave out the tables that weren't picked, from `--root-tables`, the config file or the terminal
+/// UI of `--interactive`, with the tables they depend on if requested, and check the cargo
+/// features the tables are gated behind
+///
+/// Tables picked in the terminal UI are written to the files picked with them.
+fn select_tables(
+    matches: &ArgMatches,
+    config: &Config,
+    resolved: &mut ir::ResolvedSchema,
+    table_file_mappings: &mut HashMap<String, String>,
+    has_scaffold: bool,
+) -> Result<()> {
+    // Get the tables to generate, from --root-tables or else the config file
+    let mut included_tables = match matches.get_one::<String>("root_tables") {
+        Some(tables) => tables
+            .split(',')
+            .map(|table| table.trim().to_string())
+            .collect(),
+        None => config.tables.include.clone(),
+    };
+
+    // Pick the tables to generate and their files in a terminal UI, if requested
+    if matches.get_flag("interactive") {
+        let config_path = matches
+            .get_one::<String>("config")
+            .map(String::as_str)
+            .unwrap_or(DEFAULT_CONFIG_FILE);
+        let files = table_file_mappings.clone().into_iter().collect();
+        let selection =
+            interactive::pick_tables(&resolved.tables, &included_tables, files, config_path)?;
+        if selection.save {
+            let tables = TableConfig {
+                include: selection.tables.clone(),
+                files: selection.files.clone(),
+                features: config.tables.features.clone(),
+            };
+            save_tables(config_path, &tables)?;
+        }
+        included_tables = selection.tables;
+        *table_file_mappings = selection.files.into_iter().collect();
+    }
+
+    // Check the cargo features the tables are gated behind
+    for (table_name, feature) in &config.tables.features {
+        let is_valid = !feature.is_empty()
+            && feature
+                .chars()
+                .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '+' | '.'));
+        if !is_valid {
+            return Err(Error::Config(format!(
+                "Invalid feature {} of table {}, expected a cargo feature name",
+                feature, table_name
+            )));
+        }
+        if !resolved
+            .tables
+            .iter()
+            .any(|table| &table.name == table_name)
+        {
+            warn!("Table {} not found, ignoring its feature", table_name);
+        }
+    }
+    if has_scaffold && !config.tables.features.is_empty() {
+        return Err(Error::Config(
+            "The scaffold serves every table from one router, so --scaffold can't be used with [tables.features]".to_string(),
+        ));
+    }
+
+    // Add the tables the root tables depend on, if requested
+    if matches.get_flag("with_dependencies") {
+        included_tables = resolved.with_dependencies(&included_tables);
+    }
+
+    // Leave out the tables that weren't picked, if only some were
+    if !included_tables.is_empty() {
+        for table_name in &included_tables {
+            if !resolved
+                .tables
+                .iter()
+                .any(|table| &table.name == table_name)
+            {
+                warn!("Table {} not found, ignoring it", table_name);
+            }
+        }
+        resolved
+            .tables
+            .retain(|table| included_tables.contains(&table.name));
+    }
+
+    Ok(())
+}
+
+/// The schema a run generates code for, as it was read
+struct SchemaSource {
+    /// The tables and types of the schema
+    model: Schema,
+    /// The model read with `--from-ir`, whose Rust names and types may have been edited
+    resolved: Option<ir::ResolvedSchema>,
+    /// The version of the server, when the schema was read from PostgreSQL
+    server_version: Option<ServerVersion>,
+}
+
+/// Get the kind of database the schema is read from
+fn dialect(matches: &ArgMatches) -> introspect::Dialect {
+    match matches
+        .get_one::<String>("dialect")
+        .expect("Dialect must be set")
+        .as_str()
+    {
+        "redshift" => introspect::Dialect::Redshift,
+        _ => introspect::Dialect::Postgres,
+    }
+}
+
+/// Start the transaction everything is read in, which is read-only and repeatable-read, so schema
+/// changes made while running can't mix old and new definitions
+fn read_only_transaction(client: &mut postgres::Client) -> Result<postgres::Transaction<'_>> {
+    client
+        .build_transaction()
+        .isolation_level(IsolationLevel::RepeatableRead)
+        .read_only(true)
+        .start()
+        .map_err(Error::query("transaction"))
+}
+
+/// Read the tables and types of a schema, from the model, the snapshot or the database, as the
+/// arguments say
+///
+/// `transaction` reads the database, and is `None` when the schema comes from a snapshot or a
+/// model. The view columns known to be NOT NULL are marked, and the schema is saved to a snapshot,
+/// if requested.
+fn read_schema(
+    matches: &ArgMatches,
+    schema: &str,
+    settings: Option<&ConnectionSettings>,
+    mut transaction: Option<&mut postgres::Transaction>,
+) -> Result<SchemaSource> {
+    // Get the introspection source
+    let introspection = match matches
+        .get_one::<String>("introspection")
+        .expect("Introspection must be set")
+        .as_str()
+    {
+        "information-schema" => introspect::Introspection::InformationSchema,
+        _ => introspect::Introspection::PgCatalog,
+    };
+
+    // Get the include views flag
+    let include_views = matches.get_flag("include_views");
+
+    // Get whether to read the schema without prepared statements
+    let simple_query = matches.get_flag("simple_query");
+
+    // Get whether to reuse the schema of an earlier run, and whether to read it again anyway
+    let use_cache = matches.get_flag("cached");
+    let refresh_cache = matches.get_flag("refresh");
+
+    // Get the view columns that are known to be NOT NULL
+    let mut view_not_null = Vec::new();
+    if let Some(columns) = matches.get_one::<String>("view_not_null") {
+        for column in columns.split(',') {
+            let (view, column) = column.split_once('.').ok_or_else(|| {
+                Error::Config(format!(
+                    "Invalid view column {}, expected view.column",
+                    column
+                ))
+            })?;
+            view_not_null.push((view.to_string(), column.to_string()));
+        }
+    }
+
+    // Read the model to generate from, if any. Its Rust names and types may have been edited
+    let resolved = matches
+        .get_one::<String>("from_ir")
+        .map(|path| ir::read_ir(path))
+        .transpose()?;
+
+    // Detect the server version, so only the catalog queries it supports are used. Redshift
+    // reports the version it forked from, so it is read the same way regardless
+    let server_version = match (transaction.as_mut(), dialect(matches)) {
+        (Some(client), introspect::Dialect::Postgres) => {
+            Some(ServerVersion::detect(*client, simple_query)?)
+        }
+        _ => None,
+    };
+
+    // Read the tables and types of the schema, from the model, the snapshot or the database
+    let mut model = match (
+        &resolved,
+        matches.get_one::<String>("from_snapshot"),
+        transaction,
+        server_version,
+    ) {
+        (Some(resolved), _, _, _) => resolved.to_schema(),
+        (None, Some(path), _, _) => snapshot::read_snapshot(path)?,
+        (None, None, Some(client), Some(version)) => {
+            // Reuse the schema of an earlier run while the catalogs are unchanged, if requested
+            let cache_key = match (use_cache, settings) {
+                (true, Some(settings)) => Some(cache::key(
+                    client,
+                    simple_query,
+                    settings,
+                    schema,
+                    version,
+                    introspection,
+                    include_views,
+                )?),
+                _ => None,
+            };
+            let cached = cache_key
+                .as_ref()
+                .filter(|_| !refresh_cache)
+                .and_then(cache::read);
+            match cached {
+                Some(model) => model,
+                None => {
+                    let model = introspect::introspect(
+                        client,
+                        simple_query,
+                        schema,
+                        introspection,
+                        version,
+                        include_views,
+                    )?;
+                    if let Some(key) = &cache_key {
+                        cache::write(key, &model);
+                    }
+                    model
+                }
+            }
+        }
+        (None, None, Some(client), None) => {
+            if use_cache {
+                warn!("Redshift doesn't report a server version to key the cache by, skipping --cached");
+            }
+            introspect::introspect_redshift(client, simple_query, schema, include_views)?
+        }
+        (None, None, None, _) => {
+            unreachable!("A client is connected whenever there is no snapshot or model")
+        }
+    };
+
+    // Mark the view columns the user knows can't be NULL
+    for (view, column) in &view_not_null {
+        let found = model
+            .tables
+            .iter_mut()
+            .filter(|table| &table.name == view && table.kind != TableKind::Table)
+            .flat_map(|table| table.columns.iter_mut())
+            .find(|model_column| &model_column.name == column);
+        match found {
+            Some(model_column) => model_column.nullable = false,
+            None => warn!("View column {}.{} not found, ignoring it", view, column),
+        }
+    }
+
+    // Save the schema to a snapshot, if requested
+    if let Some(path) = matches.get_one::<String>("snapshot") {
+        snapshot::write_snapshot(path, &model)?;
+    }
+
+    Ok(SchemaSource {
+        model,
+        resolved,
+        server_version,
+    })
+}
+
+/// Write the schema in another format, `ir` for the resolved model as JSON
+fn emit(resolved: &ir::ResolvedSchema, format: &str) -> String {
+    match format {
+        "markdown" => docs::markdown(resolved),
+        "mermaid" => diagram::mermaid(resolved),
+        "dot" => diagram::dot(resolved),
+        "proto" => proto::proto(resolved),
+        "sql" => ddl::schema_sql(&resolved.to_schema()),
+        _ => {
+            serde_json::to_string_pretty(resolved).expect("The model is always serializable") + "\n"
+        }
+    }
+}
+
+/// Generate the enum types and the newtypes of the types columns use, like regclass or xml, which
+/// are shared by every table, paired with their Rust names
+fn generate_types(
+    resolved: &ir::ResolvedSchema,
+    enum_derives: bool,
+    postgres_derives: bool,
+    non_exhaustive_enums: bool,
+    xml_validator: Option<&syn::Path>,
+) -> Result<Vec<(String, String)>> {
+    let mut definitions = Vec::new();
+    for enum_type in &resolved.enums {
+        info!("Generating enum for type {}", enum_type.name);
+        let definition = enums::generate_enum(
+            enum_type,
+            enum_derives,
+            postgres_derives,
+            non_exhaustive_enums,
+        )?;
+        definitions.push((enum_type.rust_name.clone(), definition.to_string()));
+    }
+
+    for newtype in newtypes::NEWTYPES {
+        let is_used = resolved.tables.iter().any(|table| {
+            table.columns.iter().any(|column| {
+                column.data_type.name == newtype.name
+                    && column.rust_type.contains(newtype.rust_name)
+            })
+        });
+        if !is_used {
+            continue;
+        }
+
+        info!("Generating newtype for type {}", newtype.name);
+        let definition = newtypes::generate_newtype(newtype, postgres_derives, xml_validator);
+        definitions.push((newtype.rust_name.to_string(), definition.to_string()));
+    }
+
+    Ok(definitions)
+}
+
+/// Check that the repository helpers can read and write every column of a schema, and generate
+/// the error of updating versioned rows, which every table that has them shares
+///
+/// Returns `None` when no table is versioned.
+fn repository_types(
+    resolved: &ir::ResolvedSchema,
+    conventions: &repository::Conventions,
+) -> Result<Option<TokenStream>> {
+    check_array_dimensions(resolved)?;
+
+    let has_versions = resolved
+        .tables
+        .iter()
+        .any(|table| !table.columns.is_empty() && repository::is_versioned(table, conventions));
+    Ok(has_versions.then(repository::generate_update_error))
+}
+
+/// Check that no column is an array of more than one dimension, since the postgres crate only
+/// decodes one-dimensional arrays, so the repository helpers can't read or write the others,
+/// nested or flattened
+fn check_array_dimensions(resolved: &ir::ResolvedSchema) -> Result<()> {
+    for table in &resolved.tables {
+        for column in &table.columns {
+            if column.data_type.dimensions > 1 {
+                return Err(Error::Config(format!(
+                    "Column {}.{} is a {}-dimensional array, which the repository helpers can't read or write, since postgres only decodes one-dimensional arrays. Leave it out with @rust(skip) in its comment, or generate without the repository helpers",
+                    table.name, column.name, column.data_type.dimensions
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Get the exports of a table for the prelude, which are gated behind its cargo feature like it
+/// is, if it has one
+fn table_exports(
+    table: &ir::ResolvedTable,
+    table_code: &TableCode,
+    module_name: Option<&str>,
+    options: &TableOptions,
+) -> Vec<String> {
+    let cfg_attribute = |table_name: &str| match options.config.tables.features.get(table_name) {
+        Some(feature) => format!("#[cfg(feature = \"{}\")] ", feature),
+        None => String::new(),
+    };
+    let export_path = |name: &str| match module_name {
+        Some(module_name) => format!("super::{}::{}", module_name, name),
+        None => format!("super::{}", name),
+    };
+
+    // Re-exporting a deprecated struct is fine too
+    let allow_deprecated = if table.deprecated.is_some() {
+        "#[allow(deprecated)] "
+    } else {
+        ""
+    };
+
+    let mut exports = vec![format!(
+        "{}pub use {};",
+        allow_deprecated,
+        export_path(&table_code.struct_name)
+    )];
+    if let Some(ref_struct_name) = &table_code.ref_struct_name {
+        exports.push(format!("pub use {};", export_path(ref_struct_name)));
+    }
+    for name in [
+        &table_code.insert_struct_name,
+        &table_code.patch_struct_name,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        exports.push(format!(
+            "{}pub use {};",
+            allow_deprecated,
+            export_path(name)
+        ));
+    }
+    for (relation_name, child) in &table_code.relation_names {
+        exports.push(format!(
+            "{}pub use {};",
+            cfg_attribute(child),
+            export_path(relation_name)
+        ));
+    }
+    if options.column_enums {
+        exports.push(format!(
+            "pub use {};",
+            export_path(&table_code.column_enum_name)
+        ));
+    }
+    if options.sea_query {
+        exports.push(format!(
+            "pub use {};",
+            export_path(&table_code.iden_enum_name)
+        ));
+    }
+    if let Some(defaults_module) = &table_code.defaults_module {
+        exports.push(format!("pub use {};", export_path(defaults_module)));
+    }
+    for export in &mut exports {
+        export.insert_str(0, &cfg_attribute(&table.name));
+    }
+
+    exports
+}
+
+/// The wrappers of the stored functions and procedures of a schema
+struct Routines {
+    /// The wrappers, which go to the output file
+    definitions: Vec<String>,
+    /// The structs the wrappers return, for the prelude
+    struct_names: Vec<String>,
+}
+
+/// Generate the wrappers of the stored functions of a schema, and of its procedures when the
+/// server has them
+fn generate_routines(
+    client: &mut postgres::Transaction,
+    version: ServerVersion,
+    simple_query: bool,
+    schema: &str,
+    use_uuid: bool,
+    row_structs: &HashMap<String, routines::RowStruct>,
+) -> Result<Routines> {
+    let mut struct_names = Vec::new();
+    let functions = routines::generate_functions(
+        client,
+        simple_query,
+        schema,
+        use_uuid,
+        row_structs,
+        &mut struct_names,
+    )?;
+    let mut definitions: Vec<String> = functions.iter().map(ToString::to_string).collect();
+
+    // Procedures were added in PostgreSQL 11
+    if version.has_procedures() {
+        let procedures = routines::generate_procedures(
+            client,
+            simple_query,
+            schema,
+            use_uuid,
+            &mut struct_names,
+        )?;
+        definitions.extend(procedures.iter().map(ToString::to_string));
+    } else {
+        warn!(
+            "PostgreSQL {} doesn't have stored procedures, skipping them",
+            version
+        );
+    }
+
+    Ok(Routines {
+        definitions,
+        struct_names,
+    })
+}
+
+/// What is generated for every table of a schema, and the shared state it's generated from
+struct TableOptions<'a> {
+    /// The config file
+    config: &'a Config,
+    /// The resolved schema the tables belong to
+    resolved: &'a ir::ResolvedSchema,
+    /// The files of the tables that are written to their own file, by table name
+    table_files: &'a HashMap<String, String>,
+    /// The names of the generated enums and newtypes
+    enum_names: &'a HashSet<String>,
+    /// The names of the generated composite structs
+    composite_names: &'a HashSet<String>,
+    /// The generated enums and composite structs that don't implement Display
+    debug_types: &'a HashSet<String>,
+    /// The columns the tables are displayed as, by table name
+    display_columns: &'a HashMap<String, String>,
+    /// The columns the repository helpers treat by convention
+    conventions: &'a repository::Conventions,
+    serde_default: bool,
+    serde_skip_none: bool,
+    non_exhaustive_structs: bool,
+    index_consts: bool,
+    ref_structs: bool,
+    display: bool,
+    column_enums: bool,
+    default_constants: bool,
+    sea_query: bool,
+    repository: bool,
+    relations: bool,
+}
+
+/// The names and fields of the struct of a table, which everything generated with the struct
+/// shares
+struct TableContext {
+    /// The name of the struct
+    struct_name: TokenStream,
+    /// How the struct refers to the structs of other tables, through the parent module when it's
+    /// written to its own file
+    parent_prefix: TokenStream,
+    /// How the struct refers to the shared types, through the types module when it's written to
+    /// its own file
+    type_prefix: TokenStream,
+    /// The fields of the struct, with how they're read from rows and passed as query parameters
+    fields: Vec<repository::RepositoryField>,
+}
+
+impl TableContext {
+    /// Get the names and fields of the struct of a table
+    fn new(table: &ir::ResolvedTable, options: &TableOptions) -> Result<TableContext> {
+        let struct_name = output::ident(&table.rust_name, &format!("table {}", table.name))?;
+        let (parent_prefix, type_prefix) = if options.table_files.contains_key(&table.name) {
+            (quote! { super:: }, quote! { super::types:: })
+        } else {
+            (quote! {}, quote! {})
+        };
+        let fields = table_fields(table, options, &type_prefix)?;
+
+        Ok(TableContext {
+            struct_name: quote!(#struct_name),
+            parent_prefix,
+            type_prefix,
+            fields,
+        })
+    }
+}
+
+/// Generate the code of a table, which only reads shared state so tables can be generated in
+/// parallel. Token streams can't be sent between threads, so the code and the fields of the
+/// struct are kept as strings
+fn generate_table(table: &ir::ResolvedTable, options: &TableOptions) -> Result<TableCode> {
+    let table_name = &table.name;
+    info!("Generating schema for table {}", table_name);
+    let context = TableContext::new(table, options)?;
+    let struct_name = &context.struct_name;
+
+    // Generate the Rust struct
+    let mut struct_definition = table_struct(table, &context, options)?;
+
+    // Whether the struct or any of its fields is deprecated
+    let is_deprecated = table.deprecated.is_some()
+        || table
+            .columns
+            .iter()
+            .any(|column| column.deprecated.is_some());
+
+    // Keep the indexes at hand too, if requested
+    if options.index_consts && !table.indexes.is_empty() {
+        struct_definition.extend(indexes::generate_indexes_const(
+            table,
+            struct_name,
+            &context.type_prefix,
+        ));
+    }
+
+    // Generate the borrowed struct, if requested and there is anything to borrow
+    let mut ref_struct_name = None;
+    if options.ref_structs {
+        let ref_fields = ref_fields(table, &context);
+        if let Some(ref_struct) =
+            refs::generate_ref_struct(struct_name, &ref_fields, options.enum_names)
+        {
+            struct_definition.extend(ref_struct);
+            ref_struct_name = Some(refs::ref_ident(struct_name));
+        }
+    }
+
+    // Generate the Display impl, if requested
+    if options.display {
+        let display_column = options.display_columns.get(table_name).map(String::as_str);
+        if let Some(display_impl) =
+            display::generate_display(table, struct_name, display_column, options.debug_types)?
+        {
+            struct_definition.extend(display_impl);
+        }
+    }
+
+    // Generate the column enum, if requested
+    let column_variants = column_variants(table)?;
+    let column_enum_name = Ident::new(&format!("{}Column", struct_name), Span::call_site());
+    if options.column_enums {
+        struct_definition.extend(column_enum(&column_enum_name, &column_variants));
+    }
+
+    // Generate the constants of the column defaults, if requested
+    let mut defaults_module = None;
+    if options.default_constants {
+        if let Some(defaults) = defaults::generate_defaults(table)? {
+            struct_definition.extend(defaults);
+            defaults_module = Some(defaults::module_name(table));
+        }
+    }
+
+    // Generate the sea-query Iden enum, if requested
+    let iden_enum_name = Ident::new(&format!("{}Iden", struct_name), Span::call_site());
+    if options.sea_query {
+        struct_definition.extend(iden_enum(&iden_enum_name, table_name, &column_variants));
+    }
+
+    // Generate the repository helpers, if requested and the table has any columns
+    let repository = table_repository(table, &context, options);
+    let has_repository = repository.is_some();
+    struct_definition.extend(repository);
+
+    // Generate the structs and helpers that load the rows referencing the table, if requested
+    let mut relation_names = Vec::new();
+    if has_repository && options.relations {
+        let (relations, names) = table_relations(table, &context, options)?;
+        struct_definition.extend(relations);
+        relation_names = names;
+    }
+
+    // Make sure the table generated valid Rust before it's written anywhere
+    output::validate(&struct_definition, &format!("table {}", table_name))?;
+
+    // Gate the table behind its cargo feature, if it has one
+    if let Some(feature) = options.config.tables.features.get(table_name) {
+        struct_definition =
+            output::cfg_feature(struct_definition, feature, &format!("table {}", table_name))?;
+    }
+
+    // The generated impls use the deprecated struct and fields themselves, which is fine
+    if is_deprecated {
+        struct_definition =
+            output::allow_deprecated(struct_definition, &format!("table {}", table_name))?;
+    }
+
+    let insert_struct_name = (has_repository && table.kind == TableKind::Table)
+        .then(|| repository::insert_struct_name(table).to_string());
+    let patch_struct_name = (has_repository
+        && !repository::patched_columns(table, options.conventions).is_empty())
+    .then(|| repository::patch_struct_name(table).to_string());
+
+    Ok(TableCode {
+        definition: struct_definition.to_string(),
+        struct_name: struct_name.to_string(),
+        fields: context.fields.iter().map(FieldCode::new).collect(),
+        deprecated: is_deprecated,
+        ref_struct_name: ref_struct_name.map(|name| name.to_string()),
+        insert_struct_name,
+        patch_struct_name,
+        relation_names,
+        column_enum_name: column_enum_name.to_string(),
+        iden_enum_name: iden_enum_name.to_string(),
+        defaults_module,
+        has_repository,
+    })
+}
+
+/// Generate the struct of a table, with its Debug impl when fields are masked, and the query of
+/// views
+fn table_struct(
+    table: &ir::ResolvedTable,
+    context: &TableContext,
+    options: &TableOptions,
+) -> Result<TokenStream> {
+    let table_name = &table.name;
+    let struct_name = &context.struct_name;
+
+    // Set up the fields for the Rust struct
+    let mut fields = Vec::new();
+
+    // Set up the fields of the Debug impl, paired with whether they're redacted
+    let mut debug_fields = Vec::new();
+
+    // Set up the traits every field implements, to know what the struct can derive
+    let mut field_traits = types::Traits {
+        copy: true,
+        eq: true,
+        hash: true,
+        partial_ord: true,
+    };
+
+    // For each column, generate the Rust struct field
+    for (column, repository_field) in table.columns.iter().zip(&context.fields) {
+        debug!("Generating schema for column {}", column.name);
+        let context = format!("column {}.{}", table_name, column.name);
+        let rust_type: syn::Type =
+            syn::parse_str(&column.rust_type).map_err(|source| Error::InvalidCode {
+                context: format!("{} as `{}`", context, column.rust_type),
+                source,
+            })?;
+        field_traits = field_traits.and(types::traits(
+            &rust_type,
+            options.enum_names,
+            options.composite_names,
+        ));
+        let rust_type = &repository_field.rust_type;
+
+        // Get the Rust field name
+        let column_name = repository_field.name.clone();
+        debug_fields.push((column_name.clone(), column.redacted || column.masked));
+        let column_name = quote!(#column_name);
+
+        // Get the serde options of the field, if requested. Columns the database can fill in
+        // may be left out, except enums and composite types which have no default
+        let mut serde_options = Vec::new();
+        let has_default =
+            column.nullable || column.default.is_some() || column.identity || column.generated;
+        let has_no_default = options.enum_names.contains(&column.rust_type)
+            || options.composite_names.contains(&column.rust_type);
+        if options.serde_default && has_default && !has_no_default {
+            serde_options.push(quote! { default });
+        }
+        let column_type = options
+            .config
+            .column_types
+            .get(&format!("{}.{}", table_name, column.name));
+        if let Some(with) = column_type.and_then(ColumnType::with) {
+            serde_options.push(quote! { with = #with });
+        }
+        if column.redacted {
+            serde_options.push(quote! { skip_serializing });
+        } else if options.serde_skip_none && column.rust_type.starts_with("Option<") {
+            serde_options.push(quote! { skip_serializing_if = "Option::is_none" });
+        }
+        let serde_attribute = if serde_options.is_empty() {
+            quote! {}
+        } else {
+            quote! { #[serde(#(#serde_options),*)] }
+        };
+
+        // Add the field to the fields vector
+        // Mark the field as deprecated, if its comment says so
+        let deprecated = output::deprecated(column.deprecated.as_deref());
+
+        fields.push(quote! {
+            #deprecated
+            #serde_attribute
+            pub #column_name: #rust_type,
+        });
+    }
+
+    // Whether the struct is deprecated
+    let struct_deprecated = output::deprecated(table.deprecated.as_deref());
+
+    // Get the extra derives of the struct
+    let mut derives = Vec::new();
+    let mut derived = HashSet::new();
+    for derive in &table.derives {
+        let path: syn::Path = syn::parse_str(derive).map_err(|source| Error::InvalidCode {
+            context: format!("derive `{}` of table {}", derive, table_name),
+            source,
+        })?;
+        derived.extend(
+            path.segments
+                .last()
+                .map(|segment| segment.ident.to_string()),
+        );
+        derives.push(quote! { #path, });
+    }
+
+    // Get the derives every field allows, unless the table opts out of them. Eq is only left
+    // out for fields that don't implement it, like floats
+    let auto_derives = [
+        ("Copy", field_traits.copy && !table.no_auto_derive),
+        ("Eq", field_traits.eq),
+        (
+            "PartialOrd",
+            field_traits.partial_ord && !table.no_auto_derive,
+        ),
+        ("Hash", field_traits.hash && !table.no_auto_derive),
+    ];
+    let mut auto_derives: Vec<Ident> = auto_derives
+        .into_iter()
+        .filter(|(derive, allowed)| *allowed && !derived.contains(*derive))
+        .map(|(derive, _)| Ident::new(derive, Span::call_site()))
+        .collect();
+    let copy_derive = match auto_derives.first() {
+        Some(derive) if derive == "Copy" => {
+            let derive = auto_derives.remove(0);
+            quote! { #derive, }
+        }
+        _ => quote! {},
+    };
+
+    // Mask the redacted and masked fields in Debug, instead of deriving it
+    let (debug_derive, debug_impl) = if debug_fields.iter().any(|(_, masked)| *masked) {
+        let name = struct_name.to_string();
+        (
+            quote! {},
+            redact::generate_debug(struct_name.clone(), &name, &debug_fields),
+        )
+    } else {
+        (quote! { Debug, }, quote! {})
+    };
+
+    // Let other crates keep compiling when columns are added, if requested
+    let struct_non_exhaustive = if options.non_exhaustive_structs {
+        quote! { #[non_exhaustive] }
+    } else {
+        quote! {}
+    };
+
+    // Document views with the query that defines them
+    let mut struct_doc = Vec::new();
+    if let Some(definition) = &table.definition {
+        let kind = match table.kind {
+            TableKind::MaterializedView => "materialized view",
+            _ => "view",
+        };
+        struct_doc.push(format!(" The `{}` {}, defined as:", table_name, kind));
+        struct_doc.push(String::new());
+        struct_doc.push(" ```sql".to_string());
+        struct_doc.extend(
+            definition
+                .lines()
+                .map(|line| format!(" {}", line.trim_end())),
+        );
+        struct_doc.push(" ```".to_string());
+    }
+
+    // List the indexes, so queries can be written against them without opening psql
+    if !table.indexes.is_empty() {
+        if !struct_doc.is_empty() {
+            struct_doc.push(String::new());
+        }
+        struct_doc.push(" Indexes:".to_string());
+        struct_doc.push(String::new());
+        struct_doc.extend(
+            table
+                .indexes
+                .iter()
+                .map(|index| format!(" - {}", indexes::describe(index))),
+        );
+    }
+
+    // Generate the struct definition
+    let mut struct_definition = quote! {
+        #(#[doc = #struct_doc])*
+        #[derive(
+            #debug_derive Clone, #copy_derive PartialEq, #(#auto_derives,)* serde::Serialize,
+            serde::Deserialize,
+            #(#derives)*
+        )]
+        #struct_deprecated
+        #struct_non_exhaustive
+        pub struct #struct_name {
+            #(#fields)*
+        }
+
+        #debug_impl
+    };
+
+    // Generate the struct of the rows to insert, without the columns the database fills in, for
+    // the repository helpers
+    if options.repository && table.kind == TableKind::Table && !fields.is_empty() {
+        let inserted: Vec<usize> = table
+            .columns
+            .iter()
+            .enumerate()
+            .filter(|(_, column)| repository::is_inserted(column, options.conventions))
+            .map(|(index, _)| index)
+            .collect();
+        let insert_struct = repository::insert_struct_name(table);
+        let insert_fields = inserted.iter().map(|index| &fields[*index]);
+        let insert_debug_fields: Vec<(Ident, bool)> = inserted
+            .iter()
+            .map(|index| debug_fields[*index].clone())
+            .collect();
+        let (debug_derive, debug_impl) = if insert_debug_fields.iter().any(|(_, masked)| *masked) {
+            (
+                quote! {},
+                redact::generate_debug(
+                    quote!(#insert_struct),
+                    &insert_struct.to_string(),
+                    &insert_debug_fields,
+                ),
+            )
+        } else {
+            (quote! { Debug, }, quote! {})
+        };
+        let insert_doc = format!(
+            " A row to insert into `{}`, without the columns the database fills in",
+            table_name
+        );
+        struct_definition.extend(quote! {
+            #[doc = #insert_doc]
+            #[derive(#debug_derive Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+            #struct_deprecated
+            pub struct #insert_struct {
+                #(#insert_fields)*
+            }
+
+            #debug_impl
+        });
+    }
+
+    // Generate the struct of the changes to make to a row, with every column `update` sets from
+    // the struct, for the repository helpers of tables with a primary key
+    let patched = repository::patched_columns(table, options.conventions);
+    if options.repository && !patched.is_empty() {
+        let patch_struct = repository::patch_struct_name(table);
+        let patch_fields = patched.iter().map(|index| {
+            let field = &context.fields[*index];
+            let name = &field.name;
+            let rust_type = &field.rust_type;
+            let deprecated = output::deprecated(table.columns[*index].deprecated.as_deref());
+            quote! {
+                #deprecated
+                pub #name: Option<#rust_type>,
+            }
+        });
+        let patch_debug_fields: Vec<(Ident, bool)> = patched
+            .iter()
+            .map(|index| debug_fields[*index].clone())
+            .collect();
+        let (debug_derive, debug_impl) = if patch_debug_fields.iter().any(|(_, masked)| *masked) {
+            (
+                quote! {},
+                redact::generate_debug(
+                    quote!(#patch_struct),
+                    &patch_struct.to_string(),
+                    &patch_debug_fields,
+                ),
+            )
+        } else {
+            (quote! { Debug, }, quote! {})
+        };
+        let patch_doc = format!(
+            " Changes to make to a row of `{}`, leaving the fields that are `None` as they are",
+            table_name
+        );
+        struct_definition.extend(quote! {
+            #[doc = #patch_doc]
+            #[derive(#debug_derive Clone, Default, PartialEq)]
+            #struct_deprecated
+            pub struct #patch_struct {
+                #(#patch_fields)*
+            }
+
+            #debug_impl
+        });
+    }
+
+    // Keep the query of views at hand too
+    if let Some(definition) = &table.definition {
+        struct_definition.extend(quote! {
+            impl #struct_name {
+                /// The query that defines the view
+                pub const DEFINITION: &'static str = #definition;
+            }
+        });
+    }
+
+    Ok(struct_definition)
+}
+
+/// Get the fields of the borrowed struct of a table
+fn ref_fields(table: &ir::ResolvedTable, context: &TableContext) -> Vec<refs::RefField> {
+    table
+        .columns
+        .iter()
+        .zip(&context.fields)
+        .map(|(column, field)| refs::RefField {
+            name: field.name.clone(),
+            rust_type: field.rust_type.clone(),
+            redacted: column.redacted,
+            masked: column.redacted || column.masked,
+        })
+        .collect()
+}
+
+/// Get the variants of the column enums of a table, paired with the names of the columns
+fn column_variants(table: &ir::ResolvedTable) -> Result<Vec<(Ident, String)>> {
+    table
+        .columns
+        .iter()
+        .map(|column| {
+            let context = format!("column {}.{}", table.name, column.name);
+            let variant = output::ident(&column.name.to_case(Case::Pascal), &context)?;
+            Ok((variant, column.name.clone()))
+        })
+        .collect()
+}
+
+/// Generate the sea-query `Iden` enum of a table, from the variants of its columns paired with
+/// their names
+fn iden_enum(name: &Ident, table_name: &str, column_variants: &[(Ident, String)]) -> TokenStream {
+    let variants = column_variants.iter().map(|(variant, column_name)| {
+        // The Table variant is reserved by sea-query for the table name itself
+        let variant = if variant == "Table" {
+            Ident::new("TableColumn", Span::call_site())
+        } else {
+            variant.clone()
+        };
+
+        // Only spell out the name when the derived snake case name would not match
+        if variant.to_string().to_case(Case::Snake) == *column_name {
+            quote! { #variant, }
+        } else {
+            quote! {
+                #[iden = #column_name]
+                #variant,
+            }
+        }
+    });
+
+    quote! {
+        #[derive(Debug, Clone, Copy, sea_query::Iden)]
+        #[iden = #table_name]
+        pub enum #name {
+            Table,
+            #(#variants)*
+        }
+    }
+}
+
+/// Generate the repository helpers of a table, if requested
+///
+/// Returns `None` when there are no helpers, like for tables without columns.
+fn table_repository(
+    table: &ir::ResolvedTable,
+    context: &TableContext,
+    options: &TableOptions,
+) -> Option<TokenStream> {
+    if !options.repository {
+        return None;
+    }
+    repository::generate_repository(
+        table,
+        &options.resolved.name,
+        &context.struct_name,
+        &context.fields,
+        options.conventions,
+        &context.type_prefix,
+    )
+}
+
+/// Generate the structs and helpers that load the rows referencing a table, with the names of
+/// the structs paired with the tables of their rows
+fn table_relations(
+    table: &ir::ResolvedTable,
+    context: &TableContext,
+    options: &TableOptions,
+) -> Result<(TokenStream, Vec<(String, String)>)> {
+    let parent_prefix = &context.parent_prefix;
+    let child_path = |child: &ir::ResolvedTable| {
+        let child_name = Ident::new(&child.rust_name, Span::call_site());
+        match options.table_files.get(&child.name) {
+            Some(module_name) => {
+                let module_name = Ident::new(module_name, Span::call_site());
+                quote! { #parent_prefix #module_name::#child_name }
+            }
+            None => quote! { #parent_prefix #child_name },
+        }
+    };
+    let child_cfg =
+        |child: &ir::ResolvedTable| match options.config.tables.features.get(&child.name) {
+            Some(feature) => quote! { #[cfg(feature = #feature)] },
+            None => quote! {},
+        };
+    let (relations, names) = relations::generate_relations(
+        table,
+        options.resolved,
+        &context.struct_name,
+        &context.fields,
+        options.conventions,
+        child_path,
+        child_cfg,
+    )?;
+    let names = names
+        .into_iter()
+        .map(|(relation_name, child)| (relation_name, child.name.clone()))
+        .collect();
+
+    Ok((relations, names))
+}
+
+/// Get the struct of a table as the stored functions that return its rows read it, from the
+/// output file, where it is in the module of `module_name` when the table is written to one
+fn row_struct(
+    table: &ir::ResolvedTable,
+    table_code: &TableCode,
+    module_name: Option<&str>,
+    options: &TableOptions,
+) -> routines::RowStruct {
+    let struct_name = table_code.struct_ident();
+    let path = match module_name {
+        Some(module_name) => {
+            let module_ident = Ident::new(module_name, Span::call_site());
+            quote!(#module_ident::#struct_name)
+        }
+        None => quote!(#struct_name),
+    };
+
+    routines::RowStruct {
+        path,
+        fields: table_code
+            .fields
+            .iter()
+            .map(|field| (field.name(), field.read()))
+            .collect(),
+        deprecated: table_code.deprecated,
+        feature: options.config.tables.features.get(&table.name).cloned(),
+    }
+}
+
+/// Get how the scaffold serves a table, whose struct is at `path` from the output file
+fn scaffold_table(
+    table: &ir::ResolvedTable,
+    table_code: &TableCode,
+    options: &TableOptions,
+    path: &TokenStream,
+) -> scaffold::ScaffoldTable {
+    let key = repository::key_indices(table)
+        .into_iter()
+        .map(|index| {
+            let field = &table_code.fields[index];
+            let rust_type = qualify_types(field.rust_type(), options, &quote! { super:: });
+            (field.name(), rust_type)
+        })
+        .collect();
+
+    scaffold::ScaffoldTable {
+        name: table.name.clone(),
+        path: quote! { super::#path },
+        handler_name: table_code.struct_name.to_case(Case::Snake),
+        key,
+        insert: table.kind == TableKind::Table,
+        update: table
+            .columns
+            .iter()
+            .any(|column| repository::is_updated(table, column)),
+        versioned: repository::is_versioned(table, options.conventions),
+        deprecated: table_code.deprecated,
+        message: proto::message_name(table),
+        fields: table_code
+            .fields
+            .iter()
+            .zip(&table.columns)
+            .map(|(field, column)| (field.name(), proto::proto_field(&column.rust_type)))
+            .collect(),
+    }
+}
+
+/// Generate the web service of `--scaffold`, which serves the tables with repository helpers
+fn scaffold_code(
+    framework: &str,
+    tables: &[scaffold::ScaffoldTable],
+    resolved: &ir::ResolvedSchema,
+) -> Result<String> {
+    let code = match framework {
+        "tonic" => scaffold::generate_tonic(tables, &proto::package(resolved)),
+        _ => scaffold::generate_axum(tables),
+    };
+    output::validate(&code, &format!("{} scaffold", framework))?;
+
+    Ok(code.to_string())
+}
+
+/// The tests generated next to the table files, with the tables they test
+struct Tests {
+    /// The tables of the round trip tests against the database, with `--with-tests`
+    round_trip: Option<Vec<roundtrip::RoundTripTable>>,
+    /// The tables of the serde round trip tests, with `--serde-tests`
+    serde: Option<Vec<roundtrip::RoundTripTable>>,
+    /// Whether the enum and composite types are tested against the database, with `--live-tests`
+    live: bool,
+}
+
+impl Tests {
+    /// Test a table whose struct is at `path` from the output file, if it can be filled with
+    /// sample values
+    fn add_table(
+        &mut self,
+        table: &ir::ResolvedTable,
+        table_code: &TableCode,
+        options: &TableOptions,
+        path: &TokenStream,
+    ) {
+        if self.round_trip.is_none() && self.serde.is_none() {
+            return;
+        }
+        let Some(test_table) = test_table(table, table_code, options, path) else {
+            return;
+        };
+
+        // The database tests also need a table they can create and insert into
+        if let Some(round_trip) = &mut self.round_trip {
+            if table_code.has_repository && ddl::can_create(options.resolved, table) {
+                round_trip.push(test_table.clone());
+            }
+        }
+
+        // Redacted fields aren't serialized, so they can't make the round trip
+        if let Some(serde) = &mut self.serde {
+            if table.columns.iter().any(|column| column.redacted) {
+                debug!(
+                    "{} has redacted columns, skipping its serde tests",
+                    table.name
+                );
+            } else {
+                serde.push(test_table);
+            }
+        }
+    }
+
+    /// Generate the requested test files in `directory`, with the path, code and module
+    /// declaration of each
+    ///
+    /// The live tests send the types to the database, so they need `postgres_derives`.
+    fn files(
+        &self,
+        directory: &str,
+        resolved: &ir::ResolvedSchema,
+        composite_structs: &[composites::CompositeStruct],
+        postgres_derives: bool,
+    ) -> Result<Vec<(String, String, &'static str)>> {
+        let mut files = Vec::new();
+
+        match &self.round_trip {
+            Some(tables) if tables.is_empty() => {
+                warn!(
+                    "No table can be created and filled with sample values, skipping --with-tests"
+                )
+            }
+            Some(tables) => {
+                let tests_path = format!("{}/tests.rs", directory);
+                info!("Generating round trip tests {}", tests_path);
+                let code = roundtrip::generate_tests(tables, &ddl::create_schema(resolved));
+                output::validate(&code, "round trip tests")?;
+                files.push((tests_path, code.to_string(), "#[cfg(test)]\nmod tests;"));
+            }
+            None => {}
+        }
+
+        match &self.serde {
+            Some(tables) if tables.is_empty() => {
+                warn!("No table can be filled with sample values, skipping --serde-tests")
+            }
+            Some(tables) => {
+                let tests_path = format!("{}/serde_tests.rs", directory);
+                info!("Generating serde round trip tests {}", tests_path);
+                let code = roundtrip::generate_serde_tests(tables);
+                output::validate(&code, "serde round trip tests")?;
+                files.push((
+                    tests_path,
+                    code.to_string(),
+                    "#[cfg(test)]\nmod serde_tests;",
+                ));
+            }
+            None => {}
+        }
+
+        if self.live && !postgres_derives {
+            warn!(
+                "Only --postgres-derives types can be sent to the database, skipping --live-tests"
+            );
+        } else if self.live && resolved.enums.is_empty() && composite_structs.is_empty() {
+            warn!("There are no enum or composite types, skipping --live-tests");
+        } else if self.live {
+            let tests_path = format!("{}/live_tests.rs", directory);
+            info!("Generating live type tests {}", tests_path);
+            let code = roundtrip::generate_live_tests(resolved, composite_structs);
+            output::validate(&code, "live type tests")?;
+            files.push((
+                tests_path,
+                code.to_string(),
+                "#[cfg(all(test, feature = \"live-tests\"))]\nmod live_tests;",
+            ));
+        }
+
+        Ok(files)
+    }
+}
+
+/// Get the round trip tests of a table, whose struct is at `path` from the output file, or
+/// `None` when a field has no sample value to fill it with
+fn test_table(
+    table: &ir::ResolvedTable,
+    table_code: &TableCode,
+    options: &TableOptions,
+    path: &TokenStream,
+) -> Option<roundtrip::RoundTripTable> {
+    let mut fields = Vec::new();
+    for (field, column) in table_code.fields.iter().zip(&table.columns) {
+        let Some(value) =
+            roundtrip::sample_value(&column.rust_type, options.resolved, &quote! { super:: })
+        else {
+            debug!(
+                "No sample value of {} for column {}, skipping the tests of {}",
+                column.rust_type, column.name, table.name
+            );
+            return None;
+        };
+        fields.push((
+            field.name(),
+            value,
+            repository::is_assigned(column)
+                || repository::is_filled_in(column, options.conventions)
+                || repository::is_text(column),
+        ));
+    }
+
+    Some(roundtrip::RoundTripTable {
+        path: quote! { super::#path },
+        test_name: table_code.struct_name.to_case(Case::Snake),
+        fields,
+        deprecated: table_code.deprecated,
+        feature: options.config.tables.features.get(&table.name).cloned(),
+    })
+}
+
+/// Generate the enum of the columns of a table, from their variants paired with the names of the
+/// columns
+///
+/// The match dereferences `self`, so the empty enum of a table without columns still compiles.
+fn column_enum(name: &Ident, variants: &[(Ident, String)]) -> TokenStream {
+    let match_arms = variants.iter().map(|(variant, column_name)| {
+        quote! { #name::#variant => #column_name, }
+    });
+    let variants = variants.iter().map(|(variant, _)| variant);
+
+    quote! {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum #name {
+            #(#variants,)*
+        }
+
+        impl AsRef<str> for #name {
+            fn as_ref(&self) -> &str {
+                match *self {
+                    #(#match_arms)*
+                }
+            }
+        }
+    }
+}
+
+/// Refer to the generated enums, newtypes and composite structs in a type through `prefix`
+fn qualify_types(
+    rust_type: TokenStream,
+    options: &TableOptions,
+    prefix: &TokenStream,
+) -> TokenStream {
+    let rust_type = types::qualify(rust_type, options.enum_names, prefix);
+    types::qualify(rust_type, options.composite_names, prefix)
+}
+
+/// Get the fields of the struct of a table, with how they're read from rows and passed as query
+/// parameters
+///
+/// `type_prefix` is how the struct refers to the shared types.
+fn table_fields(
+    table: &ir::ResolvedTable,
+    options: &TableOptions,
+    type_prefix: &TokenStream,
+) -> Result<Vec<repository::RepositoryField>> {
+    let mut fields = Vec::new();
+    for column in &table.columns {
+        let context = format!("column {}.{}", table.name, column.name);
+        let rust_type: syn::Type =
+            syn::parse_str(&column.rust_type).map_err(|source| Error::InvalidCode {
+                context: format!("{} as `{}`", context, column.rust_type),
+                source,
+            })?;
+        let rust_type = qualify_types(quote!(#rust_type), options, type_prefix);
+        let name = output::ident(&column.rust_name, &context)?;
+        fields.push(repository::repository_field(
+            column,
+            name,
+            rust_type,
+            fields.len(),
+        ));
+    }
+
+    Ok(fields)
+}
+
+/// Get the resolved tables and types of a schema as JSON, without its name, so schemas whose
+/// code is the same have the same fingerprint
+fn schema_fingerprint(resolved: &ir::ResolvedSchema) -> String {
+    let mut tables = resolved.tables.clone();
+    for table in &mut tables {
+        for column in &mut table.columns {
+            if column.data_type.schema == resolved.name {
+                column.data_type.schema.clear();
+            }
+        }
+        for foreign_key in &mut table.foreign_keys {
+            if foreign_key.referenced_schema == resolved.name {
+                foreign_key.referenced_schema.clear();
+            }
+        }
+    }
+
+    serde_json::to_string(&(&resolved.enums, &tables, &resolved.composites))
+        .expect("Resolved schemas are always serializable")
+}
+
+/// Get how the code of generated files is written, from `--serde-crate`, `--no-std`, `--edition`,
+/// `--import-style`, `--visibility` and the formatting args
+fn code_style(matches: &ArgMatches) -> Result<output::CodeStyle> {
+    let paths = output::CratePaths {
+        serde: matches
+            .get_one::<String>("serde_crate")
+            .map(|path| output::path(path, "--serde-crate").map(|_| path.clone()))
+            .transpose()?,
+        no_std: matches.get_flag("no_std"),
+        edition: match matches.get_one::<String>("edition").map(String::as_str) {
+            Some("2015") => output::Edition::E2015,
+            Some("2018") => output::Edition::E2018,
+            Some("2024") => output::Edition::E2024,
+            _ => output::Edition::E2021,
+        },
+    };
+
+    let rustfmt_bin = matches.get_one::<String>("rustfmt_bin");
+    let rustfmt_config = matches.get_one::<String>("rustfmt_config");
+    let formatter = if matches.get_flag("no_format") {
+        output::Formatter::None
+    } else if rustfmt_bin.is_some() || rustfmt_config.is_some() {
+        output::Formatter::Rustfmt {
+            bin: rustfmt_bin
+                .cloned()
+                .unwrap_or_else(|| "rustfmt".to_string()),
+            config: rustfmt_config.cloned(),
+        }
+    } else {
+        output::Formatter::Prettyplease
+    };
+
+    let imports = match matches
+        .get_one::<String>("import_style")
+        .map(String::as_str)
+    {
+        Some("use") => output::ImportStyle::Use,
+        _ => output::ImportStyle::Qualified,
+    };
+
+    let visibility = match matches.get_one::<String>("visibility").map(String::as_str) {
+        Some("pub(crate)") => output::Visibility::Crate,
+        Some("private") => output::Visibility::Private,
+        _ => output::Visibility::Public,
+    };
+
+    Ok(output::CodeStyle {
+        paths,
+        imports,
+        visibility,
+        formatter,
+    })
+}
+
+/// Check that a table file is a module name, since every file is a module next to the output file
+fn check_table_file(table: &str, file: &str) -> Result<()> {
+    if file.contains('/') {
+        return Err(Error::Config(format!(
+            "Table file {} must be a module name in the directory of the output file, not a path",
+            file
+        )));
+    }
+    output::ident(file, &format!("table file of {}", table))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn excluded_schemas_always_include_the_system_schemas() {
+        assert_eq!(excluded_schemas(None), SYSTEM_SCHEMAS);
+        assert_eq!(
+            excluded_schemas(Some("other")),
+            ["pg_catalog", "information_schema", "pg_toast", "other"]
+        );
+        assert_eq!(
+            excluded_schemas(Some("audit, pg_catalog,,other")),
+            [
+                "pg_catalog",
+                "information_schema",
+                "pg_toast",
+                "audit",
+                "other"
+            ]
+        );
+    }
+
+    #[test]
+    fn column_enum_of_a_table_without_columns_matches_on_the_value() {
+        let name = Ident::new("EmptyThingColumn", Span::call_site());
+        let code = column_enum(&name, &[]).to_string();
+        assert!(code.contains(&quote! { match *self {} }.to_string()));
+
+        let variants = [(Ident::new("Id", Span::call_site()), "id".to_string())];
+        let code = column_enum(&name, &variants).to_string();
+        assert!(code.contains(&quote! { EmptyThingColumn::Id => "id", }.to_string()));
+    }
+    #[test]
+    fn expand_refuses_the_diff_subcommand() {
+        let args = ["diff".to_string(), "previous.json".to_string()];
+        let error = expand(&args).expect_err("diff doesn't generate code");
+        assert!(error
+            .to_string()
+            .contains("The diff subcommand doesn't generate code"));
+    }
+
+    #[test]
+    fn expand_refuses_the_verify_subcommand() {
+        let args = ["verify".to_string()];
+        let error = expand(&args).expect_err("verify doesn't generate code");
+        assert!(error
+            .to_string()
+            .contains("The verify subcommand doesn't generate code"));
+    }
+}
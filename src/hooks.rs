@@ -0,0 +1,42 @@
+use std::process::Command;
+
+use tracing::info;
+
+use crate::error::{Error, Result};
+
+/// Run the shell commands of a hook one after another, with `files` as their arguments
+///
+/// Stops at the first command that fails, so a failing check fails the run.
+pub fn run_hook(hook: &'static str, commands: &[String], files: &[String]) -> Result<()> {
+    for command in commands {
+        info!("Running {} hook `{}`", hook, command);
+        let hook_error = |message: String| Error::Hook {
+            hook,
+            command: command.clone(),
+            message,
+        };
+
+        // Pass the files as real arguments, so names with spaces don't need quoting
+        let status = if cfg!(windows) {
+            Command::new("cmd")
+                .arg("/C")
+                .arg(command)
+                .args(files)
+                .status()
+        } else {
+            Command::new("sh")
+                .arg("-c")
+                .arg(format!("{} \"$@\"", command))
+                .arg(hook)
+                .args(files)
+                .status()
+        }
+        .map_err(|error| hook_error(error.to_string()))?;
+
+        if !status.success() {
+            return Err(hook_error(status.to_string()));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,314 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use syn::punctuated::Punctuated;
+use syn::visit::{self, Visit};
+use syn::visit_mut::{self, VisitMut};
+use syn::{Attribute, Ident, Token};
+
+/// The names of the std prelude, which an import would shadow
+const PRELUDE: &[&str] = &[
+    "Box",
+    "Clone",
+    "Copy",
+    "Default",
+    "Drop",
+    "Eq",
+    "Fn",
+    "FnMut",
+    "FnOnce",
+    "From",
+    "Into",
+    "Iterator",
+    "None",
+    "Ok",
+    "Option",
+    "Ord",
+    "PartialEq",
+    "PartialOrd",
+    "Result",
+    "Send",
+    "Sized",
+    "Some",
+    "String",
+    "Sync",
+    "ToOwned",
+    "ToString",
+    "Vec",
+];
+
+/// Import the types, traits and derives a generated file names by their path, for
+/// `--import-style use`, so `chrono::NaiveDate` becomes `NaiveDate` with `use chrono::NaiveDate;`
+///
+/// Only the paths of types, derives and implemented traits are imported, since a path in an
+/// expression may end with a function. A name is left qualified when importing it would clash,
+/// like when two paths end with it, the file defines it, or it's in the std prelude. Nested
+/// modules can't see the imports, so their paths are left as they are.
+pub fn use_imports(file: &mut syn::File) {
+    let mut collector = Collector::default();
+    collector.visit_file(file);
+
+    // Import the names only one path ends with
+    let mut paths_by_name: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for path in &collector.paths {
+        let name = path.rsplit("::").next().unwrap_or(path).to_string();
+        paths_by_name.entry(name).or_default().insert(path.clone());
+    }
+    let imported: HashSet<String> = paths_by_name
+        .into_iter()
+        .filter(|(name, paths)| {
+            paths.len() == 1 && !collector.names.contains(name) && !PRELUDE.contains(&name.as_str())
+        })
+        .flat_map(|(_, paths)| paths)
+        .collect();
+    if imported.is_empty() {
+        return;
+    }
+
+    // Group the imports by the module they come from, like `use serde::{Deserialize, Serialize};`
+    let mut modules: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for path in &imported {
+        if let Some((module, name)) = path.rsplit_once("::") {
+            modules
+                .entry(module.to_string())
+                .or_default()
+                .insert(name.to_string());
+        }
+    }
+    let uses: Vec<syn::Item> = modules
+        .iter()
+        .map(|(module, names)| {
+            let module = syn::parse_str::<syn::Path>(module).expect("Imported modules are paths");
+            let names: Vec<Ident> = names
+                .iter()
+                .map(|name| syn::parse_str(name).expect("Imported names are identifiers"))
+                .collect();
+            if let [name] = names.as_slice() {
+                syn::parse_quote! { use #module::#name; }
+            } else {
+                syn::parse_quote! { use #module::{#(#names),*}; }
+            }
+        })
+        .collect();
+
+    Shortener {
+        imported: &imported,
+    }
+    .visit_file_mut(file);
+    file.items.splice(0..0, uses);
+}
+
+/// Get the path of a type, derive or trait as a string, if it can be imported
+///
+/// The path needs a module to import it from, and only its last segment may have generic
+/// arguments, like `chrono::DateTime<Utc>`.
+fn importable(path: &syn::Path) -> Option<String> {
+    let segments: Vec<&syn::PathSegment> = path.segments.iter().collect();
+    let (last, modules) = segments.split_last()?;
+    if modules.is_empty()
+        || modules.iter().any(|segment| !segment.arguments.is_none())
+        || matches!(
+            modules[0].ident.to_string().as_str(),
+            "self" | "super" | "Self"
+        )
+    {
+        return None;
+    }
+
+    let mut names: Vec<String> = modules
+        .iter()
+        .map(|segment| segment.ident.to_string())
+        .collect();
+    names.push(last.ident.to_string());
+    let prefix = if path.leading_colon.is_some() {
+        "::"
+    } else {
+        ""
+    };
+    Some(format!("{}{}", prefix, names.join("::")))
+}
+
+/// Get the traits of a `#[derive(...)]` attribute
+fn derives(attribute: &Attribute) -> Option<Punctuated<syn::Path, Token![,]>> {
+    if !attribute.path().is_ident("derive") {
+        return None;
+    }
+    attribute.parse_args_with(Punctuated::parse_terminated).ok()
+}
+
+/// Collects the paths a file could import and the names it already uses
+#[derive(Default)]
+struct Collector {
+    /// The paths of types, derives and implemented traits, like `chrono::NaiveDate`
+    paths: BTreeSet<String>,
+    /// The names the file defines or uses without a path
+    names: HashSet<String>,
+}
+
+impl<'ast> Visit<'ast> for Collector {
+    fn visit_item_mod(&mut self, item: &'ast syn::ItemMod) {
+        self.names.insert(item.ident.to_string());
+    }
+
+    fn visit_ident(&mut self, ident: &'ast Ident) {
+        // Every name that shows up on its own, like a struct or a variable, could clash
+        self.names.insert(ident.to_string());
+    }
+
+    fn visit_path(&mut self, path: &'ast syn::Path) {
+        if path.segments.len() == 1 {
+            self.names.insert(path.segments[0].ident.to_string());
+        }
+        for segment in &path.segments {
+            self.visit_path_arguments(&segment.arguments);
+        }
+    }
+
+    fn visit_type_path(&mut self, type_path: &'ast syn::TypePath) {
+        if type_path.qself.is_none() {
+            self.paths.extend(importable(&type_path.path));
+        }
+        visit::visit_type_path(self, type_path);
+    }
+
+    fn visit_item_impl(&mut self, item: &'ast syn::ItemImpl) {
+        if let Some((_, path, _)) = &item.trait_ {
+            self.paths.extend(importable(path));
+        }
+        visit::visit_item_impl(self, item);
+    }
+
+    fn visit_attribute(&mut self, attribute: &'ast Attribute) {
+        if let Some(derives) = derives(attribute) {
+            self.paths.extend(derives.iter().filter_map(importable));
+        }
+    }
+}
+
+/// Shortens the imported paths of a file to their names
+struct Shortener<'a> {
+    imported: &'a HashSet<String>,
+}
+
+impl Shortener<'_> {
+    /// Shorten a path to its last segment, if it's imported
+    fn shorten(&self, path: &mut syn::Path) {
+        if importable(path).is_some_and(|imported| self.imported.contains(&imported)) {
+            let last = path.segments.pop().expect("Imported paths have segments");
+            path.leading_colon = None;
+            path.segments = Punctuated::from_iter([last.into_value()]);
+        }
+    }
+}
+
+impl VisitMut for Shortener<'_> {
+    fn visit_item_mod_mut(&mut self, _item: &mut syn::ItemMod) {}
+
+    fn visit_type_path_mut(&mut self, type_path: &mut syn::TypePath) {
+        visit_mut::visit_type_path_mut(self, type_path);
+        if type_path.qself.is_none() {
+            self.shorten(&mut type_path.path);
+        }
+    }
+
+    fn visit_item_impl_mut(&mut self, item: &mut syn::ItemImpl) {
+        visit_mut::visit_item_impl_mut(self, item);
+        if let Some((_, path, _)) = &mut item.trait_ {
+            self.shorten(path);
+        }
+    }
+
+    fn visit_attribute_mut(&mut self, attribute: &mut Attribute) {
+        if let Some(mut derives) = derives(attribute) {
+            for derive in &mut derives {
+                self.shorten(derive);
+            }
+            attribute.meta = syn::parse_quote! { derive(#derives) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn imported(code: &str) -> String {
+        let mut file = syn::parse_file(code).unwrap();
+        use_imports(&mut file);
+        prettyplease::unparse(&file)
+    }
+
+    #[test]
+    fn std_and_crate_paths_are_imported_by_module() {
+        let code = imported(
+            "#[derive(Debug, serde::Serialize, serde::Deserialize)]
+            pub struct Users {
+                pub created: chrono::DateTime<chrono::Utc>,
+                pub tags: std::collections::BTreeMap<String, crate::types::Tag>,
+                pub mood: super::types::Mood,
+            }
+            impl std::fmt::Display for Users {
+                fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                    std::fmt::Debug::fmt(self, f)
+                }
+            }",
+        );
+        assert_eq!(
+            code,
+            "use chrono::{DateTime, Utc};
+use crate::types::Tag;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Users {
+    pub created: DateTime<Utc>,
+    pub tags: BTreeMap<String, Tag>,
+    pub mood: super::types::Mood,
+}
+impl Display for Users {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+"
+        );
+    }
+
+    #[test]
+    fn colliding_names_stay_qualified() {
+        let code = imported(
+            "pub struct Uuid;
+            pub struct Events {
+                pub id: uuid::Uuid,
+                pub timeout: std::time::Duration,
+                pub delay: chrono::Duration,
+                pub name: std::string::String,
+                pub day: chrono::NaiveDate,
+            }",
+        );
+        assert_eq!(
+            code,
+            "use chrono::NaiveDate;
+pub struct Uuid;
+pub struct Events {
+    pub id: uuid::Uuid,
+    pub timeout: std::time::Duration,
+    pub delay: chrono::Duration,
+    pub name: std::string::String,
+    pub day: NaiveDate,
+}
+"
+        );
+    }
+
+    #[test]
+    fn nested_modules_are_left_alone() {
+        let code = "pub mod tests {
+    pub struct Row {
+        pub day: chrono::NaiveDate,
+    }
+}
+";
+        assert_eq!(imported(code), code);
+    }
+}
@@ -0,0 +1,93 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::ir::ResolvedTable;
+use crate::model::Index;
+
+/// Describe an index in a line of Markdown, e.g. ``users_email_key`: unique btree on `email``
+pub fn describe(index: &Index) -> String {
+    let kind = if index.primary {
+        "primary key "
+    } else if index.unique {
+        "unique "
+    } else {
+        ""
+    };
+    let columns: Vec<String> = index
+        .columns
+        .iter()
+        .map(|column| format!("`{}`", column))
+        .collect();
+    let mut description = format!(
+        "`{}`: {}{} on {}",
+        index.name,
+        kind,
+        index.method,
+        columns.join(", ")
+    );
+    if let Some(predicate) = &index.predicate {
+        description.push_str(&format!(" where `{}`", predicate));
+    }
+
+    description
+}
+
+/// Generate the `IndexMeta` struct the `INDEXES` consts are made of, for `--index-consts`
+pub fn generate_index_meta() -> TokenStream {
+    quote! {
+        /// An index of a table
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub struct IndexMeta {
+            /// The name of the index
+            pub name: &'static str,
+            /// The indexed columns or expressions, in key order
+            pub columns: &'static [&'static str],
+            /// Whether the index is unique
+            pub unique: bool,
+            /// Whether the index backs the primary key
+            pub primary: bool,
+            /// The access method of the index, like `btree` or `gin`
+            pub method: &'static str,
+            /// The condition of a partial index
+            pub predicate: Option<&'static str>,
+        }
+    }
+}
+
+/// Generate the `INDEXES` const of the struct of a table, for `--index-consts`
+///
+/// `type_prefix` is how the struct refers to the shared `IndexMeta`.
+pub fn generate_indexes_const(
+    table: &ResolvedTable,
+    struct_name: &TokenStream,
+    type_prefix: &TokenStream,
+) -> TokenStream {
+    let indexes = table.indexes.iter().map(|index| {
+        let name = &index.name;
+        let columns = &index.columns;
+        let unique = index.unique;
+        let primary = index.primary;
+        let method = &index.method;
+        let predicate = match &index.predicate {
+            Some(predicate) => quote! { Some(#predicate) },
+            None => quote! { None },
+        };
+        quote! {
+            #type_prefix IndexMeta {
+                name: #name,
+                columns: &[#(#columns),*],
+                unique: #unique,
+                primary: #primary,
+                method: #method,
+                predicate: #predicate,
+            },
+        }
+    });
+
+    quote! {
+        impl #struct_name {
+            /// The indexes of the table, ordered by name
+            pub const INDEXES: &'static [#type_prefix IndexMeta] = &[#(#indexes)*];
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::BTreeMap;
+use std::io::{self, IsTerminal};
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::error::{Error, Result};
+use crate::ir::ResolvedTable;
+use crate::model::TableKind;
+use crate::output;
+
+/// The tables picked with `--interactive`
+pub struct Selection {
+    /// The tables and views to generate, in the order of the schema
+    pub tables: Vec<String>,
+    /// The files tables are written to, keyed by table
+    pub files: BTreeMap<String, String>,
+    /// Whether to write the selection to the config file
+    pub save: bool,
+}
+
+/// The state of the table picker
+struct Picker<'a> {
+    tables: &'a [ResolvedTable],
+    picked: Vec<bool>,
+    files: BTreeMap<String, String>,
+    list: ListState,
+    /// The file being typed for the highlighted table, if any
+    editing: Option<String>,
+    /// A message for the last key, like why a file name was rejected
+    message: Option<String>,
+}
+
+/// Let the user pick the tables to generate and the files they go to in a terminal UI
+///
+/// `included` are the tables picked so far, every one when empty, and `files` their files.
+/// `config_path` is where the selection is saved when the user asks for it.
+pub fn pick_tables(
+    tables: &[ResolvedTable],
+    included: &[String],
+    files: BTreeMap<String, String>,
+    config_path: &str,
+) -> Result<Selection> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return Err(Error::Config("--interactive needs a terminal".to_string()));
+    }
+    if tables.is_empty() {
+        return Err(Error::Config(
+            "There are no tables to pick from with --interactive".to_string(),
+        ));
+    }
+
+    let mut picker = Picker {
+        tables,
+        picked: tables
+            .iter()
+            .map(|table| included.is_empty() || included.contains(&table.name))
+            .collect(),
+        files,
+        list: ListState::default().with_selected(Some(0)),
+        editing: None,
+        message: None,
+    };
+
+    // Put the terminal back the way it was, even when the picker fails
+    let mut terminal = ratatui::init();
+    let result = picker.run(&mut terminal, config_path);
+    ratatui::restore();
+
+    let save = result
+        .map_err(|error| Error::Config(format!("The table picker failed: {}", error)))?
+        .ok_or_else(|| Error::Config("Cancelled the table picker".to_string()))?;
+    Ok(Selection {
+        tables: tables
+            .iter()
+            .zip(&picker.picked)
+            .filter(|(_, picked)| **picked)
+            .map(|(table, _)| table.name.clone())
+            .collect(),
+        files: picker.files,
+        save,
+    })
+}
+
+impl Picker<'_> {
+    /// Handle keys until the user confirms, returning whether to save the selection, or `None`
+    /// when they cancel
+    fn run(
+        &mut self,
+        terminal: &mut DefaultTerminal,
+        config_path: &str,
+    ) -> io::Result<Option<bool>> {
+        loop {
+            terminal.draw(|frame| self.draw(frame, config_path))?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            let index = self.list.selected().unwrap_or(0);
+            if let Some(file) = &mut self.editing {
+                self.message = None;
+                match key.code {
+                    KeyCode::Char(c) => file.push(c),
+                    KeyCode::Backspace => {
+                        file.pop();
+                    }
+                    KeyCode::Esc => self.editing = None,
+                    KeyCode::Enter => self.set_file(index),
+                    _ => {}
+                }
+                continue;
+            }
+
+            self.message = None;
+            match key.code {
+                KeyCode::Up | KeyCode::Char('k') => self.list.select_previous(),
+                KeyCode::Down | KeyCode::Char('j') => self
+                    .list
+                    .select(Some((index + 1).min(self.tables.len() - 1))),
+                KeyCode::Char(' ') => self.picked[index] = !self.picked[index],
+                KeyCode::Char('a') => {
+                    let pick = !self.picked.iter().all(|picked| *picked);
+                    self.picked.fill(pick);
+                }
+                KeyCode::Char('f') => {
+                    let file = self.files.get(&self.tables[index].name).cloned();
+                    self.editing = Some(file.unwrap_or_default());
+                }
+                KeyCode::Enter | KeyCode::Char('w') if !self.picked.contains(&true) => {
+                    self.message = Some("Pick at least one table".to_string())
+                }
+                KeyCode::Enter => return Ok(Some(false)),
+                KeyCode::Char('w') => return Ok(Some(true)),
+                KeyCode::Esc | KeyCode::Char('q') => return Ok(None),
+                _ => {}
+            }
+        }
+    }
+
+    /// Map the table at `index` to the file being typed, or to its default file when it's empty,
+    /// which depends on `--layout`
+    fn set_file(&mut self, index: usize) {
+        let file = self.editing.take().unwrap_or_default();
+        let table = &self.tables[index].name;
+        if file.is_empty() {
+            self.files.remove(table);
+        } else if file.contains('/') || output::ident(&file, "table file").is_err() {
+            self.message = Some(format!("{} isn't a module name", file));
+            self.editing = Some(file);
+        } else {
+            self.files.insert(table.clone(), file);
+        }
+    }
+
+    /// Draw the tables with their checkboxes and files, and the keys below them
+    fn draw(&mut self, frame: &mut Frame, config_path: &str) {
+        let [list_area, help_area] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+
+        let items: Vec<ListItem> = self
+            .tables
+            .iter()
+            .zip(&self.picked)
+            .map(|(table, picked)| {
+                let kind = match table.kind {
+                    TableKind::Table => "table",
+                    TableKind::View => "view",
+                    TableKind::MaterializedView => "materialized view",
+                };
+                let file = match self.files.get(&table.name) {
+                    Some(file) => format!("{}.rs", file),
+                    None => "its default file".to_string(),
+                };
+                let checkbox = if *picked { "[x]" } else { "[ ]" };
+                ListItem::new(format!(
+                    "{} {} ({}) -> {}",
+                    checkbox, table.name, kind, file
+                ))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(Block::bordered().title(" Pick the tables to generate "))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, list_area, &mut self.list);
+
+        let help = match (&self.editing, &self.message) {
+            (Some(file), Some(message)) => format!("{}, file: {}_", message, file),
+            (Some(file), None) => format!(
+                "File, empty for its default file (enter to set, esc to cancel): {}_",
+                file
+            ),
+            (None, Some(message)) => message.clone(),
+            (None, None) => format!(
+                "space: pick  a: pick all  f: set file  enter: generate  w: generate and save to {}  q: cancel",
+                config_path
+            ),
+        };
+        frame.render_widget(Paragraph::new(help), help_area);
+    }
+}
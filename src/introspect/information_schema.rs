@@ -0,0 +1,318 @@
+use postgres::types::Type;
+use postgres::GenericClient;
+
+use crate::connection::query;
+
+use crate::model::{
+    Attribute, Column, CompositeType, DataType, ForeignKey, Schema, Scope, TableKind, TypeKind,
+    UniqueConstraint,
+};
+
+use super::{
+    push_column, push_foreign_keys, push_unique_constraints, set_definitions, set_primary_keys,
+};
+
+/// Read a schema from the information_schema views
+///
+/// The views don't describe enum labels, so enum columns are treated like any other
+/// user-defined type. Composite types are described by their attributes.
+pub fn introspect(
+    client: &mut impl GenericClient,
+    simple_query: bool,
+    schema: &str,
+    include_views: bool,
+) -> Result<Schema, postgres::Error> {
+    // Get every column of every table in one query, joined with the element type for arrays
+    let columns = query(
+        client,
+        simple_query,
+        "SELECT t.table_name, t.table_type = 'VIEW',
+            c.column_name, c.is_nullable, c.column_default,
+            c.data_type, c.udt_schema, c.udt_name, e.data_type,
+            c.character_maximum_length::integer, c.numeric_precision::integer,
+            c.numeric_scale::integer, c.datetime_precision::integer,
+            c.is_identity = 'YES', c.is_generated = 'ALWAYS'
+        FROM information_schema.tables t
+        LEFT JOIN information_schema.columns c
+            ON c.table_schema = t.table_schema AND c.table_name = t.table_name
+        LEFT JOIN information_schema.element_types e
+            ON (c.table_catalog, c.table_schema, c.table_name, 'TABLE', c.dtd_identifier)
+            = (e.object_catalog, e.object_schema, e.object_name, e.object_type, e.collection_type_identifier)
+        WHERE t.table_schema = $1 AND (t.table_type = 'BASE TABLE' OR ($2 AND t.table_type = 'VIEW'))
+        ORDER BY t.table_name, c.ordinal_position",
+        &[(&schema, Type::TEXT), (&include_views, Type::BOOL)],
+    )?;
+
+    let mut tables = Vec::new();
+    for row in columns {
+        let table_name: String = row.get(0);
+        let column_name: Option<String> = row.get(2);
+
+        let column = column_name.map(|column_name| {
+            let is_nullable: String = row.get(3);
+            let data_type: String = row.get(5);
+            let udt_schema: String = row.get(6);
+            let udt_name: String = row.get(7);
+            let element_type: Option<String> = row.get(8);
+
+            // Arrays report their element type separately, user-defined types only by name
+            let (name, dimensions) = match (data_type.as_str(), element_type) {
+                ("ARRAY", Some(element_type)) => (element_type, 1),
+                ("USER-DEFINED", _) => (udt_name, 0),
+                _ => (data_type, 0),
+            };
+
+            // Integer and floating point types report a precision too, but only numeric and
+            // the time types carry one in their type modifier
+            let (precision, scale) = if name == "numeric" {
+                (row.get(10), row.get(11))
+            } else if name.starts_with("time") {
+                (row.get(12), None)
+            } else {
+                (None, None)
+            };
+
+            Column {
+                name: column_name,
+                data_type: DataType {
+                    name,
+                    schema: udt_schema,
+                    kind: TypeKind::Base,
+                    dimensions,
+                    element_not_null: false,
+                    length: row.get(9),
+                    precision,
+                    scale,
+                },
+                nullable: is_nullable == "YES",
+                default: row.get(4),
+                identity: row.get(13),
+                generated: row.get(14),
+                comment: None,
+            }
+        });
+
+        // Tell views apart from tables
+        let kind = if row.get(1) {
+            TableKind::View
+        } else {
+            TableKind::Table
+        };
+        push_column(&mut tables, table_name, kind, None, column);
+    }
+
+    // Get the primary keys, one row per column in key order
+    let rows = query(
+        client,
+        simple_query,
+        "SELECT k.table_name, k.column_name
+        FROM information_schema.table_constraints c
+        JOIN information_schema.key_column_usage k
+            ON k.constraint_schema = c.constraint_schema AND k.constraint_name = c.constraint_name
+            AND k.table_name = c.table_name
+        WHERE c.constraint_type = 'PRIMARY KEY' AND c.table_schema = $1
+        ORDER BY k.table_name, k.ordinal_position",
+        &[(&schema, Type::TEXT)],
+    )?;
+
+    let mut primary_keys: Vec<(String, Vec<String>)> = Vec::new();
+    for row in rows {
+        let table_name: String = row.get(0);
+        if primary_keys
+            .last()
+            .map(|(last, _)| *last != table_name)
+            .unwrap_or(true)
+        {
+            primary_keys.push((table_name, Vec::new()));
+        }
+        primary_keys.last_mut().unwrap().1.push(row.get(1));
+    }
+    set_primary_keys(&mut tables, primary_keys);
+
+    // Get the foreign keys, one row per column. Each column is matched to the column it references
+    // through its position in the referenced key
+    let rows = query(
+        client,
+        simple_query,
+        "SELECT k.table_name, rc.constraint_name, u.table_schema, u.table_name,
+            k.column_name, u.column_name
+        FROM information_schema.referential_constraints rc
+        JOIN information_schema.key_column_usage k
+            ON k.constraint_schema = rc.constraint_schema AND k.constraint_name = rc.constraint_name
+        JOIN information_schema.key_column_usage u
+            ON u.constraint_schema = rc.unique_constraint_schema
+            AND u.constraint_name = rc.unique_constraint_name
+            AND u.ordinal_position = k.position_in_unique_constraint
+        WHERE k.table_schema = $1
+        ORDER BY k.table_name, rc.constraint_name, k.ordinal_position",
+        &[(&schema, Type::TEXT)],
+    )?;
+
+    let mut foreign_keys: Vec<(String, ForeignKey)> = Vec::new();
+    for row in rows {
+        let table_name: String = row.get(0);
+        let name: String = row.get(1);
+        let is_new = foreign_keys
+            .last()
+            .map(|(last_table, last)| *last_table != table_name || last.name != name)
+            .unwrap_or(true);
+        if is_new {
+            foreign_keys.push((
+                table_name,
+                ForeignKey {
+                    name,
+                    columns: Vec::new(),
+                    referenced_schema: row.get(2),
+                    referenced_table: row.get(3),
+                    referenced_columns: Vec::new(),
+                },
+            ));
+        }
+
+        let (_, foreign_key) = foreign_keys.last_mut().unwrap();
+        foreign_key.columns.push(row.get(4));
+        foreign_key.referenced_columns.push(row.get(5));
+    }
+    push_foreign_keys(&mut tables, foreign_keys);
+
+    // Get the unique constraints that can be the target of ON CONFLICT, one row per column
+    let rows = query(
+        client,
+        simple_query,
+        "SELECT k.table_name, c.constraint_name, k.column_name
+        FROM information_schema.table_constraints c
+        JOIN information_schema.key_column_usage k
+            ON k.constraint_schema = c.constraint_schema AND k.constraint_name = c.constraint_name
+            AND k.table_name = c.table_name
+        WHERE c.constraint_type = 'UNIQUE' AND c.is_deferrable = 'NO' AND c.table_schema = $1
+        ORDER BY k.table_name, c.constraint_name, k.ordinal_position",
+        &[(&schema, Type::TEXT)],
+    )?;
+
+    let mut unique_constraints: Vec<(String, UniqueConstraint)> = Vec::new();
+    for row in rows {
+        let table_name: String = row.get(0);
+        let name: String = row.get(1);
+        let is_new = unique_constraints
+            .last()
+            .map(|(last_table, last)| *last_table != table_name || last.name != name)
+            .unwrap_or(true);
+        if is_new {
+            unique_constraints.push((
+                table_name,
+                UniqueConstraint {
+                    name,
+                    columns: Vec::new(),
+                },
+            ));
+        }
+
+        let (_, unique_constraint) = unique_constraints.last_mut().unwrap();
+        unique_constraint.columns.push(row.get(2));
+    }
+    push_unique_constraints(&mut tables, unique_constraints);
+
+    // Get the query of every view, which is only shown to the owners of the view
+    if include_views {
+        let definitions = query(
+            client,
+            simple_query,
+            "SELECT table_name, view_definition FROM information_schema.views
+            WHERE table_schema = $1",
+            &[(&schema, Type::TEXT)],
+        )?;
+        let definitions = definitions
+            .iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect();
+        set_definitions(&mut tables, definitions);
+    }
+
+    // Get the attributes of the composite types, joined with the element type for arrays
+    let rows = query(
+        client,
+        simple_query,
+        "SELECT a.udt_name, a.attribute_name, a.data_type, a.attribute_udt_schema,
+            a.attribute_udt_name, e.data_type, a.character_maximum_length::integer,
+            a.numeric_precision::integer, a.numeric_scale::integer,
+            a.datetime_precision::integer
+        FROM information_schema.attributes a
+        LEFT JOIN information_schema.element_types e
+            ON (a.udt_catalog, a.udt_schema, a.udt_name, 'USER-DEFINED TYPE', a.dtd_identifier)
+            = (e.object_catalog, e.object_schema, e.object_name, e.object_type, e.collection_type_identifier)
+        WHERE a.udt_schema = $1
+        ORDER BY a.udt_name, a.ordinal_position",
+        &[(&schema, Type::TEXT)],
+    )?;
+
+    let mut composites: Vec<CompositeType> = Vec::new();
+    for row in rows {
+        let type_name: String = row.get(0);
+        if composites
+            .last()
+            .map(|composite| composite.name != type_name)
+            .unwrap_or(true)
+        {
+            composites.push(CompositeType {
+                name: type_name,
+                attributes: Vec::new(),
+            });
+        }
+
+        let (name, dimensions) = match (row.get::<_, String>(2).as_str(), row.get(5)) {
+            ("ARRAY", Some(element_type)) => (element_type, 1),
+            ("USER-DEFINED", _) => (row.get(4), 0),
+            (data_type, _) => (data_type.to_string(), 0),
+        };
+        let (precision, scale) = if name == "numeric" {
+            (row.get(7), row.get(8))
+        } else if name.starts_with("time") {
+            (row.get(9), None)
+        } else {
+            (None, None)
+        };
+        composites.last_mut().unwrap().attributes.push(Attribute {
+            name: row.get(1),
+            data_type: DataType {
+                name,
+                schema: row.get(3),
+                kind: TypeKind::Base,
+                dimensions,
+                element_not_null: false,
+                length: row.get(6),
+                precision,
+                scale,
+            },
+        });
+    }
+
+    // Tell the columns and attributes of the composite types apart from other user-defined types
+    let names: Vec<String> = composites
+        .iter()
+        .map(|composite| composite.name.clone())
+        .collect();
+    let columns = tables
+        .iter_mut()
+        .flat_map(|table| &mut table.columns)
+        .map(|column| &mut column.data_type);
+    let attributes = composites
+        .iter_mut()
+        .flat_map(|composite| &mut composite.attributes)
+        .map(|attribute| &mut attribute.data_type);
+    for data_type in columns.chain(attributes) {
+        if data_type.schema == schema && names.contains(&data_type.name) {
+            data_type.kind = TypeKind::Composite;
+        }
+    }
+
+    Ok(Schema {
+        name: schema.to_string(),
+        tables,
+        enums: Vec::new(),
+        composites,
+        scope: Some(Scope {
+            views: include_views,
+            materialized_views: false,
+        }),
+    })
+}
@@ -0,0 +1,193 @@
+use postgres::GenericClient;
+use tracing::{info, warn};
+
+use crate::error::{Error, Result};
+use crate::model::{Column, ForeignKey, Index, Schema, Table, TableKind, UniqueConstraint};
+use crate::version::ServerVersion;
+
+mod information_schema;
+mod pg_catalog;
+mod redshift;
+mod views;
+
+/// The kind of database the schema is read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    /// PostgreSQL, or a database that is compatible with its catalogs
+    Postgres,
+    /// Amazon Redshift, which only has part of information_schema and the old catalogs it forked
+    Redshift,
+}
+
+/// Where the schema is read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Introspection {
+    /// The PostgreSQL system catalogs, which expose array element types, enum labels and type
+    /// modifiers
+    PgCatalog,
+    /// The SQL standard information_schema views, for databases that only speak the standard
+    InformationSchema,
+}
+
+/// Read the tables and types of a schema from the database
+///
+/// If reading from pg_catalog fails, the information_schema views are used instead. A schema
+/// without any tables or types is an error, as it usually means the schema name is wrong.
+pub fn introspect(
+    client: &mut impl GenericClient,
+    simple_query: bool,
+    schema: &str,
+    introspection: Introspection,
+    version: ServerVersion,
+    include_views: bool,
+) -> Result<Schema> {
+    let mut result = None;
+    if introspection == Introspection::PgCatalog {
+        info!("Reading schema {} from pg_catalog", schema);
+        if !version.has_identity_columns() {
+            warn!(
+                "PostgreSQL {} doesn't have identity columns or partitioned tables, skipping their detection",
+                version
+            );
+        }
+        if !version.has_generated_columns() {
+            warn!(
+                "PostgreSQL {} doesn't have generated columns, skipping their detection",
+                version
+            );
+        }
+
+        // Read inside a savepoint, so a failure doesn't abort the transaction the caller is in
+        let mut savepoint = client.transaction().map_err(Error::query("savepoint"))?;
+        match pg_catalog::introspect(&mut savepoint, simple_query, schema, version, include_views) {
+            Ok(schema) => {
+                savepoint.commit().map_err(Error::query("savepoint"))?;
+                result = Some(schema);
+            }
+            Err(error) => {
+                savepoint.rollback().map_err(Error::query("savepoint"))?;
+                warn!(
+                    "Failed to read schema from pg_catalog, falling back to information_schema: {}",
+                    error
+                );
+            }
+        }
+    }
+
+    let result = match result {
+        Some(result) => result,
+        None => {
+            info!("Reading schema {} from information_schema", schema);
+            information_schema::introspect(client, simple_query, schema, include_views)
+                .map_err(Error::query("tables"))?
+        }
+    };
+
+    check_not_empty(result)
+}
+
+/// Read the tables of a schema from Amazon Redshift
+pub fn introspect_redshift(
+    client: &mut impl GenericClient,
+    simple_query: bool,
+    schema: &str,
+    include_views: bool,
+) -> Result<Schema> {
+    info!("Reading schema {} from Redshift information_schema", schema);
+    let result = redshift::introspect(client, simple_query, schema, include_views)
+        .map_err(Error::query("tables"))?;
+
+    check_not_empty(result)
+}
+
+/// Make sure a schema has something to generate, as an empty one usually means the schema name
+/// is wrong
+fn check_not_empty(schema: Schema) -> Result<Schema> {
+    if schema.tables.is_empty() && schema.enums.is_empty() && schema.composites.is_empty() {
+        return Err(Error::EmptySchema(schema.name));
+    }
+
+    Ok(schema)
+}
+
+/// Add a column row to the table it belongs to, given rows ordered by table name
+///
+/// Tables without any columns come back as a single row without a column, so the table is always
+/// added but the column only when there is one.
+fn push_column(
+    tables: &mut Vec<Table>,
+    table_name: String,
+    kind: TableKind,
+    comment: Option<String>,
+    column: Option<Column>,
+) {
+    if tables
+        .last()
+        .map(|table| table.name != table_name)
+        .unwrap_or(true)
+    {
+        tables.push(Table {
+            name: table_name,
+            kind,
+            columns: Vec::new(),
+            primary_key: Vec::new(),
+            foreign_keys: Vec::new(),
+            unique_constraints: Vec::new(),
+            indexes: Vec::new(),
+            comment,
+            definition: None,
+        });
+    }
+
+    if let Some(column) = column {
+        tables.last_mut().unwrap().columns.push(column);
+    }
+}
+
+/// Set the primary keys of the tables they belong to, ignoring tables that weren't read
+fn set_primary_keys(tables: &mut [Table], primary_keys: Vec<(String, Vec<String>)>) {
+    for (table_name, columns) in primary_keys {
+        if let Some(table) = tables.iter_mut().find(|table| table.name == table_name) {
+            table.primary_key = columns;
+        }
+    }
+}
+
+/// Add foreign keys to the tables they belong to, ignoring tables that weren't read
+fn push_foreign_keys(tables: &mut [Table], foreign_keys: Vec<(String, ForeignKey)>) {
+    for (table_name, foreign_key) in foreign_keys {
+        if let Some(table) = tables.iter_mut().find(|table| table.name == table_name) {
+            table.foreign_keys.push(foreign_key);
+        }
+    }
+}
+
+/// Set the queries of the views they define, ignoring views that weren't read
+fn set_definitions(tables: &mut [Table], definitions: Vec<(String, Option<String>)>) {
+    for (table_name, definition) in definitions {
+        if let Some(table) = tables.iter_mut().find(|table| table.name == table_name) {
+            table.definition = definition.map(|definition| definition.trim().to_string());
+        }
+    }
+}
+
+/// Add indexes to the tables they belong to, ignoring tables that weren't read
+fn push_indexes(tables: &mut [Table], indexes: Vec<(String, Index)>) {
+    for (table_name, index) in indexes {
+        if let Some(table) = tables.iter_mut().find(|table| table.name == table_name) {
+            table.indexes.push(index);
+        }
+    }
+}
+
+/// Add unique constraints to the tables they belong to, ignoring tables that weren't read
+fn push_unique_constraints(
+    tables: &mut [Table],
+    unique_constraints: Vec<(String, UniqueConstraint)>,
+) {
+    for (table_name, unique_constraint) in unique_constraints {
+        if let Some(table) = tables.iter_mut().find(|table| table.name == table_name) {
+            table.unique_constraints.push(unique_constraint);
+        }
+    }
+}
@@ -0,0 +1,366 @@
+use postgres::types::Type;
+use postgres::GenericClient;
+
+use crate::connection::query;
+
+use crate::model::{
+    Attribute, Column, CompositeType, DataType, EnumType, ForeignKey, Index, Schema, Scope,
+    TableKind, TypeKind, UniqueConstraint,
+};
+use crate::version::ServerVersion;
+
+use super::{
+    push_column, push_foreign_keys, push_indexes, push_unique_constraints, set_definitions,
+    set_primary_keys, views,
+};
+
+/// Read a schema from the PostgreSQL system catalogs
+///
+/// Catalog columns that the server doesn't have yet are left out of the query.
+pub fn introspect(
+    client: &mut impl GenericClient,
+    simple_query: bool,
+    schema: &str,
+    version: ServerVersion,
+    include_views: bool,
+) -> Result<Schema, postgres::Error> {
+    let identity = if version.has_identity_columns() {
+        "a.attidentity <> ''"
+    } else {
+        "false"
+    };
+    let generated = if version.has_generated_columns() {
+        "a.attgenerated <> ''"
+    } else {
+        "false"
+    };
+
+    // Partitions are tables too, but their columns come from the partitioned table
+    let partitions = if version.has_partitioning() {
+        "AND NOT c.relispartition"
+    } else {
+        ""
+    };
+
+    // Get every column of every table, and every attribute of every composite type, in one query.
+    // Domains are resolved to their base type and arrays to their element type, so the type
+    // columns always describe the element type
+    let sql = format!(
+        "SELECT c.relname, a.attname, NOT a.attnotnull, pg_catalog.pg_get_expr(d.adbin, d.adrelid),
+            CASE WHEN t.typtype = 'd' THEN t.typtypmod ELSE a.atttypmod END,
+            CASE WHEN bt.typcategory = 'A' THEN GREATEST(a.attndims, 1) ELSE 0 END,
+            et.typname, etn.nspname, et.typtype, pg_catalog.format_type(et.oid, NULL),
+            COALESCE({}, false), COALESCE({}, false), c.relkind,
+            pg_catalog.obj_description(c.oid, 'pg_class'), pg_catalog.col_description(c.oid, a.attnum),
+            COALESCE(bt.typcategory = 'A' AND at.typtype = 'd' AND at.typnotnull, false)
+        FROM pg_catalog.pg_class c
+        JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+        LEFT JOIN pg_catalog.pg_attribute a
+            ON a.attrelid = c.oid AND a.attnum > 0 AND NOT a.attisdropped
+        LEFT JOIN pg_catalog.pg_attrdef d ON d.adrelid = c.oid AND d.adnum = a.attnum
+        LEFT JOIN pg_catalog.pg_type t ON t.oid = a.atttypid
+        LEFT JOIN pg_catalog.pg_type bt
+            ON bt.oid = CASE WHEN t.typtype = 'd' THEN t.typbasetype ELSE t.oid END
+        LEFT JOIN pg_catalog.pg_type at
+            ON at.oid = CASE WHEN bt.typcategory = 'A' THEN bt.typelem ELSE bt.oid END
+        LEFT JOIN pg_catalog.pg_type et
+            ON et.oid = CASE WHEN at.typtype = 'd' THEN at.typbasetype ELSE at.oid END
+        LEFT JOIN pg_catalog.pg_namespace etn ON etn.oid = et.typnamespace
+        WHERE n.nspname = $1 AND (c.relkind IN ('r', 'p', 'c') OR ($2 AND c.relkind IN ('v', 'm'))) {}
+        ORDER BY c.relname, a.attnum",
+        identity, generated, partitions
+    );
+    let columns = query(
+        client,
+        simple_query,
+        &sql,
+        &[(&schema, Type::NAME), (&include_views, Type::BOOL)],
+    )?;
+
+    let mut tables = Vec::new();
+    let mut composites: Vec<CompositeType> = Vec::new();
+    for row in columns {
+        let table_name: String = row.get(0);
+        let column_name: Option<String> = row.get(1);
+
+        let column = column_name.map(|column_name| {
+            let type_name: String = row.get(6);
+            let type_schema: String = row.get(7);
+            let type_type: i8 = row.get(8);
+            let formatted_name: String = row.get(9);
+
+            // Built-in types use the SQL standard spelling, the same as information_schema
+            let name = if type_schema == "pg_catalog" {
+                formatted_name
+            } else {
+                type_name
+            };
+
+            let kind = match type_type as u8 {
+                b'e' => TypeKind::Enum,
+                b'c' => TypeKind::Composite,
+                b'r' | b'm' => TypeKind::Range,
+                _ => TypeKind::Base,
+            };
+
+            let mut data_type = DataType {
+                name,
+                schema: type_schema,
+                kind,
+                dimensions: row.get(5),
+                element_not_null: row.get(15),
+                length: None,
+                precision: None,
+                scale: None,
+            };
+            decode_type_modifier(&mut data_type, row.get(4));
+
+            Column {
+                name: column_name,
+                data_type,
+                nullable: row.get(2),
+                default: row.get(3),
+                identity: row.get(10),
+                generated: row.get(11),
+                comment: row.get(14),
+            }
+        });
+
+        let kind = match row.get::<_, i8>(12) as u8 {
+            // The attributes of composite types can always be NULL
+            b'c' => {
+                if composites
+                    .last()
+                    .map(|composite| composite.name != table_name)
+                    .unwrap_or(true)
+                {
+                    composites.push(CompositeType {
+                        name: table_name,
+                        attributes: Vec::new(),
+                    });
+                }
+                if let Some(column) = column {
+                    composites.last_mut().unwrap().attributes.push(Attribute {
+                        name: column.name,
+                        data_type: column.data_type,
+                    });
+                }
+                continue;
+            }
+            b'v' => TableKind::View,
+            b'm' => TableKind::MaterializedView,
+            _ => TableKind::Table,
+        };
+        push_column(&mut tables, table_name, kind, row.get(13), column);
+    }
+
+    // The catalogs report every view column as nullable, so work out which can't be NULL
+    if include_views {
+        views::infer_nullability(client, simple_query, schema, &mut tables)?;
+
+        // Get the query of every view
+        let definitions = query(
+            client,
+            simple_query,
+            "SELECT c.relname, pg_catalog.pg_get_viewdef(c.oid)
+            FROM pg_catalog.pg_class c
+            JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+            WHERE n.nspname = $1 AND c.relkind IN ('v', 'm')",
+            &[(&schema, Type::NAME)],
+        )?;
+        let definitions = definitions
+            .iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect();
+        set_definitions(&mut tables, definitions);
+    }
+
+    // Get the primary keys, with their columns in key order
+    let primary_keys = query(
+        client,
+        simple_query,
+        "SELECT t.relname,
+            ARRAY(SELECT a.attname::text FROM unnest(c.conkey) WITH ORDINALITY k(attnum, position)
+                JOIN pg_catalog.pg_attribute a ON a.attrelid = c.conrelid AND a.attnum = k.attnum
+                ORDER BY k.position)
+        FROM pg_catalog.pg_constraint c
+        JOIN pg_catalog.pg_class t ON t.oid = c.conrelid
+        JOIN pg_catalog.pg_namespace n ON n.oid = t.relnamespace
+        WHERE c.contype = 'p' AND n.nspname = $1",
+        &[(&schema, Type::NAME)],
+    )?;
+    let primary_keys = primary_keys
+        .iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect();
+    set_primary_keys(&mut tables, primary_keys);
+
+    // Get the foreign keys, with their columns in key order
+    let foreign_keys = query(
+        client,
+        simple_query,
+        "SELECT t.relname, c.conname, rn.nspname, rt.relname,
+            ARRAY(SELECT a.attname::text FROM unnest(c.conkey) WITH ORDINALITY k(attnum, position)
+                JOIN pg_catalog.pg_attribute a ON a.attrelid = c.conrelid AND a.attnum = k.attnum
+                ORDER BY k.position),
+            ARRAY(SELECT a.attname::text FROM unnest(c.confkey) WITH ORDINALITY k(attnum, position)
+                JOIN pg_catalog.pg_attribute a ON a.attrelid = c.confrelid AND a.attnum = k.attnum
+                ORDER BY k.position)
+        FROM pg_catalog.pg_constraint c
+        JOIN pg_catalog.pg_class t ON t.oid = c.conrelid
+        JOIN pg_catalog.pg_namespace n ON n.oid = t.relnamespace
+        JOIN pg_catalog.pg_class rt ON rt.oid = c.confrelid
+        JOIN pg_catalog.pg_namespace rn ON rn.oid = rt.relnamespace
+        WHERE c.contype = 'f' AND n.nspname = $1
+        ORDER BY t.relname, c.conname",
+        &[(&schema, Type::NAME)],
+    )?;
+    let foreign_keys = foreign_keys
+        .iter()
+        .map(|row| {
+            let foreign_key = ForeignKey {
+                name: row.get(1),
+                columns: row.get(4),
+                referenced_schema: row.get(2),
+                referenced_table: row.get(3),
+                referenced_columns: row.get(5),
+            };
+            (row.get(0), foreign_key)
+        })
+        .collect();
+    push_foreign_keys(&mut tables, foreign_keys);
+
+    // Get the unique constraints that can be the target of ON CONFLICT, with their columns in key
+    // order
+    let unique_constraints = query(
+        client,
+        simple_query,
+        "SELECT t.relname, c.conname,
+            ARRAY(SELECT a.attname::text FROM unnest(c.conkey) WITH ORDINALITY k(attnum, position)
+                JOIN pg_catalog.pg_attribute a ON a.attrelid = c.conrelid AND a.attnum = k.attnum
+                ORDER BY k.position)
+        FROM pg_catalog.pg_constraint c
+        JOIN pg_catalog.pg_class t ON t.oid = c.conrelid
+        JOIN pg_catalog.pg_namespace n ON n.oid = t.relnamespace
+        WHERE c.contype = 'u' AND NOT c.condeferrable AND n.nspname = $1
+        ORDER BY t.relname, c.conname",
+        &[(&schema, Type::NAME)],
+    )?;
+    let unique_constraints = unique_constraints
+        .iter()
+        .map(|row| {
+            let unique_constraint = UniqueConstraint {
+                name: row.get(1),
+                columns: row.get(2),
+            };
+            (row.get(0), unique_constraint)
+        })
+        .collect();
+    push_unique_constraints(&mut tables, unique_constraints);
+
+    // Get the indexes with their key columns, or expressions, in key order. Before covering
+    // indexes, every column of an index was part of the key
+    let key_columns = if version.has_covering_indexes() {
+        "x.indnkeyatts"
+    } else {
+        "x.indnatts"
+    };
+    let sql = format!(
+        "SELECT t.relname, i.relname, am.amname, x.indisunique, x.indisprimary,
+            ARRAY(SELECT pg_catalog.pg_get_indexdef(x.indexrelid, k, true)
+                FROM generate_series(1, {}) k ORDER BY k),
+            pg_catalog.pg_get_expr(x.indpred, x.indrelid, true)
+        FROM pg_catalog.pg_index x
+        JOIN pg_catalog.pg_class t ON t.oid = x.indrelid
+        JOIN pg_catalog.pg_namespace n ON n.oid = t.relnamespace
+        JOIN pg_catalog.pg_class i ON i.oid = x.indexrelid
+        JOIN pg_catalog.pg_am am ON am.oid = i.relam
+        WHERE n.nspname = $1
+        ORDER BY t.relname, i.relname",
+        key_columns
+    );
+    let indexes = query(client, simple_query, &sql, &[(&schema, Type::NAME)])?;
+    let indexes = indexes
+        .iter()
+        .map(|row| {
+            let index = Index {
+                name: row.get(1),
+                columns: row.get(5),
+                unique: row.get(3),
+                primary: row.get(4),
+                method: row.get(2),
+                predicate: row.get(6),
+            };
+            (row.get(0), index)
+        })
+        .collect();
+    push_indexes(&mut tables, indexes);
+
+    // Get the enum types and their labels
+    let labels = query(
+        client,
+        simple_query,
+        "SELECT t.typname, e.enumlabel
+        FROM pg_catalog.pg_type t
+        JOIN pg_catalog.pg_namespace n ON n.oid = t.typnamespace
+        JOIN pg_catalog.pg_enum e ON e.enumtypid = t.oid
+        WHERE n.nspname = $1
+        ORDER BY t.typname, e.enumsortorder",
+        &[(&schema, Type::NAME)],
+    )?;
+
+    let mut enums: Vec<EnumType> = Vec::new();
+    for row in labels {
+        let type_name: String = row.get(0);
+        if enums
+            .last()
+            .map(|enum_type| enum_type.name != type_name)
+            .unwrap_or(true)
+        {
+            enums.push(EnumType {
+                name: type_name,
+                labels: Vec::new(),
+            });
+        }
+        enums.last_mut().unwrap().labels.push(row.get(1));
+    }
+
+    Ok(Schema {
+        name: schema.to_string(),
+        tables,
+        enums,
+        composites,
+        scope: Some(Scope {
+            views: include_views,
+            materialized_views: include_views,
+        }),
+    })
+}
+
+/// Decode the length, precision, and scale packed into a type modifier
+fn decode_type_modifier(data_type: &mut DataType, type_modifier: i32) {
+    if type_modifier < 0 {
+        return;
+    }
+
+    match data_type.name.as_str() {
+        // The modifier includes the size of the varlena header
+        "character" | "character varying" => {
+            data_type.length = Some(type_modifier - 4);
+        }
+        "bit" | "bit varying" => {
+            data_type.length = Some(type_modifier);
+        }
+        "numeric" => {
+            data_type.precision = Some(((type_modifier - 4) >> 16) & 0xffff);
+            data_type.scale = Some((type_modifier - 4) & 0xffff);
+        }
+        "time without time zone"
+        | "time with time zone"
+        | "timestamp without time zone"
+        | "timestamp with time zone" => {
+            data_type.precision = Some(type_modifier);
+        }
+        _ => {}
+    }
+}
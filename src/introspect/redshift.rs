@@ -0,0 +1,113 @@
+use postgres::types::Type;
+use postgres::GenericClient;
+
+use crate::connection::query;
+
+use crate::model::{Column, DataType, Schema, Scope, TableKind, TypeKind};
+
+use super::{push_column, set_definitions};
+
+/// Read a schema from an Amazon Redshift cluster
+///
+/// Redshift only has part of information_schema and none of the newer catalog columns. It has no
+/// arrays, enums or composite types, and identity columns show up as an `"identity"(...)` default.
+pub fn introspect(
+    client: &mut impl GenericClient,
+    simple_query: bool,
+    schema: &str,
+    include_views: bool,
+) -> Result<Schema, postgres::Error> {
+    // Get every column of every table in one query
+    let columns = query(
+        client,
+        simple_query,
+        "SELECT t.table_name, t.table_type = 'VIEW',
+            c.column_name, c.is_nullable, c.column_default, c.data_type,
+            c.character_maximum_length::integer, c.numeric_precision::integer,
+            c.numeric_scale::integer, c.datetime_precision::integer
+        FROM information_schema.tables t
+        LEFT JOIN information_schema.columns c
+            ON c.table_schema = t.table_schema AND c.table_name = t.table_name
+        WHERE t.table_schema = $1 AND (t.table_type = 'BASE TABLE' OR ($2 AND t.table_type = 'VIEW'))
+        ORDER BY t.table_name, c.ordinal_position",
+        &[(&schema, Type::TEXT), (&include_views, Type::BOOL)],
+    )?;
+
+    let mut tables = Vec::new();
+    for row in columns {
+        let table_name: String = row.get(0);
+        let column_name: Option<String> = row.get(2);
+
+        let column = column_name.map(|column_name| {
+            let is_nullable: String = row.get(3);
+            let default: Option<String> = row.get(4);
+            let name: String = row.get(5);
+
+            // Only numeric and the time types carry a precision in their declaration
+            let (precision, scale) = if name == "numeric" {
+                (row.get(7), row.get(8))
+            } else if name.starts_with("time") {
+                (row.get(9), None)
+            } else {
+                (None, None)
+            };
+
+            Column {
+                name: column_name,
+                data_type: DataType {
+                    name,
+                    schema: "pg_catalog".to_string(),
+                    kind: TypeKind::Base,
+                    dimensions: 0,
+                    element_not_null: false,
+                    length: row.get(6),
+                    precision,
+                    scale,
+                },
+                nullable: is_nullable == "YES",
+                identity: default
+                    .as_deref()
+                    .map(|default| default.starts_with("\"identity\"("))
+                    .unwrap_or(false),
+                default,
+                generated: false,
+                comment: None,
+            }
+        });
+
+        // Tell views apart from tables
+        let kind = if row.get(1) {
+            TableKind::View
+        } else {
+            TableKind::Table
+        };
+        push_column(&mut tables, table_name, kind, None, column);
+    }
+
+    // Get the query of every view, which is only shown to the owners of the view
+    if include_views {
+        let definitions = query(
+            client,
+            simple_query,
+            "SELECT table_name, view_definition FROM information_schema.views
+            WHERE table_schema = $1",
+            &[(&schema, Type::TEXT)],
+        )?;
+        let definitions = definitions
+            .iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect();
+        set_definitions(&mut tables, definitions);
+    }
+
+    Ok(Schema {
+        name: schema.to_string(),
+        tables,
+        enums: Vec::new(),
+        composites: Vec::new(),
+        scope: Some(Scope {
+            views: include_views,
+            materialized_views: false,
+        }),
+    })
+}
@@ -0,0 +1,584 @@
+use std::collections::{HashMap, HashSet};
+
+use postgres::types::Type;
+use postgres::GenericClient;
+use tracing::debug;
+
+use crate::connection::query;
+use crate::model::{Table, TableKind};
+
+/// A value in the text form of a `pg_node_tree`, such as a view's stored query
+///
+/// Nodes look like `{NAME :field value ...}` and lists like `(value ...)`.
+#[derive(Debug)]
+enum Value {
+    Node(String, Vec<(String, Value)>),
+    List(Vec<Value>),
+    Atom(String),
+}
+
+impl Value {
+    /// Get a field of a node
+    fn field(&self, name: &str) -> Option<&Value> {
+        match self {
+            Value::Node(_, fields) => fields
+                .iter()
+                .find(|(field, _)| field == name)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    /// Get the name of a node
+    fn node_name(&self) -> Option<&str> {
+        match self {
+            Value::Node(name, _) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Get an atom, `<>` is NULL
+    fn atom(&self) -> Option<&str> {
+        match self {
+            Value::Atom(atom) if atom != "<>" => Some(atom),
+            _ => None,
+        }
+    }
+
+    /// Get the items of a list, NULL is an empty list
+    fn items(&self) -> &[Value] {
+        match self {
+            Value::List(items) => items,
+            _ => &[],
+        }
+    }
+
+    /// Get an integer field of a node
+    fn integer(&self, name: &str) -> Option<i64> {
+        self.field(name)?.atom()?.parse().ok()
+    }
+}
+
+/// Split a node tree into tokens, dropping the backslashes that escape special characters
+fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' | '{' | '}' => {
+                tokens.push(c.to_string());
+                chars.next();
+            }
+            _ => {
+                let mut token = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '(' | ')' | '{' | '}') {
+                        break;
+                    }
+                    chars.next();
+                    if c == '\\' {
+                        if let Some(escaped) = chars.next() {
+                            token.push(escaped);
+                        }
+                    } else {
+                        token.push(c);
+                    }
+                }
+                tokens.push(token);
+            }
+        }
+    }
+
+    tokens
+}
+
+/// Parse the value starting at `tokens[*position]`
+fn parse_value(tokens: &[String], position: &mut usize) -> Value {
+    let token = tokens.get(*position).map(String::as_str).unwrap_or(")");
+    *position += 1;
+    match token {
+        "{" => {
+            let name = tokens.get(*position).cloned().unwrap_or_default();
+            *position += 1;
+
+            let mut fields = Vec::new();
+            while let Some(token) = tokens.get(*position) {
+                if token == "}" {
+                    *position += 1;
+                    break;
+                }
+
+                // A field is usually one value, but some (like constant datums) are several
+                let name = token.trim_start_matches(':').to_string();
+                *position += 1;
+                let mut values = Vec::new();
+                while let Some(token) = tokens.get(*position) {
+                    if token == "}" || (token.starts_with(':') && !values.is_empty()) {
+                        break;
+                    }
+                    values.push(parse_value(tokens, position));
+                }
+                let value = if values.len() == 1 {
+                    values.pop().unwrap()
+                } else {
+                    Value::List(values)
+                };
+                fields.push((name, value));
+            }
+
+            Value::Node(name, fields)
+        }
+        "(" => {
+            let mut items = Vec::new();
+            while let Some(token) = tokens.get(*position) {
+                if token == ")" {
+                    *position += 1;
+                    break;
+                }
+                items.push(parse_value(tokens, position));
+            }
+            Value::List(items)
+        }
+        token => Value::Atom(token.to_string()),
+    }
+}
+
+/// Collect the range table indexes under a join tree node that an outer join can fill with NULLs
+fn collect_nullable(node: &Value, nullable: bool, result: &mut HashSet<i64>) {
+    match node.node_name() {
+        Some("RANGETBLREF") => {
+            if let (true, Some(index)) = (nullable, node.integer("rtindex")) {
+                result.insert(index);
+            }
+        }
+        Some("FROMEXPR") => {
+            for item in node.field("fromlist").map(Value::items).unwrap_or_default() {
+                collect_nullable(item, nullable, result);
+            }
+        }
+        Some("JOINEXPR") => {
+            // Left, full, and right joins are 1, 2, and 3
+            let join_type = node.integer("jointype").unwrap_or(0);
+            if let Some(left) = node.field("larg") {
+                collect_nullable(left, nullable || matches!(join_type, 2 | 3), result);
+            }
+            if let Some(right) = node.field("rarg") {
+                collect_nullable(right, nullable || matches!(join_type, 1 | 2), result);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Find the table column behind each column of a view, given the view's `_RETURN` rule
+///
+/// Only columns that are a plain reference to a table column are included, and only when nothing
+/// in the query can turn them into NULL: outer joins, set operations, and grouping sets.
+fn column_origins(ev_action: &str) -> HashMap<String, (u32, i16)> {
+    let tokens = tokenize(ev_action);
+    let root = parse_value(&tokens, &mut 0);
+    let mut origins = HashMap::new();
+
+    let Some(query) = root.items().first() else {
+        return origins;
+    };
+    if query.field("setOperations").and_then(Value::atom).is_some()
+        || !query
+            .field("groupingSets")
+            .map(Value::items)
+            .unwrap_or_default()
+            .is_empty()
+    {
+        return origins;
+    }
+
+    let mut nullable = HashSet::new();
+    if let Some(join_tree) = query.field("jointree") {
+        collect_nullable(join_tree, false, &mut nullable);
+    }
+    let range_table = query.field("rtable").map(Value::items).unwrap_or_default();
+
+    for entry in query
+        .field("targetList")
+        .map(Value::items)
+        .unwrap_or_default()
+    {
+        let (Some(name), Some(table), Some(column)) = (
+            entry.field("resname").and_then(Value::atom),
+            entry.integer("resorigtbl"),
+            entry.integer("resorigcol"),
+        ) else {
+            continue;
+        };
+        if table == 0 || entry.field("resjunk").and_then(Value::atom) == Some("true") {
+            continue;
+        }
+
+        // The column must come straight from a relation, not from an expression or a join's
+        // merged column
+        let Some(variable) = entry
+            .field("expr")
+            .filter(|expr| expr.node_name() == Some("VAR"))
+        else {
+            continue;
+        };
+        let Some(index) = variable.integer("varno") else {
+            continue;
+        };
+        let is_relation = usize::try_from(index - 1)
+            .ok()
+            .and_then(|index| range_table.get(index))
+            .and_then(|entry| entry.integer("rtekind"))
+            == Some(0);
+        if variable.integer("varlevelsup") != Some(0) || !is_relation || nullable.contains(&index) {
+            continue;
+        }
+
+        origins.insert(name.to_string(), (table as u32, column as i16));
+    }
+
+    origins
+}
+
+/// Mark the view columns that can't be NULL, which the catalogs report as nullable
+///
+/// A view column is NOT NULL when it comes straight from a NOT NULL column of a table or of
+/// another view whose column was inferred the same way.
+pub fn infer_nullability(
+    client: &mut impl GenericClient,
+    simple_query: bool,
+    schema: &str,
+    tables: &mut [Table],
+) -> Result<(), postgres::Error> {
+    // Get the query of every view in the schema
+    let rules = query(
+        client,
+        simple_query,
+        "SELECT c.relname, c.oid, r.ev_action::text
+        FROM pg_catalog.pg_rewrite r
+        JOIN pg_catalog.pg_class c ON c.oid = r.ev_class
+        JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+        WHERE n.nspname = $1 AND c.relkind IN ('v', 'm') AND r.rulename = '_RETURN'",
+        &[(&schema, Type::NAME)],
+    )?;
+
+    let mut view_oids: HashMap<u32, String> = HashMap::new();
+    let mut origins: HashMap<String, HashMap<String, (u32, i16)>> = HashMap::new();
+    for row in rules {
+        let name: String = row.get(0);
+        view_oids.insert(row.get(1), name.clone());
+        origins.insert(name, column_origins(row.get(2)));
+    }
+
+    // Look up whether the columns the views come from are NOT NULL
+    let (relations, columns): (Vec<u32>, Vec<i16>) = origins
+        .values()
+        .flat_map(|columns| columns.values().copied())
+        .unzip();
+    let not_null: HashSet<(u32, i16)> = query(
+        client,
+        simple_query,
+        "SELECT a.attrelid, a.attnum
+        FROM pg_catalog.pg_attribute a
+        JOIN unnest($1::oid[], $2::int2[]) AS origin(relation, number)
+            ON a.attrelid = origin.relation AND a.attnum = origin.number
+        WHERE a.attnotnull",
+        &[(&relations, Type::OID_ARRAY), (&columns, Type::INT2_ARRAY)],
+    )?
+    .iter()
+    .map(|row| (row.get(0), row.get(1)))
+    .collect();
+
+    mark_not_null(tables, &view_oids, &origins, &not_null);
+
+    Ok(())
+}
+
+/// Mark the view columns whose origin is NOT NULL, given the origins of the columns of each view
+/// by name, the views by oid, and the table columns that are NOT NULL
+fn mark_not_null(
+    tables: &mut [Table],
+    view_oids: &HashMap<u32, String>,
+    origins: &HashMap<String, HashMap<String, (u32, i16)>>,
+    not_null: &HashSet<(u32, i16)>,
+) {
+    // Views built on other views need the inferred columns of those, so repeat until nothing
+    // changes
+    loop {
+        let mut changed = false;
+        for index in 0..tables.len() {
+            if tables[index].kind == TableKind::Table {
+                continue;
+            }
+            let Some(view_origins) = origins.get(&tables[index].name) else {
+                continue;
+            };
+
+            for column_index in 0..tables[index].columns.len() {
+                let column = &tables[index].columns[column_index];
+                let Some(&(relation, number)) = view_origins.get(&column.name) else {
+                    continue;
+                };
+                if !column.nullable {
+                    continue;
+                }
+
+                // Views have no dropped columns, so the column number is the position
+                let origin_not_null = match view_oids.get(&relation) {
+                    Some(view) => tables
+                        .iter()
+                        .find(|table| &table.name == view)
+                        .and_then(|table| table.columns.get(usize::try_from(number - 1).ok()?))
+                        .map(|column| !column.nullable)
+                        .unwrap_or(false),
+                    None => not_null.contains(&(relation, number)),
+                };
+
+                if origin_not_null {
+                    debug!(
+                        "Inferred {}.{} is NOT NULL",
+                        tables[index].name, tables[index].columns[column_index].name
+                    );
+                    tables[index].columns[column_index].nullable = false;
+                    changed = true;
+                }
+            }
+        }
+
+        if !changed {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Column, DataType, TypeKind};
+
+    // The oids of the tables the captured views select from, `a (id integer NOT NULL, name text
+    // NOT NULL, note text)` and `b (id integer NOT NULL, a_id integer NOT NULL, label text NOT
+    // NULL)`
+    const A: u32 = 23406;
+    const B: u32 = 23411;
+
+    // The `_RETURN` rules of views on those tables, as PostgreSQL 15 prints `ev_action`
+    const VIEWS: &[(&str, u32, &str, &[&str])] = &[
+        (
+            "inner_join",
+            23416,
+            include_str!("testdata/inner_join.txt"),
+            &["id", "note", "label"],
+        ),
+        (
+            "left_join",
+            23420,
+            include_str!("testdata/left_join.txt"),
+            &["id", "label"],
+        ),
+        (
+            "right_join",
+            23424,
+            include_str!("testdata/right_join.txt"),
+            &["id", "label"],
+        ),
+        (
+            "full_join",
+            23428,
+            include_str!("testdata/full_join.txt"),
+            &["id", "label"],
+        ),
+        (
+            "set_operation",
+            23432,
+            include_str!("testdata/set_operation.txt"),
+            &["id", "name"],
+        ),
+        (
+            "subquery",
+            23436,
+            include_str!("testdata/subquery.txt"),
+            &["id", "name"],
+        ),
+        (
+            "correlated",
+            23440,
+            include_str!("testdata/correlated.txt"),
+            &["id", "label"],
+        ),
+        (
+            "expressions",
+            23444,
+            include_str!("testdata/expressions.txt"),
+            &["next_id", "upper_name", "Renamed"],
+        ),
+        (
+            "on_view",
+            23448,
+            include_str!("testdata/on_view.txt"),
+            &["id", "label"],
+        ),
+    ];
+
+    fn relation(name: &str, kind: TableKind, columns: &[&str], nullable: bool) -> Table {
+        Table {
+            name: name.to_string(),
+            kind,
+            columns: columns
+                .iter()
+                .map(|column| Column {
+                    name: column.to_string(),
+                    data_type: DataType {
+                        name: "text".to_string(),
+                        schema: "pg_catalog".to_string(),
+                        kind: TypeKind::Base,
+                        dimensions: 0,
+                        element_not_null: false,
+                        length: None,
+                        precision: None,
+                        scale: None,
+                    },
+                    nullable,
+                    default: None,
+                    identity: false,
+                    generated: false,
+                    comment: None,
+                })
+                .collect(),
+            primary_key: Vec::new(),
+            foreign_keys: Vec::new(),
+            unique_constraints: Vec::new(),
+            indexes: Vec::new(),
+            comment: None,
+            definition: None,
+        }
+    }
+
+    /// Infer the nullability of every captured view, returning the NOT NULL columns of each
+    fn inferred() -> HashMap<String, Vec<String>> {
+        let mut tables = vec![
+            relation("a", TableKind::Table, &["id", "name", "note"], false),
+            relation("b", TableKind::Table, &["id", "a_id", "label"], false),
+        ];
+        let mut view_oids = HashMap::new();
+        let mut origins = HashMap::new();
+        for (name, oid, ev_action, columns) in VIEWS {
+            tables.push(relation(name, TableKind::View, columns, true));
+            view_oids.insert(*oid, name.to_string());
+            origins.insert(name.to_string(), column_origins(ev_action));
+        }
+        let not_null = HashSet::from([(A, 1), (A, 2), (B, 1), (B, 2), (B, 3)]);
+
+        mark_not_null(&mut tables, &view_oids, &origins, &not_null);
+        tables
+            .iter()
+            .filter(|table| table.kind == TableKind::View)
+            .map(|table| {
+                let columns = table
+                    .columns
+                    .iter()
+                    .filter(|column| !column.nullable)
+                    .map(|column| column.name.clone())
+                    .collect();
+                (table.name.clone(), columns)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn nodes_lists_and_escapes_are_parsed() {
+        let tokens = tokenize(r#"({ALIAS :aliasname my\ view :colnames ("a\(b" "c")} <>)"#);
+        assert_eq!(
+            tokens,
+            [
+                "(",
+                "{",
+                "ALIAS",
+                ":aliasname",
+                "my view",
+                ":colnames",
+                "(",
+                "\"a(b\"",
+                "\"c\"",
+                ")",
+                "}",
+                "<>",
+                ")"
+            ]
+        );
+
+        let root = parse_value(&tokens, &mut 0);
+        let [alias, null] = root.items() else {
+            panic!("expected two items, got {:?}", root);
+        };
+        assert_eq!(alias.node_name(), Some("ALIAS"));
+        assert_eq!(
+            alias.field("aliasname").and_then(Value::atom),
+            Some("my view")
+        );
+        assert_eq!(
+            alias.field("colnames").map(|names| names.items().len()),
+            Some(2)
+        );
+        assert_eq!(null.atom(), None);
+        assert!(null.items().is_empty());
+    }
+
+    #[test]
+    fn constants_with_several_values_are_one_field() {
+        let tokens = tokenize("{CONST :consttype 23 :constvalue 4 [ 1 0 0 0 ] :location 7}");
+        let node = parse_value(&tokens, &mut 0);
+        assert_eq!(node.integer("consttype"), Some(23));
+        assert_eq!(
+            node.field("constvalue").map(|value| value.items().len()),
+            Some(7)
+        );
+        assert_eq!(node.integer("location"), Some(7));
+    }
+
+    #[test]
+    fn columns_only_come_from_tables_no_outer_join_makes_nullable() {
+        let origins = column_origins(include_str!("testdata/inner_join.txt"));
+        assert_eq!(
+            origins,
+            HashMap::from([
+                ("id".to_string(), (A, 1)),
+                ("note".to_string(), (A, 3)),
+                ("label".to_string(), (B, 3)),
+            ])
+        );
+
+        let origins = column_origins(include_str!("testdata/left_join.txt"));
+        assert_eq!(origins, HashMap::from([("id".to_string(), (A, 1))]));
+        let origins = column_origins(include_str!("testdata/right_join.txt"));
+        assert_eq!(origins, HashMap::from([("label".to_string(), (B, 3))]));
+        assert!(column_origins(include_str!("testdata/full_join.txt")).is_empty());
+        assert!(column_origins(include_str!("testdata/set_operation.txt")).is_empty());
+        assert!(column_origins("").is_empty());
+    }
+
+    #[test]
+    fn nullability_follows_joins_set_operations_subqueries_and_expressions() {
+        let inferred = inferred();
+        let not_null = |view: &str| inferred[view].clone();
+
+        // Inner joins keep both sides, but a nullable column stays nullable
+        assert_eq!(not_null("inner_join"), ["id", "label"]);
+        // Outer joins fill the other side with NULLs
+        assert_eq!(not_null("left_join"), ["id"]);
+        assert_eq!(not_null("right_join"), ["label"]);
+        assert!(not_null("full_join").is_empty());
+        // Set operations combine columns of different tables
+        assert!(not_null("set_operation").is_empty());
+        // Columns of subqueries aren't followed, and a scalar subquery can find no row
+        assert!(not_null("subquery").is_empty());
+        assert_eq!(not_null("correlated"), ["id"]);
+        // Expressions can be NULL, but a renamed column is still the column
+        assert_eq!(not_null("expressions"), ["Renamed"]);
+        // Views on views use what was inferred for the inner view
+        assert_eq!(not_null("on_view"), ["id", "label"]);
+    }
+}
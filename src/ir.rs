@@ -0,0 +1,651 @@
+use std::{collections::BTreeMap, fmt, fs};
+
+use convert_case::{Case, Casing};
+use proc_macro2::TokenStream;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::composites::composite_ident;
+use crate::config::{ArrayConfig, ColumnType, EnumConfig, VariantCase};
+use crate::directives;
+use crate::enums::enum_ident;
+use crate::error::{Error, Result};
+use crate::model::{
+    Attribute, Column, CompositeType, DataType, EnumType, ForeignKey, Index, Schema, Table,
+    TableKind, UniqueConstraint,
+};
+use crate::types;
+
+/// A schema with the Rust names and types of everything resolved, which is what code is
+/// generated from
+///
+/// Written with `--emit ir`, so other tools can consume exactly what the Rust code is based on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResolvedSchema {
+    /// The name of the schema
+    pub name: String,
+    /// The enum types in the schema, ordered by name
+    pub enums: Vec<ResolvedEnum>,
+    /// The tables in the schema, ordered by name
+    pub tables: Vec<ResolvedTable>,
+    /// The composite types in the schema, ordered by name
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub composites: Vec<ResolvedComposite>,
+}
+
+/// An enum type and the Rust enum generated for it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResolvedEnum {
+    /// The name of the type
+    pub name: String,
+    /// The name of the Rust enum
+    pub rust_name: String,
+    /// The labels of the enum, in sort order
+    pub variants: Vec<ResolvedVariant>,
+}
+
+/// An enum label and the Rust variant generated for it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResolvedVariant {
+    /// The label in the database
+    pub label: String,
+    /// The name of the Rust variant
+    pub rust_name: String,
+}
+
+/// A composite type and the Rust struct generated for it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResolvedComposite {
+    /// The name of the type
+    pub name: String,
+    /// The name of the Rust struct
+    pub rust_name: String,
+    /// The attributes of the type, in the order they were declared
+    pub fields: Vec<ResolvedAttribute>,
+}
+
+/// An attribute of a composite type and the Rust field generated for it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResolvedAttribute {
+    /// The name of the attribute
+    pub name: String,
+    /// The name of the Rust field
+    pub rust_name: String,
+    /// The type of the Rust field, which is always an `Option`
+    pub rust_type: String,
+    /// The type of the attribute
+    pub data_type: DataType,
+}
+
+/// A table and the Rust struct generated for it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResolvedTable {
+    /// The name of the table
+    pub name: String,
+    /// Whether this is a table or a view
+    pub kind: TableKind,
+    /// The name of the Rust struct
+    pub rust_name: String,
+    /// The columns of the table, in the order they were declared
+    pub columns: Vec<ResolvedColumn>,
+    /// The columns of the primary key, in key order. Empty when the table doesn't have one
+    #[serde(default)]
+    pub primary_key: Vec<String>,
+    /// The foreign keys of the table, ordered by name
+    pub foreign_keys: Vec<ForeignKey>,
+    /// The unique constraints of the table, ordered by name
+    #[serde(default)]
+    pub unique_constraints: Vec<UniqueConstraint>,
+    /// The indexes of the table, ordered by name
+    #[serde(default)]
+    pub indexes: Vec<Index>,
+    /// Extra derives for the Rust struct, like `Copy` or `Hash`
+    #[serde(default)]
+    pub derives: Vec<String>,
+    /// Whether to leave out the `Copy`, `PartialOrd` and `Hash` derives the fields would allow
+    #[serde(default)]
+    pub no_auto_derive: bool,
+    /// Why the table is deprecated, which may be empty, if it is
+    #[serde(default)]
+    pub deprecated: Option<String>,
+    /// The comment on the table, if any
+    pub comment: Option<String>,
+    /// The query that defines a view, if the database shows it
+    #[serde(default)]
+    pub definition: Option<String>,
+}
+
+/// A column and the Rust field generated for it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResolvedColumn {
+    /// The name of the column
+    pub name: String,
+    /// The name of the Rust field
+    pub rust_name: String,
+    /// The type of the Rust field, including the `Option` of nullable columns
+    pub rust_type: String,
+    /// The type of the column
+    pub data_type: DataType,
+    /// Whether the column accepts NULL
+    pub nullable: bool,
+    /// The default expression of the column, if any
+    pub default: Option<String>,
+    /// Whether the column is an identity column, numbered by the database
+    pub identity: bool,
+    /// Whether the column is a generated column, computed from the other columns
+    pub generated: bool,
+    /// The comment on the column, if any
+    pub comment: Option<String>,
+    /// Why the column is deprecated, which may be empty, if it is
+    #[serde(default)]
+    pub deprecated: Option<String>,
+    /// Whether the column holds sensitive data, which is left out of serialization and `Debug`
+    #[serde(default)]
+    pub redacted: bool,
+    /// Whether the column is left out of `Debug`, but still serialized
+    #[serde(default)]
+    pub masked: bool,
+}
+
+impl ResolvedSchema {
+    /// Resolve the Rust names and types of everything in a schema
+    ///
+    /// `@rust(...)` directives in the comments of tables and columns are applied, so they can
+    /// skip, rename, or retype them.
+    pub fn resolve(
+        schema: &Schema,
+        use_uuid: bool,
+        precise_types: bool,
+        citext_as_string: bool,
+        bytes_type: types::BytesType,
+        arrays: &ArrayConfig,
+        enums: &EnumConfig,
+    ) -> Result<Self> {
+        let enums = schema
+            .enums
+            .iter()
+            .map(|enum_type| resolve_enum(enum_type, enums.variant_case))
+            .collect();
+
+        let composites = schema
+            .composites
+            .iter()
+            .map(|composite| ResolvedComposite {
+                name: composite.name.clone(),
+                rust_name: composite_ident(&composite.name).to_string(),
+                fields: composite
+                    .attributes
+                    .iter()
+                    .map(|attribute| ResolvedAttribute {
+                        name: attribute.name.clone(),
+                        rust_name: attribute.name.to_case(Case::Snake),
+                        rust_type: format!(
+                            "Option<{}>",
+                            type_string(&types::map_column_type(
+                                &attribute.data_type,
+                                &schema.name,
+                                use_uuid,
+                                precise_types,
+                                citext_as_string,
+                                bytes_type,
+                                arrays,
+                            ))
+                        ),
+                        data_type: attribute.data_type.clone(),
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        let mut tables = Vec::new();
+        for table in &schema.tables {
+            let context = format!("table {}", table.name);
+            let table_directives = directives::parse(table.comment.as_deref(), &context)?;
+            if table_directives.rust_type.is_some() {
+                return Err(Error::Config(format!(
+                    "Invalid @rust directive on {}: type only applies to columns",
+                    context
+                )));
+            }
+            if table_directives.skip {
+                info!("Skipping table {} as its comment says so", table.name);
+                continue;
+            }
+
+            let mut columns = Vec::new();
+            for column in &table.columns {
+                let context = format!("column {}.{}", table.name, column.name);
+                let column_directives = directives::parse(column.comment.as_deref(), &context)?;
+                if !column_directives.derives.is_empty() {
+                    return Err(Error::Config(format!(
+                        "Invalid @rust directive on {}: derive only applies to tables",
+                        context
+                    )));
+                }
+                if column_directives.no_auto_derive {
+                    return Err(Error::Config(format!(
+                        "Invalid @rust directive on {}: no_auto_derive only applies to tables",
+                        context
+                    )));
+                }
+                if column_directives.skip {
+                    info!("Skipping {} as its comment says so", context);
+                    continue;
+                }
+
+                let rust_type = match &column_directives.rust_type {
+                    Some(rust_type) => rust_type.clone(),
+                    None => type_string(&types::map_column_type(
+                        &column.data_type,
+                        &schema.name,
+                        use_uuid,
+                        precise_types,
+                        citext_as_string,
+                        bytes_type,
+                        arrays,
+                    )),
+                };
+
+                // If the column is nullable, set the Rust type to an Option
+                let rust_type = if column.nullable {
+                    format!("Option<{}>", rust_type)
+                } else {
+                    rust_type
+                };
+
+                columns.push(ResolvedColumn {
+                    name: column.name.clone(),
+                    rust_name: column_directives
+                        .rename
+                        .unwrap_or_else(|| column.name.to_case(Case::Snake)),
+                    rust_type,
+                    data_type: column.data_type.clone(),
+                    nullable: column.nullable,
+                    default: column.default.clone(),
+                    identity: column.identity,
+                    generated: column.generated,
+                    comment: column.comment.clone(),
+                    deprecated: column_directives.deprecated,
+                    redacted: false,
+                    masked: false,
+                });
+            }
+
+            tables.push(ResolvedTable {
+                name: table.name.clone(),
+                kind: table.kind,
+                rust_name: table_directives
+                    .rename
+                    .unwrap_or_else(|| table.name.to_case(Case::Pascal)),
+                columns,
+                primary_key: table.primary_key.clone(),
+                foreign_keys: table.foreign_keys.clone(),
+                unique_constraints: table.unique_constraints.clone(),
+                indexes: table.indexes.clone(),
+                derives: table_directives.derives,
+                no_auto_derive: table_directives.no_auto_derive,
+                deprecated: table_directives.deprecated,
+                comment: table.comment.clone(),
+                definition: table.definition.clone(),
+            });
+        }
+
+        Ok(ResolvedSchema {
+            name: schema.name.clone(),
+            enums,
+            tables,
+            composites,
+        })
+    }
+
+    /// Get the columns that fell back to `String` because their type has no Rust mapping
+    ///
+    /// Columns whose type was set by a directive, `column_types` from the config file or an
+    /// edited model are left out, since someone picked their type, even if it is `String`.
+    pub fn fallback_columns(
+        &self,
+        use_uuid: bool,
+        precise_types: bool,
+        citext_as_string: bool,
+        bytes_type: types::BytesType,
+        arrays: &ArrayConfig,
+        column_types: &BTreeMap<String, ColumnType>,
+    ) -> Vec<FallbackColumn> {
+        let mut fallback_columns = Vec::new();
+        for table in &self.tables {
+            for column in &table.columns {
+                if !types::falls_back(&column.data_type, &self.name)
+                    || column_types.contains_key(&format!("{}.{}", table.name, column.name))
+                {
+                    continue;
+                }
+                let context = format!("column {}.{}", table.name, column.name);
+                let has_directive = directives::parse(column.comment.as_deref(), &context)
+                    .is_ok_and(|directives| directives.rust_type.is_some());
+
+                let rust_type = type_string(&types::map_column_type(
+                    &column.data_type,
+                    &self.name,
+                    use_uuid,
+                    precise_types,
+                    citext_as_string,
+                    bytes_type,
+                    arrays,
+                ));
+                let rust_type = if column.nullable {
+                    format!("Option<{}>", rust_type)
+                } else {
+                    rust_type
+                };
+                if !has_directive && column.rust_type == rust_type {
+                    fallback_columns.push(FallbackColumn {
+                        schema: self.name.clone(),
+                        table: table.name.clone(),
+                        column: column.name.clone(),
+                        data_type: column.data_type.to_string(),
+                    });
+                }
+            }
+        }
+
+        fallback_columns
+    }
+
+    /// Get the names of `roots` and of every table they reference through foreign keys,
+    /// transitively, for `--with-dependencies`
+    ///
+    /// References to tables of other schemas aren't followed, since they aren't generated with
+    /// this one.
+    pub fn with_dependencies(&self, roots: &[String]) -> Vec<String> {
+        let mut tables = roots.to_vec();
+        let mut index = 0;
+        while let Some(table_name) = tables.get(index) {
+            index += 1;
+            let Some(table) = self.tables.iter().find(|table| &table.name == table_name) else {
+                continue;
+            };
+            let table_name = table_name.clone();
+            for foreign_key in &table.foreign_keys {
+                let referenced = &foreign_key.referenced_table;
+                if foreign_key.referenced_schema != self.name {
+                    warn!(
+                        "Table {} references {}.{}, which isn't in schema {}, leaving it out",
+                        table_name, foreign_key.referenced_schema, referenced, self.name
+                    );
+                } else if !tables.contains(referenced) {
+                    info!(
+                        "Including table {}, which {} references",
+                        referenced, table_name
+                    );
+                    tables.push(referenced.clone());
+                }
+            }
+        }
+
+        tables
+    }
+
+    /// Get the schema the model was resolved from, without the Rust names and types
+    pub fn to_schema(&self) -> Schema {
+        let tables = self
+            .tables
+            .iter()
+            .map(|table| Table {
+                name: table.name.clone(),
+                kind: table.kind,
+                columns: table
+                    .columns
+                    .iter()
+                    .map(ResolvedColumn::to_column)
+                    .collect(),
+                primary_key: table.primary_key.clone(),
+                foreign_keys: table.foreign_keys.clone(),
+                unique_constraints: table.unique_constraints.clone(),
+                indexes: table.indexes.clone(),
+                comment: table.comment.clone(),
+                definition: table.definition.clone(),
+            })
+            .collect();
+
+        let enums = self
+            .enums
+            .iter()
+            .map(|enum_type| EnumType {
+                name: enum_type.name.clone(),
+                labels: enum_type
+                    .variants
+                    .iter()
+                    .map(|variant| variant.label.clone())
+                    .collect(),
+            })
+            .collect();
+
+        let composites = self
+            .composites
+            .iter()
+            .map(|composite| CompositeType {
+                name: composite.name.clone(),
+                attributes: composite
+                    .fields
+                    .iter()
+                    .map(|field| Attribute {
+                        name: field.name.clone(),
+                        data_type: field.data_type.clone(),
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        Schema {
+            name: self.name.clone(),
+            tables,
+            enums,
+            composites,
+            scope: None,
+        }
+    }
+}
+
+/// A column whose type has no Rust mapping of its own, so it fell back to `String`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FallbackColumn {
+    /// The schema of the table
+    pub schema: String,
+    /// The name of the table
+    pub table: String,
+    /// The name of the column
+    pub column: String,
+    /// The type of the column
+    pub data_type: String,
+}
+
+impl fmt::Display for FallbackColumn {
+    /// Format the column as `table.column: type`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}: {}", self.table, self.column, self.data_type)
+    }
+}
+
+impl ResolvedColumn {
+    /// Get the column the field was resolved from, without its Rust name and type
+    pub fn to_column(&self) -> Column {
+        Column {
+            name: self.name.clone(),
+            data_type: self.data_type.clone(),
+            nullable: self.nullable,
+            default: self.default.clone(),
+            identity: self.identity,
+            generated: self.generated,
+            comment: self.comment.clone(),
+        }
+    }
+}
+
+/// Read a model written with `--emit ir`
+pub fn read_ir(path: &str) -> Result<ResolvedSchema> {
+    info!("Reading schema model from {}", path);
+    let contents = fs::read_to_string(path).map_err(Error::io(path))?;
+
+    serde_json::from_str(&contents).map_err(|source| Error::Snapshot {
+        path: path.into(),
+        source,
+    })
+}
+
+/// Resolve the names of an enum type and its variants
+///
+/// Labels are converted to PascalCase variants. Labels that start with a digit or are empty get a
+/// `V` prefix, and labels that only differ in case or punctuation are numbered.
+fn resolve_enum(enum_type: &EnumType, variant_case: VariantCase) -> ResolvedEnum {
+    let (case, prefix, separator) = match variant_case {
+        VariantCase::Pascal => (Case::Pascal, "V", ""),
+        VariantCase::UpperSnake => (Case::UpperSnake, "V_", "_"),
+        VariantCase::Snake => (Case::Snake, "v_", "_"),
+    };
+
+    let mut used_names: Vec<String> = Vec::new();
+    let variants = enum_type
+        .labels
+        .iter()
+        .map(|label| {
+            let mut name = label.to_case(case);
+            if !name.starts_with(|c: char| c.is_alphabetic()) {
+                name = format!("{}{}", prefix, name);
+            }
+            // Keywords like `Self` or `type` can't be variants
+            if syn::parse_str::<syn::Ident>(&name).is_err() {
+                name.push('_');
+            }
+
+            // Number labels that convert to a name that is already used, which can also be the
+            // numbered name of an earlier duplicate
+            let base = name.clone();
+            let mut number = 1;
+            while used_names.contains(&name) {
+                number += 1;
+                name = format!("{}{}{}", base, separator, number);
+            }
+            used_names.push(name.clone());
+
+            ResolvedVariant {
+                label: label.clone(),
+                rust_name: name,
+            }
+        })
+        .collect();
+
+    ResolvedEnum {
+        name: enum_type.name.clone(),
+        rust_name: enum_ident(&enum_type.name).to_string(),
+        variants,
+    }
+}
+
+/// Write a type the way it would be written by hand, e.g. `Option<Vec<u8>>`
+///
+/// Tokens are printed with spaces between them, which are only kept between words.
+fn type_string(rust_type: &TokenStream) -> String {
+    let tokens = rust_type.to_string();
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+
+    let chars: Vec<char> = tokens.chars().collect();
+    let mut result = String::new();
+    for (index, c) in chars.iter().enumerate() {
+        if *c == ' ' {
+            let before = index.checked_sub(1).map(|before| chars[before]);
+            let after = chars.get(index + 1);
+            if !(before.is_some_and(is_word) && after.is_some_and(|after| is_word(*after))) {
+                continue;
+            }
+        }
+        result.push(*c);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Get the variant names of an enum with some labels
+    fn variants(labels: &[&str], variant_case: VariantCase) -> Vec<String> {
+        let enum_type = EnumType {
+            name: "status".to_string(),
+            labels: labels.iter().map(|label| label.to_string()).collect(),
+        };
+        resolve_enum(&enum_type, variant_case)
+            .variants
+            .into_iter()
+            .map(|variant| variant.rust_name)
+            .collect()
+    }
+
+    #[test]
+    fn labels_are_converted_to_the_case_of_variants() {
+        let labels = ["in_progress", "on hold", "DONE", "reOpened"];
+        assert_eq!(
+            variants(&labels, VariantCase::Pascal),
+            ["InProgress", "OnHold", "Done", "ReOpened"]
+        );
+        assert_eq!(
+            variants(&labels, VariantCase::UpperSnake),
+            ["IN_PROGRESS", "ON_HOLD", "DONE", "RE_OPENED"]
+        );
+        assert_eq!(
+            variants(&labels, VariantCase::Snake),
+            ["in_progress", "on_hold", "done", "re_opened"]
+        );
+    }
+
+    #[test]
+    fn labels_that_dont_start_with_a_letter_are_prefixed() {
+        // Words are split between letters and digits
+        let labels = ["1st", "2nd_place", "_hidden"];
+        assert_eq!(
+            variants(&labels, VariantCase::Pascal),
+            ["V1St", "V2NdPlace", "Hidden"]
+        );
+        assert_eq!(
+            variants(&labels, VariantCase::UpperSnake),
+            ["V_1_ST", "V_2_ND_PLACE", "HIDDEN"]
+        );
+        assert_eq!(
+            variants(&labels, VariantCase::Snake),
+            ["v_1_st", "v_2_nd_place", "hidden"]
+        );
+    }
+
+    #[test]
+    fn keywords_get_an_underscore() {
+        assert_eq!(
+            variants(&["self", "none"], VariantCase::Pascal),
+            ["Self_", "None"]
+        );
+        assert_eq!(
+            variants(&["type", "match", "plain"], VariantCase::Snake),
+            ["type_", "match_", "plain"]
+        );
+    }
+
+    #[test]
+    fn labels_that_convert_to_the_same_name_are_numbered() {
+        assert_eq!(
+            variants(&["on-hold", "on_hold", "On Hold"], VariantCase::Pascal),
+            ["OnHold", "OnHold2", "OnHold3"]
+        );
+        assert_eq!(
+            variants(&["on-hold", "ON HOLD"], VariantCase::UpperSnake),
+            ["ON_HOLD", "ON_HOLD_2"]
+        );
+        // A label can already have the name a duplicate would be numbered with
+        assert_eq!(
+            variants(&["a", "A", "a2"], VariantCase::Pascal),
+            ["A", "A2", "A22"]
+        );
+        assert_eq!(
+            variants(&["a2", "a", "A"], VariantCase::Pascal),
+            ["A2", "A", "A3"]
+        );
+    }
+}
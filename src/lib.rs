@@ -0,0 +1,48 @@
+mod aws_iam;
+pub mod build;
+mod cache;
+pub mod cli;
+mod composites;
+mod config;
+mod connection;
+mod ddl;
+mod defaults;
+mod dependencies;
+mod diagram;
+mod diff;
+mod directives;
+mod display;
+mod docs;
+mod enums;
+pub mod error;
+mod generate;
+mod hooks;
+mod imports;
+mod indexes;
+mod interactive;
+mod introspect;
+mod ir;
+pub mod logging;
+mod metadata;
+mod migration;
+mod model;
+mod newtypes;
+mod output;
+mod pgpass;
+mod pgservice;
+mod proto;
+mod redact;
+mod refs;
+mod relations;
+mod report;
+mod repository;
+mod roundtrip;
+mod routines;
+mod scaffold;
+mod sequences;
+mod snapshot;
+mod tunnel;
+mod types;
+mod version;
+
+pub use generate::{diff_schema, expand, run, verify, DiffOutcome, VerifyOutcome};
@@ -0,0 +1,104 @@
+use std::{fmt, fs::File, io, sync::Mutex};
+
+use tracing::{
+    field::{Field, Visit},
+    Event, Level, Subscriber,
+};
+use tracing_subscriber::{
+    fmt::writer::BoxMakeWriter,
+    layer::{Context, SubscriberExt},
+    FmtSubscriber, Layer,
+};
+
+use crate::error::{Error, Result};
+
+/// How log lines are formatted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable lines
+    Pretty,
+    /// One JSON object per line, for CI pipelines and log collectors
+    Json,
+}
+
+/// The messages of every warning logged so far, for the `--report`
+static WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Set up the global tracing subscriber
+///
+/// A verbosity of 0 logs at info level, 1 at debug level and 2 or more at trace level. Logs go to
+/// `log_file` when it's given, otherwise to stderr so reports printed to stdout stay clean.
+/// Warnings are also kept, to be listed by [`warnings`].
+pub fn init(verbosity: u8, format: LogFormat, log_file: Option<&str>) -> Result<()> {
+    let env_filter = match verbosity {
+        0 => "info",
+        1 => "debug",
+        _ => "trace",
+    };
+
+    // Colors only make sense on a terminal
+    let (writer, ansi) = match log_file {
+        Some(path) => {
+            let file = File::create(path).map_err(Error::io(path))?;
+            (BoxMakeWriter::new(Mutex::new(file)), false)
+        }
+        None => (BoxMakeWriter::new(io::stderr), format == LogFormat::Pretty),
+    };
+
+    let builder = FmtSubscriber::builder()
+        .with_max_level(tracing::Level::TRACE)
+        .with_env_filter(env_filter)
+        .with_writer(writer)
+        .with_ansi(ansi);
+
+    // Set the global default subscriber
+    match format {
+        LogFormat::Pretty => {
+            tracing::subscriber::set_global_default(builder.finish().with(WarningLayer))
+        }
+        LogFormat::Json => {
+            tracing::subscriber::set_global_default(builder.json().finish().with(WarningLayer))
+        }
+    }
+    .expect("The global subscriber is only set once");
+
+    Ok(())
+}
+
+/// Get the messages of every warning logged so far
+///
+/// Warnings are only kept once [`init`] has set up logging.
+pub fn warnings() -> Vec<String> {
+    WARNINGS
+        .lock()
+        .map(|warnings| warnings.clone())
+        .unwrap_or_default()
+}
+
+/// A layer that keeps the message of every warning
+struct WarningLayer;
+
+impl<S: Subscriber> Layer<S> for WarningLayer {
+    fn on_event(&self, event: &Event<'_>, _context: Context<'_, S>) {
+        if *event.metadata().level() != Level::WARN {
+            return;
+        }
+
+        let mut message = MessageVisitor(String::new());
+        event.record(&mut message);
+        if let Ok(mut warnings) = WARNINGS.lock() {
+            warnings.push(message.0);
+        }
+    }
+}
+
+/// Read the message of an event
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{:?}", value);
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use convert_case::{Case, Casing};
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::Ident;
+
+use crate::error::{Error, Result};
+use crate::ir::ResolvedSchema;
+use crate::model::TableKind;
+
+/// Generate a `TableMeta` const per table and view, and a `TABLES` slice of all of them, for
+/// `--metadata`
+///
+/// The consts are named after the structs in upper snake case, e.g. `USER_POSTS` for `UserPosts`.
+pub fn generate_metadata(schema: &ResolvedSchema) -> Result<TokenStream> {
+    let mut consts = Vec::new();
+    let mut names = Vec::new();
+    for table in &schema.tables {
+        let const_name = table.rust_name.to_case(Case::UpperSnake);
+        if const_name == "TABLES" || names.contains(&const_name) {
+            return Err(Error::Config(format!(
+                "The metadata of table {} would be named {}, which is already taken",
+                table.name, const_name
+            )));
+        }
+        let ident = Ident::new(&const_name, Span::call_site());
+        names.push(const_name);
+
+        let name = &table.name;
+        let schema_name = &schema.name;
+        let is_view = table.kind != TableKind::Table;
+        let columns = table.columns.iter().map(|column| {
+            let name = &column.name;
+            let postgres_type = column.data_type.to_string();
+            let nullable = column.nullable;
+            quote! {
+                ColumnMeta {
+                    name: #name,
+                    postgres_type: #postgres_type,
+                    nullable: #nullable,
+                },
+            }
+        });
+        let primary_key = &table.primary_key;
+        let doc = format!(" The metadata of `{}.{}`", schema.name, table.name);
+
+        consts.push(quote! {
+            #[doc = #doc]
+            pub const #ident: TableMeta = TableMeta {
+                name: #name,
+                schema: #schema_name,
+                is_view: #is_view,
+                columns: &[#(#columns)*],
+                primary_key: &[#(#primary_key),*],
+            };
+        });
+    }
+    let idents = names.iter().map(|name| Ident::new(name, Span::call_site()));
+
+    Ok(quote! {
+        /// A column of a table or view
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub struct ColumnMeta {
+            /// The name of the column
+            pub name: &'static str,
+            /// The type of the column, as it would be declared
+            pub postgres_type: &'static str,
+            /// Whether the column accepts NULL
+            pub nullable: bool,
+        }
+
+        /// A table or view and its columns
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub struct TableMeta {
+            /// The name of the table
+            pub name: &'static str,
+            /// The schema of the table
+            pub schema: &'static str,
+            /// Whether this is a view or materialized view
+            pub is_view: bool,
+            /// The columns of the table, in the order they were declared
+            pub columns: &'static [ColumnMeta],
+            /// The columns of the primary key, in key order. Empty when the table doesn't have one
+            pub primary_key: &'static [&'static str],
+        }
+
+        impl TableMeta {
+            /// Get a column by its name
+            pub fn column(&self, name: &str) -> Option<&'static ColumnMeta> {
+                self.columns.iter().find(|column| column.name == name)
+            }
+        }
+
+        #(#consts)*
+
+        /// Every table and view, ordered by name
+        pub static TABLES: &[TableMeta] = &[#(#idents),*];
+
+        /// Get a table or view by its name
+        pub fn table(name: &str) -> Option<&'static TableMeta> {
+            TABLES.iter().find(|table| table.name == name)
+        }
+    })
+}
@@ -0,0 +1,352 @@
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use chrono::Utc;
+use tracing::info;
+
+use crate::ddl::{
+    column_definition, column_type, literal, write_create_enum, write_create_table,
+    write_create_view, write_drop, write_foreign_keys,
+};
+use crate::diff::SchemaDiff;
+use crate::error::{Error, Result};
+use crate::model::{Schema, Table, TableKind};
+use crate::repository::quote_name;
+
+/// How migration files are named, after the tool that runs them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationStyle {
+    /// `<timestamp>_<name>.sql`, for `sqlx migrate`
+    Sqlx,
+    /// `V<version>__<name>.sql`, for refinery
+    Refinery,
+}
+
+/// Write a migration skeleton for the differences between two versions of a schema to a new file
+/// in a directory, returning its path
+pub fn write_migration(
+    directory: &str,
+    style: MigrationStyle,
+    name: &str,
+    schema_diff: &SchemaDiff,
+    old: &Schema,
+    new: &Schema,
+) -> Result<String> {
+    fs::create_dir_all(directory).map_err(Error::io(directory))?;
+    let file_name = match style {
+        MigrationStyle::Sqlx => format!("{}_{}.sql", Utc::now().format("%Y%m%d%H%M%S"), name),
+        MigrationStyle::Refinery => format!("V{}__{}.sql", next_version(directory)?, name),
+    };
+    let path = Path::new(directory).join(file_name);
+
+    info!("Writing migration skeleton to {}", path.display());
+    fs::write(&path, migration_sql(schema_diff, old, new)).map_err(Error::io(&path))?;
+
+    Ok(path.display().to_string())
+}
+
+/// Get the version after the highest one of the refinery migrations in a directory
+fn next_version(directory: &str) -> Result<u64> {
+    let mut version = 0;
+    for entry in fs::read_dir(directory).map_err(Error::io(directory))? {
+        let file_name = entry.map_err(Error::io(directory))?.file_name();
+        let file_name = file_name.to_string_lossy();
+        let existing = file_name
+            .strip_prefix(['V', 'U'])
+            .and_then(|rest| rest.split_once("__"))
+            .and_then(|(number, _)| number.parse::<u64>().ok());
+        if let Some(existing) = existing {
+            version = version.max(existing);
+        }
+    }
+
+    Ok(version + 1)
+}
+
+/// Write the SQL that turns the old version of a schema into the new one
+///
+/// It is a skeleton to review rather than a finished migration: renames show up as a drop and an
+/// add, type changes are cast with `USING`, and the changes PostgreSQL can't make in place, like
+/// removing enum labels, are left as comments.
+pub fn migration_sql(schema_diff: &SchemaDiff, old: &Schema, new: &Schema) -> String {
+    let schema = quote_name(&new.name);
+    let mut sql = String::new();
+    writeln!(
+        sql,
+        "-- Migration skeleton for schema {}, review it before applying it",
+        new.name
+    )
+    .unwrap();
+
+    // Types go first, since new columns may use them
+    for name in &schema_diff.added_enums {
+        let Some(enum_type) = new.enums.iter().find(|enum_type| &enum_type.name == name) else {
+            continue;
+        };
+        writeln!(sql).unwrap();
+        write_create_enum(&mut sql, &schema, enum_type);
+    }
+    for enum_diff in &schema_diff.changed_enums {
+        writeln!(sql).unwrap();
+        for label in &enum_diff.new_labels {
+            if !enum_diff.old_labels.contains(label) {
+                writeln!(
+                    sql,
+                    "ALTER TYPE {}.{} ADD VALUE IF NOT EXISTS {};",
+                    schema,
+                    quote_name(&enum_diff.name),
+                    literal(label)
+                )
+                .unwrap();
+            }
+        }
+        for label in &enum_diff.old_labels {
+            if !enum_diff.new_labels.contains(label) {
+                writeln!(
+                    sql,
+                    "-- TODO: {} was removed from {}, which needs the type to be recreated",
+                    literal(label),
+                    enum_diff.name
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    // Tables go before views, which may select from them, and foreign keys after every table
+    let added: Vec<&Table> = schema_diff
+        .added_tables
+        .iter()
+        .filter_map(|name| new.tables.iter().find(|table| &table.name == name))
+        .collect();
+    for table in added.iter().filter(|table| table.kind == TableKind::Table) {
+        writeln!(sql).unwrap();
+        write_create_table(&mut sql, &schema, table);
+    }
+    for table in &added {
+        write_foreign_keys(&mut sql, &schema, table);
+    }
+
+    for table_diff in &schema_diff.changed_tables {
+        let (Some(old_table), Some(table)) = (
+            old.tables
+                .iter()
+                .find(|table| table.name == table_diff.name),
+            new.tables
+                .iter()
+                .find(|table| table.name == table_diff.name),
+        ) else {
+            continue;
+        };
+
+        // Views can't change their columns in place, so they are recreated
+        if old_table.kind != TableKind::Table || table.kind != TableKind::Table {
+            writeln!(sql).unwrap();
+            write_drop(&mut sql, &schema, old_table);
+            match table.kind {
+                TableKind::Table => {
+                    writeln!(sql).unwrap();
+                    write_create_table(&mut sql, &schema, table);
+                }
+                _ => write_create_view(&mut sql, &schema, table),
+            }
+            continue;
+        }
+
+        let name = format!("{}.{}", schema, quote_name(&table.name));
+        writeln!(sql).unwrap();
+        for summary in &table_diff.added_columns {
+            let Some(column) = table
+                .columns
+                .iter()
+                .find(|column| column.name == summary.name)
+            else {
+                continue;
+            };
+            if !column.nullable && column.default.is_none() && !column.identity && !column.generated
+            {
+                writeln!(
+                    sql,
+                    "-- TODO: {} can't be NULL and has no default, so rows that already exist need a value",
+                    column.name
+                )
+                .unwrap();
+            }
+            writeln!(
+                sql,
+                "ALTER TABLE {} ADD COLUMN {};",
+                name,
+                column_definition(column)
+            )
+            .unwrap();
+        }
+        for summary in &table_diff.removed_columns {
+            writeln!(
+                sql,
+                "ALTER TABLE {} DROP COLUMN {};",
+                name,
+                quote_name(&summary.name)
+            )
+            .unwrap();
+        }
+        for change in &table_diff.changed_columns {
+            let Some(column) = table
+                .columns
+                .iter()
+                .find(|column| column.name == change.name)
+            else {
+                continue;
+            };
+            let column_name = quote_name(&column.name);
+            if change.old.data_type != change.new.data_type {
+                let new_type = column_type(&column.data_type);
+                writeln!(
+                    sql,
+                    "ALTER TABLE {} ALTER COLUMN {} TYPE {} USING {}::{};",
+                    name, column_name, new_type, column_name, new_type
+                )
+                .unwrap();
+            }
+            if change.old.nullable != change.new.nullable {
+                let action = if column.nullable { "DROP" } else { "SET" };
+                writeln!(
+                    sql,
+                    "ALTER TABLE {} ALTER COLUMN {} {} NOT NULL;",
+                    name, column_name, action
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    for table in added.iter().filter(|table| table.kind != TableKind::Table) {
+        writeln!(sql).unwrap();
+        write_create_view(&mut sql, &schema, table);
+    }
+
+    // Removed relations are dropped before the types they may use
+    let removed: Vec<&Table> = schema_diff
+        .removed_tables
+        .iter()
+        .filter_map(|name| old.tables.iter().find(|table| &table.name == name))
+        .collect();
+    if !removed.is_empty() {
+        writeln!(sql).unwrap();
+    }
+    for table in removed
+        .iter()
+        .filter(|table| table.kind != TableKind::Table)
+    {
+        write_drop(&mut sql, &schema, table);
+    }
+    for table in removed
+        .iter()
+        .filter(|table| table.kind == TableKind::Table)
+    {
+        write_drop(&mut sql, &schema, table);
+    }
+    if !schema_diff.removed_enums.is_empty() {
+        writeln!(sql).unwrap();
+    }
+    for name in &schema_diff.removed_enums {
+        writeln!(sql, "DROP TYPE {}.{};", schema, quote_name(name)).unwrap();
+    }
+
+    sql
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::diff_schemas;
+    use crate::model::{Column, DataType, Scope, TypeKind};
+
+    fn relation(name: &str, kind: TableKind) -> Table {
+        Table {
+            name: name.to_string(),
+            kind,
+            columns: vec![Column {
+                name: "id".to_string(),
+                data_type: DataType {
+                    name: "integer".to_string(),
+                    schema: "pg_catalog".to_string(),
+                    kind: TypeKind::Base,
+                    dimensions: 0,
+                    element_not_null: false,
+                    length: None,
+                    precision: None,
+                    scale: None,
+                },
+                nullable: false,
+                default: None,
+                identity: false,
+                generated: false,
+                comment: None,
+            }],
+            primary_key: Vec::new(),
+            foreign_keys: Vec::new(),
+            unique_constraints: Vec::new(),
+            indexes: Vec::new(),
+            comment: None,
+            definition: (kind != TableKind::Table).then(|| "SELECT id FROM users".to_string()),
+        }
+    }
+
+    fn schema(tables: Vec<Table>, views: bool, materialized_views: bool) -> Schema {
+        Schema {
+            name: "public".to_string(),
+            tables,
+            enums: Vec::new(),
+            composites: Vec::new(),
+            scope: Some(Scope {
+                views,
+                materialized_views,
+            }),
+        }
+    }
+
+    #[test]
+    fn views_that_were_not_read_are_not_dropped() {
+        let old = schema(
+            vec![
+                relation("active_users", TableKind::View),
+                relation("totals", TableKind::MaterializedView),
+                relation("users", TableKind::Table),
+            ],
+            true,
+            true,
+        );
+
+        // Read without --include-views
+        let new = schema(vec![relation("users", TableKind::Table)], false, false);
+        let sql = migration_sql(&diff_schemas(&old, &new), &old, &new);
+        assert!(!sql.contains("DROP"), "{}", sql);
+
+        // Read from information_schema, which doesn't show materialized views
+        let new = schema(
+            vec![
+                relation("active_users", TableKind::View),
+                relation("users", TableKind::Table),
+            ],
+            true,
+            false,
+        );
+        let sql = migration_sql(&diff_schemas(&old, &new), &old, &new);
+        assert!(!sql.contains("DROP"), "{}", sql);
+
+        // Views that are gone while both read them are still dropped
+        let new = schema(vec![relation("users", TableKind::Table)], true, true);
+        let sql = migration_sql(&diff_schemas(&old, &new), &old, &new);
+        assert!(
+            sql.contains("DROP VIEW \"public\".\"active_users\";"),
+            "{}",
+            sql
+        );
+        assert!(
+            sql.contains("DROP MATERIALIZED VIEW \"public\".\"totals\";"),
+            "{}",
+            sql
+        );
+    }
+}
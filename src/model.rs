@@ -0,0 +1,243 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// A PostgreSQL schema, as read from the database
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Schema {
+    /// The name of the schema
+    pub name: String,
+    /// The tables in the schema, ordered by name
+    pub tables: Vec<Table>,
+    /// The enum types in the schema, ordered by name
+    pub enums: Vec<EnumType>,
+    /// The composite types in the schema, ordered by name. Left out when empty, so the checksums
+    /// of schemas without any stay the same
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub composites: Vec<CompositeType>,
+    /// The kinds of relations that were read. `None` for snapshots taken before it was recorded,
+    /// and left out of the checksum, which only covers what is in the schema
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<Scope>,
+}
+
+/// The kinds of relations read from the database, which depend on the options and the catalogs
+/// the schema was read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Scope {
+    /// Whether views were read, with `--include-views`
+    pub views: bool,
+    /// Whether materialized views were read, which information_schema doesn't show
+    pub materialized_views: bool,
+}
+
+impl Schema {
+    /// Whether relations of a kind were read. Snapshots that don't record it are only known to
+    /// have read the kinds they have a relation of
+    pub fn includes(&self, kind: TableKind) -> bool {
+        match (self.scope, kind) {
+            (_, TableKind::Table) => true,
+            (Some(scope), TableKind::View) => scope.views,
+            (Some(scope), TableKind::MaterializedView) => scope.materialized_views,
+            (None, kind) => self.tables.iter().any(|table| table.kind == kind),
+        }
+    }
+}
+
+/// A table or view and its columns
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Table {
+    /// The name of the table
+    pub name: String,
+    /// Whether this is a table or a view
+    #[serde(default)]
+    pub kind: TableKind,
+    /// The columns of the table, in the order they were declared
+    pub columns: Vec<Column>,
+    /// The columns of the primary key, in key order. Empty when the table doesn't have one
+    #[serde(default)]
+    pub primary_key: Vec<String>,
+    /// The foreign keys of the table, ordered by name
+    #[serde(default)]
+    pub foreign_keys: Vec<ForeignKey>,
+    /// The unique constraints of the table, ordered by name. Left out when empty, so the
+    /// checksums of schemas without any stay the same
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unique_constraints: Vec<UniqueConstraint>,
+    /// The indexes of the table, ordered by name. Left out when empty, so snapshots of
+    /// databases the indexes can't be read from stay the same
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub indexes: Vec<Index>,
+    /// The comment on the table, if any
+    #[serde(default)]
+    pub comment: Option<String>,
+    /// The query that defines a view, if the database shows it. Left out for tables, so the
+    /// checksums of schemas without views stay the same
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub definition: Option<String>,
+}
+
+/// The kind of relation a [`Table`] describes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TableKind {
+    /// A regular or partitioned table
+    #[default]
+    Table,
+    /// A view
+    View,
+    /// A materialized view
+    MaterializedView,
+}
+
+/// A column of a table
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Column {
+    /// The name of the column
+    pub name: String,
+    /// The type of the column
+    pub data_type: DataType,
+    /// Whether the column accepts NULL
+    pub nullable: bool,
+    /// The default expression of the column, if any
+    pub default: Option<String>,
+    /// Whether the column is an identity column, numbered by the database
+    #[serde(default)]
+    pub identity: bool,
+    /// Whether the column is a generated column, computed from the other columns
+    #[serde(default)]
+    pub generated: bool,
+    /// The comment on the column, if any
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
+/// A foreign key from columns of a table to the key of another table
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ForeignKey {
+    /// The name of the constraint
+    pub name: String,
+    /// The referencing columns, in key order
+    pub columns: Vec<String>,
+    /// The schema of the referenced table
+    pub referenced_schema: String,
+    /// The referenced table
+    pub referenced_table: String,
+    /// The referenced columns, in the same order as `columns`
+    pub referenced_columns: Vec<String>,
+}
+
+/// A unique constraint on columns of a table
+///
+/// Deferrable constraints are left out, since they can't be the target of `ON CONFLICT`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UniqueConstraint {
+    /// The name of the constraint
+    pub name: String,
+    /// The columns of the constraint, in key order
+    pub columns: Vec<String>,
+}
+
+/// An index on a table, including the ones that back its primary key and unique constraints
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Index {
+    /// The name of the index
+    pub name: String,
+    /// The indexed columns or expressions, in key order. `INCLUDE` columns are left out
+    pub columns: Vec<String>,
+    /// Whether the index is unique
+    pub unique: bool,
+    /// Whether the index backs the primary key
+    pub primary: bool,
+    /// The access method of the index, like `btree` or `gin`
+    pub method: String,
+    /// The condition of a partial index
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub predicate: Option<String>,
+}
+
+/// The type of a column
+///
+/// Domains are resolved to the type they are based on. For arrays, everything but `dimensions`
+/// describes the element type.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DataType {
+    /// The name of the type. Built-in types use the same spelling as information_schema (e.g.
+    /// `integer`, `character varying`), other types use their name in the catalog
+    pub name: String,
+    /// The schema the type belongs to
+    pub schema: String,
+    /// What kind of type this is
+    pub kind: TypeKind,
+    /// The number of array dimensions, zero when the column isn't an array
+    pub dimensions: i32,
+    /// Whether the elements of an array can't be NULL, which is only known when the element type
+    /// is a `NOT NULL` domain
+    #[serde(default)]
+    pub element_not_null: bool,
+    /// The maximum length of a character or bit string type
+    pub length: Option<i32>,
+    /// The precision of a numeric, time, or timestamp type
+    pub precision: Option<i32>,
+    /// The scale of a numeric type
+    pub scale: Option<i32>,
+}
+
+/// The kind of a PostgreSQL type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TypeKind {
+    /// A built-in or extension type
+    Base,
+    /// An enum type
+    Enum,
+    /// A composite type
+    Composite,
+    /// A range or multirange type
+    Range,
+}
+
+/// An enum type and its labels
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnumType {
+    /// The name of the type
+    pub name: String,
+    /// The labels of the enum, in sort order
+    pub labels: Vec<String>,
+}
+
+/// A composite type and its attributes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompositeType {
+    /// The name of the type
+    pub name: String,
+    /// The attributes of the type, in the order they were declared
+    pub attributes: Vec<Attribute>,
+}
+
+/// An attribute of a composite type, which can always be NULL
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Attribute {
+    /// The name of the attribute
+    pub name: String,
+    /// The type of the attribute
+    pub data_type: DataType,
+}
+
+impl fmt::Display for DataType {
+    /// Format the type the way it would be declared, e.g. `numeric(10,2)` or `text[]`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        match (self.length, self.precision, self.scale) {
+            (Some(length), _, _) => write!(f, "({})", length)?,
+            (None, Some(precision), Some(scale)) => write!(f, "({},{})", precision, scale)?,
+            (None, Some(precision), None) => write!(f, "({})", precision)?,
+            _ => {}
+        }
+        for _ in 0..self.dimensions {
+            write!(f, "[]")?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,412 @@
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::Ident;
+
+/// A type whose columns get a newtype rather than a plain number or `String`, like system types
+/// whose values are OIDs or transaction ids that only mean something to the database
+pub struct Newtype {
+    /// The name of the type in PostgreSQL
+    pub name: &'static str,
+    /// The name of the newtype
+    pub rust_name: &'static str,
+    /// What the newtype wraps
+    kind: Kind,
+    /// What the values of the type are, for the doc comment of the newtype
+    description: &'static str,
+}
+
+/// What a newtype wraps
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    /// A `u32`, with the name of the type in `postgres_types::Type`
+    U32(&'static str),
+    /// A `u64`, with the name of the type in `postgres_types::Type`
+    U64(&'static str),
+    /// A `String`
+    Text,
+}
+
+/// The types that get a newtype
+pub const NEWTYPES: &[Newtype] = &[
+    Newtype {
+        name: "regclass",
+        rust_name: "RegClass",
+        kind: Kind::U32("REGCLASS"),
+        description: "the OID of a table, index, sequence or other relation",
+    },
+    Newtype {
+        name: "regcollation",
+        rust_name: "RegCollation",
+        kind: Kind::U32("REGCOLLATION"),
+        description: "the OID of a collation",
+    },
+    Newtype {
+        name: "regconfig",
+        rust_name: "RegConfig",
+        kind: Kind::U32("REGCONFIG"),
+        description: "the OID of a text search configuration",
+    },
+    Newtype {
+        name: "regdictionary",
+        rust_name: "RegDictionary",
+        kind: Kind::U32("REGDICTIONARY"),
+        description: "the OID of a text search dictionary",
+    },
+    Newtype {
+        name: "regnamespace",
+        rust_name: "RegNamespace",
+        kind: Kind::U32("REGNAMESPACE"),
+        description: "the OID of a schema",
+    },
+    Newtype {
+        name: "regoper",
+        rust_name: "RegOper",
+        kind: Kind::U32("REGOPER"),
+        description: "the OID of an operator",
+    },
+    Newtype {
+        name: "regoperator",
+        rust_name: "RegOperator",
+        kind: Kind::U32("REGOPERATOR"),
+        description: "the OID of an operator, shown with its argument types",
+    },
+    Newtype {
+        name: "regproc",
+        rust_name: "RegProc",
+        kind: Kind::U32("REGPROC"),
+        description: "the OID of a function",
+    },
+    Newtype {
+        name: "regprocedure",
+        rust_name: "RegProcedure",
+        kind: Kind::U32("REGPROCEDURE"),
+        description: "the OID of a function, shown with its argument types",
+    },
+    Newtype {
+        name: "regrole",
+        rust_name: "RegRole",
+        kind: Kind::U32("REGROLE"),
+        description: "the OID of a role",
+    },
+    Newtype {
+        name: "regtype",
+        rust_name: "RegType",
+        kind: Kind::U32("REGTYPE"),
+        description: "the OID of a data type",
+    },
+    Newtype {
+        name: "cid",
+        rust_name: "Cid",
+        kind: Kind::U32("CID"),
+        description: "a command id within a transaction",
+    },
+    Newtype {
+        name: "xid",
+        rust_name: "Xid",
+        kind: Kind::U32("XID"),
+        description: "a 32-bit transaction id, which wraps around",
+    },
+    Newtype {
+        name: "xid8",
+        rust_name: "Xid8",
+        kind: Kind::U64("XID8"),
+        description: "a 64-bit transaction id, which doesn't wrap around",
+    },
+    Newtype {
+        name: "xml",
+        rust_name: "Xml",
+        kind: Kind::Text,
+        description: "an XML document or content fragment",
+    },
+    Newtype {
+        name: "tsquery",
+        rust_name: "TsQuery",
+        kind: Kind::Text,
+        description: "a full-text search query, like `fat & (rat | cat)`",
+    },
+    Newtype {
+        name: "ltree",
+        rust_name: "Ltree",
+        kind: Kind::Text,
+        description: "a path of labels in a tree, like `Top.Science.Astronomy`",
+    },
+    Newtype {
+        name: "citext",
+        rust_name: "CiString",
+        kind: Kind::Text,
+        description: "text that compares and hashes without regard to case, like the database does",
+    },
+];
+
+impl Newtype {
+    /// Whether the newtype wraps a number, so it's `Copy` and ordered
+    pub fn is_number(&self) -> bool {
+        self.kind != Kind::Text
+    }
+}
+
+/// Find the newtype of a PostgreSQL type, if it gets one
+pub fn find(name: &str) -> Option<&'static Newtype> {
+    NEWTYPES.iter().find(|newtype| newtype.name == name)
+}
+
+/// Find a newtype by its Rust name
+pub fn find_rust_name(rust_name: &str) -> Option<&'static Newtype> {
+    NEWTYPES
+        .iter()
+        .find(|newtype| newtype.rust_name == rust_name)
+}
+
+/// Generate a newtype, which implements `Display` with the value it wraps
+///
+/// With `postgres`, it implements `ToSql` and `FromSql` too. Numbers are read and written the way
+/// postgres does for `oid` and `bigint`, and text the way it does for `String`, which
+/// understands `ltree`. `xml_validator` is the path of a function that checks `xml` values,
+/// which `Xml` then runs when it's converted from a `String` or deserialized.
+pub fn generate_newtype(
+    newtype: &Newtype,
+    postgres: bool,
+    xml_validator: Option<&syn::Path>,
+) -> TokenStream {
+    let rust_name = Ident::new(newtype.rust_name, Span::call_site());
+    let doc = format!(
+        " A value of the `{}` type, {}",
+        newtype.name, newtype.description
+    );
+    let mut code = match newtype.kind {
+        Kind::U32(_) | Kind::U64(_) => {
+            let inner = if matches!(newtype.kind, Kind::U64(_)) {
+                quote! { u64 }
+            } else {
+                quote! { u32 }
+            };
+            quote! {
+                #[doc = #doc]
+                #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize)]
+                #[serde(transparent)]
+                pub struct #rust_name(pub #inner);
+            }
+        }
+        Kind::Text => generate_text(newtype, &rust_name, &doc, xml_validator),
+    };
+    code.extend(quote! {
+        impl std::fmt::Display for #rust_name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+    });
+    if postgres {
+        code.extend(generate_postgres(newtype, &rust_name));
+    }
+
+    code
+}
+
+/// Generate a newtype that wraps a `String`, with the helpers of its type
+fn generate_text(
+    newtype: &Newtype,
+    rust_name: &Ident,
+    doc: &str,
+    xml_validator: Option<&syn::Path>,
+) -> TokenStream {
+    // Checked values can't be deserialized from any string
+    let validator = xml_validator.filter(|_| newtype.name == "xml");
+    let serde_options = match validator {
+        Some(_) => quote! { #[serde(try_from = "String")] },
+        None => quote! { #[serde(transparent)] },
+    };
+    // citext compares lowercased text, so its equality can't be derived
+    let is_case_insensitive = newtype.name == "citext";
+    let derives = if is_case_insensitive {
+        quote! {}
+    } else {
+        quote! { PartialEq, Eq, Hash, }
+    };
+    let mut code = quote! {
+        #[doc = #doc]
+        #[derive(Debug, Clone, #derives serde::Serialize, serde::Deserialize)]
+        #serde_options
+        pub struct #rust_name(pub String);
+
+        impl #rust_name {
+            /// Get the text of the value
+            pub fn as_str(&self) -> &str {
+                &self.0
+            }
+        }
+    };
+    code.extend(match validator {
+        Some(validator) => {
+            let path = quote!(#validator).to_string().replace(' ', "");
+            let doc = format!(" Check the text with `{}`", path);
+            quote! {
+                impl std::convert::TryFrom<String> for #rust_name {
+                    type Error = String;
+
+                    #[doc = #doc]
+                    fn try_from(text: String) -> Result<Self, Self::Error> {
+                        #validator(&text).map_err(|error| error.to_string())?;
+                        Ok(#rust_name(text))
+                    }
+                }
+            }
+        }
+        None => quote! {
+            impl From<String> for #rust_name {
+                fn from(text: String) -> Self {
+                    #rust_name(text)
+                }
+            }
+        },
+    });
+
+    if is_case_insensitive {
+        code.extend(quote! {
+            impl PartialEq for #rust_name {
+                fn eq(&self, other: &Self) -> bool {
+                    self.0.to_lowercase() == other.0.to_lowercase()
+                }
+            }
+
+            impl Eq for #rust_name {}
+
+            impl std::hash::Hash for #rust_name {
+                fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+                    std::hash::Hash::hash(&self.0.to_lowercase(), state);
+                }
+            }
+        });
+    }
+
+    // Paths can be taken apart without a round trip to the database
+    if newtype.name == "ltree" {
+        code.extend(quote! {
+            impl #rust_name {
+                /// Get the labels of the path, from the root down
+                pub fn labels(&self) -> impl Iterator<Item = &str> {
+                    self.0.split('.').filter(|label| !label.is_empty())
+                }
+
+                /// Get the number of labels in the path, like `nlevel`
+                pub fn depth(&self) -> usize {
+                    self.labels().count()
+                }
+
+                /// Get the path without its last label, or `None` for an empty path
+                pub fn parent(&self) -> Option<Self> {
+                    let labels: Vec<&str> = self.labels().collect();
+                    let (_, parent) = labels.split_last()?;
+                    Some(#rust_name(parent.join(".")))
+                }
+
+                /// Get the path with another label at the end
+                pub fn child(&self, label: &str) -> Self {
+                    if self.0.is_empty() {
+                        #rust_name(label.to_string())
+                    } else {
+                        #rust_name(format!("{}.{}", self.0, label))
+                    }
+                }
+
+                /// Whether the path is `other` or one of its ancestors, like `@>`
+                pub fn is_ancestor_of(&self, other: &Self) -> bool {
+                    let mut labels = other.labels();
+                    self.labels().all(|label| labels.next() == Some(label))
+                }
+
+                /// Whether the path is `other` or one of its descendants, like `<@`
+                pub fn is_descendant_of(&self, other: &Self) -> bool {
+                    other.is_ancestor_of(self)
+                }
+            }
+        });
+    }
+
+    code
+}
+
+/// Generate the `ToSql` and `FromSql` implementations of a newtype
+fn generate_postgres(newtype: &Newtype, rust_name: &Ident) -> TokenStream {
+    // postgres has no conversions for u64, so 64-bit ids go through i64, which has the same bytes
+    let (from_sql, to_sql, from_accepts, to_accepts) = match newtype.kind {
+        Kind::U32(postgres_name) => {
+            let postgres_name = Ident::new(postgres_name, Span::call_site());
+            (
+                quote! {
+                    <u32 as postgres_types::FromSql>::from_sql(&postgres_types::Type::OID, raw)
+                        .map(#rust_name)
+                },
+                quote! {
+                    <u32 as postgres_types::ToSql>::to_sql(
+                        &self.0,
+                        &postgres_types::Type::OID,
+                        out,
+                    )
+                },
+                quote! { *ty == postgres_types::Type::#postgres_name },
+                quote! { *ty == postgres_types::Type::#postgres_name },
+            )
+        }
+        Kind::U64(postgres_name) => {
+            let postgres_name = Ident::new(postgres_name, Span::call_site());
+            (
+                quote! {
+                    <i64 as postgres_types::FromSql>::from_sql(&postgres_types::Type::INT8, raw)
+                        .map(|value| #rust_name(value as u64))
+                },
+                quote! {
+                    <i64 as postgres_types::ToSql>::to_sql(
+                        &(self.0 as i64),
+                        &postgres_types::Type::INT8,
+                        out,
+                    )
+                },
+                quote! { *ty == postgres_types::Type::#postgres_name },
+                quote! { *ty == postgres_types::Type::#postgres_name },
+            )
+        }
+        // xml and tsquery are read and written as text by the repository helpers
+        Kind::Text => (
+            quote! { <String as postgres_types::FromSql>::from_sql(ty, raw).map(#rust_name) },
+            quote! { <String as postgres_types::ToSql>::to_sql(&self.0, ty, out) },
+            quote! { <String as postgres_types::FromSql>::accepts(ty) },
+            quote! { <String as postgres_types::ToSql>::accepts(ty) },
+        ),
+    };
+    let ty = match newtype.kind {
+        Kind::Text => quote! { ty },
+        _ => quote! { _ty },
+    };
+
+    quote! {
+        impl<'a> postgres_types::FromSql<'a> for #rust_name {
+            fn from_sql(
+                #ty: &postgres_types::Type,
+                raw: &'a [u8],
+            ) -> Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+                #from_sql
+            }
+
+            fn accepts(ty: &postgres_types::Type) -> bool {
+                #from_accepts
+            }
+        }
+
+        impl postgres_types::ToSql for #rust_name {
+            fn to_sql(
+                &self,
+                #ty: &postgres_types::Type,
+                out: &mut postgres_types::private::BytesMut,
+            ) -> Result<postgres_types::IsNull, Box<dyn std::error::Error + Sync + Send>> {
+                #to_sql
+            }
+
+            fn accepts(ty: &postgres_types::Type) -> bool {
+                #to_accepts
+            }
+
+            postgres_types::to_sql_checked!();
+        }
+    }
+}
@@ -0,0 +1,769 @@
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::{fs, path::Path, thread};
+
+use proc_macro2::TokenStream;
+use quote::{quote, ToTokens};
+use syn::punctuated::Punctuated;
+use syn::visit_mut::{self, VisitMut};
+use syn::{Attribute, Ident, Token};
+use tracing::debug;
+
+use crate::error::{Error, Result};
+use crate::imports;
+
+/// Parse a name as a Rust identifier
+///
+/// Keywords, like `type`, are raw identifiers. Names that can't be one, like `self` or `1st`, are
+/// reported with `context` instead of generating code that doesn't compile.
+pub fn ident(name: &str, context: &str) -> Result<Ident> {
+    syn::parse_str::<Ident>(name)
+        .or_else(|source| syn::parse_str::<Ident>(&format!("r#{}", name)).map_err(|_| source))
+        .map_err(|source| Error::InvalidCode {
+            context: format!("{} as `{}`", context, name),
+            source,
+        })
+}
+
+/// Parse a path, like the `--serde-crate`, reporting it with `context` when it isn't one
+pub fn path(path: &str, context: &str) -> Result<syn::Path> {
+    syn::parse_str::<syn::Path>(path).map_err(|source| Error::InvalidCode {
+        context: format!("{} as `{}`", context, path),
+        source,
+    })
+}
+
+/// Make sure the code generated for `context`, such as a table, is valid Rust
+pub fn validate(code: &TokenStream, context: &str) -> Result<()> {
+    syn::parse2::<syn::File>(code.clone()).map_err(|source| Error::InvalidCode {
+        context: context.to_string(),
+        source,
+    })?;
+
+    Ok(())
+}
+
+/// Get the `#[deprecated]` attribute of a struct or field, given why it's deprecated if it is
+pub fn deprecated(reason: Option<&str>) -> TokenStream {
+    match reason {
+        Some("") => quote! { #[deprecated] },
+        Some(note) => quote! { #[deprecated(note = #note)] },
+        None => quote! {},
+    }
+}
+
+/// Allow the impls in the code generated for `context` to use deprecated structs and fields, so
+/// only the code that uses them warns
+pub fn allow_deprecated(code: TokenStream, context: &str) -> Result<TokenStream> {
+    let mut file = syn::parse2::<syn::File>(code).map_err(|source| Error::InvalidCode {
+        context: context.to_string(),
+        source,
+    })?;
+    for item in &mut file.items {
+        if let syn::Item::Impl(item) = item {
+            item.attrs.push(syn::parse_quote!(#[allow(deprecated)]));
+        }
+    }
+
+    Ok(file.into_token_stream())
+}
+
+/// Gate every item in the code generated for `context` behind a cargo feature
+pub fn cfg_feature(code: TokenStream, feature: &str, context: &str) -> Result<TokenStream> {
+    let mut file = syn::parse2::<syn::File>(code).map_err(|source| Error::InvalidCode {
+        context: context.to_string(),
+        source,
+    })?;
+    for item in &mut file.items {
+        let attrs = match item {
+            syn::Item::Const(item) => &mut item.attrs,
+            syn::Item::Enum(item) => &mut item.attrs,
+            syn::Item::Fn(item) => &mut item.attrs,
+            syn::Item::Impl(item) => &mut item.attrs,
+            syn::Item::Mod(item) => &mut item.attrs,
+            syn::Item::Static(item) => &mut item.attrs,
+            syn::Item::Struct(item) => &mut item.attrs,
+            syn::Item::Trait(item) => &mut item.attrs,
+            syn::Item::Type(item) => &mut item.attrs,
+            syn::Item::Use(item) => &mut item.attrs,
+            _ => continue,
+        };
+        attrs.insert(0, syn::parse_quote!(#[cfg(feature = #feature)]));
+    }
+
+    Ok(file.into_token_stream())
+}
+
+/// Parse and format the code of a generated file, written in `style`
+///
+/// It's formatted in-process by default, so it's the same no matter which toolchain is installed.
+pub fn format_code(code: &str, path: &str, style: &CodeStyle) -> Result<String> {
+    let mut file = syn::parse_file(code).map_err(|source| Error::InvalidCode {
+        context: path.to_string(),
+        source,
+    })?;
+    if !style.paths.is_default() {
+        rewrite_paths(&mut file, &style.paths);
+    }
+    if style.imports == ImportStyle::Use {
+        imports::use_imports(&mut file);
+    }
+    if style.visibility != Visibility::Public {
+        VisibilityRewriter(style.visibility).visit_file_mut(&mut file);
+    }
+
+    match &style.formatter {
+        Formatter::Prettyplease => Ok(prettyplease::unparse(&file)),
+        Formatter::Rustfmt { bin, config } => rustfmt(
+            &prettyplease::unparse(&file),
+            path,
+            bin,
+            config.as_deref(),
+            style.paths.edition,
+        ),
+        Formatter::None => Ok(file.into_token_stream().to_string() + "\n"),
+    }
+}
+
+/// Format code with a rustfmt binary, which reads the `config` rustfmt.toml if there is one, or
+/// looks one up from the current directory
+fn rustfmt(
+    code: &str,
+    path: &str,
+    bin: &str,
+    config: Option<&str>,
+    edition: Edition,
+) -> Result<String> {
+    let format_error = |message: String| Error::Format {
+        path: path.to_string(),
+        message,
+    };
+
+    let mut command = Command::new(bin);
+    command
+        .args(["--edition", edition.as_str(), "--emit", "stdout"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(config) = config {
+        command.args(["--config-path", config]);
+    }
+    let mut child = command
+        .spawn()
+        .map_err(|error| format_error(format!("couldn't run {}: {}", bin, error)))?;
+
+    // Write on another thread, so rustfmt can't block on a full stdout while it's being written to
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let code = code.to_string();
+    let writer = thread::spawn(move || stdin.write_all(code.as_bytes()));
+    let output = child
+        .wait_with_output()
+        .map_err(|error| format_error(error.to_string()))?;
+    writer
+        .join()
+        .expect("Writing to rustfmt doesn't panic")
+        .map_err(|error| format_error(error.to_string()))?;
+
+    if !output.status.success() {
+        return Err(format_error(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    String::from_utf8(output.stdout).map_err(|error| format_error(error.to_string()))
+}
+
+/// How generated files are formatted
+#[derive(Clone, Default)]
+pub enum Formatter {
+    /// In-process with prettyplease
+    #[default]
+    Prettyplease,
+    /// With rustfmt, so a project's rustfmt.toml is honored, for `--rustfmt-bin` and
+    /// `--rustfmt-config`
+    Rustfmt { bin: String, config: Option<String> },
+    /// Not at all, for `--no-format`
+    None,
+}
+
+/// How generated code names the types of other crates and modules, for `--import-style`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImportStyle {
+    /// By their path, like `chrono::NaiveDate`
+    #[default]
+    Qualified,
+    /// By their name, with `use` items at the top of the file
+    Use,
+}
+
+/// The visibility of the generated items, for `--visibility`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Visibility {
+    /// `pub`
+    #[default]
+    Public,
+    /// `pub(crate)`, so a library crate doesn't export them
+    Crate,
+    /// Private to the module of the output file
+    Private,
+}
+
+/// Replaces the `pub` of every item, field and module of a file with a [`Visibility`]
+struct VisibilityRewriter(Visibility);
+
+impl VisitMut for VisibilityRewriter {
+    fn visit_visibility_mut(&mut self, visibility: &mut syn::Visibility) {
+        if let syn::Visibility::Public(_) = visibility {
+            *visibility = match self.0 {
+                Visibility::Public => return,
+                Visibility::Crate => syn::parse_quote! { pub(crate) },
+                Visibility::Private => syn::Visibility::Inherited,
+            };
+        }
+    }
+}
+
+/// How the code of generated files is written
+#[derive(Clone, Default)]
+pub struct CodeStyle {
+    /// The crates it uses and the edition it's written for
+    pub paths: CratePaths,
+    /// How it names the types of other crates
+    pub imports: ImportStyle,
+    /// The visibility of its items
+    pub visibility: Visibility,
+    /// How it's formatted
+    pub formatter: Formatter,
+}
+
+/// The Rust edition the generated code is written for, with `--edition`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Edition {
+    E2015,
+    E2018,
+    #[default]
+    E2021,
+    E2024,
+}
+
+impl Edition {
+    /// Get the edition as it's written in Cargo.toml
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Edition::E2015 => "2015",
+            Edition::E2018 => "2018",
+            Edition::E2021 => "2021",
+            Edition::E2024 => "2024",
+        }
+    }
+}
+
+/// The crates generated code uses, which 2015 edition code refers to through the crate root
+const EXTERNAL_CRATES: &[&str] = &[
+    "alloc",
+    "axum",
+    "bytes",
+    "chrono",
+    "core",
+    "postgres",
+    "postgres_types",
+    "prost",
+    "rust_decimal",
+    "sea_query",
+    "serde",
+    "serde_json",
+    "std",
+    "strum",
+    "testcontainers_modules",
+    "tokio",
+    "tonic",
+    "tonic_prost",
+    "uuid",
+];
+
+/// The crates the generated code uses, when they aren't the usual ones, and the edition it's
+/// written for
+#[derive(Clone, Default)]
+pub struct CratePaths {
+    /// The path serde is used through, for `--serde-crate`, which is a valid path. It's kept as a
+    /// string so files can be formatted on several threads
+    pub serde: Option<String>,
+    /// Whether to use `core` and `alloc` instead of `std`, for `--no-std`
+    pub no_std: bool,
+    /// The edition of the crate the code is generated into
+    pub edition: Edition,
+}
+
+impl CratePaths {
+    /// Whether the generated code is written as is
+    pub fn is_default(&self) -> bool {
+        self.serde.is_none()
+            && !self.no_std
+            && matches!(self.edition, Edition::E2018 | Edition::E2021)
+    }
+}
+
+/// Rewrite the code of a generated file to use the crates in `paths`
+///
+/// Paths through `serde` go through the `--serde-crate`, and the types that derive its traits get
+/// `#[serde(crate = "...")]` so the derived impls do too. With `--no-std`, paths through `std` go
+/// through `core` or `alloc`, and the file imports the items of the std prelude it uses from
+/// `alloc`, like `String` and `Vec`. The 2015 edition resolves paths from the current module, so
+/// paths through other crates go through the `extern crate`s of the crate root, and the 2024
+/// edition reserves `gen`, so it's a raw identifier.
+fn rewrite_paths(file: &mut syn::File, paths: &CratePaths) {
+    let mut rewriter = PathRewriter {
+        paths,
+        serde: paths
+            .serde
+            .as_deref()
+            .map(|serde| syn::parse_str(serde).expect("--serde-crate is a valid path")),
+        prelude: BTreeSet::new(),
+    };
+    rewriter.visit_file_mut(file);
+
+    if !rewriter.prelude.is_empty() {
+        let items = rewriter
+            .prelude
+            .iter()
+            .map(|item| syn::parse_str::<syn::Path>(item).expect("Prelude items are paths"));
+        file.items
+            .insert(0, syn::parse_quote! { use alloc::{#(#items),*}; });
+    }
+}
+
+/// Rewrites the paths of a file for [`rewrite_paths`]
+struct PathRewriter<'a> {
+    paths: &'a CratePaths,
+    /// The path serde is used through, parsed
+    serde: Option<syn::Path>,
+    /// The items of the std prelude the file uses that come from `alloc`, like `vec::Vec`
+    prelude: BTreeSet<&'static str>,
+}
+
+impl PathRewriter<'_> {
+    /// Point a path through `serde` or `std` at the crate it goes through instead, from the crate
+    /// root in the 2015 edition
+    fn rewrite(&self, path: &mut syn::Path) {
+        self.rewrite_crate(path);
+
+        let Some(first) = path.segments.first() else {
+            return;
+        };
+        let external = EXTERNAL_CRATES.iter().any(|name| first.ident == name)
+            || self
+                .serde
+                .as_ref()
+                .and_then(|serde| serde.segments.first())
+                .is_some_and(|serde| {
+                    serde.ident == first.ident && !is_relative(&serde.ident.to_string())
+                });
+        if self.paths.edition == Edition::E2015 && path.segments.len() > 1 && external {
+            path.segments
+                .insert(0, Ident::new("crate", first.ident.span()).into());
+        }
+    }
+
+    /// Point a path through `serde` or `std` at the crate it goes through instead
+    fn rewrite_crate(&self, path: &mut syn::Path) {
+        let Some(first) = path.segments.first() else {
+            return;
+        };
+        if first.ident == "serde" {
+            if let Some(serde) = &self.serde {
+                let rest: Vec<_> = path.segments.iter().skip(1).cloned().collect();
+                path.leading_colon = serde.leading_colon;
+                path.segments = serde.segments.clone();
+                path.segments.extend(rest);
+            }
+        } else if first.ident == "std" && self.paths.no_std {
+            let module = path
+                .segments
+                .iter()
+                .nth(1)
+                .map(|segment| segment.ident.to_string());
+            let name = match module.as_deref() {
+                Some("borrow" | "boxed" | "collections" | "rc" | "string" | "vec") => "alloc",
+                _ => "core",
+            };
+            path.segments[0].ident = Ident::new(name, first.ident.span());
+        }
+    }
+
+    /// Get the `#[serde(crate = "...")]` attribute, if serde is used through another path
+    fn serde_attribute(&self) -> Option<Attribute> {
+        let serde = self.serde.as_ref()?;
+        let name = serde.to_token_stream().to_string().replace(' ', "");
+        Some(syn::parse_quote! { #[serde(crate = #name)] })
+    }
+
+    /// Add the `#[serde(crate = "...")]` attribute after the derive of serde's traits, if any
+    fn add_serde_attribute(&self, attributes: &mut Vec<Attribute>) {
+        let Some(serde_attribute) = self.serde_attribute() else {
+            return;
+        };
+        let derives_serde = attributes.iter().position(|attribute| {
+            derives(attribute).is_some_and(|derives| {
+                derives.iter().any(|derive| {
+                    derive
+                        .segments
+                        .first()
+                        .is_some_and(|first| first.ident == "serde")
+                })
+            })
+        });
+        if let Some(index) = derives_serde {
+            attributes.insert(index + 1, serde_attribute);
+        }
+    }
+
+    /// Import an item of the std prelude from `alloc` if it isn't in the core prelude, given its
+    /// name or the name of a method of its trait
+    fn use_prelude(&mut self, name: &str) {
+        let item = match name {
+            "Box" => "boxed::Box",
+            "String" => "string::String",
+            "ToOwned" | "to_owned" => "borrow::ToOwned",
+            "ToString" | "to_string" => "string::ToString",
+            "Vec" => "vec::Vec",
+            _ => return,
+        };
+        if self.paths.no_std {
+            self.prelude.insert(item);
+        }
+    }
+}
+
+impl VisitMut for PathRewriter<'_> {
+    fn visit_path_mut(&mut self, path: &mut syn::Path) {
+        if path.leading_colon.is_none() && path.segments.len() == 1 {
+            self.use_prelude(&path.segments[0].ident.to_string());
+        }
+        self.rewrite(path);
+        visit_mut::visit_path_mut(self, path);
+    }
+
+    fn visit_ident_mut(&mut self, ident: &mut Ident) {
+        if self.paths.edition >= Edition::E2024 && ident == "gen" {
+            *ident = Ident::new_raw("gen", ident.span());
+        }
+    }
+
+    fn visit_expr_method_call_mut(&mut self, call: &mut syn::ExprMethodCall) {
+        self.use_prelude(&call.method.to_string());
+        visit_mut::visit_expr_method_call_mut(self, call);
+    }
+
+    fn visit_macro_mut(&mut self, mac: &mut syn::Macro) {
+        for name in ["format", "vec"] {
+            if self.paths.no_std && mac.path.is_ident(name) {
+                self.prelude.insert(name);
+            }
+        }
+        visit_mut::visit_macro_mut(self, mac);
+    }
+
+    fn visit_attribute_mut(&mut self, attribute: &mut Attribute) {
+        // The traits of a derive aren't parsed as paths, and the other attributes, like
+        // `#[serde(...)]`, are kept as they are
+        if let Some(mut derives) = derives(attribute) {
+            for derive in &mut derives {
+                self.rewrite(derive);
+            }
+            attribute.meta = syn::parse_quote! { derive(#derives) };
+        }
+    }
+
+    fn visit_item_struct_mut(&mut self, item: &mut syn::ItemStruct) {
+        self.add_serde_attribute(&mut item.attrs);
+        visit_mut::visit_item_struct_mut(self, item);
+    }
+
+    fn visit_item_enum_mut(&mut self, item: &mut syn::ItemEnum) {
+        self.add_serde_attribute(&mut item.attrs);
+        visit_mut::visit_item_enum_mut(self, item);
+    }
+}
+
+/// Whether a path that starts with `name` is relative to the current crate
+fn is_relative(name: &str) -> bool {
+    matches!(name, "crate" | "self" | "super" | "Self")
+}
+
+/// Get the traits of a `#[derive(...)]` attribute
+fn derives(attribute: &Attribute) -> Option<Punctuated<syn::Path, Token![,]>> {
+    if !attribute.path().is_ident("derive") {
+        return None;
+    }
+    attribute.parse_args_with(Punctuated::parse_terminated).ok()
+}
+
+/// The header written at the top of every generated file
+pub struct Header {
+    template: String,
+    timestamp: String,
+    checksum: String,
+}
+
+impl Header {
+    /// The header used when the config doesn't set one
+    pub const DEFAULT: &'static str = "// This file was generated by rustgres-schema\n\
+        // Do not edit this file directly\n\
+        // Generated on {timestamp}\n\
+        // Schema checksum: {checksum}\n";
+
+    /// Create the header for this run from a template, where `{timestamp}` is the current time
+    /// and `{checksum}` is the checksum of the schema the code was generated from
+    pub fn new(template: Option<&str>) -> Self {
+        let mut template = template.unwrap_or(Self::DEFAULT).to_string();
+        if !template.is_empty() && !template.ends_with('\n') {
+            template.push('\n');
+        }
+
+        // Add a timestamp, in the format of YYYY-MM-DD HH:MM:SS
+        let timestamp = chrono::offset::Local::now()
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string();
+
+        Header {
+            template,
+            timestamp,
+            checksum: String::new(),
+        }
+    }
+
+    /// No header, for files that aren't Rust code
+    pub fn none() -> Self {
+        Header {
+            template: String::new(),
+            timestamp: String::new(),
+            checksum: String::new(),
+        }
+    }
+
+    /// Set the checksum of the schema the code was generated from
+    pub fn with_checksum(mut self, checksum: String) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    /// Get the checksum of the schema the code was generated from
+    pub fn checksum(&self) -> &str {
+        &self.checksum
+    }
+
+    /// Get the header with the timestamp and checksum filled in
+    fn render(&self) -> String {
+        self.template
+            .replace("{timestamp}", &self.timestamp)
+            .replace("{checksum}", &self.checksum)
+    }
+
+    /// Get the rest of an existing file, if it starts with this header from any run
+    ///
+    /// Only the text around the timestamp of a line is compared, since it changes every run. The
+    /// checksum has to match, so the header is rewritten when only the schema changed.
+    fn strip<'a>(&self, contents: &'a str) -> Option<&'a str> {
+        let mut rest = contents;
+        for line in self.template.split_inclusive('\n') {
+            let (existing, remainder) = rest.split_at(rest.find('\n').map(|end| end + 1)?);
+            let line = line.replace("{checksum}", &self.checksum);
+            let matches = match line.split_once("{timestamp}") {
+                Some((prefix, suffix)) => {
+                    existing.len() >= prefix.len() + suffix.len()
+                        && existing.starts_with(prefix)
+                        && existing.ends_with(suffix)
+                }
+                None => existing == line,
+            };
+            if !matches {
+                return None;
+            }
+            rest = remainder;
+        }
+
+        Some(rest)
+    }
+
+    /// Read the schema checksum from the header of an existing file, if the header has one
+    pub fn read_checksum(&self, contents: &str) -> Option<String> {
+        let line = self
+            .template
+            .lines()
+            .position(|line| line.contains("{checksum}"))?;
+        let (prefix, suffix) = self.template.lines().nth(line)?.split_once("{checksum}")?;
+        let existing = contents.lines().nth(line)?;
+
+        existing
+            .strip_prefix(prefix)?
+            .strip_suffix(suffix)
+            .map(str::to_string)
+    }
+}
+
+/// Write a generated file with the header, unless it already has the same contents, returning
+/// whether it was written
+///
+/// The timestamp in the header is ignored when comparing, so unchanged files keep their
+/// modification time and don't trigger a rebuild. The contents are written to a temporary file
+/// that is renamed into place, so a crash can't leave a truncated file behind.
+pub fn write_file(path: &str, header: &Header, code: &str) -> Result<bool> {
+    if let Ok(existing) = fs::read_to_string(path) {
+        if header.strip(&existing) == Some(code) {
+            debug!("{} is unchanged, not writing it", path);
+            return Ok(false);
+        }
+    }
+
+    // Create the directory if it doesn't exist
+    let path = Path::new(path);
+    let dir_path = path.parent().unwrap_or(Path::new("."));
+    if !dir_path.as_os_str().is_empty() && !dir_path.exists() {
+        fs::create_dir_all(dir_path).map_err(Error::io(dir_path))?;
+    }
+
+    // Write next to the file, so the rename stays on the same file system
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp_path = path.with_file_name(format!(".{}.tmp", file_name));
+    debug!("Writing {}", path.display());
+    fs::write(&temp_path, header.render() + code).map_err(Error::io(&temp_path))?;
+    fs::rename(&temp_path, path).map_err(|error| {
+        fs::remove_file(&temp_path).ok();
+        Error::io(path)(error)
+    })?;
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_ignores_the_timestamp_but_not_the_checksum() {
+        let header = Header::new(None).with_checksum("abc".to_string());
+        let contents = "// This file was generated by rustgres-schema\n\
+            // Do not edit this file directly\n\
+            // Generated on 2001-02-03 04:05:06\n\
+            // Schema checksum: abc\n\
+            pub struct Users;\n";
+        assert_eq!(header.strip(contents), Some("pub struct Users;\n"));
+        assert_eq!(
+            header.strip(&(header.render() + "pub struct Users;\n")),
+            Some("pub struct Users;\n")
+        );
+
+        let header = Header::new(None).with_checksum("def".to_string());
+        assert_eq!(header.strip(contents), None);
+    }
+
+    #[test]
+    fn strip_needs_the_whole_header() {
+        let header = Header::new(Some("// Generated code, {timestamp}"));
+        assert_eq!(
+            header.strip("// Generated code, yesterday\nfn main() {}\n"),
+            Some("fn main() {}\n")
+        );
+        assert_eq!(header.strip("// Hand-written code\nfn main() {}\n"), None);
+        assert_eq!(header.strip("fn main() {}"), None);
+        assert_eq!(Header::none().strip("fn main() {}"), Some("fn main() {}"));
+    }
+
+    #[test]
+    fn read_checksum_reads_the_line_of_the_template() {
+        let header = Header::new(Some("// Generated\n// Checksum {checksum}, don't edit\n"));
+        assert_eq!(
+            header
+                .read_checksum("// Generated\n// Checksum abc, don't edit\nfn main() {}\n")
+                .as_deref(),
+            Some("abc")
+        );
+        assert_eq!(header.read_checksum("// Generated\nfn main() {}\n"), None);
+        assert_eq!(Header::none().read_checksum("// Checksum abc\n"), None);
+    }
+
+    /// Format a file the way it's written for an edition
+    fn for_edition(edition: Edition) -> String {
+        let code = "#[derive(Debug, serde::Serialize)]
+            pub struct Events {
+                pub at: chrono::NaiveDateTime,
+                pub gen: i32,
+                pub mood: crate::types::Mood,
+                pub tags: std::vec::Vec<String>,
+            }";
+        let style = CodeStyle {
+            paths: CratePaths {
+                edition,
+                ..CratePaths::default()
+            },
+            ..CodeStyle::default()
+        };
+        format_code(code, "events.rs", &style).unwrap()
+    }
+
+    #[test]
+    fn paths_are_written_for_each_edition() {
+        let code = "#[derive(Debug, serde::Serialize)]
+pub struct Events {
+    pub at: chrono::NaiveDateTime,
+    pub gen: i32,
+    pub mood: crate::types::Mood,
+    pub tags: std::vec::Vec<String>,
+}
+";
+        assert_eq!(for_edition(Edition::E2018), code);
+        assert_eq!(for_edition(Edition::E2021), code);
+
+        // Other crates are reached through the crate root
+        assert_eq!(
+            for_edition(Edition::E2015),
+            "#[derive(Debug, crate::serde::Serialize)]
+pub struct Events {
+    pub at: crate::chrono::NaiveDateTime,
+    pub gen: i32,
+    pub mood: crate::types::Mood,
+    pub tags: crate::std::vec::Vec<String>,
+}
+"
+        );
+
+        // `gen` is reserved
+        assert_eq!(
+            for_edition(Edition::E2024),
+            code.replace("pub gen:", "pub r#gen:")
+        );
+    }
+
+    #[test]
+    fn serde_crate_is_reached_through_the_crate_root_in_2015() {
+        let style = |serde: &str| CodeStyle {
+            paths: CratePaths {
+                serde: Some(serde.to_string()),
+                edition: Edition::E2015,
+                ..CratePaths::default()
+            },
+            ..CodeStyle::default()
+        };
+        let code = "#[derive(serde::Serialize)] pub struct Events { pub id: i32 }";
+
+        assert_eq!(
+            format_code(code, "events.rs", &style("shared::serde")).unwrap(),
+            "#[derive(crate::shared::serde::Serialize)]
+#[serde(crate = \"shared::serde\")]
+pub struct Events {
+    pub id: i32,
+}
+"
+        );
+        // A path that is already relative to the crate is left as it is
+        assert_eq!(
+            format_code(code, "events.rs", &style("crate::serde")).unwrap(),
+            "#[derive(crate::serde::Serialize)]
+#[serde(crate = \"crate::serde\")]
+pub struct Events {
+    pub id: i32,
+}
+"
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use std::{env, fs, path::PathBuf};
+
+use tracing::{debug, warn};
+
+/// Find the password for a connection in the password file, the same way libpq does
+///
+/// The file is `PGPASSFILE` if it's set, otherwise `~/.pgpass` (`%APPDATA%\postgresql\pgpass.conf`
+/// on Windows). Each line is `hostname:port:database:username:password`, where any of the first
+/// four fields can be `*`, and the first matching line wins.
+pub fn find_password(host: &str, port: &str, database: &str, username: &str) -> Option<String> {
+    let path = password_file()?;
+    let contents = fs::read_to_string(&path).ok()?;
+
+    // Like libpq, refuse to use a password file other users can read
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = fs::metadata(&path).ok()?.permissions().mode();
+        if mode & 0o077 != 0 {
+            warn!(
+                "Password file {} has group or world access, permissions should be u=rw (0600) or less",
+                path.display()
+            );
+            return None;
+        }
+    }
+
+    debug!("Looking up password in {}", path.display());
+    lookup(&contents, host, port, database, username)
+}
+
+/// Find the password for a connection in the contents of a password file, skipping comments and
+/// lines without every field
+fn lookup(
+    contents: &str,
+    host: &str,
+    port: &str,
+    database: &str,
+    username: &str,
+) -> Option<String> {
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .find_map(|line| {
+            let fields = split_fields(line);
+            if fields.len() < 5 {
+                return None;
+            }
+
+            let matches = |field: &str, value: &str| field == "*" || field == value;
+            if matches(&fields[0], host)
+                && matches(&fields[1], port)
+                && matches(&fields[2], database)
+                && matches(&fields[3], username)
+            {
+                Some(fields[4].clone())
+            } else {
+                None
+            }
+        })
+}
+
+/// Get the path of the password file
+fn password_file() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("PGPASSFILE") {
+        return Some(PathBuf::from(path));
+    }
+
+    if cfg!(windows) {
+        env::var_os("APPDATA").map(|app_data| {
+            PathBuf::from(app_data)
+                .join("postgresql")
+                .join("pgpass.conf")
+        })
+    } else {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join(".pgpass"))
+    }
+}
+
+/// Split a password file line on `:`, where `\:` and `\\` are a literal `:` and `\`
+///
+/// The password is the last field and keeps any further colons.
+fn split_fields(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    fields.last_mut().unwrap().push(escaped);
+                }
+            }
+            ':' if fields.len() < 5 => fields.push(String::new()),
+            _ => fields.last_mut().unwrap().push(c),
+        }
+    }
+
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_fields_unescapes_colons_and_backslashes() {
+        assert_eq!(
+            split_fields(r"db\:internal:5432:app:me:p\\ss"),
+            ["db:internal", "5432", "app", "me", r"p\ss"]
+        );
+        assert_eq!(
+            split_fields("localhost:5432:app:me:pass:with:colons"),
+            ["localhost", "5432", "app", "me", "pass:with:colons"]
+        );
+        assert_eq!(split_fields("localhost:5432"), ["localhost", "5432"]);
+    }
+
+    #[test]
+    fn lookup_takes_the_first_matching_line() {
+        let contents = "\
+# Comments and blank lines are skipped, so is a line without every field
+
+*:*:app:me
+localhost:5432:app:me:first
+*:*:*:me:wildcard
+localhost:5432:app:me:second
+";
+        assert_eq!(
+            lookup(contents, "localhost", "5432", "app", "me").as_deref(),
+            Some("first")
+        );
+        assert_eq!(
+            lookup(contents, "db.internal", "6432", "other", "me").as_deref(),
+            Some("wildcard")
+        );
+        assert_eq!(lookup(contents, "localhost", "5432", "app", "you"), None);
+        assert_eq!(
+            lookup(
+                "#localhost:5432:app:me:commented",
+                "#localhost",
+                "5432",
+                "app",
+                "me"
+            ),
+            None
+        );
+    }
+}
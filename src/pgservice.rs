@@ -0,0 +1,130 @@
+use std::{collections::BTreeMap, env, fs, path::PathBuf};
+
+use tracing::debug;
+
+use crate::error::{Error, Result};
+
+/// Find the connection settings of a service in the connection service file, the same way libpq
+/// does
+///
+/// The file is `PGSERVICEFILE` if it's set, otherwise `~/.pg_service.conf`
+/// (`%APPDATA%\postgresql\.pg_service.conf` on Windows), and then `pg_service.conf` in
+/// `PGSYSCONFDIR`. Each service is a `[name]` section of `key=value` lines, like `host=db.internal`,
+/// and the first file that has the service wins.
+pub fn find_service(name: &str) -> Result<BTreeMap<String, String>> {
+    for path in service_files() {
+        let Ok(contents) = fs::read_to_string(&path) else {
+            continue;
+        };
+
+        debug!("Looking up service {} in {}", name, path.display());
+        if let Some(settings) = parse_service(&contents, name) {
+            return Ok(settings);
+        }
+    }
+
+    Err(Error::Config(format!(
+        "Service {} isn't defined in the connection service file",
+        name
+    )))
+}
+
+/// Get the paths of the service files, in the order they are searched
+fn service_files() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    if let Some(path) = env::var_os("PGSERVICEFILE") {
+        paths.push(PathBuf::from(path));
+    } else if cfg!(windows) {
+        if let Some(app_data) = env::var_os("APPDATA") {
+            paths.push(
+                PathBuf::from(app_data)
+                    .join("postgresql")
+                    .join(".pg_service.conf"),
+            );
+        }
+    } else if let Some(home) = env::var_os("HOME") {
+        paths.push(PathBuf::from(home).join(".pg_service.conf"));
+    }
+
+    if let Some(directory) = env::var_os("PGSYSCONFDIR") {
+        paths.push(PathBuf::from(directory).join("pg_service.conf"));
+    }
+
+    paths
+}
+
+/// Get the settings in the section of a service, or `None` when the file doesn't have it
+fn parse_service(contents: &str, name: &str) -> Option<BTreeMap<String, String>> {
+    let mut settings = None;
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(section) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            // The first section of the service is the one that counts
+            if settings.is_some() {
+                break;
+            }
+            if section == name {
+                settings = Some(BTreeMap::new());
+            }
+            continue;
+        }
+
+        if let Some(settings) = &mut settings {
+            if let Some((key, value)) = line.split_once('=') {
+                settings.insert(key.trim().to_string(), value.trim().to_string());
+            }
+        }
+    }
+
+    settings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVICE_FILE: &str = "\
+# Comments and blank lines are skipped
+
+[staging]
+host = staging.internal
+port=5433
+dbname=app
+
+[production]
+host=db.internal
+user=app
+[staging]
+host=ignored.internal
+";
+
+    #[test]
+    fn parse_service_reads_the_first_section_of_the_service() {
+        let settings = parse_service(SERVICE_FILE, "staging").expect("staging is defined");
+        assert_eq!(
+            settings,
+            BTreeMap::from([
+                ("dbname".to_string(), "app".to_string()),
+                ("host".to_string(), "staging.internal".to_string()),
+                ("port".to_string(), "5433".to_string()),
+            ])
+        );
+
+        let settings = parse_service(SERVICE_FILE, "production").expect("production is defined");
+        assert_eq!(settings["host"], "db.internal");
+        assert_eq!(settings["user"], "app");
+    }
+
+    #[test]
+    fn parse_service_skips_files_without_the_section() {
+        assert_eq!(parse_service(SERVICE_FILE, "development"), None);
+        assert_eq!(parse_service("host=db.internal\n", "development"), None);
+        assert_eq!(parse_service("", "staging"), None);
+    }
+}
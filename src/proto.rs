@@ -0,0 +1,178 @@
+use std::fmt::Write;
+
+use convert_case::{Case, Casing};
+
+use crate::ir::{ResolvedSchema, ResolvedTable};
+use crate::model::TableKind;
+use crate::repository;
+
+/// How a field is carried in a protobuf message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtoKind {
+    /// A protobuf scalar with the same Rust type as the field
+    Scalar,
+    /// An `i16`, widened to `int32` since protobuf has no smaller integers
+    Int16,
+    /// A `bytes::Bytes`, which prost reads as a `Vec<u8>`
+    Bytes,
+    /// Any other type, as a string of its JSON
+    Json,
+}
+
+/// The protobuf type of a field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProtoField {
+    /// The protobuf scalar type
+    pub proto_type: &'static str,
+    /// How the field is converted to and from it
+    pub kind: ProtoKind,
+    /// Whether the field is a proto3 `optional`, which is an `Option` in Rust
+    pub optional: bool,
+}
+
+/// Get the protobuf type of a field from its Rust type
+///
+/// Nullable scalars become `optional` fields, while nullable JSON is written as `null`.
+pub fn proto_field(rust_type: &str) -> ProtoField {
+    let (inner, optional) = match rust_type
+        .strip_prefix("Option<")
+        .and_then(|inner| inner.strip_suffix('>'))
+    {
+        Some(inner) => (inner, true),
+        None => (rust_type, false),
+    };
+
+    let scalar = |proto_type| ProtoField {
+        proto_type,
+        kind: ProtoKind::Scalar,
+        optional,
+    };
+    match inner {
+        "i32" => scalar("int32"),
+        "i64" => scalar("int64"),
+        "u32" => scalar("uint32"),
+        "f32" => scalar("float"),
+        "f64" => scalar("double"),
+        "bool" => scalar("bool"),
+        "String" => scalar("string"),
+        "Vec<u8>" => scalar("bytes"),
+        "bytes::Bytes" => ProtoField {
+            proto_type: "bytes",
+            kind: ProtoKind::Bytes,
+            optional,
+        },
+        "i16" => ProtoField {
+            proto_type: "int32",
+            kind: ProtoKind::Int16,
+            optional,
+        },
+        _ => ProtoField {
+            proto_type: "string",
+            kind: ProtoKind::Json,
+            optional: false,
+        },
+    }
+}
+
+/// Get the protobuf package of a schema, which is also the module `tonic::include_proto!` reads
+pub fn package(schema: &ResolvedSchema) -> String {
+    schema.name.to_case(Case::Snake)
+}
+
+/// Get the name of the message of a table, which prost keeps as the name of its struct
+pub fn message_name(table: &ResolvedTable) -> String {
+    table.rust_name.to_case(Case::Pascal)
+}
+
+/// Write a proto3 file with a message per table, and a service per table with the RPCs its
+/// repository helpers support, for `--emit proto`
+pub fn proto(schema: &ResolvedSchema) -> String {
+    let mut proto = String::from("syntax = \"proto3\";\n\n");
+    writeln!(proto, "package {};", package(schema)).unwrap();
+
+    for table in &schema.tables {
+        let message = message_name(table);
+        writeln!(proto).unwrap();
+        if let Some(comment) = &table.comment {
+            for line in comment.lines() {
+                writeln!(proto, "// {}", line).unwrap();
+            }
+        }
+        // Tables without columns don't get repository helpers to serve
+        if table.columns.is_empty() {
+            writeln!(proto, "message {} {{}}", message).unwrap();
+            continue;
+        }
+
+        writeln!(proto, "message {} {{", message).unwrap();
+        for (number, column) in table.columns.iter().enumerate() {
+            write_field(&mut proto, &column.rust_name, &column.rust_type, number + 1);
+        }
+        writeln!(proto, "}}").unwrap();
+
+        writeln!(proto, "\nmessage List{}Request {{}}", message).unwrap();
+        writeln!(proto, "\nmessage List{}Response {{", message).unwrap();
+        writeln!(proto, "  repeated {} rows = 1;", message).unwrap();
+        writeln!(proto, "}}").unwrap();
+
+        // Rows of tables with a primary key are addressed by it
+        let key = repository::key_indices(table);
+        let keyed = table.kind == TableKind::Table && !key.is_empty();
+        if keyed {
+            writeln!(proto, "\nmessage {}Key {{", message).unwrap();
+            for (number, index) in key.iter().enumerate() {
+                let column = &table.columns[*index];
+                write_field(&mut proto, &column.rust_name, &column.rust_type, number + 1);
+            }
+            writeln!(proto, "}}").unwrap();
+            writeln!(proto, "\nmessage Delete{}Response {{}}", message).unwrap();
+        }
+
+        writeln!(proto, "\nservice {}Service {{", message).unwrap();
+        writeln!(
+            proto,
+            "  rpc List(List{0}Request) returns (List{0}Response);",
+            message
+        )
+        .unwrap();
+        if table.kind == TableKind::Table {
+            writeln!(proto, "  rpc Create({0}) returns ({0});", message).unwrap();
+        }
+        if keyed {
+            writeln!(proto, "  rpc Get({0}Key) returns ({0});", message).unwrap();
+            if table
+                .columns
+                .iter()
+                .any(|column| repository::is_updated(table, column))
+            {
+                writeln!(proto, "  rpc Update({0}) returns ({0});", message).unwrap();
+            }
+            writeln!(
+                proto,
+                "  rpc Delete({0}Key) returns (Delete{0}Response);",
+                message
+            )
+            .unwrap();
+        }
+        writeln!(proto, "}}").unwrap();
+    }
+
+    proto
+}
+
+/// Write a message field
+fn write_field(proto: &mut String, name: &str, rust_type: &str, number: usize) {
+    let field = proto_field(rust_type);
+    let optional = if field.optional { "optional " } else { "" };
+    let comment = if field.kind == ProtoKind::Json {
+        " // JSON"
+    } else {
+        ""
+    };
+    writeln!(
+        proto,
+        "  {}{} {} = {};{}",
+        optional, field.proto_type, name, number, comment
+    )
+    .unwrap();
+}
@@ -0,0 +1,70 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use regex::Regex;
+use syn::Ident;
+use tracing::info;
+
+use crate::ir::ResolvedSchema;
+
+/// What happens to the columns that match `--redact-pattern`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedactMode {
+    /// Keep the fields, but leave them out of serialization and `Debug`
+    Mask,
+    /// Leave the columns out of the structs altogether
+    Skip,
+}
+
+/// Mask or skip the columns whose name matches `pattern`
+pub fn apply(resolved: &mut ResolvedSchema, pattern: &Regex, mode: RedactMode) {
+    for table in &mut resolved.tables {
+        let table_name = &table.name;
+        match mode {
+            RedactMode::Mask => {
+                for column in &mut table.columns {
+                    if pattern.is_match(&column.name) {
+                        info!("Redacting column {}.{}", table_name, column.name);
+                        column.redacted = true;
+                    }
+                }
+            }
+            RedactMode::Skip => table.columns.retain(|column| {
+                let is_match = pattern.is_match(&column.name);
+                if is_match {
+                    info!(
+                        "Skipping column {}.{} as it is sensitive",
+                        table_name, column.name
+                    );
+                }
+                !is_match
+            }),
+        }
+    }
+}
+
+/// Generate a `Debug` impl for `target` that writes `<redacted>` in place of the masked fields
+///
+/// `name` is the name of the struct that is written, and `fields` are its fields with whether
+/// they're masked, in order. Both the columns matching `--redact-pattern` and the ones set in
+/// the config file's `[debug]` section are masked.
+pub fn generate_debug(target: TokenStream, name: &str, fields: &[(Ident, bool)]) -> TokenStream {
+    let fields = fields.iter().map(|(field, masked)| {
+        let label = field.to_string();
+        let label = label.trim_start_matches("r#");
+        if *masked {
+            quote! { .field(#label, &format_args!("<redacted>")) }
+        } else {
+            quote! { .field(#label, &self.#field) }
+        }
+    });
+
+    quote! {
+        impl std::fmt::Debug for #target {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.debug_struct(#name)
+                    #(#fields)*
+                    .finish()
+            }
+        }
+    }
+}
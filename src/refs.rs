@@ -0,0 +1,185 @@
+use std::collections::HashSet;
+
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::Ident;
+use tracing::debug;
+
+use crate::redact;
+
+/// A field of a table struct, for generating its borrowed counterpart
+pub struct RefField {
+    /// The name of the field
+    pub name: Ident,
+    /// The type of the field in the owned struct
+    pub rust_type: TokenStream,
+    /// Whether the field is left out of serialization
+    pub redacted: bool,
+    /// Whether the field is written as `<redacted>` by `Debug`
+    pub masked: bool,
+}
+
+/// The Rust types that are `Copy`, so they don't need to be cloned to convert a struct
+const COPY_TYPES: [&str; 10] = [
+    "bool",
+    "i8",
+    "i16",
+    "i32",
+    "i64",
+    "f32",
+    "f64",
+    "char",
+    "u32",
+    "rust_decimal::Decimal",
+];
+
+/// How a field of the owned struct is borrowed
+enum Borrow {
+    /// `String` as `&'a str`
+    Str,
+    /// `Vec<u8>` as `&'a [u8]`
+    Bytes,
+    /// A `Copy` type is copied
+    Copy,
+    /// Anything else is cloned
+    Owned,
+}
+
+/// Get the name of the borrowed struct of a table struct
+pub fn ref_ident(struct_name: &TokenStream) -> Ident {
+    Ident::new(&format!("{}Ref", struct_name), Span::call_site())
+}
+
+/// Generate a `[Struct]Ref<'a>` struct that borrows the text and bytes fields of a table struct,
+/// with conversions to and from the owned struct
+///
+/// Tables without any text or bytes fields have nothing to borrow, so they don't get one. The
+/// generated enums in `enum_names` are `Copy`.
+pub fn generate_ref_struct(
+    struct_name: &TokenStream,
+    fields: &[RefField],
+    enum_names: &HashSet<String>,
+) -> Option<TokenStream> {
+    let borrows: Vec<(Borrow, bool)> = fields
+        .iter()
+        .map(|field| borrow(&field.rust_type.to_string(), enum_names))
+        .collect();
+    if borrows
+        .iter()
+        .all(|(borrow, _)| matches!(borrow, Borrow::Copy | Borrow::Owned))
+    {
+        debug!(
+            "{} has no text or bytes fields, skipping its Ref struct",
+            struct_name
+        );
+        return None;
+    }
+
+    let ref_name = ref_ident(struct_name);
+    let mut ref_fields = Vec::new();
+    let mut to_owned = Vec::new();
+    let mut to_ref = Vec::new();
+    for (field, (borrow, optional)) in fields.iter().zip(&borrows) {
+        let name = &field.name;
+        let rust_type = &field.rust_type;
+        let skip = if field.redacted {
+            quote! { #[serde(skip_serializing)] }
+        } else {
+            quote! {}
+        };
+        let (ref_type, owned, borrowed) = match borrow {
+            Borrow::Str => (
+                quote! { &'a str },
+                quote! { to_owned() },
+                quote! { as_str() },
+            ),
+            Borrow::Bytes => (
+                quote! { &'a [u8] },
+                quote! { to_vec() },
+                quote! { as_slice() },
+            ),
+            Borrow::Copy => {
+                ref_fields.push(quote! { #skip pub #name: #rust_type, });
+                to_owned.push(quote! { #name: row.#name, });
+                to_ref.push(quote! { #name: row.#name, });
+                continue;
+            }
+            Borrow::Owned => {
+                ref_fields.push(quote! { #skip pub #name: #rust_type, });
+                to_owned.push(quote! { #name: row.#name, });
+                to_ref.push(quote! { #name: row.#name.clone(), });
+                continue;
+            }
+        };
+
+        if *optional {
+            ref_fields.push(quote! { #skip pub #name: Option<#ref_type>, });
+            to_owned.push(quote! { #name: row.#name.map(|value| value.#owned), });
+            to_ref.push(quote! { #name: row.#name.as_deref(), });
+        } else {
+            ref_fields.push(quote! { #skip pub #name: #ref_type, });
+            to_owned.push(quote! { #name: row.#name.#owned, });
+            to_ref.push(quote! { #name: row.#name.#borrowed, });
+        }
+    }
+
+    // Fields are masked in Debug, like they are in the owned struct
+    let (debug_derive, debug_impl) = if fields.iter().any(|field| field.masked) {
+        let fields: Vec<(Ident, bool)> = fields
+            .iter()
+            .map(|field| (field.name.clone(), field.masked))
+            .collect();
+        let debug_impl =
+            redact::generate_debug(quote! { #ref_name<'_> }, &ref_name.to_string(), &fields);
+        (quote! {}, debug_impl)
+    } else {
+        (quote! { Debug, }, quote! {})
+    };
+
+    Some(quote! {
+        #[derive(#debug_derive Clone, PartialEq, serde::Serialize)]
+        pub struct #ref_name<'a> {
+            #(#ref_fields)*
+        }
+
+        #debug_impl
+
+        impl From<#ref_name<'_>> for #struct_name {
+            fn from(row: #ref_name<'_>) -> Self {
+                #struct_name {
+                    #(#to_owned)*
+                }
+            }
+        }
+
+        impl<'a> From<&'a #struct_name> for #ref_name<'a> {
+            fn from(row: &'a #struct_name) -> Self {
+                #ref_name {
+                    #(#to_ref)*
+                }
+            }
+        }
+    })
+}
+
+/// Work out how a field is borrowed from its type, and whether it's an `Option`
+fn borrow(rust_type: &str, enum_names: &HashSet<String>) -> (Borrow, bool) {
+    let rust_type = rust_type.replace(' ', "");
+    let (rust_type, optional) = match rust_type
+        .strip_prefix("Option<")
+        .and_then(|inner| inner.strip_suffix('>'))
+    {
+        Some(inner) => (inner, true),
+        None => (rust_type.as_str(), false),
+    };
+
+    // Enums of table files are referred to through the types module of the parent
+    let name = rust_type.trim_start_matches("super::types::");
+    let borrow = match rust_type {
+        "String" => Borrow::Str,
+        "Vec<u8>" => Borrow::Bytes,
+        _ if COPY_TYPES.contains(&name) || enum_names.contains(name) => Borrow::Copy,
+        _ => Borrow::Owned,
+    };
+    (borrow, optional)
+}
@@ -0,0 +1,224 @@
+use convert_case::{Case, Casing};
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::Ident;
+use tracing::debug;
+
+use crate::error::Result;
+use crate::ir::{ResolvedColumn, ResolvedSchema, ResolvedTable};
+use crate::model::{ForeignKey, TableKind};
+use crate::output;
+use crate::repository::{self, Conventions, RepositoryField};
+
+/// Generate a `[Parent]With[Children]` struct per table that references a table with a foreign
+/// key, holding a row with the rows that reference it, and a `load_with_...` helper that loads
+/// them for a set of rows, for `--relations`
+///
+/// The rows that reference the parents are loaded with a second query, which is matched to them
+/// in Rust, so it suits pages of rows rather than whole tables. `child_path` is the path to the
+/// struct of a table from the module of `table`, and `child_cfg` the attributes that gate the
+/// code using it, if its table is behind a cargo feature. Returns the code and the names of the
+/// structs, with the tables they hold the rows of.
+pub fn generate_relations<'a>(
+    table: &ResolvedTable,
+    schema: &'a ResolvedSchema,
+    struct_name: &TokenStream,
+    fields: &[RepositoryField],
+    conventions: &Conventions,
+    child_path: impl Fn(&ResolvedTable) -> TokenStream,
+    child_cfg: impl Fn(&ResolvedTable) -> TokenStream,
+) -> Result<(TokenStream, Vec<(String, &'a ResolvedTable)>)> {
+    let mut code = TokenStream::new();
+    let mut names = Vec::new();
+    if table.kind != TableKind::Table || fields.is_empty() {
+        return Ok((code, names));
+    }
+
+    let parent_field = table.rust_name.to_case(Case::Snake);
+    for child in &schema.tables {
+        let foreign_keys: Vec<&ForeignKey> = child
+            .foreign_keys
+            .iter()
+            .filter(|foreign_key| {
+                foreign_key.referenced_schema == schema.name
+                    && foreign_key.referenced_table == table.name
+            })
+            .collect();
+
+        for foreign_key in &foreign_keys {
+            // Tables that reference the parent more than once, or are the parent, are told apart
+            // by the columns of the key
+            let mut child_field = child.rust_name.to_case(Case::Snake);
+            if foreign_keys.len() > 1 || child.name == table.name {
+                let columns: Vec<String> = foreign_key
+                    .columns
+                    .iter()
+                    .map(|name| name.to_case(Case::Snake))
+                    .collect();
+                child_field = format!("{}_by_{}", child_field, columns.join("_and_"));
+            }
+
+            let Some(pairs) = column_pairs(table, child, foreign_key) else {
+                debug!(
+                    "{} of {} doesn't match the columns it references, skipping its relation",
+                    foreign_key.name, child.name
+                );
+                continue;
+            };
+
+            let context = format!("relation {} of {}", foreign_key.name, child.name);
+            let relation_name = format!(
+                "{}With{}",
+                table.rust_name,
+                child_field.to_case(Case::Pascal)
+            );
+            let relation = output::ident(&relation_name, &context)?;
+            let parent = output::ident(&parent_field, &context)?;
+            let children = output::ident(&child_field, &context)?;
+            let method = output::ident(&format!("load_with_{}", child_field), &context)?;
+            let child_struct = child_path(child);
+            let cfg = child_cfg(child);
+
+            // Select the children of every parent, leaving out the soft deleted ones like `list`
+            let mut conditions: Vec<String> = pairs
+                .iter()
+                .enumerate()
+                .map(|(index, (_, column))| {
+                    format!(
+                        "{} = ANY(${})",
+                        repository::quote_name(&column.name),
+                        index + 1
+                    )
+                })
+                .collect();
+            if let Some(column) = repository::soft_delete_column(child, &conventions.soft_delete) {
+                conditions.push(format!("{} IS NULL", repository::quote_name(&column.name)));
+            }
+            let mut select_sql = format!(
+                "SELECT {} FROM {}.{} WHERE {}",
+                repository::select_list(&child.columns),
+                repository::quote_name(&schema.name),
+                repository::quote_name(&child.name),
+                conditions.join(" AND ")
+            );
+            let order: Vec<String> = repository::key_indices(child)
+                .into_iter()
+                .map(|index| repository::quote_name(&child.columns[index].name))
+                .collect();
+            if !order.is_empty() {
+                select_sql.push_str(&format!(" ORDER BY {}", order.join(", ")));
+            }
+
+            // Pass the referenced values of every parent as an array per column. Rows that match
+            // every array but not a single parent are dropped when they are matched
+            let keys: Vec<Ident> = (0..pairs.len())
+                .map(|index| Ident::new(&format!("keys_{}", index), Span::call_site()))
+                .collect();
+            let params = pairs.iter().map(|(referenced, _)| {
+                let index = table
+                    .columns
+                    .iter()
+                    .position(|column| column.name == referenced.name)
+                    .expect("The referenced column is a column of the table");
+                &fields[index].param
+            });
+            let mut matches = Vec::new();
+            for (referenced, column) in &pairs {
+                let parent_value = output::ident(&referenced.rust_name, &context)?;
+                let child_value = output::ident(&column.rust_name, &context)?;
+                matches.push(match (column.nullable, referenced.nullable) {
+                    (false, false) => quote! { child.#child_value == loaded.#parent.#parent_value },
+                    (true, false) => quote! {
+                        child.#child_value.as_ref() == Some(&loaded.#parent.#parent_value)
+                    },
+                    (false, true) => quote! {
+                        Some(&child.#child_value) == loaded.#parent.#parent_value.as_ref()
+                    },
+                    (true, true) => quote! {
+                        child.#child_value.is_some()
+                            && child.#child_value == loaded.#parent.#parent_value
+                    },
+                });
+            }
+
+            // Referencing a deprecated table is fine
+            let allow_deprecated = if child.deprecated.is_some() {
+                quote! { #[allow(deprecated)] }
+            } else {
+                quote! {}
+            };
+            let doc = format!(
+                " A row of `{}` with the rows of `{}` that reference it with `{}`",
+                table.name, child.name, foreign_key.name
+            );
+            code.extend(quote! {
+                #[doc = #doc]
+                #cfg
+                #allow_deprecated
+                #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+                pub struct #relation {
+                    pub #parent: #struct_name,
+                    pub #children: Vec<#child_struct>,
+                }
+
+                #cfg
+                #allow_deprecated
+                impl #struct_name {
+                    pub fn #method(client: &mut impl postgres::GenericClient, rows: Vec<Self>) -> Result<Vec<#relation>, postgres::Error> {
+                        if rows.is_empty() {
+                            return Ok(Vec::new());
+                        }
+
+                        #(let #keys: Vec<_> = rows.iter().map(|row| #params).collect();)*
+                        let children = client.query(#select_sql, &[#(&#keys),*])?;
+                        let mut loaded: Vec<#relation> = rows
+                            .into_iter()
+                            .map(|row| #relation { #parent: row, #children: Vec::new() })
+                            .collect();
+                        for child in children.iter().map(#child_struct::from_row) {
+                            if let Some(loaded) = loaded.iter_mut().find(|loaded| #(#matches)&&*) {
+                                loaded.#children.push(child);
+                            }
+                        }
+
+                        Ok(loaded)
+                    }
+                }
+            });
+            names.push((relation_name, child));
+        }
+    }
+
+    Ok((code, names))
+}
+
+/// Get the referenced column of the parent and the referencing column of the child for each
+/// column of a foreign key, or `None` when a column is missing or their types differ
+fn column_pairs<'a>(
+    table: &'a ResolvedTable,
+    child: &'a ResolvedTable,
+    foreign_key: &ForeignKey,
+) -> Option<Vec<(&'a ResolvedColumn, &'a ResolvedColumn)>> {
+    foreign_key
+        .columns
+        .iter()
+        .zip(&foreign_key.referenced_columns)
+        .map(|(name, referenced_name)| {
+            let column = child.columns.iter().find(|column| &column.name == name)?;
+            let referenced = table
+                .columns
+                .iter()
+                .find(|column| &column.name == referenced_name)?;
+            (inner_type(&column.rust_type) == inner_type(&referenced.rust_type))
+                .then_some((referenced, column))
+        })
+        .collect()
+}
+
+/// Get the type of a field without its `Option`
+fn inner_type(rust_type: &str) -> &str {
+    rust_type
+        .strip_prefix("Option<")
+        .and_then(|inner| inner.strip_suffix('>'))
+        .unwrap_or(rust_type)
+}
@@ -0,0 +1,49 @@
+use std::fs;
+
+use serde::Serialize;
+use tracing::info;
+
+use crate::error::{Error, Result};
+use crate::ir::FallbackColumn;
+
+/// A summary of a run, written with `--report` so CI can check what was generated
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    /// The schemas code was generated for
+    pub schemas: Vec<String>,
+    /// The number of tables and views that were read
+    pub tables: usize,
+    /// The number of structs generated for them
+    pub structs: usize,
+    /// The number of enums generated
+    pub enums: usize,
+    /// The files that were written
+    pub files_written: Vec<String>,
+    /// The files that already had the same contents, so they weren't written
+    pub files_unchanged: Vec<String>,
+    /// The files of tables that no longer exist, which were deleted
+    pub files_deleted: Vec<String>,
+    /// The columns whose type has no Rust mapping, so they fell back to `String`
+    pub fallback_columns: Vec<FallbackColumn>,
+    /// The messages of every warning that was logged
+    pub warnings: Vec<String>,
+}
+
+impl Report {
+    /// Add what was generated for another schema
+    pub fn merge(&mut self, other: Report) {
+        self.schemas.extend(other.schemas);
+        self.tables += other.tables;
+        self.structs += other.structs;
+        self.enums += other.enums;
+        self.fallback_columns.extend(other.fallback_columns);
+    }
+}
+
+/// Write the report as JSON
+pub fn write_report(path: &str, report: &Report) -> Result<()> {
+    info!("Writing run report to {}", path);
+    let contents = serde_json::to_string_pretty(report).expect("Reports are always serializable");
+
+    fs::write(path, contents + "\n").map_err(Error::io(path))
+}
@@ -0,0 +1,1469 @@
+use proc_macro2::{Literal, Span, TokenStream};
+use quote::quote;
+use syn::Ident;
+use tracing::debug;
+
+use crate::ir::{ResolvedColumn, ResolvedTable};
+use crate::model::{TableKind, TypeKind};
+use crate::newtypes;
+use crate::types;
+
+/// A field of a table struct, for reading and writing its column
+pub struct RepositoryField {
+    /// The name of the field
+    pub name: Ident,
+    /// The type of the field
+    pub rust_type: TokenStream,
+    /// The expression that reads the field from `row`
+    pub read: TokenStream,
+    /// The expression that reads the field from `row`, a row of a binary COPY of every column
+    pub copy_read: TokenStream,
+    /// The expression that passes the field of `self` as a query parameter
+    pub write: TokenStream,
+    /// The expression of the field of `row` as a query parameter that borrows from it, for
+    /// statements that take several rows
+    pub param: TokenStream,
+}
+
+/// How a field is passed to and from postgres, when postgres can't convert its type itself
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Carrier {
+    /// postgres converts the type of the field
+    Direct,
+    /// A JSON column with its own type, through postgres' `Json` wrapper
+    Json,
+    /// A `char`, as a `String`, since postgres has no char conversions
+    Char,
+    /// A `bytes::Bytes`, as a `Vec<u8>` and a `&[u8]`
+    Bytes,
+}
+
+impl Carrier {
+    /// Get the carrier of the field of a column
+    fn of(column: &ResolvedColumn) -> Carrier {
+        let rust_type = column.rust_type.replace(' ', "");
+        let rust_type = rust_type
+            .strip_prefix("Option<")
+            .and_then(|inner| inner.strip_suffix('>'))
+            .unwrap_or(&rust_type);
+        if ["json", "jsonb"].contains(&column.data_type.name.as_str())
+            && column.data_type.dimensions == 0
+            && !rust_type.contains("serde_json::Value")
+        {
+            Carrier::Json
+        } else if rust_type == "char" {
+            Carrier::Char
+        } else if rust_type == "bytes::Bytes" {
+            Carrier::Bytes
+        } else {
+            Carrier::Direct
+        }
+    }
+}
+
+/// Get the field of a column, with how it's read from rows and passed as a query parameter, where
+/// `index` is the position of the column in a binary COPY of every column
+pub fn repository_field(
+    column: &ResolvedColumn,
+    name: Ident,
+    rust_type: TokenStream,
+    index: usize,
+) -> RepositoryField {
+    let column_name = &column.name;
+    let carrier = Carrier::of(column);
+    let read = match (carrier, column.nullable) {
+        (Carrier::Json, false) => quote! { row.get::<_, postgres::types::Json<_>>(#column_name).0 },
+        (Carrier::Json, true) => quote! {
+            row.get::<_, Option<postgres::types::Json<_>>>(#column_name).map(|json| json.0)
+        },
+        (Carrier::Char, false) => quote! {
+            row.get::<_, String>(#column_name).chars().next().unwrap_or_default()
+        },
+        (Carrier::Char, true) => quote! {
+            row.get::<_, Option<String>>(#column_name).and_then(|text| text.chars().next())
+        },
+        (Carrier::Bytes, false) => quote! {
+            bytes::Bytes::from(row.get::<_, Vec<u8>>(#column_name))
+        },
+        (Carrier::Bytes, true) => quote! {
+            row.get::<_, Option<Vec<u8>>>(#column_name).map(bytes::Bytes::from)
+        },
+        (Carrier::Direct, _) => quote! { row.get(#column_name) },
+    };
+    let index = Literal::usize_unsuffixed(index);
+    let copy_read = match (carrier, column.nullable) {
+        (Carrier::Json, false) => quote! { row.get::<postgres::types::Json<_>>(#index).0 },
+        (Carrier::Json, true) => quote! {
+            row.get::<Option<postgres::types::Json<_>>>(#index).map(|json| json.0)
+        },
+        (Carrier::Char, false) => quote! {
+            row.get::<String>(#index).chars().next().unwrap_or_default()
+        },
+        (Carrier::Char, true) => quote! {
+            row.get::<Option<String>>(#index).and_then(|text| text.chars().next())
+        },
+        (Carrier::Bytes, false) => quote! { bytes::Bytes::from(row.get::<Vec<u8>>(#index)) },
+        (Carrier::Bytes, true) => quote! {
+            row.get::<Option<Vec<u8>>>(#index).map(bytes::Bytes::from)
+        },
+        (Carrier::Direct, _) => quote! { row.get(#index) },
+    };
+    let write = match (carrier, column.nullable) {
+        (Carrier::Json, false) => quote! { &postgres::types::Json(&self.#name) },
+        (Carrier::Json, true) => quote! { &self.#name.as_ref().map(postgres::types::Json) },
+        (Carrier::Char, false) => quote! { &self.#name.to_string() },
+        (Carrier::Char, true) => quote! { &self.#name.map(String::from) },
+        (Carrier::Bytes, false) => quote! { &self.#name.as_ref() },
+        (Carrier::Bytes, true) => quote! { &self.#name.as_deref() },
+        (Carrier::Direct, _) => quote! { &self.#name },
+    };
+    let param = match (carrier, column.nullable) {
+        (Carrier::Json, false) => quote! { postgres::types::Json(&row.#name) },
+        (Carrier::Json, true) => quote! { row.#name.as_ref().map(postgres::types::Json) },
+        (Carrier::Char, false) => quote! { row.#name.to_string() },
+        (Carrier::Char, true) => quote! { row.#name.map(String::from) },
+        (Carrier::Bytes, false) => quote! { row.#name.as_ref() },
+        (Carrier::Bytes, true) => quote! { row.#name.as_deref() },
+        (Carrier::Direct, _) => quote! { &row.#name },
+    };
+
+    RepositoryField {
+        name,
+        rust_type,
+        read,
+        copy_read,
+        write,
+        param,
+    }
+}
+
+/// The columns the repository helpers treat by convention, by name
+#[derive(Debug, Clone)]
+pub struct Conventions {
+    /// The nullable timestamp column that marks rows soft deleted
+    pub soft_delete: String,
+    /// The timestamp column of when a row was created
+    pub created_at: String,
+    /// The timestamp column of when a row was last updated
+    pub updated_at: String,
+    /// How the created and updated timestamps are filled in
+    pub timestamps: Timestamps,
+    /// The integer columns that version rows for optimistic locking, the first a table has wins
+    pub versions: Vec<String>,
+}
+
+/// How the helpers fill in the created and updated timestamps of a row
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timestamps {
+    /// Set both to `now()` on insert, and the updated one on update
+    Now,
+    /// Leave both out, for the defaults and triggers of the database to fill in
+    Database,
+    /// Write them from the struct like any other column
+    Off,
+}
+
+/// Which convention timestamp a column is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Timestamp {
+    Created,
+    Updated,
+}
+
+/// Generate the repository helpers of a table struct, which read and write its rows with any
+/// `postgres::GenericClient`, so they work with a `Client` as well as inside a `Transaction`
+///
+/// Every table and view gets `from_row`, `list`, `stream_all` and `copy_out`. Tables get
+/// `insert`, `insert_many` and `copy_in`, and `insert` on the struct of the rows to insert, which
+/// `insert_many` takes, and tables with a primary key also get `get`, `update`, `delete` and
+/// `apply` for their patch struct. The row, insert and patch structs convert into each other
+/// with `From`. Tables with a primary key or unique constraint get `upsert` on it, and
+/// `upsert_by_...` on each other unique constraint. `fields` are in the order of the columns.
+/// Tables without columns have nothing to read or write, so they don't get any.
+///
+/// Tables with the soft delete column of `conventions` leave the rows it marks deleted out of
+/// `list`, `stream_all` and `get`, which get `_with_deleted` variants, and get `soft_delete` to
+/// mark them. Their created and updated timestamps are filled in as `conventions` says, except by
+/// `copy_in`, which copies rows as they are.
+///
+/// Tables with a version column of `conventions` are locked optimistically: `update` only updates
+/// a row whose version still matches and bumps it, and otherwise returns the `UpdateError` of
+/// [`generate_update_error`], which is referred to through `type_prefix`. `upsert` bumps it too.
+pub fn generate_repository(
+    table: &ResolvedTable,
+    schema: &str,
+    struct_name: &TokenStream,
+    fields: &[RepositoryField],
+    conventions: &Conventions,
+    type_prefix: &TokenStream,
+) -> Option<TokenStream> {
+    if fields.is_empty() {
+        debug!(
+            "{} has no columns, skipping its repository helpers",
+            table.name
+        );
+        return None;
+    }
+
+    let columns: Vec<(&ResolvedColumn, &RepositoryField)> =
+        table.columns.iter().zip(fields).collect();
+    let from = format!("{}.{}", quote_name(schema), quote_name(&table.name));
+    let column_list = select_list(&table.columns);
+
+    // Map a row into the struct
+    let mappings = fields.iter().map(|field| {
+        let name = &field.name;
+        let read = &field.read;
+        quote! { #name: #read, }
+    });
+    let mut methods = vec![quote! {
+        pub fn from_row(row: &postgres::Row) -> Self {
+            #struct_name {
+                #(#mappings)*
+            }
+        }
+    }];
+
+    // Get the primary key columns, unless one of them was left out of the struct
+    let key: Vec<(&ResolvedColumn, &RepositoryField)> = key_indices(table)
+        .into_iter()
+        .map(|index| columns[index])
+        .collect();
+
+    // Soft deleted rows are left out of reads, and the `_with_deleted` variants include them
+    let soft_delete = soft_delete_column(table, &conventions.soft_delete);
+    let version =
+        version_column(table, &conventions.versions).map(|column| quote_name(&column.name));
+    let mut variants = vec![(
+        "",
+        soft_delete.map(|column| format!("{} IS NULL", quote_name(&column.name))),
+    )];
+    if soft_delete.is_some() {
+        variants.push(("_with_deleted", None));
+    }
+
+    for (suffix, condition) in &variants {
+        // List every row, in the order of the primary key when there is one
+        let mut list_sql = format!("SELECT {} FROM {}", column_list, from);
+        if let Some(condition) = condition {
+            list_sql.push_str(&format!(" WHERE {}", condition));
+        }
+        if !key.is_empty() {
+            let order: Vec<String> = key
+                .iter()
+                .map(|(column, _)| quote_name(&column.name))
+                .collect();
+            list_sql.push_str(&format!(" ORDER BY {}", order.join(", ")));
+        }
+        let list = Ident::new(&format!("list{}", suffix), Span::call_site());
+        methods.push(quote! {
+            pub fn #list(client: &mut impl postgres::GenericClient) -> Result<Vec<Self>, postgres::Error> {
+                let rows = client.query(#list_sql, &[])?;
+                Ok(rows.iter().map(Self::from_row).collect())
+            }
+        });
+
+        // Stream the same rows, reading them as they arrive instead of all at once
+        let stream_all = Ident::new(&format!("stream_all{}", suffix), Span::call_site());
+        methods.push(quote! {
+            pub fn #stream_all(client: &mut impl postgres::GenericClient) -> Result<impl Iterator<Item = Result<Self, postgres::Error>> + '_, postgres::Error> {
+                let rows = client.query_raw(#list_sql, std::iter::empty::<&dyn postgres::types::ToSql>())?;
+                Ok(postgres::fallible_iterator::FallibleIterator::iterator(rows)
+                    .map(|row| row.map(|row| Self::from_row(&row))))
+            }
+        });
+    }
+
+    // Stream every row out with a binary COPY
+    let select_sql = format!("SELECT {} FROM {}", column_list, from);
+    let copy_out_sql = format!("COPY ({}) TO STDOUT (FORMAT binary)", select_sql);
+    let all_columns: Vec<&ResolvedColumn> = columns.iter().map(|(column, _)| *column).collect();
+    let copy_out_types = copy_types(&all_columns, &select_sql);
+    let copy_mappings = fields.iter().map(|field| {
+        let name = &field.name;
+        let read = &field.copy_read;
+        quote! { #name: #read, }
+    });
+    methods.push(quote! {
+        pub fn copy_out(client: &mut impl postgres::GenericClient) -> Result<impl Iterator<Item = Result<Self, postgres::Error>> + '_, postgres::Error> {
+            let types = #copy_out_types;
+            let reader = client.copy_out(#copy_out_sql)?;
+            let rows = postgres::binary_copy::BinaryCopyOutIter::new(reader, &types);
+            Ok(postgres::fallible_iterator::FallibleIterator::iterator(rows).map(|row| {
+                row.map(|row| #struct_name {
+                    #(#copy_mappings)*
+                })
+            }))
+        }
+    });
+
+    // Search the full-text search columns, ranking the best matches first. The first one is the
+    // main one, the others are named after their fields
+    let searched = columns
+        .iter()
+        .filter(|(column, _)| is_text(column) && column.data_type.name == "tsvector")
+        .filter(|(column, _)| column.data_type.dimensions == 0);
+    for (index, (column, field)) in searched.enumerate() {
+        let name = quote_name(&column.name);
+        let mut search_sql = format!(
+            "SELECT {} FROM {} WHERE {} @@ plainto_tsquery($1)",
+            column_list, from, name
+        );
+        if let Some(condition) = &variants[0].1 {
+            search_sql.push_str(&format!(" AND {}", condition));
+        }
+
+        // Ties are in the order of the primary key, like `list`
+        let order: Vec<String> = [format!("ts_rank({}, plainto_tsquery($1)) DESC", name)]
+            .into_iter()
+            .chain(key.iter().map(|(column, _)| quote_name(&column.name)))
+            .collect();
+        search_sql.push_str(&format!(" ORDER BY {}", order.join(", ")));
+        let method = if index == 0 {
+            Ident::new("search", Span::call_site())
+        } else {
+            Ident::new(&format!("search_by_{}", field.name), Span::call_site())
+        };
+        methods.push(quote! {
+            pub fn #method(client: &mut impl postgres::GenericClient, query: &str) -> Result<Vec<Self>, postgres::Error> {
+                let rows = client.query(#search_sql, &[&query])?;
+                Ok(rows.iter().map(Self::from_row).collect())
+            }
+        });
+    }
+
+    // Views can only be read
+    if table.kind != TableKind::Table {
+        return Some(quote! {
+            impl #struct_name {
+                #(#methods)*
+            }
+        });
+    }
+
+    // The timestamps set to now() go after the columns that are passed, so the placeholders of
+    // those stay in order
+    let timestamp = |column: &ResolvedColumn| timestamp_of(column, conventions);
+    let now_columns: Vec<&ResolvedColumn> = table
+        .columns
+        .iter()
+        .filter(|column| conventions.timestamps == Timestamps::Now && timestamp(column).is_some())
+        .collect();
+    let now_names: Vec<String> = now_columns
+        .iter()
+        .map(|column| quote_name(&column.name))
+        .collect();
+    let now_values = vec!["now()"; now_columns.len()];
+
+    // Insert the columns the database doesn't assign itself
+    let inserted: Vec<&(&ResolvedColumn, &RepositoryField)> = columns
+        .iter()
+        .filter(|(column, _)| is_inserted(column, conventions))
+        .collect();
+    let insert_names: Vec<String> = inserted
+        .iter()
+        .map(|(column, _)| quote_name(&column.name))
+        .chain(now_names.iter().cloned())
+        .collect();
+    let insert_sql = if insert_names.is_empty() {
+        format!(
+            "INSERT INTO {} DEFAULT VALUES RETURNING {}",
+            from, column_list
+        )
+    } else {
+        let mut values = placeholders(1, inserted.iter().map(|(column, _)| *column));
+        values.extend(now_values.iter().map(|value| value.to_string()));
+        format!(
+            "INSERT INTO {} ({}) VALUES ({}) RETURNING {}",
+            from,
+            insert_names.join(", "),
+            values.join(", "),
+            column_list
+        )
+    };
+    let insert_values: Vec<&TokenStream> = inserted.iter().map(|(_, field)| &field.write).collect();
+    methods.push(quote! {
+        pub fn insert(&self, client: &mut impl postgres::GenericClient) -> Result<Self, postgres::Error> {
+            let row = client.query_one(#insert_sql, &[#(#insert_values),*])?;
+            Ok(Self::from_row(&row))
+        }
+    });
+
+    // The struct of the rows to insert has the same fields as the ones that are inserted
+    let insert_struct = insert_struct_name(table);
+    let mut code = quote! {
+        impl #insert_struct {
+            pub fn insert(&self, client: &mut impl postgres::GenericClient) -> Result<#struct_name, postgres::Error> {
+                let row = client.query_one(#insert_sql, &[#(#insert_values),*])?;
+                Ok(#struct_name::from_row(&row))
+            }
+        }
+    };
+
+    // Turn a row into one to insert, and into the changes that would make another row like it
+    let inserted_names = inserted.iter().map(|(_, field)| &field.name);
+    code.extend(quote! {
+        impl From<#struct_name> for #insert_struct {
+            fn from(row: #struct_name) -> Self {
+                #insert_struct {
+                    #(#inserted_names: row.#inserted_names,)*
+                }
+            }
+        }
+    });
+    let patched = patched_columns(table, conventions);
+    if !patched.is_empty() {
+        let patch_struct = patch_struct_name(table);
+        let patch_names: Vec<&Ident> = patched.iter().map(|index| &fields[*index].name).collect();
+        let new_values = patched.iter().map(|index| {
+            let name = &fields[*index].name;
+            if is_inserted(&table.columns[*index], conventions) {
+                quote! { Some(row.#name) }
+            } else {
+                quote! { None }
+            }
+        });
+        code.extend(quote! {
+            impl From<#struct_name> for #patch_struct {
+                fn from(row: #struct_name) -> Self {
+                    #patch_struct {
+                        #(#patch_names: Some(row.#patch_names),)*
+                    }
+                }
+            }
+
+            impl From<#insert_struct> for #patch_struct {
+                fn from(row: #insert_struct) -> Self {
+                    #patch_struct {
+                        #(#patch_names: #new_values,)*
+                    }
+                }
+            }
+        });
+
+        // Apply the changes to a row in memory, for `update` to write
+        methods.push(quote! {
+            pub fn apply(&mut self, patch: #patch_struct) {
+                #(
+                    if let Some(value) = patch.#patch_names {
+                        self.#patch_names = value;
+                    }
+                )*
+            }
+        });
+    }
+
+    // Insert or update on a conflict with the primary key or a unique constraint
+    for (index, target) in conflict_targets(table).iter().enumerate() {
+        // The columns of the target are inserted even when the database would assign them, or
+        // there could never be a conflict
+        let upserted: Vec<(usize, &(&ResolvedColumn, &RepositoryField))> = columns
+            .iter()
+            .enumerate()
+            .filter(|(index, (column, _))| {
+                !column.generated
+                    && (!is_assigned(column) && timestamp(column).is_none()
+                        || target.contains(index))
+            })
+            .collect();
+        if upserted.is_empty() {
+            continue;
+        }
+        let now_upserted: Vec<&String> = now_columns
+            .iter()
+            .zip(&now_names)
+            .filter(|(column, _)| !target.iter().any(|index| columns[*index].0 == **column))
+            .map(|(_, name)| name)
+            .collect();
+
+        let overriding = if upserted.iter().any(|(_, (column, _))| column.identity) {
+            " OVERRIDING SYSTEM VALUE"
+        } else {
+            ""
+        };
+        let conflict: Vec<String> = target
+            .iter()
+            .map(|index| quote_name(&columns[*index].0.name))
+            .collect();
+
+        // Set every other column from the new row, but keep when the row was created. When there
+        // is none, the target is set to itself, so the existing row is still returned
+        let mut assignments: Vec<String> = upserted
+            .iter()
+            .filter(|(index, _)| !target.contains(index))
+            .map(|(_, (column, _))| quote_name(&column.name))
+            .chain(
+                now_columns
+                    .iter()
+                    .zip(&now_names)
+                    .filter(|(column, _)| timestamp(column) == Some(Timestamp::Updated))
+                    .filter(|(_, name)| now_upserted.contains(name))
+                    .map(|(_, name)| name.clone()),
+            )
+            .map(|name| match &version {
+                // Upserting over a row is a change to it like any other
+                Some(version) if *version == name => {
+                    format!("{} = {}.{} + 1", name, from, name)
+                }
+                _ => format!("{} = EXCLUDED.{}", name, name),
+            })
+            .collect();
+        if assignments.is_empty() {
+            assignments.push(format!("{} = EXCLUDED.{}", conflict[0], conflict[0]));
+        }
+
+        let mut values = placeholders(1, upserted.iter().map(|(_, (column, _))| *column));
+        values.extend(now_upserted.iter().map(|_| "now()".to_string()));
+        let upsert_sql = format!(
+            "INSERT INTO {} ({}){} VALUES ({}) ON CONFLICT ({}) DO UPDATE SET {} RETURNING {}",
+            from,
+            upserted
+                .iter()
+                .map(|(_, (column, _))| quote_name(&column.name))
+                .chain(now_upserted.iter().map(|name| name.to_string()))
+                .collect::<Vec<_>>()
+                .join(", "),
+            overriding,
+            values.join(", "),
+            conflict.join(", "),
+            assignments.join(", "),
+            column_list
+        );
+        let upsert_values = upserted.iter().map(|(_, (_, field))| &field.write);
+
+        // The first target is the main one, the others are named after their columns
+        let method = if index == 0 {
+            Ident::new("upsert", Span::call_site())
+        } else {
+            let names: Vec<String> = target
+                .iter()
+                .map(|index| columns[*index].1.name.to_string())
+                .collect();
+            Ident::new(
+                &format!("upsert_by_{}", names.join("_and_")),
+                Span::call_site(),
+            )
+        };
+        methods.push(quote! {
+            pub fn #method(&self, client: &mut impl postgres::GenericClient) -> Result<Self, postgres::Error> {
+                let row = client.query_one(#upsert_sql, &[#(#upsert_values),*])?;
+                Ok(Self::from_row(&row))
+            }
+        });
+    }
+
+    // Write rows in with a binary COPY, including the values of identity and serial columns. A
+    // COPY can't cast the columns passed as another type
+    let copied: Vec<&(&ResolvedColumn, &RepositoryField)> = columns
+        .iter()
+        .filter(|(column, _)| !column.generated)
+        .collect();
+    if copied.iter().any(|(column, _)| is_cast(column)) {
+        debug!(
+            "{} has a column that is passed as another type, skipping copy_in",
+            table.name
+        );
+    } else if !copied.is_empty() {
+        let copied_names = copied
+            .iter()
+            .map(|(column, _)| quote_name(&column.name))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let copy_in_sql = format!(
+            "COPY {} ({}) FROM STDIN (FORMAT binary)",
+            from, copied_names
+        );
+        let copied_columns: Vec<&ResolvedColumn> =
+            copied.iter().map(|(column, _)| *column).collect();
+        let copy_in_types = copy_types(
+            &copied_columns,
+            &format!("SELECT {} FROM {}", copied_names, from),
+        );
+        let params = copied.iter().map(|(_, field)| borrowed(&field.param));
+        methods.push(quote! {
+            pub fn copy_in<'a>(client: &mut impl postgres::GenericClient, rows: impl IntoIterator<Item = &'a Self>) -> Result<u64, postgres::Error> {
+                let types = #copy_in_types;
+                let writer = client.copy_in(#copy_in_sql)?;
+                let mut writer = postgres::binary_copy::BinaryCopyInWriter::new(writer, &types);
+                for row in rows {
+                    writer.write(&[#(#params),*])?;
+                }
+                writer.finish()
+            }
+        });
+    }
+
+    // Insert many rows with as few statements as the parameter limit allows, in one transaction
+    if inserted.is_empty() {
+        methods.push(quote! {
+            pub fn insert_many(client: &mut impl postgres::GenericClient, rows: &[#insert_struct]) -> Result<Vec<Self>, postgres::Error> {
+                rows.iter().map(|row| row.insert(client)).collect()
+            }
+        });
+    } else {
+        let count = Literal::usize_unsuffixed(inserted.len());
+        let insert_prefix = format!("INSERT INTO {} ({}) VALUES ", from, insert_names.join(", "));
+        let insert_suffix = format!(" RETURNING {}", column_list);
+        let row_values = ["({}".to_string()]
+            .into_iter()
+            .chain(now_values.iter().map(|value| format!(", {}", value)))
+            .chain([")".to_string()])
+            .collect::<String>();
+        let params = inserted.iter().map(|(_, field)| &field.param);
+        let casts: Vec<String> = inserted
+            .iter()
+            .map(|(column, _)| write_cast(column))
+            .collect();
+        let placeholder = if casts.iter().all(String::is_empty) {
+            quote! { format!("${}", index * #count + column) }
+        } else {
+            quote! { format!("${}{}", index * #count + column, [#(#casts),*][column - 1]) }
+        };
+
+        // A statement takes at most 65535 parameters, so the rows are inserted in chunks
+        methods.push(quote! {
+            pub fn insert_many(client: &mut impl postgres::GenericClient, rows: &[#insert_struct]) -> Result<Vec<Self>, postgres::Error> {
+                let mut transaction = client.transaction()?;
+                let mut inserted = Vec::with_capacity(rows.len());
+                for chunk in rows.chunks(65535 / #count) {
+                    let mut values = Vec::with_capacity(chunk.len());
+                    let mut params: Vec<Box<dyn postgres::types::ToSql + Sync + '_>> =
+                        Vec::with_capacity(chunk.len() * #count);
+                    for (index, row) in chunk.iter().enumerate() {
+                        let placeholders: Vec<String> = (1..=#count)
+                            .map(|column| #placeholder)
+                            .collect();
+                        values.push(format!(#row_values, placeholders.join(", ")));
+                        #(params.push(Box::new(#params));)*
+                    }
+
+                    let sql = [#insert_prefix, &values.join(", "), #insert_suffix].concat();
+                    let params: Vec<&(dyn postgres::types::ToSql + Sync)> =
+                        params.iter().map(|param| param.as_ref()).collect();
+                    let returned = transaction.query(&sql, &params)?;
+                    inserted.extend(returned.iter().map(Self::from_row));
+                }
+                transaction.commit()?;
+                Ok(inserted)
+            }
+        });
+    }
+
+    if key.is_empty() {
+        code.extend(quote! {
+            impl #struct_name {
+                #(#methods)*
+            }
+        });
+        return Some(code);
+    }
+
+    // The primary key is passed as arguments, borrowing owned types
+    let key_arguments: Vec<TokenStream> = key
+        .iter()
+        .map(|(_, field)| {
+            let name = &field.name;
+            let rust_type = key_type(&field.rust_type);
+            quote! { #name: #rust_type }
+        })
+        .collect();
+    let key_names: Vec<&Ident> = key.iter().map(|(_, field)| &field.name).collect();
+    let key_condition = |start: usize| {
+        key.iter()
+            .zip(placeholders(start, key.iter().map(|(column, _)| *column)))
+            .map(|((column, _), placeholder)| {
+                format!("{} = {}", quote_name(&column.name), placeholder)
+            })
+            .collect::<Vec<_>>()
+            .join(" AND ")
+    };
+
+    // Get a row by its primary key
+    for (suffix, condition) in &variants {
+        let mut get_sql = format!(
+            "SELECT {} FROM {} WHERE {}",
+            column_list,
+            from,
+            key_condition(1)
+        );
+        if let Some(condition) = condition {
+            get_sql.push_str(&format!(" AND {}", condition));
+        }
+        let get = Ident::new(&format!("get{}", suffix), Span::call_site());
+        methods.push(quote! {
+            pub fn #get(client: &mut impl postgres::GenericClient, #(#key_arguments),*) -> Result<Option<Self>, postgres::Error> {
+                let row = client.query_opt(#get_sql, &[#(&#key_names),*])?;
+                Ok(row.as_ref().map(Self::from_row))
+            }
+        });
+    }
+
+    // Update the columns that aren't part of the key or assigned by the database, and touch the
+    // updated timestamp and bump the version
+    let versioned = |column: &ResolvedColumn| version == Some(quote_name(&column.name));
+    let mut updated: Vec<&(&ResolvedColumn, &RepositoryField)> = columns
+        .iter()
+        .filter(|(column, _)| {
+            is_updated(table, column) && timestamp(column).is_none() && !versioned(column)
+        })
+        .collect();
+    let touched: Vec<&String> = now_columns
+        .iter()
+        .zip(&now_names)
+        .filter(|(column, _)| is_updated(table, column))
+        .filter(|(column, _)| timestamp(column) == Some(Timestamp::Updated))
+        .map(|(_, name)| name)
+        .collect();
+
+    // Tables with nothing but timestamps to update still get `update`, which sets them as usual
+    if updated.is_empty() && touched.is_empty() && version.is_none() {
+        updated = columns
+            .iter()
+            .filter(|(column, _)| is_updated(table, column))
+            .collect();
+    }
+    let update_values: Vec<&TokenStream> = updated
+        .iter()
+        .copied()
+        .chain(&key)
+        .map(|(_, field)| &field.write)
+        .collect();
+    let mut assignments: Vec<String> = updated
+        .iter()
+        .zip(placeholders(1, updated.iter().map(|(column, _)| *column)))
+        .map(|((column, _), placeholder)| format!("{} = {}", quote_name(&column.name), placeholder))
+        .chain(touched.iter().map(|name| format!("{} = now()", name)))
+        .collect();
+    let mut condition = key_condition(updated.len() + 1);
+    if let Some(version) = &version {
+        // Only update the row as it was read
+        assignments.push(format!("{} = {} + 1", version, version));
+        condition.push_str(&format!(" AND {} = ${}", version, update_values.len() + 1));
+    }
+    let update_sql = format!(
+        "UPDATE {} SET {} WHERE {} RETURNING {}",
+        from,
+        assignments.join(", "),
+        condition,
+        column_list
+    );
+    if let Some((_, field)) = columns.iter().find(|(column, _)| versioned(column)) {
+        // A row that wasn't updated but exists is stale
+        let exists_sql = format!("SELECT 1 FROM {} WHERE {}", from, key_condition(1));
+        let version_value = &field.write;
+        let key_values = key.iter().map(|(_, field)| &field.write);
+        methods.push(quote! {
+            pub fn update(&self, client: &mut impl postgres::GenericClient) -> Result<Option<Self>, #type_prefix UpdateError> {
+                match client.query_opt(#update_sql, &[#(#update_values,)* #version_value])? {
+                    Some(row) => Ok(Some(Self::from_row(&row))),
+                    None if client.query_opt(#exists_sql, &[#(#key_values),*])?.is_some() => {
+                        Err(#type_prefix UpdateError::StaleRow)
+                    }
+                    None => Ok(None),
+                }
+            }
+        });
+    } else if !assignments.is_empty() {
+        methods.push(quote! {
+            pub fn update(&self, client: &mut impl postgres::GenericClient) -> Result<Option<Self>, postgres::Error> {
+                let row = client.query_opt(#update_sql, &[#(#update_values),*])?;
+                Ok(row.as_ref().map(Self::from_row))
+            }
+        });
+    }
+
+    // Delete a row by its primary key
+    let delete_sql = format!("DELETE FROM {} WHERE {}", from, key_condition(1));
+    methods.push(quote! {
+        pub fn delete(client: &mut impl postgres::GenericClient, #(#key_arguments),*) -> Result<bool, postgres::Error> {
+            let deleted = client.execute(#delete_sql, &[#(&#key_names),*])?;
+            Ok(deleted > 0)
+        }
+    });
+
+    // Soft delete a row by its primary key, unless it already is
+    if let Some(column) = soft_delete {
+        let name = quote_name(&column.name);
+        let soft_delete_sql = format!(
+            "UPDATE {} SET {} = now() WHERE {} AND {} IS NULL",
+            from,
+            name,
+            key_condition(1),
+            name
+        );
+        methods.push(quote! {
+            pub fn soft_delete(client: &mut impl postgres::GenericClient, #(#key_arguments),*) -> Result<bool, postgres::Error> {
+                let deleted = client.execute(#soft_delete_sql, &[#(&#key_names),*])?;
+                Ok(deleted > 0)
+            }
+        });
+    }
+
+    code.extend(quote! {
+        impl #struct_name {
+            #(#methods)*
+        }
+    });
+    Some(code)
+}
+
+/// Get the column of a table that marks its rows soft deleted, if it has one
+///
+/// The column has to be a nullable timestamp or date, which is NULL until the row is deleted.
+pub fn soft_delete_column<'a>(table: &'a ResolvedTable, name: &str) -> Option<&'a ResolvedColumn> {
+    let column = table.columns.iter().find(|column| column.name == name)?;
+    if !column.nullable || !is_timestamp(column) {
+        debug!(
+            "{}.{} isn't a nullable timestamp, so rows aren't soft deleted with it",
+            table.name, column.name
+        );
+        return None;
+    }
+
+    Some(column)
+}
+
+/// Get the column of a table that versions its rows for optimistic locking, if it has one
+///
+/// The column has to be a non-null integer outside the primary key, which the helpers bump.
+pub fn version_column<'a>(
+    table: &'a ResolvedTable,
+    names: &[String],
+) -> Option<&'a ResolvedColumn> {
+    let column = names
+        .iter()
+        .find_map(|name| table.columns.iter().find(|column| &column.name == name))?;
+    let is_integer = ["smallint", "integer", "bigint"].contains(&column.data_type.name.as_str());
+    if column.nullable
+        || !is_integer
+        || column.data_type.dimensions > 0
+        || is_assigned(column)
+        || table.primary_key.contains(&column.name)
+    {
+        debug!(
+            "{} of {} isn't a non-null integer outside the primary key, so it can't be a version",
+            column.name, table.name
+        );
+        return None;
+    }
+
+    Some(column)
+}
+
+/// Whether the `update` helper of a table checks and bumps a version, so it returns an
+/// `UpdateError`
+pub fn is_versioned(table: &ResolvedTable, conventions: &Conventions) -> bool {
+    table.kind == TableKind::Table
+        && !key_indices(table).is_empty()
+        && version_column(table, &conventions.versions).is_some()
+}
+
+/// Generate the error `update` returns for tables with a version column, which is shared by every
+/// table
+pub fn generate_update_error() -> TokenStream {
+    quote! {
+        /// The error of updating a row that has a version
+        #[derive(Debug)]
+        pub enum UpdateError {
+            /// The row was updated since it was read, so its version no longer matches
+            StaleRow,
+            /// The query failed
+            Postgres(postgres::Error),
+        }
+
+        impl std::fmt::Display for UpdateError {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                match self {
+                    UpdateError::StaleRow => write!(f, "The row was updated since it was read"),
+                    UpdateError::Postgres(error) => error.fmt(f),
+                }
+            }
+        }
+
+        impl std::error::Error for UpdateError {
+            fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+                match self {
+                    UpdateError::StaleRow => None,
+                    UpdateError::Postgres(error) => Some(error),
+                }
+            }
+        }
+
+        impl From<postgres::Error> for UpdateError {
+            fn from(error: postgres::Error) -> Self {
+                UpdateError::Postgres(error)
+            }
+        }
+    }
+}
+
+/// Whether the helpers fill in a column themselves or leave it to the database, as the created or
+/// updated timestamp of `conventions`, so the value of the struct isn't written
+pub fn is_filled_in(column: &ResolvedColumn, conventions: &Conventions) -> bool {
+    timestamp_of(column, conventions).is_some()
+}
+
+/// Get which convention timestamp a column is, if the helpers treat it as one
+fn timestamp_of(column: &ResolvedColumn, conventions: &Conventions) -> Option<Timestamp> {
+    if conventions.timestamps == Timestamps::Off || column.generated || !is_timestamp(column) {
+        return None;
+    }
+
+    if column.name == conventions.created_at {
+        Some(Timestamp::Created)
+    } else if column.name == conventions.updated_at {
+        Some(Timestamp::Updated)
+    } else {
+        None
+    }
+}
+
+/// Whether a column is a timestamp or date, which `now()` can be assigned to
+fn is_timestamp(column: &ResolvedColumn) -> bool {
+    let names = [
+        "timestamp with time zone",
+        "timestamp without time zone",
+        "date",
+    ];
+    names.contains(&column.data_type.name.as_str()) && column.data_type.dimensions == 0
+}
+
+/// Get the indices of the primary key columns of a table, in key order
+///
+/// Empty when the table doesn't have one, or when one of its columns was left out of the struct.
+pub fn key_indices(table: &ResolvedTable) -> Vec<usize> {
+    let key: Vec<usize> = table
+        .primary_key
+        .iter()
+        .filter_map(|name| table.columns.iter().position(|column| &column.name == name))
+        .collect();
+
+    if key.len() == table.primary_key.len() {
+        key
+    } else {
+        Vec::new()
+    }
+}
+
+/// Borrow the query parameter of a field, unless it's a reference already
+fn borrowed(param: &TokenStream) -> TokenStream {
+    if param.to_string().starts_with('&') {
+        param.clone()
+    } else {
+        quote! { &#param }
+    }
+}
+
+/// Get the expression of the types of the columns of a binary COPY
+///
+/// Built-in types are known up front. Otherwise, the types are looked up by preparing `select`,
+/// which selects the same columns.
+fn copy_types(columns: &[&ResolvedColumn], select: &str) -> TokenStream {
+    let types: Option<Vec<TokenStream>> = columns
+        .iter()
+        .map(
+            |column| match (wire_type(column), column.data_type.dimensions) {
+                (None, _) => types::postgres_type(&column.data_type),
+                (Some(wire_type), 0) => {
+                    let name = Ident::new(&wire_type.to_uppercase(), Span::call_site());
+                    Some(quote! { postgres::types::Type::#name })
+                }
+                (Some(wire_type), _) => {
+                    let name = Ident::new(
+                        &format!("{}_ARRAY", wire_type.to_uppercase()),
+                        Span::call_site(),
+                    );
+                    Some(quote! { postgres::types::Type::#name })
+                }
+            },
+        )
+        .collect();
+    match types {
+        Some(types) => quote! { [#(#types),*] },
+        None => quote! {
+            client
+                .prepare(#select)?
+                .columns()
+                .iter()
+                .map(|column| column.type_().clone())
+                .collect::<Vec<_>>()
+        },
+    }
+}
+
+/// Get the columns `ON CONFLICT` can target, by their indices: the primary key first, then every
+/// unique constraint that doesn't repeat it
+///
+/// Constraints with a column that was left out of the struct are skipped.
+pub fn conflict_targets(table: &ResolvedTable) -> Vec<Vec<usize>> {
+    let mut targets = Vec::new();
+    let key = key_indices(table);
+    if !key.is_empty() {
+        targets.push(key);
+    }
+
+    for unique_constraint in &table.unique_constraints {
+        let target: Vec<usize> = unique_constraint
+            .columns
+            .iter()
+            .filter_map(|name| table.columns.iter().position(|column| &column.name == name))
+            .collect();
+        if target.len() != unique_constraint.columns.len() {
+            debug!(
+                "{} has unique constraint {} on a column that was left out, skipping its upsert",
+                table.name, unique_constraint.name
+            );
+            continue;
+        }
+
+        let mut sorted = target.clone();
+        sorted.sort_unstable();
+        let is_repeated = targets.iter().any(|existing: &Vec<usize>| {
+            let mut existing = existing.clone();
+            existing.sort_unstable();
+            existing == sorted
+        });
+        if !is_repeated {
+            targets.push(target);
+        }
+    }
+
+    targets
+}
+
+/// Whether `update` sets a column, which it does unless the column is part of the primary key or
+/// assigned by the database
+pub fn is_updated(table: &ResolvedTable, column: &ResolvedColumn) -> bool {
+    !table.primary_key.contains(&column.name) && !column.identity && !column.generated
+}
+
+/// Whether `insert` writes a column, which it does unless the database assigns it or it's a
+/// timestamp the helpers set to `now()` or leave to the database
+pub fn is_inserted(column: &ResolvedColumn, conventions: &Conventions) -> bool {
+    !is_assigned(column) && timestamp_of(column, conventions).is_none()
+}
+
+/// Get the indices of the columns of a table's patch struct, which are the ones `update` sets from
+/// the struct rather than by convention
+///
+/// Empty when `update` isn't generated, for views and tables without a primary key.
+pub fn patched_columns(table: &ResolvedTable, conventions: &Conventions) -> Vec<usize> {
+    if table.kind != TableKind::Table || key_indices(table).is_empty() {
+        return Vec::new();
+    }
+
+    let version = version_column(table, &conventions.versions);
+    table
+        .columns
+        .iter()
+        .enumerate()
+        .filter(|(_, column)| {
+            is_updated(table, column)
+                && timestamp_of(column, conventions).is_none()
+                && version
+                    .map(|version| version.name != column.name)
+                    .unwrap_or(true)
+        })
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Get the name of the struct of the changes to make to a row of a table, like `UsersPatch`
+pub fn patch_struct_name(table: &ResolvedTable) -> Ident {
+    Ident::new(&format!("{}Patch", table.rust_name), Span::call_site())
+}
+
+/// Get the name of the struct of the rows to insert into a table, like `NewUsers`
+pub fn insert_struct_name(table: &ResolvedTable) -> Ident {
+    Ident::new(&format!("New{}", table.rust_name), Span::call_site())
+}
+
+/// Whether the database assigns the value of a column on insert: identity, serial and generated
+/// columns
+pub fn is_assigned(column: &ResolvedColumn) -> bool {
+    column.identity
+        || column.generated
+        || column
+            .default
+            .as_deref()
+            .is_some_and(|default| default.starts_with("nextval("))
+}
+
+/// Get the type a primary key column is passed as, borrowing owned types
+pub fn key_type(rust_type: &TokenStream) -> TokenStream {
+    match rust_type.to_string().as_str() {
+        "String" => quote! { &str },
+        "Vec < u8 >" => quote! { &[u8] },
+        _ => rust_type.clone(),
+    }
+}
+
+/// Quote the name of a table or column for SQL
+pub fn quote_name(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Get the query placeholders of columns from `$start`
+fn placeholders<'a>(
+    start: usize,
+    columns: impl IntoIterator<Item = &'a ResolvedColumn>,
+) -> Vec<String> {
+    columns
+        .into_iter()
+        .enumerate()
+        .map(|(index, column)| format!("${}{}", start + index, write_cast(column)))
+        .collect()
+}
+
+/// Whether a column is passed as text, because its field is a `String`, or a newtype of one, but
+/// the postgres crate only reads and writes `String`s as the text types, not as types like
+/// `inet`, `interval`, `tsvector` or composite types. The database normalizes the text, so it
+/// may not read back as it was written
+pub fn is_text(column: &ResolvedColumn) -> bool {
+    let element = element_type(&column.rust_type);
+    let element = element.rsplit("::").next().unwrap_or(&element);
+    let is_string = element == "String"
+        || newtypes::find_rust_name(element).is_some_and(|newtype| !newtype.is_number());
+    let data_type = &column.data_type;
+    let is_read_as_string = data_type.schema == "pg_catalog"
+        && [
+            "text",
+            "character varying",
+            "character",
+            "name",
+            "citext",
+            "ltree",
+            "lquery",
+            "ltxtquery",
+        ]
+        .contains(&data_type.name.as_str());
+    is_string && !is_read_as_string
+}
+
+/// Get the type of the elements of a field, without its `Option`s and `Vec`s
+fn element_type(rust_type: &str) -> String {
+    let mut element = rust_type.replace(' ', "");
+    while let Some(inner) = element
+        .strip_prefix("Option<")
+        .or_else(|| element.strip_prefix("Vec<"))
+        .and_then(|inner| inner.strip_suffix('>'))
+    {
+        element = inner.to_string();
+    }
+    element
+}
+
+/// Get the list of expressions that select every column of a table, in the order `from_row` reads
+/// them
+pub fn select_list(columns: &[ResolvedColumn]) -> String {
+    columns
+        .iter()
+        .map(select_expression)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Get the type a column is passed to and from postgres as, when the postgres crate can't convert
+/// its own type into its field: text for the `String`s of [`is_text`], and `numeric` as the integer or
+/// float type of its field
+fn wire_type(column: &ResolvedColumn) -> Option<&'static str> {
+    if is_text(column) {
+        return Some("text");
+    }
+    let data_type = &column.data_type;
+    if data_type.kind != TypeKind::Base
+        || data_type.schema != "pg_catalog"
+        || data_type.name != "numeric"
+    {
+        return None;
+    }
+    match element_type(&column.rust_type).as_str() {
+        "i16" => Some("int2"),
+        "i32" => Some("int4"),
+        "i64" => Some("int8"),
+        "f32" => Some("float4"),
+        "f64" => Some("float8"),
+        _ => None,
+    }
+}
+
+/// Whether a column is passed as another type than its own, see [`wire_type`]
+pub fn is_cast(column: &ResolvedColumn) -> bool {
+    wire_type(column).is_some()
+}
+
+/// Get the expression that selects a column, cast to the type it's passed as
+fn select_expression(column: &ResolvedColumn) -> String {
+    let name = quote_name(&column.name);
+    match wire_type(column) {
+        Some(wire_type) => {
+            let dimensions = "[]".repeat(column.data_type.dimensions.max(0) as usize);
+            format!("{}::{}{} AS {}", name, wire_type, dimensions, name)
+        }
+        None => name,
+    }
+}
+
+/// Get the cast of the placeholder of a column, from the type it's passed as
+fn write_cast(column: &ResolvedColumn) -> String {
+    match wire_type(column) {
+        Some(wire_type) => {
+            let data_type = &column.data_type;
+            let dimensions = "[]".repeat(data_type.dimensions.max(0) as usize);
+            // Types of other schemas, like composite types, are qualified with theirs
+            let name = match data_type.schema.as_str() {
+                "pg_catalog" => data_type.name.clone(),
+                schema => format!("{}.{}", quote_name(schema), quote_name(&data_type.name)),
+            };
+            format!("::{}{}::{}{}", wire_type, dimensions, name, dimensions)
+        }
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ArrayConfig;
+    use crate::model::DataType;
+
+    fn numeric(name: &str, precision: Option<i32>, rust_type: &str) -> ResolvedColumn {
+        ResolvedColumn {
+            name: name.to_string(),
+            rust_name: name.to_string(),
+            rust_type: rust_type.to_string(),
+            data_type: DataType {
+                name: "numeric".to_string(),
+                schema: "pg_catalog".to_string(),
+                kind: TypeKind::Base,
+                dimensions: 0,
+                element_not_null: false,
+                length: None,
+                precision,
+                scale: precision.map(|_| 0),
+            },
+            nullable: rust_type.starts_with("Option"),
+            default: None,
+            identity: false,
+            generated: false,
+            comment: None,
+            deprecated: None,
+            redacted: false,
+            masked: false,
+        }
+    }
+
+    #[test]
+    fn numeric_is_cast_to_the_type_of_its_field() {
+        let small = numeric("small", Some(3), "Option<i16>");
+        assert_eq!(select_expression(&small), "\"small\"::int2 AS \"small\"");
+        assert_eq!(write_cast(&small), "::int2::numeric");
+
+        let mut big = numeric("big", Some(18), "Vec<i64>");
+        big.data_type.dimensions = 1;
+        assert_eq!(select_expression(&big), "\"big\"::int8[] AS \"big\"");
+        assert_eq!(write_cast(&big), "::int8[]::numeric[]");
+
+        let decimal = numeric("decimal", Some(10), "rust_decimal::Decimal");
+        assert!(!is_cast(&decimal));
+        assert_eq!(select_expression(&decimal), "\"decimal\"");
+    }
+
+    #[test]
+    fn numeric_is_passed_as_the_type_its_precision_maps_to() {
+        for (precision, scale, passed_as) in [
+            (Some(4), Some(0), Some("int2")),
+            (Some(5), Some(0), Some("int4")),
+            (Some(9), Some(0), Some("int4")),
+            (Some(10), Some(0), Some("int8")),
+            (Some(18), Some(0), Some("int8")),
+            (Some(19), Some(0), None),
+            (Some(28), Some(2), None),
+            (Some(29), Some(2), Some("float8")),
+            (None, None, Some("float8")),
+        ] {
+            let mut column = numeric("amount", precision, "");
+            column.data_type.scale = scale;
+            column.rust_type = types::map_column_type(
+                &column.data_type,
+                "public",
+                false,
+                true,
+                false,
+                types::BytesType::default(),
+                &ArrayConfig::default(),
+            )
+            .to_string()
+            .replace(' ', "");
+            assert_eq!(
+                wire_type(&column),
+                passed_as,
+                "numeric({:?},{:?}) as {}",
+                precision,
+                scale,
+                column.rust_type
+            );
+        }
+
+        // Columns mapped to f32 in the config are passed as float4
+        let single = numeric("single", Some(6), "Option<f32>");
+        assert_eq!(
+            select_expression(&single),
+            "\"single\"::float4 AS \"single\""
+        );
+        assert_eq!(write_cast(&single), "::float4::numeric");
+    }
+
+    #[test]
+    fn strings_of_types_postgres_doesnt_read_as_strings_are_cast_to_text() {
+        let string = |name: &str, schema: &str, kind, rust_type: &str| {
+            let mut column = numeric(name, None, rust_type);
+            column.data_type.name = name.to_string();
+            column.data_type.schema = schema.to_string();
+            column.data_type.kind = kind;
+            column
+        };
+
+        let inet = string("inet", "pg_catalog", TypeKind::Base, "String");
+        assert_eq!(select_expression(&inet), "\"inet\"::text AS \"inet\"");
+        assert_eq!(write_cast(&inet), "::text::inet");
+
+        let mut uuids = string("uuid", "pg_catalog", TypeKind::Base, "Option<Vec<String>>");
+        uuids.data_type.dimensions = 1;
+        assert_eq!(write_cast(&uuids), "::text[]::uuid[]");
+
+        let address = string("address", "public", TypeKind::Composite, "Option<String>");
+        assert_eq!(write_cast(&address), "::text::\"public\".\"address\"");
+
+        let xml = string("xml", "pg_catalog", TypeKind::Base, "Option<super::Xml>");
+        assert!(is_text(&xml));
+
+        for (name, rust_type) in [
+            ("text", "String"),
+            ("character varying", "Option<String>"),
+            ("citext", "CiString"),
+            ("inet", "std::net::IpAddr"),
+        ] {
+            let column = string(name, "pg_catalog", TypeKind::Base, rust_type);
+            assert!(!is_cast(&column), "{}", name);
+        }
+    }
+
+    #[test]
+    fn only_the_columns_the_database_doesnt_fill_in_are_inserted() {
+        let mut conventions = Conventions {
+            soft_delete: "deleted_at".to_string(),
+            created_at: "created_at".to_string(),
+            updated_at: "updated_at".to_string(),
+            timestamps: Timestamps::Now,
+            versions: Vec::new(),
+        };
+        let mut identity = numeric("id", None, "i64");
+        identity.identity = true;
+        let mut serial = numeric("number", None, "i32");
+        serial.default = Some("nextval('number_seq'::regclass)".to_string());
+        let mut generated = numeric("total", None, "f64");
+        generated.generated = true;
+        let mut created_at = numeric("created_at", None, "chrono::DateTime<chrono::Utc>");
+        created_at.data_type.name = "timestamp with time zone".to_string();
+        let mut defaulted = numeric("amount", None, "f64");
+        defaulted.default = Some("0".to_string());
+
+        for column in [&identity, &serial, &generated, &created_at] {
+            assert!(!is_inserted(column, &conventions), "{}", column.name);
+        }
+        assert!(is_inserted(&defaulted, &conventions));
+
+        conventions.timestamps = Timestamps::Off;
+        assert!(is_inserted(&created_at, &conventions));
+    }
+
+    #[test]
+    fn patches_have_the_columns_update_sets_from_the_struct() {
+        let conventions = Conventions {
+            soft_delete: "deleted_at".to_string(),
+            created_at: "created_at".to_string(),
+            updated_at: "updated_at".to_string(),
+            timestamps: Timestamps::Now,
+            versions: vec!["version".to_string()],
+        };
+        let mut id = numeric("id", None, "i64");
+        id.data_type.name = "bigint".to_string();
+        let mut version = numeric("version", None, "i32");
+        version.data_type.name = "integer".to_string();
+        let mut updated_at = numeric("updated_at", None, "chrono::DateTime<chrono::Utc>");
+        updated_at.data_type.name = "timestamp with time zone".to_string();
+        let mut total = numeric("total", None, "f64");
+        total.generated = true;
+        let mut table = ResolvedTable {
+            name: "orders".to_string(),
+            kind: TableKind::Table,
+            rust_name: "Orders".to_string(),
+            columns: vec![
+                id,
+                numeric("amount", None, "f64"),
+                version,
+                updated_at,
+                total,
+                numeric("note", None, "Option<f64>"),
+            ],
+            primary_key: vec!["id".to_string()],
+            foreign_keys: Vec::new(),
+            unique_constraints: Vec::new(),
+            indexes: Vec::new(),
+            derives: Vec::new(),
+            no_auto_derive: false,
+            deprecated: None,
+            comment: None,
+            definition: None,
+        };
+        assert_eq!(patched_columns(&table, &conventions), [1, 5]);
+        assert_eq!(patch_struct_name(&table), "OrdersPatch");
+        assert_eq!(insert_struct_name(&table), "NewOrders");
+
+        // Rows without a primary key can't be updated
+        table.primary_key.clear();
+        assert!(patched_columns(&table, &conventions).is_empty());
+    }
+
+    #[test]
+    #[ignore = "needs a database in DATABASE_URL"]
+    fn numeric_reads_and_writes_as_the_type_of_its_field() {
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL is set");
+        let mut client = postgres::Client::connect(&url, postgres::NoTls).unwrap();
+        let columns = [
+            numeric("small", Some(3), "Option<i16>"),
+            numeric("medium", Some(9), "i32"),
+            numeric("big", Some(18), "i64"),
+            numeric("float", None, "f64"),
+        ];
+
+        let row = client
+            .query_one(
+                &format!(
+                    "SELECT {} FROM (VALUES (12::numeric(3, 0), 123456789::numeric(9, 0), \
+                     123456789012345678::numeric(18, 0), 1.5::numeric)) AS t ({})",
+                    select_list(&columns),
+                    "small, medium, big, float"
+                ),
+                &[],
+            )
+            .unwrap();
+        assert_eq!(row.get::<_, Option<i16>>("small"), Some(12));
+        assert_eq!(row.get::<_, i32>("medium"), 123456789);
+        assert_eq!(row.get::<_, i64>("big"), 123456789012345678);
+        assert_eq!(row.get::<_, f64>("float"), 1.5);
+
+        let row = client
+            .query_one(
+                &format!(
+                    "SELECT {}::text",
+                    placeholders(1, &columns).join("::text, ")
+                ),
+                &[&Some(12i16), &123456789i32, &123456789012345678i64, &1.5f64],
+            )
+            .unwrap();
+        let written: Vec<String> = (0..4).map(|index| row.get(index)).collect();
+        assert_eq!(written, ["12", "123456789", "123456789012345678", "1.5"]);
+    }
+}
@@ -0,0 +1,324 @@
+use std::str::FromStr;
+
+use convert_case::{Case, Casing};
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::Ident;
+
+use crate::composites::CompositeStruct;
+use crate::ir::ResolvedSchema;
+use crate::repository::quote_name;
+
+/// A table to generate a round trip test for
+#[derive(Clone)]
+pub struct RoundTripTable {
+    /// The path to the struct of the table, from the module of the tests
+    pub path: TokenStream,
+    /// The snake case name the test is named after
+    pub test_name: String,
+    /// The name and sample value of every field, and whether the database assigns or normalizes it
+    pub fields: Vec<(Ident, TokenStream, bool)>,
+    /// Whether the struct or any of its fields is deprecated
+    pub deprecated: bool,
+    /// The cargo feature the struct is gated behind, which its tests are too
+    pub feature: Option<String>,
+}
+
+impl RoundTripTable {
+    /// Get the attribute that gates the tests of the table behind its feature, if it has one
+    fn cfg(&self) -> TokenStream {
+        match &self.feature {
+            Some(feature) => quote! { #[cfg(feature = #feature)] },
+            None => quote! {},
+        }
+    }
+}
+
+/// Generate a test per table that inserts a row of sample values into a fresh database and
+/// selects it back, for `--with-tests`
+///
+/// The database is a Postgres container started with testcontainers, which `schema_sql` is
+/// applied to first.
+pub fn generate_tests(tables: &[RoundTripTable], schema_sql: &str) -> TokenStream {
+    let tests = tables.iter().map(|table| {
+        let path = &table.path;
+        let test = Ident::new(
+            &format!("{}_round_trip", table.test_name),
+            Span::call_site(),
+        );
+        let names = table.fields.iter().map(|(name, _, _)| name);
+        let values = table.fields.iter().map(|(_, value, _)| value);
+
+        // The values the database assigns or normalizes itself are ignored on insert
+        let kept = table
+            .fields
+            .iter()
+            .filter(|(_, _, assigned)| !assigned)
+            .map(|(name, _, _)| name);
+
+        let cfg = table.cfg();
+        quote! {
+            #cfg
+            #[test]
+            fn #test() {
+                let (_container, mut client) = database();
+                let row = #path {
+                    #(#names: #values,)*
+                };
+
+                let inserted = row.insert(&mut client).expect("Inserting the row");
+                #(assert_eq!(inserted.#kept, row.#kept);)*
+
+                let selected = #path::list(&mut client).expect("Selecting the row");
+                assert_eq!(selected, vec![inserted]);
+            }
+        }
+    });
+
+    // The tests use deprecated structs on purpose
+    let allow_deprecated = if tables.iter().any(|table| table.deprecated) {
+        quote! { #![allow(deprecated)] }
+    } else {
+        quote! {}
+    };
+
+    // Keep the SQL readable as a raw string, with enough hashes that it can't end it early
+    let mut hashes = String::from("#");
+    while schema_sql.contains(&format!("\"{}", hashes)) {
+        hashes.push('#');
+    }
+    let schema_sql = TokenStream::from_str(&format!("r{0}\"\n{1}\"{0}", hashes, schema_sql))
+        .expect("A raw string is always a valid token");
+
+    quote! {
+        #allow_deprecated
+
+        use testcontainers_modules::postgres::Postgres;
+        use testcontainers_modules::testcontainers::runners::SyncRunner;
+        use testcontainers_modules::testcontainers::Container;
+
+        /// The schema the code was generated from, without its foreign keys
+        const SCHEMA: &str = #schema_sql;
+
+        /// Start a Postgres container with the schema, which needs Docker
+        fn database() -> (Container<Postgres>, postgres::Client) {
+            let container = Postgres::default().start().expect("Starting Postgres");
+            let host = container.get_host().expect("Getting the host of Postgres");
+            let port = container
+                .get_host_port_ipv4(5432)
+                .expect("Getting the port of Postgres");
+            let config = format!(
+                "host={} port={} user=postgres password=postgres dbname=postgres",
+                host, port
+            );
+            let mut client =
+                postgres::Client::connect(&config, postgres::NoTls).expect("Connecting to Postgres");
+            client.batch_execute(SCHEMA).expect("Creating the schema");
+
+            (container, client)
+        }
+
+        #(#tests)*
+    }
+}
+
+/// Generate a test per table that serializes a row of sample values to JSON and deserializes it
+/// back, for `--serde-tests`
+pub fn generate_serde_tests(tables: &[RoundTripTable]) -> TokenStream {
+    let tests = tables.iter().map(|table| {
+        let path = &table.path;
+        let test = Ident::new(
+            &format!("{}_serde_round_trip", table.test_name),
+            Span::call_site(),
+        );
+        let names = table.fields.iter().map(|(name, _, _)| name);
+        let values = table.fields.iter().map(|(_, value, _)| value);
+
+        let cfg = table.cfg();
+        quote! {
+            #cfg
+            #[test]
+            fn #test() {
+                let row = #path {
+                    #(#names: #values,)*
+                };
+
+                let json = serde_json::to_string(&row).expect("Serializing the row");
+                let deserialized: #path = serde_json::from_str(&json).expect("Deserializing the row");
+                assert_eq!(deserialized, row);
+            }
+        }
+    });
+
+    // The tests use deprecated structs on purpose
+    let allow_deprecated = if tables.iter().any(|table| table.deprecated) {
+        quote! { #![allow(deprecated)] }
+    } else {
+        quote! {}
+    };
+
+    quote! {
+        #allow_deprecated
+
+        #(#tests)*
+    }
+}
+
+/// Generate a test per enum and composite type that sends every value to the database and reads
+/// it back, for `--live-tests`
+///
+/// The values go through the `ToSql` and `FromSql` derives, so the tests check their
+/// `#[postgres(name)]` attributes against the catalog of the database in `DATABASE_URL`. Every
+/// field of a composite type is `None`, which still checks their names.
+pub fn generate_live_tests(
+    schema: &ResolvedSchema,
+    composite_structs: &[CompositeStruct],
+) -> TokenStream {
+    let qualified = |name: &str| format!("{}.{}", quote_name(&schema.name), quote_name(name));
+
+    let enum_tests = schema.enums.iter().map(|enum_type| {
+        let enum_name = Ident::new(&enum_type.rust_name, Span::call_site());
+        let test = Ident::new(
+            &format!("{}_live_round_trip", enum_type.name.to_case(Case::Snake)),
+            Span::call_site(),
+        );
+        let variants = enum_type
+            .variants
+            .iter()
+            .map(|variant| Ident::new(&variant.rust_name, Span::call_site()));
+        let labels = enum_type.variants.iter().map(|variant| &variant.label);
+        let sql = format!(
+            "SELECT $1::{0}::text, $2::text::{0}",
+            qualified(&enum_type.name)
+        );
+
+        quote! {
+            #[test]
+            fn #test() {
+                let mut client = database();
+                for (value, label) in [#((super::#enum_name::#variants, #labels)),*] {
+                    let row = client
+                        .query_one(#sql, &[&value, &label])
+                        .expect("Sending the value");
+                    let text: String = row.get(0);
+                    let decoded: super::#enum_name = row.get(1);
+                    assert_eq!(text, label);
+                    assert_eq!(decoded, value);
+                }
+            }
+        }
+    });
+
+    let composite_tests = composite_structs.iter().map(|composite| {
+        let type_name = &composite.type_name;
+        let path = &composite.row_struct.path;
+        let test = Ident::new(
+            &format!("{}_live_round_trip", type_name.to_case(Case::Snake)),
+            Span::call_site(),
+        );
+        let fields = composite.row_struct.fields.iter().map(|(field, _)| field);
+        let sql = format!("SELECT $1::{}", qualified(type_name));
+
+        quote! {
+            #[test]
+            fn #test() {
+                let mut client = database();
+                let value = super::#path {
+                    #(#fields: None,)*
+                };
+                let row = client.query_one(#sql, &[&value]).expect("Sending the value");
+                let decoded: super::#path = row.get(0);
+                assert_eq!(decoded, value);
+            }
+        }
+    });
+
+    quote! {
+        /// Connect to the database the types were generated from
+        fn database() -> postgres::Client {
+            let url = std::env::var("DATABASE_URL")
+                .expect("DATABASE_URL must be set for the live tests");
+            postgres::Client::connect(&url, postgres::NoTls).expect("Connecting to DATABASE_URL")
+        }
+
+        #(#enum_tests)*
+        #(#composite_tests)*
+    }
+}
+
+/// Get a sample value of a Rust type, for a field of a round trip test
+///
+/// Enums and composite types of the schema are referred to through `prefix`, and composite
+/// types have every field `None`. `None` when there is no obvious value.
+pub fn sample_value(
+    rust_type: &str,
+    schema: &ResolvedSchema,
+    prefix: &TokenStream,
+) -> Option<TokenStream> {
+    let rust_type: String = rust_type.chars().filter(|c| !c.is_whitespace()).collect();
+    if let Some(inner) = generic_argument(&rust_type, "Option") {
+        let value = sample_value(inner, schema, prefix)?;
+        return Some(quote! { Some(#value) });
+    }
+    if rust_type == "Vec<u8>" {
+        return Some(quote! { vec![1, 2, 3] });
+    }
+    if rust_type == "bytes::Bytes" {
+        return Some(quote! { bytes::Bytes::from_static(&[1, 2, 3]) });
+    }
+    if let Some(inner) = generic_argument(&rust_type, "Vec") {
+        let value = sample_value(inner, schema, prefix)?;
+        return Some(quote! { vec![#value] });
+    }
+
+    let date = quote! { chrono::NaiveDate::from_ymd_opt(2024, 1, 2).unwrap() };
+    let value = match rust_type.as_str() {
+        "i8" | "i16" | "i32" | "i64" | "u32" => quote! { 1 },
+        "f32" | "f64" => quote! { 1.5 },
+        "bool" => quote! { true },
+        "char" => quote! { 'a' },
+        "String" => quote! { "a".to_string() },
+        "rust_decimal::Decimal" => quote! { rust_decimal::Decimal::new(150, 2) },
+        "chrono::NaiveDate" => date,
+        "chrono::NaiveTime" => quote! { chrono::NaiveTime::from_hms_opt(3, 4, 5).unwrap() },
+        "chrono::NaiveDateTime" => quote! { #date.and_hms_opt(3, 4, 5).unwrap() },
+        "chrono::DateTime<chrono::Utc>" => quote! { #date.and_hms_opt(3, 4, 5).unwrap().and_utc() },
+        "uuid::Uuid" => quote! { uuid::Uuid::from_u128(1) },
+        "serde_json::Value" => quote! { serde_json::json!({ "key": "value" }) },
+        _ => {
+            // Composite types of the schema have every field NULL
+            if let Some(composite) = schema
+                .composites
+                .iter()
+                .find(|composite| composite.rust_name == rust_type)
+            {
+                let struct_name = Ident::new(&composite.rust_name, Span::call_site());
+                let fields = composite
+                    .fields
+                    .iter()
+                    .map(|field| Ident::new(&field.rust_name, Span::call_site()));
+                return Some(quote! { #prefix #struct_name { #(#fields: None),* } });
+            }
+
+            // Enums of the schema start at their first variant
+            let enum_type = schema
+                .enums
+                .iter()
+                .find(|enum_type| enum_type.rust_name == rust_type)?;
+            let variant = enum_type.variants.first()?;
+            let enum_name = Ident::new(&enum_type.rust_name, Span::call_site());
+            let variant = Ident::new(&variant.rust_name, Span::call_site());
+            quote! { #prefix #enum_name::#variant }
+        }
+    };
+
+    Some(value)
+}
+
+/// Get the type argument of a generic type like `Option<T>`
+fn generic_argument<'a>(rust_type: &'a str, generic: &str) -> Option<&'a str> {
+    rust_type
+        .strip_prefix(generic)?
+        .strip_prefix('<')?
+        .strip_suffix('>')
+}
@@ -0,0 +1,396 @@
+use std::collections::HashMap;
+
+use convert_case::{Case, Casing};
+use postgres::types::Type;
+use postgres::GenericClient;
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::Ident;
+use tracing::{debug, info, warn};
+
+use crate::connection::query;
+use crate::error::{Error, Result};
+use crate::types::map_data_type;
+
+/// A struct that rows can be mapped into, either generated from a table or from a composite type
+#[derive(Clone)]
+pub struct RowStruct {
+    /// The path to the struct, relative to the output file
+    pub path: TokenStream,
+    /// The Rust field name of each field and the expression that reads it from `row`
+    pub fields: Vec<(Ident, TokenStream)>,
+    /// Whether the struct or any of its fields is deprecated, so mapping rows into it has to
+    /// allow that
+    pub deprecated: bool,
+    /// The cargo feature the struct is gated behind, which the wrappers returning it are too
+    pub feature: Option<String>,
+}
+
+/// A parameter of a stored function
+struct Parameter {
+    name: Option<String>,
+    data_type: String,
+    mode: String,
+}
+
+/// Generate typed wrapper functions for every stored function in the schema
+///
+/// Functions that return a table or composite type map their rows into its struct in
+/// `row_structs`, keyed by type. Structs for the OUT parameters of functions that return records
+/// are generated as well, and their names are added to `struct_names` so they can be re-exported.
+pub fn generate_functions(
+    client: &mut impl GenericClient,
+    simple_query: bool,
+    schema: &str,
+    use_uuid: bool,
+    row_structs: &HashMap<String, RowStruct>,
+    struct_names: &mut Vec<String>,
+) -> Result<Vec<TokenStream>> {
+    // Get the functions from the database, skipping the ones installed by extensions
+    let routines = query(
+        client,
+        simple_query,
+        "SELECT r.specific_name, r.routine_name, r.data_type, r.type_udt_name, p.proretset
+        FROM information_schema.routines r
+        JOIN pg_catalog.pg_proc p ON r.specific_name = p.proname || '_' || p.oid
+        WHERE r.specific_schema = $1 AND r.routine_type = 'FUNCTION'
+        AND NOT EXISTS (SELECT 1 FROM pg_catalog.pg_depend d WHERE d.objid = p.oid AND d.deptype = 'e')
+        ORDER BY r.routine_name, r.specific_name",
+        &[(&schema, Type::TEXT)],
+    )
+    .map_err(Error::query("functions"))?;
+
+    let mut definitions = Vec::new();
+    let mut used_names: Vec<String> = Vec::new();
+
+    for routine in routines {
+        let specific_name: String = routine.get(0);
+        let routine_name: String = routine.get(1);
+        let data_type: String = routine.get(2);
+        let type_udt_name: String = routine.get(3);
+        let returns_set: bool = routine.get(4);
+
+        // Trigger functions can't be called directly
+        if data_type == "trigger" || data_type == "event_trigger" {
+            debug!("Skipping trigger function {}", routine_name);
+            continue;
+        }
+
+        info!("Generating wrapper for function {}", routine_name);
+        let parameters = query_parameters(client, simple_query, schema, &specific_name)?;
+
+        let function_ident = unique_ident(&mut used_names, &routine_name);
+
+        // The input parameters become the arguments of the wrapper
+        let input_parameters: Vec<&Parameter> = parameters
+            .iter()
+            .filter(|parameter| parameter.mode != "OUT")
+            .collect();
+        let (arguments, argument_names) = build_arguments(&input_parameters, use_uuid);
+        let placeholders: Vec<String> = (1..=input_parameters.len())
+            .map(|index| format!("${}", index))
+            .collect();
+
+        let call = format!(
+            "\"{}\".\"{}\"({})",
+            schema,
+            routine_name,
+            placeholders.join(", ")
+        );
+
+        // Work out which struct, if any, the rows map into
+        let out_parameters: Vec<&Parameter> = parameters
+            .iter()
+            .filter(|parameter| parameter.mode == "OUT" || parameter.mode == "INOUT")
+            .collect();
+        let row_struct = if data_type == "USER-DEFINED" {
+            row_structs.get(&type_udt_name).map(|row_struct| {
+                (
+                    row_struct.path.clone(),
+                    row_struct.fields.clone(),
+                    row_struct.deprecated,
+                    row_struct.feature.clone(),
+                )
+            })
+        } else if data_type == "record" {
+            if out_parameters.is_empty() {
+                warn!(
+                    "Skipping function {} as it returns an anonymous record",
+                    routine_name
+                );
+                continue;
+            }
+
+            // Generate a struct for the OUT parameters
+            let struct_name = format!("{}Row", routine_name.to_case(Case::Pascal));
+            let struct_ident = Ident::new(&struct_name, Span::call_site());
+            let mut fields = Vec::new();
+            let mut row_fields = Vec::new();
+            for parameter in &out_parameters {
+                let name = parameter.name.clone().unwrap_or_default();
+                let field = Ident::new(&name.to_case(Case::Snake), Span::call_site());
+                let rust_type = map_data_type(&parameter.data_type, use_uuid);
+                fields.push(quote! { pub #field: Option<#rust_type>, });
+                row_fields.push((field, quote! { row.get(#name) }));
+            }
+            definitions.push(quote! {
+                #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+                pub struct #struct_ident {
+                    #(#fields)*
+                }
+            });
+            struct_names.push(struct_name);
+            Some((quote!(#struct_ident), row_fields, false, None))
+        } else {
+            None
+        };
+
+        // Generate the wrapper itself
+        let definition = match (row_struct, returns_set) {
+            (Some((path, fields, deprecated, feature)), returns_set) => {
+                let sql = format!("SELECT * FROM {}", call);
+                let field_mappings = fields.iter().map(|(field, read)| {
+                    quote! { #field: #read, }
+                });
+                let mapping = quote! { #path { #(#field_mappings)* } };
+                let allow_deprecated = if deprecated {
+                    quote! { #[allow(deprecated)] }
+                } else {
+                    quote! {}
+                };
+                let cfg = match feature {
+                    Some(feature) => quote! { #[cfg(feature = #feature)] },
+                    None => quote! {},
+                };
+
+                if returns_set {
+                    quote! {
+                        #cfg
+                        #allow_deprecated
+                        pub fn #function_ident(client: &mut postgres::Client, #(#arguments),*) -> Result<Vec<#path>, postgres::Error> {
+                            let rows = client.query(#sql, &[#(&#argument_names),*])?;
+                            Ok(rows.iter().map(|row| #mapping).collect())
+                        }
+                    }
+                } else {
+                    quote! {
+                        #cfg
+                        #allow_deprecated
+                        pub fn #function_ident(client: &mut postgres::Client, #(#arguments),*) -> Result<#path, postgres::Error> {
+                            let row = client.query_one(#sql, &[#(&#argument_names),*])?;
+                            Ok(#mapping)
+                        }
+                    }
+                }
+            }
+            (None, _) if data_type == "void" => {
+                let sql = format!("SELECT {}", call);
+                quote! {
+                    pub fn #function_ident(client: &mut postgres::Client, #(#arguments),*) -> Result<(), postgres::Error> {
+                        client.execute(#sql, &[#(&#argument_names),*])?;
+                        Ok(())
+                    }
+                }
+            }
+            (None, true) => {
+                let sql = format!("SELECT * FROM {}", call);
+                let rust_type = map_data_type(&data_type, use_uuid);
+                quote! {
+                    pub fn #function_ident(client: &mut postgres::Client, #(#arguments),*) -> Result<Vec<Option<#rust_type>>, postgres::Error> {
+                        let rows = client.query(#sql, &[#(&#argument_names),*])?;
+                        Ok(rows.iter().map(|row| row.get(0)).collect())
+                    }
+                }
+            }
+            (None, false) => {
+                let sql = format!("SELECT {}", call);
+                let rust_type = map_data_type(&data_type, use_uuid);
+                quote! {
+                    pub fn #function_ident(client: &mut postgres::Client, #(#arguments),*) -> Result<Option<#rust_type>, postgres::Error> {
+                        let row = client.query_one(#sql, &[#(&#argument_names),*])?;
+                        Ok(row.get(0))
+                    }
+                }
+            }
+        };
+
+        definitions.push(definition);
+    }
+
+    Ok(definitions)
+}
+
+/// Generate `CALL` helpers for every stored procedure in the schema
+///
+/// Procedures with OUT or INOUT parameters get a struct holding the values they return, and the
+/// struct names are added to `struct_names` so they can be re-exported.
+pub fn generate_procedures(
+    client: &mut impl GenericClient,
+    simple_query: bool,
+    schema: &str,
+    use_uuid: bool,
+    struct_names: &mut Vec<String>,
+) -> Result<Vec<TokenStream>> {
+    // Get the procedures from the database, skipping the ones installed by extensions
+    let routines = query(
+        client,
+        simple_query,
+        "SELECT r.specific_name, r.routine_name
+        FROM information_schema.routines r
+        JOIN pg_catalog.pg_proc p ON r.specific_name = p.proname || '_' || p.oid
+        WHERE r.specific_schema = $1 AND r.routine_type = 'PROCEDURE'
+        AND NOT EXISTS (SELECT 1 FROM pg_catalog.pg_depend d WHERE d.objid = p.oid AND d.deptype = 'e')
+        ORDER BY r.routine_name, r.specific_name",
+        &[(&schema, Type::TEXT)],
+    )
+    .map_err(Error::query("procedures"))?;
+
+    let mut definitions = Vec::new();
+    let mut used_names: Vec<String> = Vec::new();
+
+    for routine in routines {
+        let specific_name: String = routine.get(0);
+        let routine_name: String = routine.get(1);
+
+        info!("Generating CALL helper for procedure {}", routine_name);
+        let parameters = query_parameters(client, simple_query, schema, &specific_name)?;
+        let procedure_ident = unique_ident(&mut used_names, &routine_name);
+
+        // IN and INOUT parameters are passed by the caller, OUT parameters must be given as NULL
+        let input_parameters: Vec<&Parameter> = parameters
+            .iter()
+            .filter(|parameter| parameter.mode != "OUT")
+            .collect();
+        let (arguments, argument_names) = build_arguments(&input_parameters, use_uuid);
+        let mut placeholders = Vec::new();
+        let mut index = 0;
+        for parameter in &parameters {
+            if parameter.mode == "OUT" {
+                placeholders.push("NULL".to_string());
+            } else {
+                index += 1;
+                placeholders.push(format!("${}", index));
+            }
+        }
+        let sql = format!(
+            "CALL \"{}\".\"{}\"({})",
+            schema,
+            routine_name,
+            placeholders.join(", ")
+        );
+
+        // Without OUT or INOUT parameters there is nothing to return
+        let out_parameters: Vec<&Parameter> = parameters
+            .iter()
+            .filter(|parameter| parameter.mode == "OUT" || parameter.mode == "INOUT")
+            .collect();
+        if out_parameters.is_empty() {
+            definitions.push(quote! {
+                pub fn #procedure_ident(client: &mut postgres::Client, #(#arguments),*) -> Result<(), postgres::Error> {
+                    client.execute(#sql, &[#(&#argument_names),*])?;
+                    Ok(())
+                }
+            });
+            continue;
+        }
+
+        // Generate a struct for the values returned through the OUT and INOUT parameters
+        let struct_name = format!("{}Out", routine_name.to_case(Case::Pascal));
+        let struct_ident = Ident::new(&struct_name, Span::call_site());
+        let mut fields = Vec::new();
+        let mut field_mappings = Vec::new();
+        for parameter in &out_parameters {
+            let name = parameter.name.clone().unwrap_or_default();
+            let field = Ident::new(&name.to_case(Case::Snake), Span::call_site());
+            let rust_type = map_data_type(&parameter.data_type, use_uuid);
+            fields.push(quote! { pub #field: Option<#rust_type>, });
+            field_mappings.push(quote! { #field: row.get(#name), });
+        }
+
+        definitions.push(quote! {
+            #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+            pub struct #struct_ident {
+                #(#fields)*
+            }
+
+            pub fn #procedure_ident(client: &mut postgres::Client, #(#arguments),*) -> Result<#struct_ident, postgres::Error> {
+                let row = client.query_one(#sql, &[#(&#argument_names),*])?;
+                Ok(#struct_ident { #(#field_mappings)* })
+            }
+        });
+        struct_names.push(struct_name);
+    }
+
+    Ok(definitions)
+}
+
+/// Get a snake case identifier for a routine, numbering overloads after the first
+fn unique_ident(used_names: &mut Vec<String>, routine_name: &str) -> Ident {
+    let mut name = routine_name.to_case(Case::Snake);
+    let count = used_names.iter().filter(|used| **used == name).count();
+    used_names.push(name.clone());
+    if count > 0 {
+        name = format!("{}_{}", name, count + 1);
+    }
+
+    Ident::new(&name, Span::call_site())
+}
+
+/// Build the wrapper arguments for the parameters the caller passes in
+///
+/// INOUT parameters are optional, since NULL is a common input for them.
+fn build_arguments(parameters: &[&Parameter], use_uuid: bool) -> (Vec<TokenStream>, Vec<Ident>) {
+    let mut arguments = Vec::new();
+    let mut argument_names = Vec::new();
+    for (index, parameter) in parameters.iter().enumerate() {
+        let name = match &parameter.name {
+            Some(name) => name.to_case(Case::Snake),
+            None => format!("arg{}", index + 1),
+        };
+        let name = Ident::new(&name, Span::call_site());
+        let rust_type = argument_type(map_data_type(&parameter.data_type, use_uuid));
+        if parameter.mode == "INOUT" {
+            arguments.push(quote! { #name: Option<#rust_type> });
+        } else {
+            arguments.push(quote! { #name: #rust_type });
+        }
+        argument_names.push(name);
+    }
+
+    (arguments, argument_names)
+}
+
+/// Get the parameters of a routine, in order
+fn query_parameters(
+    client: &mut impl GenericClient,
+    simple_query: bool,
+    schema: &str,
+    specific_name: &str,
+) -> Result<Vec<Parameter>> {
+    let parameters = query(
+        client,
+        simple_query,
+        "SELECT parameter_name, data_type, parameter_mode FROM information_schema.parameters
+        WHERE specific_schema = $1 AND specific_name = $2 ORDER BY ordinal_position",
+        &[(&schema, Type::TEXT), (&specific_name, Type::TEXT)],
+    )
+    .map_err(Error::query("parameters"))?;
+
+    Ok(parameters
+        .iter()
+        .map(|parameter| Parameter {
+            name: parameter.get(0),
+            data_type: parameter.get(1),
+            mode: parameter.get(2),
+        })
+        .collect())
+}
+
+/// Borrow owned types when they are taken as arguments
+fn argument_type(rust_type: TokenStream) -> TokenStream {
+    match rust_type.to_string().as_str() {
+        "String" => quote! { &str },
+        "Vec < u8 >" => quote! { &[u8] },
+        _ => rust_type,
+    }
+}
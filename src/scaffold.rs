@@ -0,0 +1,540 @@
+use convert_case::{Case, Casing};
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::Ident;
+
+use crate::proto::{ProtoField, ProtoKind};
+
+/// A table to scaffold handlers for, with what its repository helpers can do
+pub struct ScaffoldTable {
+    /// The name of the table, which is also its route
+    pub name: String,
+    /// The path to the struct of the table, from the module of the scaffold
+    pub path: TokenStream,
+    /// The snake case name the handlers of the table are named after
+    pub handler_name: String,
+    /// The name and type of each primary key field, empty when there is no primary key
+    pub key: Vec<(Ident, TokenStream)>,
+    /// Whether rows can be inserted, which views can't be
+    pub insert: bool,
+    /// Whether rows can be updated, which needs a column besides the primary key
+    pub update: bool,
+    /// Whether updates check the version of the row, so they can fail with `UpdateError`
+    pub versioned: bool,
+    /// Whether the struct or any of its fields is deprecated
+    pub deprecated: bool,
+    /// The name of the protobuf message of the table
+    pub message: String,
+    /// The name and protobuf type of every field, in the order of the columns
+    pub fields: Vec<(Ident, ProtoField)>,
+}
+
+/// Generate axum handlers for the repository helpers of every table, and a router that serves
+/// them, for `--scaffold axum`
+///
+/// `postgres::Client` blocks, so the handlers share one behind a mutex and run their queries on
+/// the blocking thread pool.
+pub fn generate_axum(tables: &[ScaffoldTable]) -> TokenStream {
+    let mut handlers = Vec::new();
+    let mut routes = Vec::new();
+    for table in tables {
+        let path = &table.path;
+        let ident = |action: &str| {
+            Ident::new(
+                &format!("{}_{}", action, table.handler_name),
+                Span::call_site(),
+            )
+        };
+
+        // List every row, and insert new ones into tables
+        let list = ident("list");
+        handlers.push(quote! {
+            pub async fn #list(State(db): State<Db>) -> Result<Json<Vec<#path>>, ApiError> {
+                Ok(Json(with_client(db, #path::list).await?))
+            }
+        });
+        let mut collection_route = quote! { get(#list) };
+        if table.insert {
+            let create = ident("create");
+            handlers.push(quote! {
+                pub async fn #create(
+                    State(db): State<Db>,
+                    Json(row): Json<#path>,
+                ) -> Result<(StatusCode, Json<#path>), ApiError> {
+                    let row = with_client(db, move |client| row.insert(client)).await?;
+                    Ok((StatusCode::CREATED, Json(row)))
+                }
+            });
+            collection_route.extend(quote! { .post(#create) });
+        }
+        let collection_path = format!("/{}", table.name);
+        routes.push(quote! { .route(#collection_path, #collection_route) });
+
+        // Rows of tables with a primary key are addressed by it
+        if table.key.is_empty() || !table.insert {
+            continue;
+        }
+        let key_names: Vec<&Ident> = table.key.iter().map(|(name, _)| name).collect();
+        let key_types: Vec<&TokenStream> =
+            table.key.iter().map(|(_, rust_type)| rust_type).collect();
+        let key_pattern = if key_names.len() == 1 {
+            quote! { #(#key_names)* }
+        } else {
+            quote! { (#(#key_names),*) }
+        };
+        let key_type = if key_types.len() == 1 {
+            quote! { #(#key_types)* }
+        } else {
+            quote! { (#(#key_types),*) }
+        };
+        let key_arguments =
+            table
+                .key
+                .iter()
+                .map(|(name, rust_type)| match rust_type.to_string().as_str() {
+                    "String" | "Vec < u8 >" => quote! { &#name },
+                    _ => quote! { #name },
+                });
+        let key_arguments = quote! { #(#key_arguments),* };
+
+        let get_row = ident("get");
+        handlers.push(quote! {
+            pub async fn #get_row(
+                State(db): State<Db>,
+                Path(#key_pattern): Path<#key_type>,
+            ) -> Result<Json<#path>, ApiError> {
+                with_client(db, move |client| #path::get(client, #key_arguments))
+                    .await?
+                    .map(Json)
+                    .ok_or_else(not_found)
+            }
+        });
+        let mut row_route = quote! { get(#get_row) };
+
+        if table.update {
+            let update = ident("update");
+
+            // Stale rows are conflicts, which `UpdateError` converts to
+            let updated = if table.versioned {
+                quote! { with_client(db, move |client| Ok(row.update(client))).await?? }
+            } else {
+                quote! { with_client(db, move |client| row.update(client)).await? }
+            };
+            handlers.push(quote! {
+                pub async fn #update(
+                    State(db): State<Db>,
+                    Path(#key_pattern): Path<#key_type>,
+                    Json(mut row): Json<#path>,
+                ) -> Result<Json<#path>, ApiError> {
+                    #(row.#key_names = #key_names;)*
+                    #updated.map(Json).ok_or_else(not_found)
+                }
+            });
+            row_route.extend(quote! { .put(#update) });
+        }
+
+        let delete = ident("delete");
+        handlers.push(quote! {
+            pub async fn #delete(
+                State(db): State<Db>,
+                Path(#key_pattern): Path<#key_type>,
+            ) -> Result<StatusCode, ApiError> {
+                if with_client(db, move |client| #path::delete(client, #key_arguments)).await? {
+                    Ok(StatusCode::NO_CONTENT)
+                } else {
+                    Err(not_found())
+                }
+            }
+        });
+        row_route.extend(quote! { .delete(#delete) });
+
+        let placeholders: Vec<String> = key_names
+            .iter()
+            .map(|name| format!("{{{}}}", name))
+            .collect();
+        let row_path = format!("/{}/{}", table.name, placeholders.join("/"));
+        routes.push(quote! { .route(#row_path, #row_route) });
+    }
+
+    // The handlers use deprecated structs on purpose
+    let allow_deprecated = if tables.iter().any(|table| table.deprecated) {
+        quote! { #![allow(deprecated)] }
+    } else {
+        quote! {}
+    };
+
+    // Stale rows of versioned tables are conflicts
+    let update_error = if tables.iter().any(|table| table.versioned) {
+        quote! {
+            impl From<super::UpdateError> for ApiError {
+                fn from(error: super::UpdateError) -> Self {
+                    match error {
+                        super::UpdateError::StaleRow => ApiError(StatusCode::CONFLICT, super::UpdateError::StaleRow.to_string()),
+                        super::UpdateError::Postgres(error) => error.into(),
+                    }
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // Only tables with a primary key take it from the path
+    let path_import = if tables
+        .iter()
+        .any(|table| !table.key.is_empty() && table.insert)
+    {
+        quote! { use axum::extract::Path; }
+    } else {
+        quote! {}
+    };
+
+    quote! {
+        #allow_deprecated
+
+        #path_import
+        use axum::extract::State;
+        use axum::http::StatusCode;
+        use axum::response::{IntoResponse, Response};
+        use axum::routing::get;
+        use axum::{Json, Router};
+
+        /// The database connection the handlers share
+        pub type Db = std::sync::Arc<std::sync::Mutex<postgres::Client>>;
+
+        /// An error response, with its status code and message
+        #[derive(Debug)]
+        pub struct ApiError(pub StatusCode, pub String);
+
+        impl IntoResponse for ApiError {
+            fn into_response(self) -> Response {
+                (self.0, self.1).into_response()
+            }
+        }
+
+        impl From<postgres::Error> for ApiError {
+            fn from(error: postgres::Error) -> Self {
+                ApiError(StatusCode::INTERNAL_SERVER_ERROR, error.to_string())
+            }
+        }
+
+        #update_error
+
+        fn not_found() -> ApiError {
+            ApiError(StatusCode::NOT_FOUND, "Not found".to_string())
+        }
+
+        /// Run a query on the blocking thread pool, since `postgres::Client` blocks
+        async fn with_client<T, F>(db: Db, query: F) -> Result<T, ApiError>
+        where
+            T: Send + 'static,
+            F: FnOnce(&mut postgres::Client) -> Result<T, postgres::Error> + Send + 'static,
+        {
+            tokio::task::spawn_blocking(move || {
+                let mut client = db.lock().map_err(|_| {
+                    ApiError(StatusCode::INTERNAL_SERVER_ERROR, "Database connection poisoned".to_string())
+                })?;
+                Ok(query(&mut client)?)
+            })
+            .await
+            .map_err(|error| ApiError(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()))?
+        }
+
+        #(#handlers)*
+
+        /// Serve the handlers of every table
+        pub fn router(db: Db) -> Router {
+            Router::new()
+                #(#routes)*
+                .with_state(db)
+        }
+    }
+}
+
+/// Generate a tonic service for the repository helpers of every table, implementing the services
+/// written by `--emit proto`, for `--scaffold tonic`
+///
+/// Messages are converted to and from the structs with `TryFrom`, carrying types protobuf has no
+/// scalar for as JSON strings.
+pub fn generate_tonic(tables: &[ScaffoldTable], package: &str) -> TokenStream {
+    let mut impls = Vec::new();
+    let mut services = Vec::new();
+    for table in tables {
+        let path = &table.path;
+        let message = Ident::new(&table.message, Span::call_site());
+        let proto_ident = |name: String| Ident::new(&name, Span::call_site());
+        let list_request = proto_ident(format!("List{}Request", table.message));
+        let list_response = proto_ident(format!("List{}Response", table.message));
+        let key_message = proto_ident(format!("{}Key", table.message));
+        let delete_response = proto_ident(format!("Delete{}Response", table.message));
+        let service = proto_ident(format!("{}Service", table.message));
+        let server_module = proto_ident(format!(
+            "{}_server",
+            service.to_string().to_case(Case::Snake)
+        ));
+        let server = proto_ident(format!("{}Server", service));
+
+        // Convert between the struct and its message
+        let names: Vec<&Ident> = table.fields.iter().map(|(name, _)| name).collect();
+        let to_message = table.fields.iter().map(|(name, field)| {
+            let value = quote! { row.#name };
+            to_proto(&value, field)
+        });
+        let from_message = table.fields.iter().map(|(name, field)| {
+            let value = quote! { message.#name };
+            from_proto(&value, field)
+        });
+        impls.push(quote! {
+            impl TryFrom<#path> for proto::#message {
+                type Error = Status;
+
+                fn try_from(row: #path) -> Result<Self, Status> {
+                    Ok(proto::#message {
+                        #(#names: #to_message,)*
+                    })
+                }
+            }
+
+            impl TryFrom<proto::#message> for #path {
+                type Error = Status;
+
+                fn try_from(message: proto::#message) -> Result<Self, Status> {
+                    Ok(#path {
+                        #(#names: #from_message,)*
+                    })
+                }
+            }
+        });
+
+        // List every row, and insert new ones into tables
+        let mut methods = vec![quote! {
+            async fn list(
+                &self,
+                _request: Request<proto::#list_request>,
+            ) -> Result<Response<proto::#list_response>, Status> {
+                let rows = with_client(self.db.clone(), #path::list)
+                    .await?
+                    .into_iter()
+                    .map(proto::#message::try_from)
+                    .collect::<Result<_, _>>()?;
+                Ok(Response::new(proto::#list_response { rows }))
+            }
+        }];
+        if table.insert {
+            methods.push(quote! {
+                async fn create(
+                    &self,
+                    request: Request<proto::#message>,
+                ) -> Result<Response<proto::#message>, Status> {
+                    let row = #path::try_from(request.into_inner())?;
+                    let row = with_client(self.db.clone(), move |client| row.insert(client)).await?;
+                    Ok(Response::new(row.try_into()?))
+                }
+            });
+        }
+
+        // Rows of tables with a primary key are addressed by it
+        if !table.key.is_empty() && table.insert {
+            let key_names: Vec<&Ident> = table.key.iter().map(|(name, _)| name).collect();
+            let key_values = table.key.iter().map(|(name, _)| {
+                let field = table
+                    .fields
+                    .iter()
+                    .find(|(field_name, _)| field_name == name)
+                    .map(|(_, field)| field)
+                    .expect("Key fields are fields of the table");
+                from_proto(&quote! { key.#name }, field)
+            });
+            let key_arguments =
+                table
+                    .key
+                    .iter()
+                    .map(|(name, rust_type)| match rust_type.to_string().as_str() {
+                        "String" | "Vec < u8 >" => quote! { &#name },
+                        _ => quote! { #name },
+                    });
+            let key_arguments = quote! { #(#key_arguments),* };
+            let read_key = quote! {
+                let key = request.into_inner();
+                #(let #key_names = #key_values;)*
+            };
+
+            methods.push(quote! {
+                async fn get(
+                    &self,
+                    request: Request<proto::#key_message>,
+                ) -> Result<Response<proto::#message>, Status> {
+                    #read_key
+                    let row = with_client(self.db.clone(), move |client| #path::get(client, #key_arguments))
+                        .await?
+                        .ok_or_else(not_found)?;
+                    Ok(Response::new(row.try_into()?))
+                }
+            });
+
+            if table.update {
+                let updated = if table.versioned {
+                    quote! {
+                        with_client(self.db.clone(), move |client| Ok(row.update(client)))
+                            .await?
+                            .map_err(update_status)?
+                    }
+                } else {
+                    quote! { with_client(self.db.clone(), move |client| row.update(client)).await? }
+                };
+                methods.push(quote! {
+                    async fn update(
+                        &self,
+                        request: Request<proto::#message>,
+                    ) -> Result<Response<proto::#message>, Status> {
+                        let row = #path::try_from(request.into_inner())?;
+                        let row = #updated.ok_or_else(not_found)?;
+                        Ok(Response::new(row.try_into()?))
+                    }
+                });
+            }
+
+            methods.push(quote! {
+                async fn delete(
+                    &self,
+                    request: Request<proto::#key_message>,
+                ) -> Result<Response<proto::#delete_response>, Status> {
+                    #read_key
+                    if with_client(self.db.clone(), move |client| #path::delete(client, #key_arguments)).await? {
+                        Ok(Response::new(proto::#delete_response {}))
+                    } else {
+                        Err(not_found())
+                    }
+                }
+            });
+        }
+
+        impls.push(quote! {
+            #[tonic::async_trait]
+            impl proto::#server_module::#service for Service {
+                #(#methods)*
+            }
+        });
+        services
+            .push(quote! { .add_service(proto::#server_module::#server::new(service.clone())) });
+    }
+
+    // The conversions use deprecated structs on purpose
+    let allow_deprecated = if tables.iter().any(|table| table.deprecated) {
+        quote! { #![allow(deprecated)] }
+    } else {
+        quote! {}
+    };
+
+    // Only write the conversion helpers some field needs
+    let kinds: Vec<ProtoKind> = tables
+        .iter()
+        .flat_map(|table| table.fields.iter().map(|(_, field)| field.kind))
+        .collect();
+    let mut helpers = Vec::new();
+    if kinds.contains(&ProtoKind::Json) {
+        helpers.push(quote! {
+            fn to_json<T: serde::Serialize>(value: &T) -> Result<String, Status> {
+                serde_json::to_string(value).map_err(|error| Status::internal(error.to_string()))
+            }
+
+            fn from_json<T: serde::de::DeserializeOwned>(json: &str) -> Result<T, Status> {
+                serde_json::from_str(json).map_err(|error| Status::invalid_argument(error.to_string()))
+            }
+        });
+    }
+    if kinds.contains(&ProtoKind::Int16) {
+        helpers.push(quote! {
+            fn to_i16(value: i32) -> Result<i16, Status> {
+                i16::try_from(value).map_err(|error| Status::invalid_argument(error.to_string()))
+            }
+        });
+    }
+
+    if tables.iter().any(|table| table.versioned) {
+        helpers.push(quote! {
+            /// Stale rows abort the update, as a conflict with another one
+            fn update_status(error: super::UpdateError) -> Status {
+                match error {
+                    super::UpdateError::StaleRow => Status::aborted(super::UpdateError::StaleRow.to_string()),
+                    super::UpdateError::Postgres(error) => Status::internal(error.to_string()),
+                }
+            }
+        });
+    }
+
+    quote! {
+        #allow_deprecated
+
+        use tonic::{Request, Response, Status};
+
+        /// The messages and services of `--emit proto`, compiled by tonic-prost-build
+        pub mod proto {
+            tonic::include_proto!(#package);
+        }
+
+        /// The database connection the services share
+        pub type Db = std::sync::Arc<std::sync::Mutex<postgres::Client>>;
+
+        /// The services of every table, sharing one database connection
+        #[derive(Clone)]
+        pub struct Service {
+            db: Db,
+        }
+
+        fn not_found() -> Status {
+            Status::not_found("Not found")
+        }
+
+        #(#helpers)*
+
+        /// Run a query on the blocking thread pool, since `postgres::Client` blocks
+        async fn with_client<T, F>(db: Db, query: F) -> Result<T, Status>
+        where
+            T: Send + 'static,
+            F: FnOnce(&mut postgres::Client) -> Result<T, postgres::Error> + Send + 'static,
+        {
+            tokio::task::spawn_blocking(move || {
+                let mut client = db
+                    .lock()
+                    .map_err(|_| Status::internal("Database connection poisoned"))?;
+                query(&mut client).map_err(|error| Status::internal(error.to_string()))
+            })
+            .await
+            .map_err(|error| Status::internal(error.to_string()))?
+        }
+
+        #(#impls)*
+
+        /// Serve the services of every table
+        pub fn routes(db: Db) -> tonic::service::Routes {
+            let service = Service { db };
+            tonic::service::Routes::default()
+                #(#services)*
+        }
+    }
+}
+
+/// Convert a struct field to its protobuf type
+fn to_proto(value: &TokenStream, field: &ProtoField) -> TokenStream {
+    match (field.kind, field.optional) {
+        (ProtoKind::Scalar, _) => value.clone(),
+        (ProtoKind::Int16, false) => quote! { i32::from(#value) },
+        (ProtoKind::Int16, true) => quote! { #value.map(i32::from) },
+        (ProtoKind::Bytes, false) => quote! { #value.to_vec() },
+        (ProtoKind::Bytes, true) => quote! { #value.map(|value| value.to_vec()) },
+        (ProtoKind::Json, _) => quote! { to_json(&#value)? },
+    }
+}
+
+/// Convert a protobuf field to the type of the struct field
+fn from_proto(value: &TokenStream, field: &ProtoField) -> TokenStream {
+    match (field.kind, field.optional) {
+        (ProtoKind::Scalar, _) => value.clone(),
+        (ProtoKind::Int16, false) => quote! { to_i16(#value)? },
+        (ProtoKind::Int16, true) => quote! { #value.map(to_i16).transpose()? },
+        (ProtoKind::Bytes, false) => quote! { bytes::Bytes::from(#value) },
+        (ProtoKind::Bytes, true) => quote! { #value.map(bytes::Bytes::from) },
+        (ProtoKind::Json, _) => quote! { from_json(&#value)? },
+    }
+}
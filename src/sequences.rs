@@ -0,0 +1,166 @@
+use convert_case::{Case, Casing};
+use postgres::types::Type;
+use postgres::GenericClient;
+use proc_macro2::TokenStream;
+use quote::quote;
+use tracing::info;
+
+use crate::connection::query;
+use crate::error::{Error, Result};
+use crate::output;
+
+/// A sequence in the schema
+struct Sequence {
+    name: String,
+    /// The table and column the sequence numbers, for serial, identity and `OWNED BY` columns
+    owned_by: Option<(String, String)>,
+}
+
+/// Read the sequences of a schema, with the columns that own them
+fn introspect(
+    client: &mut impl GenericClient,
+    simple_query: bool,
+    schema: &str,
+) -> Result<Vec<Sequence>> {
+    let rows = query(
+        client,
+        simple_query,
+        "SELECT s.relname, t.relname, a.attname
+        FROM pg_catalog.pg_class s
+        JOIN pg_catalog.pg_namespace n ON n.oid = s.relnamespace
+        LEFT JOIN pg_catalog.pg_depend d ON d.classid = 'pg_catalog.pg_class'::regclass
+            AND d.objid = s.oid AND d.refclassid = 'pg_catalog.pg_class'::regclass
+            AND d.deptype IN ('a', 'i')
+        LEFT JOIN pg_catalog.pg_class t ON t.oid = d.refobjid
+        LEFT JOIN pg_catalog.pg_attribute a ON a.attrelid = d.refobjid AND a.attnum = d.refobjsubid
+        WHERE n.nspname = $1 AND s.relkind = 'S'
+        ORDER BY s.relname",
+        &[(&schema, Type::TEXT)],
+    )
+    .map_err(Error::query("sequences"))?;
+
+    Ok(rows
+        .iter()
+        .map(|row| {
+            let table: Option<String> = row.get(1);
+            let column: Option<String> = row.get(2);
+            Sequence {
+                name: row.get(0),
+                owned_by: table.zip(column),
+            }
+        })
+        .collect())
+}
+
+/// Generate a `Sequence` const per sequence in the schema, a `SEQUENCES` slice of all of them
+/// and a `next_` helper per sequence, for `--sequences`
+///
+/// The consts are named after the sequences in upper snake case, e.g. `USERS_ID_SEQ`, and the
+/// helpers without their `_seq` suffix, e.g. `next_users_id`. Returns `None` when the schema has
+/// no sequences.
+pub fn generate_sequences(
+    client: &mut impl GenericClient,
+    simple_query: bool,
+    schema: &str,
+) -> Result<Option<TokenStream>> {
+    let sequences = introspect(client, simple_query, schema)?;
+    if sequences.is_empty() {
+        return Ok(None);
+    }
+
+    let mut consts = Vec::new();
+    let mut idents = Vec::new();
+    let mut names = Vec::new();
+    for sequence in &sequences {
+        info!("Generating helpers for sequence {}", sequence.name);
+        let context = format!("sequence {}", sequence.name);
+        let const_name = sequence.name.to_case(Case::UpperSnake);
+        let function_name = format!(
+            "next_{}",
+            sequence
+                .name
+                .strip_suffix("_seq")
+                .unwrap_or(&sequence.name)
+                .to_case(Case::Snake)
+        );
+        for name in [&const_name, &function_name] {
+            if name == "SEQUENCES" || names.contains(name) {
+                return Err(Error::Config(format!(
+                    "The helpers of sequence {} would be named {}, which is already taken",
+                    sequence.name, name
+                )));
+            }
+            names.push(name.clone());
+        }
+        let const_ident = output::ident(&const_name, &context)?;
+        let function_ident = output::ident(&function_name, &context)?;
+
+        let name = &sequence.name;
+        let (owned_by, const_doc) = match &sequence.owned_by {
+            Some((table, column)) => (
+                quote! { Some((#table, #column)) },
+                format!(
+                    " The `{}` sequence, which numbers `{}.{}`",
+                    name, table, column
+                ),
+            ),
+            None => (quote! { None }, format!(" The `{}` sequence", name)),
+        };
+        let function_doc = format!(" Take the next value of the `{}` sequence", name);
+
+        consts.push(quote! {
+            #[doc = #const_doc]
+            pub const #const_ident: Sequence = Sequence {
+                name: #name,
+                schema: #schema,
+                owned_by: #owned_by,
+            };
+
+            #[doc = #function_doc]
+            pub fn #function_ident(client: &mut impl postgres::GenericClient) -> Result<i64, postgres::Error> {
+                #const_ident.next_value(client)
+            }
+        });
+        idents.push(const_ident);
+    }
+
+    Ok(Some(quote! {
+        /// A sequence, and the column it numbers if any
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub struct Sequence {
+            /// The name of the sequence
+            pub name: &'static str,
+            /// The schema of the sequence
+            pub schema: &'static str,
+            /// The table and column the sequence numbers, for serial, identity and `OWNED BY`
+            /// columns
+            pub owned_by: Option<(&'static str, &'static str)>,
+        }
+
+        impl Sequence {
+            /// Take the next value of the sequence
+            pub fn next_value(&self, client: &mut impl postgres::GenericClient) -> Result<i64, postgres::Error> {
+                let row = client.query_one(
+                    "SELECT nextval(format('%I.%I', $1::text, $2::text))",
+                    &[&self.schema, &self.name],
+                )?;
+                Ok(row.get(0))
+            }
+
+            /// Take the next `count` values of the sequence at once, to hand out IDs before the
+            /// rows are inserted
+            pub fn next_values(&self, client: &mut impl postgres::GenericClient, count: i64) -> Result<Vec<i64>, postgres::Error> {
+                let rows = client.query(
+                    "SELECT nextval(format('%I.%I', $1::text, $2::text)) FROM generate_series(1, $3::int8)",
+                    &[&self.schema, &self.name, &count],
+                )?;
+                Ok(rows.iter().map(|row| row.get(0)).collect())
+            }
+        }
+
+        /// Every sequence in the schema
+        pub const SEQUENCES: &[Sequence] = &[#(#idents),*];
+
+        #(#consts)*
+    }))
+}
@@ -0,0 +1,60 @@
+use std::fmt::Write;
+use std::fs;
+
+use sha2::{Digest, Sha256};
+use tracing::info;
+
+use crate::error::{Error, Result};
+use crate::model::Schema;
+
+/// Write the schema to a JSON snapshot file
+pub fn write_snapshot(path: &str, schema: &Schema) -> Result<()> {
+    info!("Writing schema snapshot to {}", path);
+    let contents = serde_json::to_string_pretty(schema).map_err(|source| Error::Snapshot {
+        path: path.into(),
+        source,
+    })?;
+
+    fs::write(path, contents + "\n").map_err(Error::io(path))
+}
+
+/// Read a schema from a JSON snapshot file
+pub fn read_snapshot(path: &str) -> Result<Schema> {
+    info!("Reading schema snapshot from {}", path);
+    let contents = fs::read_to_string(path).map_err(Error::io(path))?;
+
+    serde_json::from_str(&contents).map_err(|source| Error::Snapshot {
+        path: path.into(),
+        source,
+    })
+}
+
+/// Get a checksum of a schema, which changes whenever anything read from the database does
+pub fn checksum(schema: &Schema) -> String {
+    let schema = Schema {
+        scope: None,
+        ..schema.clone()
+    };
+    let contents = serde_json::to_vec(&schema).expect("Schemas are always serializable");
+    sha256(&contents)
+}
+
+/// Combine the checksums of several schemas into one, for `--schema '*'`
+///
+/// The checksum of a single schema is kept as it is, so `verify` still works on it.
+pub fn combine_checksums(checksums: &[&str]) -> String {
+    match checksums {
+        [checksum] => checksum.to_string(),
+        _ => sha256(checksums.join("\n").as_bytes()),
+    }
+}
+
+/// Get the SHA-256 hash of some bytes, in hexadecimal
+fn sha256(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .fold(String::new(), |mut hex, byte| {
+            write!(hex, "{:02x}", byte).unwrap();
+            hex
+        })
+}
@@ -0,0 +1,293 @@
+use std::{
+    env,
+    io::{self, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use ssh2::{CheckResult, KnownHostFileKind, Session};
+use tracing::{debug, info, warn};
+
+use crate::error::{Error, Result};
+
+/// The SSH server to tunnel through, parsed from `user@host[:port]`
+pub struct SshDestination {
+    pub user: String,
+    pub host: String,
+    pub port: u16,
+}
+
+impl SshDestination {
+    /// Parse a `user@host[:port]` destination, the port defaults to 22
+    pub fn parse(destination: &str) -> Result<Self> {
+        let invalid = || {
+            Error::Config(format!(
+                "Invalid SSH destination {}, expected user@host[:port]",
+                destination
+            ))
+        };
+
+        let (user, address) = destination.split_once('@').ok_or_else(invalid)?;
+        let (host, port) = match address.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| invalid())?),
+            None => (address, 22),
+        };
+        if user.is_empty() || host.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(SshDestination {
+            user: user.to_string(),
+            host: host.to_string(),
+            port,
+        })
+    }
+}
+
+/// Open an SSH tunnel to `remote_host:remote_port` and return the local port that forwards to it
+///
+/// The remote host is resolved by the SSH server, so it can be a name only the bastion knows. The
+/// tunnel is served by a background thread for the rest of the process. With
+/// `accept_unknown_host`, a server that isn't in `~/.ssh/known_hosts` is trusted.
+pub fn open(
+    destination: &SshDestination,
+    identity: Option<&str>,
+    accept_unknown_host: bool,
+    remote_host: &str,
+    remote_port: u16,
+) -> Result<u16> {
+    let tunnel_error = |message: &str, error: &dyn std::fmt::Display| {
+        Error::Tunnel(format!("{}: {}", message, error))
+    };
+
+    info!(
+        "Opening SSH tunnel through {}@{}:{}",
+        destination.user, destination.host, destination.port
+    );
+
+    // Connect to the SSH server
+    let stream = TcpStream::connect((destination.host.as_str(), destination.port))
+        .map_err(|error| tunnel_error("failed to reach SSH server", &error))?;
+    let mut session =
+        Session::new().map_err(|error| tunnel_error("failed to start SSH session", &error))?;
+    session.set_tcp_stream(stream);
+    session
+        .handshake()
+        .map_err(|error| tunnel_error("SSH handshake failed", &error))?;
+
+    check_host_key(&session, destination, accept_unknown_host)?;
+    authenticate(&session, destination, identity)?;
+
+    // Listen on a free local port, the PostgreSQL client connects to it
+    let listener = TcpListener::bind(("127.0.0.1", 0))
+        .map_err(|error| tunnel_error("failed to listen for the SSH tunnel", &error))?;
+    let local_port = listener
+        .local_addr()
+        .map_err(|error| tunnel_error("failed to listen for the SSH tunnel", &error))?
+        .port();
+
+    debug!(
+        "SSH tunnel listening on 127.0.0.1:{} for {}:{}",
+        local_port, remote_host, remote_port
+    );
+
+    let remote_host = remote_host.to_string();
+    thread::spawn(move || {
+        for client in listener.incoming() {
+            let result =
+                client.and_then(|client| forward(&session, client, &remote_host, remote_port));
+            if let Err(error) = result {
+                warn!("SSH tunnel connection closed: {}", error);
+            }
+        }
+    });
+
+    Ok(local_port)
+}
+
+/// Make sure the SSH server is the one in `~/.ssh/known_hosts`
+///
+/// Like ssh with `StrictHostKeyChecking`, unknown and changed keys are refused, and so is a server
+/// that can't be checked because there's no known hosts file. With `accept_unknown_host`, only
+/// changed keys are.
+fn check_host_key(
+    session: &Session,
+    destination: &SshDestination,
+    accept_unknown_host: bool,
+) -> Result<()> {
+    let (key, _) = session
+        .host_key()
+        .ok_or_else(|| Error::Tunnel("SSH server didn't send a host key".to_string()))?;
+    let mut known_hosts = session
+        .known_hosts()
+        .map_err(|error| Error::Tunnel(format!("failed to read known hosts: {}", error)))?;
+
+    let Some(file) = ssh_directory().map(|directory| directory.join("known_hosts")) else {
+        if accept_unknown_host {
+            warn!("Couldn't find the home directory, skipping SSH host key verification");
+            return Ok(());
+        }
+        return Err(Error::Tunnel(
+            "couldn't find the home directory to read known_hosts from, pass --ssh-accept-unknown-host to connect anyway"
+                .to_string(),
+        ));
+    };
+    if let Err(error) = known_hosts.read_file(&file, KnownHostFileKind::OpenSSH) {
+        if accept_unknown_host {
+            warn!(
+                "Couldn't read {}, skipping SSH host key verification: {}",
+                file.display(),
+                error
+            );
+            return Ok(());
+        }
+        return Err(Error::Tunnel(format!(
+            "failed to read {} to check the host key of {}, pass --ssh-accept-unknown-host to connect anyway: {}",
+            file.display(),
+            destination.host,
+            error
+        )));
+    }
+
+    match known_hosts.check_port(&destination.host, destination.port, key) {
+        CheckResult::Match => Ok(()),
+        CheckResult::NotFound if accept_unknown_host => {
+            warn!(
+                "Host key for {} is not in {}, connecting anyway",
+                destination.host,
+                file.display()
+            );
+            Ok(())
+        }
+        CheckResult::NotFound => Err(Error::Tunnel(format!(
+            "host key for {} is not in {}, connect with ssh once to add it",
+            destination.host,
+            file.display()
+        ))),
+        CheckResult::Mismatch => Err(Error::Tunnel(format!(
+            "host key for {} doesn't match {}",
+            destination.host,
+            file.display()
+        ))),
+        CheckResult::Failure => Err(Error::Tunnel(format!(
+            "failed to check the host key for {}",
+            destination.host
+        ))),
+    }
+}
+
+/// Authenticate with the SSH agent, then the given identity file, then the default keys
+fn authenticate(
+    session: &Session,
+    destination: &SshDestination,
+    identity: Option<&str>,
+) -> Result<()> {
+    let user = destination.user.as_str();
+    if session.userauth_agent(user).is_ok() {
+        debug!("Authenticated with the SSH agent");
+        return Ok(());
+    }
+
+    // Use the identity file when it's given, otherwise the keys ssh would try
+    let keys: Vec<PathBuf> = match identity {
+        Some(identity) => vec![PathBuf::from(identity)],
+        None => ssh_directory()
+            .map(|directory| {
+                ["id_ed25519", "id_ecdsa", "id_rsa"]
+                    .iter()
+                    .map(|name| directory.join(name))
+                    .filter(|path| path.exists())
+                    .collect()
+            })
+            .unwrap_or_default(),
+    };
+
+    for key in &keys {
+        match session.userauth_pubkey_file(user, None, key, None) {
+            Ok(()) => {
+                debug!("Authenticated with {}", key.display());
+                return Ok(());
+            }
+            Err(error) => debug!("SSH key {} was rejected: {}", key.display(), error),
+        }
+    }
+
+    Err(Error::Tunnel(format!(
+        "SSH authentication as {} failed, add a key to the SSH agent or pass --ssh-identity",
+        user
+    )))
+}
+
+/// Copy data both ways between a local connection and a channel to the remote host
+fn forward(
+    session: &Session,
+    mut client: TcpStream,
+    remote_host: &str,
+    remote_port: u16,
+) -> io::Result<()> {
+    session.set_blocking(true);
+    let mut channel = session.channel_direct_tcpip(remote_host, remote_port, None)?;
+
+    // Poll both sides, since the session can't be shared between threads
+    session.set_blocking(false);
+    client.set_nonblocking(true)?;
+
+    let mut buffer = [0; 16 * 1024];
+    loop {
+        let mut idle = true;
+
+        match client.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => {
+                write_all(&mut channel, &buffer[..read])?;
+                idle = false;
+            }
+            Err(error) if error.kind() == ErrorKind::WouldBlock => {}
+            Err(error) => return Err(error),
+        }
+
+        match channel.read(&mut buffer) {
+            Ok(0) if channel.eof() => break,
+            Ok(0) => {}
+            Ok(read) => {
+                write_all(&mut client, &buffer[..read])?;
+                idle = false;
+            }
+            Err(error) if error.kind() == ErrorKind::WouldBlock => {}
+            Err(error) => return Err(error),
+        }
+
+        if idle {
+            thread::sleep(Duration::from_millis(2));
+        }
+    }
+
+    session.set_blocking(true);
+    channel.close().ok();
+    Ok(())
+}
+
+/// Write all of `data` to a non-blocking writer
+fn write_all(writer: &mut impl Write, mut data: &[u8]) -> io::Result<()> {
+    while !data.is_empty() {
+        match writer.write(data) {
+            Ok(0) => return Err(ErrorKind::WriteZero.into()),
+            Ok(written) => data = &data[written..],
+            Err(error) if error.kind() == ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(1))
+            }
+            Err(error) => return Err(error),
+        }
+    }
+
+    Ok(())
+}
+
+/// Get the `~/.ssh` directory
+fn ssh_directory() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(|home| Path::new(&home).join(".ssh"))
+}
@@ -0,0 +1,474 @@
+use std::collections::HashSet;
+
+use proc_macro2::{Group, Ident, Span, TokenStream, TokenTree};
+use quote::quote;
+
+use crate::composites::composite_ident;
+use crate::config::{ArrayConfig, Dimensions};
+use crate::enums::enum_ident;
+use crate::model::{DataType, TypeKind};
+use crate::newtypes;
+
+/// The Rust type of `bytea` columns
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BytesType {
+    /// `Vec<u8>`
+    #[default]
+    Vec,
+    /// `bytes::Bytes`, which is cheap to clone and slice
+    Bytes,
+}
+
+/// Map the type of a column to a Rust type
+///
+/// Enums and composite types of `schema` are referred to by the name of the generated enum or
+/// struct, and types like `regclass`
+/// or `xml` by the name of their newtype, except `citext` with `citext_as_string`. `bytea` is
+/// mapped to `bytes_type`, except in arrays. Arrays are mapped to `Vec`s as described by `arrays`.
+/// With `precise`, the precision and length of a column pick a closer type, see
+/// [`precise_type`].
+pub fn map_column_type(
+    data_type: &DataType,
+    schema: &str,
+    use_uuid: bool,
+    precise: bool,
+    citext_as_string: bool,
+    bytes_type: BytesType,
+    arrays: &ArrayConfig,
+) -> TokenStream {
+    let mut rust_type = if data_type.kind == TypeKind::Enum && data_type.schema == schema {
+        let ident = enum_ident(&data_type.name);
+        quote! { #ident }
+    } else if data_type.kind == TypeKind::Composite && data_type.schema == schema {
+        let ident = composite_ident(&data_type.name);
+        quote! { #ident }
+    } else if let Some(rust_type) = precise.then(|| precise_type(data_type)).flatten() {
+        rust_type
+    } else if let Some(newtype) = newtypes::find(&data_type.name)
+        .filter(|newtype| !(citext_as_string && newtype.name == "citext"))
+    {
+        let ident = Ident::new(newtype.rust_name, Span::call_site());
+        quote! { #ident }
+    } else if data_type.name == "bytea"
+        && data_type.dimensions == 0
+        && bytes_type == BytesType::Bytes
+    {
+        quote! { bytes::Bytes }
+    } else {
+        map_data_type(&data_type.name, use_uuid)
+    };
+
+    if data_type.dimensions == 0 {
+        return rust_type;
+    }
+
+    if arrays.nullable_elements && !data_type.element_not_null {
+        rust_type = quote! { Option<#rust_type> };
+    }
+    let dimensions = match arrays.dimensions {
+        Dimensions::Nested => data_type.dimensions,
+        Dimensions::Flatten | Dimensions::Reject => 1,
+    };
+    for _ in 0..dimensions {
+        rust_type = quote! { Vec<#rust_type> };
+    }
+
+    rust_type
+}
+
+/// Map a type by its precision, scale or length, when they fit a closer Rust type
+///
+/// `numeric` without a scale becomes the smallest integer that holds its precision, like `i64` for
+/// `numeric(10,0)`, and with a scale `rust_decimal::Decimal`, up to the 28 digits it holds.
+/// `character(1)` becomes `char`.
+fn precise_type(data_type: &DataType) -> Option<TokenStream> {
+    let rust_type = match (
+        data_type.name.as_str(),
+        data_type.precision,
+        data_type.scale,
+    ) {
+        ("numeric", Some(precision), Some(0)) if precision <= 4 => quote! { i16 },
+        ("numeric", Some(precision), Some(0)) if precision <= 9 => quote! { i32 },
+        ("numeric", Some(precision), Some(0)) if precision <= 18 => quote! { i64 },
+        ("numeric", Some(precision), Some(_)) if precision <= 28 => {
+            quote! { rust_decimal::Decimal }
+        }
+        ("character", _, _) if data_type.length == Some(1) => quote! { char },
+        _ => return None,
+    };
+
+    Some(rust_type)
+}
+
+/// Refer to the generated types named in `names` through `prefix`, e.g. `super::` for structs
+/// that are written to their own file
+pub fn qualify(
+    rust_type: TokenStream,
+    names: &HashSet<String>,
+    prefix: &TokenStream,
+) -> TokenStream {
+    let mut qualified = TokenStream::new();
+    let mut after_colon = false;
+    for token in rust_type {
+        let is_colon = matches!(&token, TokenTree::Punct(punct) if punct.as_char() == ':');
+        match &token {
+            // Only names that aren't already part of a path
+            TokenTree::Ident(ident) if !after_colon && names.contains(&ident.to_string()) => {
+                qualified.extend(prefix.clone());
+                qualified.extend([token.clone()]);
+            }
+            TokenTree::Group(group) => {
+                let stream = qualify(group.stream(), names, prefix);
+                let mut qualified_group = Group::new(group.delimiter(), stream);
+                qualified_group.set_span(group.span());
+                qualified.extend([TokenTree::Group(qualified_group)]);
+            }
+            _ => qualified.extend([token.clone()]),
+        }
+        after_colon = is_colon;
+    }
+
+    qualified
+}
+
+/// Map a PostgreSQL data type, as reported by information_schema, to a Rust type
+pub fn map_data_type(data_type: &str, use_uuid: bool) -> TokenStream {
+    known_data_type(data_type, use_uuid).unwrap_or_else(|| quote! { String })
+}
+
+/// The traits a field type implements, of the ones a struct may derive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Traits {
+    pub copy: bool,
+    pub eq: bool,
+    pub hash: bool,
+    pub partial_ord: bool,
+}
+
+impl Traits {
+    /// Get the traits both types implement
+    pub fn and(self, other: Traits) -> Traits {
+        Traits {
+            copy: self.copy && other.copy,
+            eq: self.eq && other.eq,
+            hash: self.hash && other.hash,
+            partial_ord: self.partial_ord && other.partial_ord,
+        }
+    }
+}
+
+/// Get the traits a field type implements, given the names of the generated enums and composite
+/// structs
+///
+/// Types this doesn't know, like the ones set with `[column_types]`, are assumed to be `Eq`, like
+/// every struct always derived, and nothing else.
+pub fn traits(
+    rust_type: &syn::Type,
+    enum_names: &HashSet<String>,
+    composite_names: &HashSet<String>,
+) -> Traits {
+    let all = |copy| Traits {
+        copy,
+        eq: true,
+        hash: true,
+        partial_ord: true,
+    };
+    let unknown = Traits {
+        copy: false,
+        eq: true,
+        hash: false,
+        partial_ord: false,
+    };
+    let syn::Type::Path(type_path) = rust_type else {
+        return unknown;
+    };
+    let Some(last) = type_path.path.segments.last() else {
+        return unknown;
+    };
+    let path: Vec<String> = type_path
+        .path
+        .segments
+        .iter()
+        .map(|segment| segment.ident.to_string())
+        .collect();
+
+    // The traits of a container are the ones of the type it holds, except Copy for the heap
+    let inner = match &last.arguments {
+        syn::PathArguments::AngleBracketed(arguments) => {
+            arguments.args.iter().find_map(|argument| match argument {
+                syn::GenericArgument::Type(inner) => {
+                    Some(traits(inner, enum_names, composite_names))
+                }
+                _ => None,
+            })
+        }
+        _ => None,
+    };
+    match (path.join("::").as_str(), inner) {
+        ("Option", Some(inner)) => inner,
+        ("Vec" | "Box", Some(inner)) => Traits {
+            copy: false,
+            ..inner
+        },
+        ("bool" | "char" | "i8" | "i16" | "i32" | "i64" | "u8" | "u16" | "u32" | "u64", None) => {
+            all(true)
+        }
+        ("f32" | "f64", None) => Traits {
+            copy: true,
+            eq: false,
+            hash: false,
+            partial_ord: true,
+        },
+        ("String", None) => all(false),
+        (
+            "chrono::NaiveDate"
+            | "chrono::NaiveTime"
+            | "chrono::NaiveDateTime"
+            | "uuid::Uuid"
+            | "rust_decimal::Decimal",
+            None,
+        ) => all(true),
+        ("bytes::Bytes", None) => all(false),
+        ("chrono::DateTime", Some(_)) => all(true),
+        ("serde_json::Value", None) => Traits {
+            copy: false,
+            eq: true,
+            hash: false,
+            partial_ord: false,
+        },
+        // The newtypes of system types are numbers, the others text without a useful order
+        _ if path.len() <= 2 && newtypes::find_rust_name(&last.ident.to_string()).is_some() => {
+            match newtypes::find_rust_name(&last.ident.to_string()) {
+                Some(newtype) if newtype.is_number() => all(true),
+                _ => Traits {
+                    copy: false,
+                    eq: true,
+                    hash: true,
+                    partial_ord: false,
+                },
+            }
+        }
+        // The composite structs only derive PartialEq, since their fields may be floats
+        _ if path.len() <= 2 && composite_names.contains(&last.ident.to_string()) => Traits {
+            copy: false,
+            eq: false,
+            hash: false,
+            partial_ord: false,
+        },
+        // The generated enums are Copy, Eq and Hash, but have no order
+        _ if path.len() <= 2 && enum_names.contains(&last.ident.to_string()) => Traits {
+            copy: true,
+            eq: true,
+            hash: true,
+            partial_ord: false,
+        },
+        _ => unknown,
+    }
+}
+
+/// Whether a column has a type without a Rust mapping of its own, which falls back to `String`
+pub fn falls_back(data_type: &DataType, schema: &str) -> bool {
+    let is_generated = matches!(data_type.kind, TypeKind::Enum | TypeKind::Composite)
+        && data_type.schema == schema;
+    !is_generated
+        && newtypes::find(&data_type.name).is_none()
+        && known_data_type(&data_type.name, false).is_none()
+}
+
+/// Map a PostgreSQL data type to a Rust type, or `None` when it has no mapping of its own
+fn known_data_type(data_type: &str, use_uuid: bool) -> Option<TokenStream> {
+    let rust_type = match data_type {
+        "bigint" => quote! { i64 },
+        "bigserial" => quote! { i64 },
+        "binary varying" => quote! { Vec<u8> },
+        "bit" => quote! { i8 },
+        "bit varying" => quote! { i8 },
+        "boolean" => quote! { bool },
+        "box" => quote! { String },
+        "bytea" => quote! { Vec<u8> },
+        "character" => quote! { String },
+        "character varying" => quote! { String },
+        "cidr" => quote! { String },
+        "circle" => quote! { String },
+        "citext" => quote! { String },
+        "date" => quote! { chrono::NaiveDate },
+        "double precision" => quote! { f64 },
+        "inet" => quote! { String },
+        "integer" => quote! { i32 },
+        "interval" => quote! { String },
+        "json" => quote! { serde_json::Value },
+        "jsonb" => quote! { serde_json::Value },
+        "line" => quote! { String },
+        "lseg" => quote! { String },
+        "macaddr" => quote! { String },
+        "money" => quote! { String },
+        "numeric" => quote! { f64 },
+        "oid" => quote! { u32 },
+        "path" => quote! { String },
+        "pg_lsn" => quote! { String },
+        "point" => quote! { String },
+        "polygon" => quote! { String },
+        "real" => quote! { f32 },
+        "smallint" => quote! { i16 },
+        "smallserial" => quote! { i16 },
+        "serial" => quote! { i32 },
+        "super" => quote! { serde_json::Value },
+        "text" => quote! { String },
+        "timestamp" => quote! { chrono::NaiveDateTime },
+        "timestamp with time zone" => quote! { chrono::DateTime<chrono::Utc> },
+        "timestamp without time zone" => quote! { chrono::NaiveDateTime },
+        "timestamptz" => quote! { chrono::DateTime<chrono::Utc> },
+        "tsvector" => quote! { String },
+        "uuid" => match use_uuid {
+            true => quote! { uuid::Uuid },
+            false => quote! { String },
+        },
+        _ => return None,
+    };
+
+    Some(rust_type)
+}
+
+/// Get the `postgres::types::Type` of a built-in type, or `None` for enums, composite types and
+/// types that extensions add, whose OIDs are only known at runtime
+pub fn postgres_type(data_type: &DataType) -> Option<TokenStream> {
+    if data_type.kind != TypeKind::Base || data_type.schema != "pg_catalog" {
+        return None;
+    }
+
+    let name = match data_type.name.as_str() {
+        "bigint" => "INT8",
+        "cid" => "CID",
+        "bit" => "BIT",
+        "bit varying" => "VARBIT",
+        "boolean" => "BOOL",
+        "bytea" => "BYTEA",
+        "character" => "BPCHAR",
+        "character varying" => "VARCHAR",
+        "cidr" => "CIDR",
+        "date" => "DATE",
+        "double precision" => "FLOAT8",
+        "inet" => "INET",
+        "integer" => "INT4",
+        "interval" => "INTERVAL",
+        "json" => "JSON",
+        "jsonb" => "JSONB",
+        "macaddr" => "MACADDR",
+        "money" => "MONEY",
+        "name" => "NAME",
+        "numeric" => "NUMERIC",
+        "oid" => "OID",
+        "point" => "POINT",
+        "real" => "FLOAT4",
+        "regclass" => "REGCLASS",
+        "regcollation" => "REGCOLLATION",
+        "regconfig" => "REGCONFIG",
+        "regdictionary" => "REGDICTIONARY",
+        "regnamespace" => "REGNAMESPACE",
+        "regoper" => "REGOPER",
+        "regoperator" => "REGOPERATOR",
+        "regproc" => "REGPROC",
+        "regprocedure" => "REGPROCEDURE",
+        "regrole" => "REGROLE",
+        "regtype" => "REGTYPE",
+        "smallint" => "INT2",
+        "text" => "TEXT",
+        "time with time zone" => "TIMETZ",
+        "time without time zone" => "TIME",
+        "timestamp with time zone" => "TIMESTAMPTZ",
+        "timestamp without time zone" => "TIMESTAMP",
+        "tsvector" => "TS_VECTOR",
+        "uuid" => "UUID",
+        "xid" => "XID",
+        "xid8" => "XID8",
+        "xml" => "XML",
+        _ => return None,
+    };
+    let name = if data_type.dimensions > 0 {
+        format!("{}_ARRAY", name)
+    } else {
+        name.to_string()
+    };
+    let ident = Ident::new(&name, Span::call_site());
+
+    Some(quote! { postgres::types::Type::#ident })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps_map_to_chrono() {
+        let mapped = |data_type| map_data_type(data_type, false).to_string();
+        assert_eq!(
+            mapped("timestamp with time zone"),
+            quote! { chrono::DateTime<chrono::Utc> }.to_string()
+        );
+        assert_eq!(
+            mapped("timestamptz"),
+            quote! { chrono::DateTime<chrono::Utc> }.to_string()
+        );
+        assert_eq!(
+            mapped("timestamp without time zone"),
+            quote! { chrono::NaiveDateTime }.to_string()
+        );
+        assert_eq!(
+            mapped("timestamp"),
+            quote! { chrono::NaiveDateTime }.to_string()
+        );
+    }
+
+    #[test]
+    fn numeric_precision_picks_the_smallest_type_that_holds_it() {
+        let mapped = |precision, scale, precise| {
+            let data_type = DataType {
+                name: "numeric".to_string(),
+                schema: "pg_catalog".to_string(),
+                kind: TypeKind::Base,
+                dimensions: 0,
+                element_not_null: false,
+                length: None,
+                precision,
+                scale,
+            };
+            map_column_type(
+                &data_type,
+                "public",
+                false,
+                precise,
+                false,
+                BytesType::default(),
+                &ArrayConfig::default(),
+            )
+            .to_string()
+        };
+
+        for (precision, scale, rust_type) in [
+            (Some(1), Some(0), "i16"),
+            (Some(4), Some(0), "i16"),
+            (Some(5), Some(0), "i32"),
+            (Some(9), Some(0), "i32"),
+            (Some(10), Some(0), "i64"),
+            (Some(18), Some(0), "i64"),
+            // Integers wider than i64 are decimals
+            (Some(19), Some(0), "rust_decimal :: Decimal"),
+            (Some(28), Some(0), "rust_decimal :: Decimal"),
+            (Some(4), Some(2), "rust_decimal :: Decimal"),
+            (Some(28), Some(10), "rust_decimal :: Decimal"),
+            // Decimal only holds 28 digits
+            (Some(29), Some(0), "f64"),
+            (Some(29), Some(2), "f64"),
+            (None, None, "f64"),
+        ] {
+            assert_eq!(
+                mapped(precision, scale, true),
+                rust_type,
+                "numeric({:?},{:?})",
+                precision,
+                scale
+            );
+            assert_eq!(mapped(precision, scale, false), "f64");
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use std::fmt;
+
+use postgres::GenericClient;
+use tracing::info;
+
+use crate::connection::query;
+use crate::error::{Error, Result};
+
+/// The version of the PostgreSQL server, used to only query what the server supports
+///
+/// Stored as `server_version_num`, e.g. 150003 for 15.3 and 90624 for 9.6.24.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ServerVersion(pub i32);
+
+impl ServerVersion {
+    /// Ask the server for its version
+    pub fn detect(client: &mut impl GenericClient, simple_query: bool) -> Result<Self> {
+        let rows = query(client, simple_query, "SHOW server_version_num", &[])
+            .map_err(Error::query("server version"))?;
+        let version: String = rows
+            .first()
+            .ok_or_else(|| Error::Config("The server didn't report its version".to_string()))?
+            .get(0);
+        let version = version.parse().map_err(|_| {
+            Error::Config(format!("Unexpected PostgreSQL server version {}", version))
+        })?;
+
+        let version = ServerVersion(version);
+        info!("Connected to PostgreSQL {}", version);
+        Ok(version)
+    }
+
+    /// Whether columns can be `GENERATED ... AS IDENTITY`, added in PostgreSQL 10
+    pub fn has_identity_columns(self) -> bool {
+        self.0 >= 100000
+    }
+
+    /// Whether tables can be partitioned declaratively, added in PostgreSQL 10
+    pub fn has_partitioning(self) -> bool {
+        self.0 >= 100000
+    }
+
+    /// Whether columns can be `GENERATED ALWAYS AS (...) STORED`, added in PostgreSQL 12
+    pub fn has_generated_columns(self) -> bool {
+        self.0 >= 120000
+    }
+
+    /// Whether indexes can have `INCLUDE` columns that aren't part of the key, added in
+    /// PostgreSQL 11
+    pub fn has_covering_indexes(self) -> bool {
+        self.0 >= 110000
+    }
+
+    /// Whether the server has stored procedures, added in PostgreSQL 11
+    pub fn has_procedures(self) -> bool {
+        self.0 >= 110000
+    }
+}
+
+impl fmt::Display for ServerVersion {
+    /// Format the version the way PostgreSQL does, e.g. `15.3` or `9.6.24`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let major = self.0 / 10000;
+        if major >= 10 {
+            write!(f, "{}.{}", major, self.0 % 10000)
+        } else {
+            write!(f, "{}.{}.{}", major, self.0 / 100 % 100, self.0 % 100)
+        }
+    }
+}